@@ -0,0 +1,98 @@
+// Records what each stretch of a bit stream was decoded as, and writes it out in the text format
+// sigrok's protocol decoders use for annotations, so a decode can be laid over the capture it came
+// from in Pulseview
+use crate::Reader;
+use std::io::{Error, Read, Write};
+
+// Bits start to end (end exclusive) of the stream, counted like Reader::bits_read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub start: u64,
+    pub end: u64,
+    pub row: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    annotations: Vec<Annotation>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn record(&mut self, start: u64, end: u64, row: &str, text: &str) {
+        self.annotations.push(Annotation {
+            start,
+            end,
+            row: row.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    // Reads a field and notes it down with its value in hex
+    pub fn read_bits<R: Read>(
+        &mut self,
+        reader: &mut Reader<R>,
+        row: &str,
+        number_of_bits: usize,
+    ) -> Result<u128, Error> {
+        let start = reader.bits_read();
+        let value = reader.read_bits(number_of_bits)?;
+        self.record(start, reader.bits_read(), row, &format!("{:X}", value));
+        Ok(value)
+    }
+
+    // One line per annotation, `start-end decoder: row: "text"` in samples, which is what
+    // sigrok-cli prints for a decoder with sample numbers turned on. samples_per_bit maps bit
+    // positions back onto the capture, e.g. the decimation given to read_sigrok_logic.
+    pub fn write_sigrok<W: Write>(
+        &self,
+        mut output: W,
+        decoder: &str,
+        samples_per_bit: u64,
+    ) -> Result<(), Error> {
+        for annotation in &self.annotations {
+            writeln!(
+                output,
+                "{}-{} {}: {}: \"{}\"",
+                annotation.start * samples_per_bit,
+                annotation.end * samples_per_bit,
+                decoder,
+                annotation.row,
+                annotation.text.replace('"', "\\\"")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn exports_sigrok_annotations() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_1111, 0x42]));
+        let mut trace = Trace::new();
+
+        assert_eq!(trace.read_bits(&mut reader, "flag", 1).unwrap(), 1);
+        reader.read_bits(7).unwrap();
+        trace.read_bits(&mut reader, "data", 8).unwrap();
+        trace.record(0, 16, "frame", "say \"hi\"");
+
+        let mut output = Vec::new();
+        trace.write_sigrok(&mut output, "mine", 8).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "0-8 mine: flag: \"1\"\n64-128 mine: data: \"42\"\n0-128 mine: frame: \"say \\\"hi\\\"\"\n"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// Variants of the Vec returning reads that allocate from a bumpalo arena instead of the heap, so a
+// server can decode a message into one arena and free it all at once
+#[cfg(feature = "framing")]
+use crate::frame_codec::FrameCodec;
+use crate::{Reader, PREALLOCATE_LIMIT};
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use std::io::{Error, Read};
+
+impl<R: Read> Reader<R> {
+    pub fn read_bytes_in<'b>(
+        &mut self,
+        number_of_bytes: usize,
+        arena: &'b Bump,
+    ) -> Result<BumpVec<'b, u8>, Error> {
+        let mut result = BumpVec::with_capacity_in(number_of_bytes.min(PREALLOCATE_LIMIT), arena);
+        for _ in 0..number_of_bytes {
+            result.push(self.read_byte()?);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "framing")]
+impl FrameCodec {
+    pub fn decode_record_in<'b, R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        arena: &'b Bump,
+    ) -> Result<BumpVec<'b, i64>, Error> {
+        let mut record = BumpVec::with_capacity_in(self.fields().len(), arena);
+        for field in self.fields() {
+            let raw = reader.read_bits(field.width)?;
+            record.push(field.decode_value(raw));
+        }
+        Ok(record)
+    }
+
+    pub fn decode_batch_in<'b, R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        arena: &'b Bump,
+    ) -> Result<BumpVec<'b, i64>, Error> {
+        let count = reader.read_usize(32)?;
+        let capacity = count
+            .saturating_mul(self.fields().len())
+            .min(PREALLOCATE_LIMIT);
+        let mut records = BumpVec::with_capacity_in(capacity, arena);
+        self.decode_records(reader, count, |value| records.push(value))?;
+        Ok(records)
+    }
+}
+
+#[cfg(all(test, feature = "framing"))]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn decodes_into_arena() {
+        let mut codec = FrameCodec::new();
+        codec.add_field("id", 4, false).unwrap();
+        codec.add_field("temperature", 8, true).unwrap();
+        codec.set_delta_width("temperature", 4).unwrap();
+
+        let records = [1, 20, 2, 22, 3, -100];
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.write_bytes(b"hdr".to_vec()).unwrap();
+        codec.encode_record(&records[..2], &mut writer).unwrap();
+        codec.encode_batch(&records, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let arena = Bump::new();
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        assert_eq!(reader.read_bytes_in(3, &arena).unwrap(), b"hdr");
+        assert_eq!(
+            codec.decode_record_in(&mut reader, &arena).unwrap(),
+            [1, 20]
+        );
+        assert_eq!(codec.decode_batch_in(&mut reader, &arena).unwrap(), records);
+        assert!(arena.allocated_bytes() > 0);
+    }
+}
@@ -0,0 +1,181 @@
+// A BitWrite into an owned [u8; N], for stack only targets and hot paths that can't allocate. It
+// never grows, so a write that doesn't fit fails as UnexpectedEof and writes none of its bits.
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error};
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayBitWriter<const N: usize> {
+    buffer: [u8; N],
+    bit_order: BitOrder,
+    bits_written: usize,
+}
+
+impl<const N: usize> ArrayBitWriter<N> {
+    pub fn new() -> ArrayBitWriter<N> {
+        ArrayBitWriter::with_bit_order(BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(bit_order: BitOrder) -> ArrayBitWriter<N> {
+        ArrayBitWriter {
+            buffer: [0; N],
+            bit_order,
+            bits_written: 0,
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.bits_written.is_multiple_of(8)
+    }
+
+    // Bits left before the array is full
+    pub fn remaining_bits(&self) -> usize {
+        N * 8 - self.bits_written
+    }
+
+    // The bytes written so far, the last one zero padded if it's partly written
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.bits_written.div_ceil(8)]
+    }
+
+    fn check_room(&self, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > self.remaining_bits() {
+            let missing = number_of_bits - self.remaining_bits();
+            return Err(Error::eof(self.bits_written as u64, missing as u64));
+        }
+        Ok(())
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn write_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.check_room(8 * bytes.len())?;
+        if self.is_aligned() {
+            let start = self.bits_written / 8;
+            self.buffer[start..start + bytes.len()].copy_from_slice(bytes);
+            self.bits_written += 8 * bytes.len();
+            return Ok(());
+        }
+        for &byte in bytes {
+            self.write_bits(byte as u128, 8)?;
+        }
+        Ok(())
+    }
+
+    // The array and how many bits of it were written. Bits past that are zero.
+    pub fn finish(self) -> ([u8; N], usize) {
+        (self.buffer, self.bits_written)
+    }
+}
+
+impl<const N: usize> Default for ArrayBitWriter<N> {
+    fn default() -> ArrayBitWriter<N> {
+        ArrayBitWriter::new()
+    }
+}
+
+impl<const N: usize> BitWrite for ArrayBitWriter<N> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(
+                self.bits_written as u64,
+                number_of_bits,
+                128,
+            ));
+        }
+        self.check_room(number_of_bits)?;
+        // Each pass fills as much of the current byte as it can. The array starts zeroed and is
+        // only ever added to, so or-ing the bits in is enough.
+        let mut remaining = number_of_bits;
+        while remaining > 0 {
+            let used = self.bits_written % 8;
+            let count = remaining.min(8 - used);
+            let mask = (1u128 << count) - 1;
+            let byte = &mut self.buffer[self.bits_written / 8];
+            match self.bit_order {
+                BitOrder::MsbFirst => {
+                    let piece = (bits >> (remaining - count) & mask) as u8;
+                    *byte |= piece << (8 - used - count);
+                }
+                BitOrder::LsbFirst => {
+                    let piece = (bits >> (number_of_bits - remaining) & mask) as u8;
+                    *byte |= piece << used;
+                }
+            }
+            self.bits_written += count;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.bits_written as u64
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.write_slice(&bytes)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::Writer;
+
+    #[test]
+    pub fn matches_writer() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut writer = ArrayBitWriter::<1024>::with_bit_order(bit_order);
+            for index in 0..150u128 {
+                let width = (index % 100 + 1) as usize;
+                let value =
+                    index.wrapping_mul(0x9E37_79B9_7F4A_7C15) & (u128::MAX >> (128 - width));
+                expected.write_bits(value, width).unwrap();
+                writer.write_bits(value, width).unwrap();
+            }
+            expected.write_bytes(vec![1, 2, 3]).unwrap();
+            writer.write_slice(&[1, 2, 3]).unwrap();
+            expected.write_bits(0b101, 3).unwrap();
+            writer.write_bits(0b101, 3).unwrap();
+            expected.pad_to_byte().unwrap();
+            writer.pad_to_byte().unwrap();
+            expected.write_bytes(vec![4, 5]).unwrap();
+            writer.write_slice(&[4, 5]).unwrap();
+
+            assert_eq!(writer.bits_written(), expected.bits_written());
+            let expected = expected.into_inner().unwrap();
+            assert_eq!(writer.as_bytes(), &expected[..]);
+            let (buffer, bit_len) = writer.finish();
+            assert_eq!(bit_len, expected.len() * 8);
+            assert!(buffer[expected.len()..].iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    pub fn refuses_to_overflow() {
+        let mut writer = ArrayBitWriter::<2>::new();
+        writer.write_bits(0b1011, 4).unwrap();
+        assert_eq!(writer.remaining_bits(), 12);
+        let error = writer.write_bits(0x1FFF, 13).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnexpectedEof {
+                bit_position: 4,
+                bits_missing: 1
+            }
+        ));
+        assert!(writer.write_slice(&[0xFF, 0xFF]).is_err());
+        assert_eq!(writer.as_bytes(), [0b1011_0000]);
+        writer.write_bits(0xFFF, 12).unwrap();
+        assert!(writer.write_bit(false).is_err());
+        assert_eq!(writer.finish(), ([0xBF, 0xFF], 16));
+    }
+}
@@ -0,0 +1,378 @@
+// The async reader and writer, written once for both tokio's and futures' IO traits. The two sets
+// of traits have the same shape but aren't the same traits, so async_bit_types! is expanded in
+// tokio_io and futures_io after they import their own versions of
+//   AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt, AsyncBufReadExt, BufReader
+// and define the two calls that differ between them:
+//   fn consume(reader: &mut BufReader<R>, amount: usize)
+//   async fn skip_bytes(reader: &mut BufReader<R>, count: u64) -> io::Result<u64>
+// along with use crate::{BitOrder, Error}, crate::bit_write::check_fits and std::io::ErrorKind.
+
+// Whole bytes an AsyncBitWriter holds before it awaits the inner writer
+pub(crate) const BUFFER_SIZE: usize = 8192;
+
+pub(crate) fn low_mask(number_of_bits: usize) -> u64 {
+    u64::MAX
+        .checked_shr(64 - number_of_bits as u32)
+        .unwrap_or(0)
+}
+
+macro_rules! async_bit_types {
+    () => {
+        pub struct AsyncBitReader<R: AsyncRead + Unpin> {
+            // Same layout as Reader's cache: the next bit is the top of the low `cached` bits
+            // in MSB first order and the bottom one in LSB first order
+            cache: u64,
+            cached: usize,
+            bit_order: BitOrder,
+            bits_read: u64,
+            reader: BufReader<R>,
+        }
+
+        impl<R: AsyncRead + Unpin> AsyncBitReader<R> {
+            pub fn new(inner_reader: R) -> AsyncBitReader<R> {
+                AsyncBitReader::with_bit_order(inner_reader, BitOrder::MsbFirst)
+            }
+
+            pub fn with_bit_order(inner_reader: R, bit_order: BitOrder) -> AsyncBitReader<R> {
+                AsyncBitReader {
+                    cache: 0,
+                    cached: 0,
+                    bit_order,
+                    bits_read: 0,
+                    reader: BufReader::new(inner_reader),
+                }
+            }
+
+            pub fn bit_order(&self) -> BitOrder {
+                self.bit_order
+            }
+
+            pub fn is_aligned(&self) -> bool {
+                self.cached.is_multiple_of(8)
+            }
+
+            pub fn pending_bits(&self) -> usize {
+                self.cached % 8
+            }
+
+            pub fn bits_read(&self) -> u64 {
+                self.bits_read
+            }
+
+            fn push_byte(&mut self, byte: u8) {
+                match self.bit_order {
+                    BitOrder::MsbFirst => self.cache = self.cache << 8 | byte as u64,
+                    BitOrder::LsbFirst => self.cache |= (byte as u64) << self.cached,
+                }
+                self.cached += 8;
+            }
+
+            // Like Reader::refill, only awaiting the inner reader while there are fewer than
+            // wanted bits
+            async fn refill(&mut self, wanted: usize) -> Result<(), Error> {
+                while self.cached <= 56 {
+                    if self.reader.buffer().is_empty() && self.cached >= wanted {
+                        break;
+                    }
+                    let buffer = match self.reader.fill_buf().await {
+                        Ok(buffer) => buffer,
+                        Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(Error::io(self.bits_read, e)),
+                    };
+                    if buffer.is_empty() {
+                        break;
+                    }
+                    let n = buffer.len().min((64 - self.cached) / 8);
+                    let mut bytes = [0; 8];
+                    bytes[..n].copy_from_slice(&buffer[..n]);
+                    consume(&mut self.reader, n);
+                    for &byte in &bytes[..n] {
+                        self.push_byte(byte);
+                    }
+                }
+                Ok(())
+            }
+
+            fn take_cached(&mut self, number_of_bits: usize) -> u64 {
+                let bits = match self.bit_order {
+                    BitOrder::MsbFirst => {
+                        let bits = self.cache >> (self.cached - number_of_bits);
+                        self.cache &= $crate::async_bits::low_mask(self.cached - number_of_bits);
+                        bits
+                    }
+                    BitOrder::LsbFirst => {
+                        let bits = self.cache & $crate::async_bits::low_mask(number_of_bits);
+                        self.cache = self.cache.checked_shr(number_of_bits as u32).unwrap_or(0);
+                        bits
+                    }
+                };
+                self.cached -= number_of_bits;
+                self.bits_read += number_of_bits as u64;
+                bits
+            }
+
+            pub async fn read_bit(&mut self) -> Result<bool, Error> {
+                Ok(self.read_bits(1).await? == 1)
+            }
+
+            pub async fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+                if number_of_bits > 128 {
+                    return Err(Error::too_wide(self.bits_read, number_of_bits, 128));
+                }
+                let mut output = 0u128;
+                let mut remaining = number_of_bits;
+                while remaining > 0 {
+                    let count = remaining.min(56);
+                    self.refill(count).await?;
+                    if self.cached < count {
+                        return Err(Error::eof(self.bits_read, (remaining - self.cached) as u64));
+                    }
+                    let bits = self.take_cached(count) as u128;
+                    match self.bit_order {
+                        BitOrder::MsbFirst => output = output << count | bits,
+                        BitOrder::LsbFirst => output |= bits << (number_of_bits - remaining),
+                    }
+                    remaining -= count;
+                }
+                Ok(output)
+            }
+
+            pub async fn read_byte(&mut self) -> Result<u8, Error> {
+                Ok(self.read_bits(8).await? as u8)
+            }
+
+            pub async fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+                let mut result = Vec::new();
+                if self.is_aligned() {
+                    while result.len() < number_of_bytes && self.cached > 0 {
+                        result.push(self.take_cached(8) as u8);
+                    }
+                    let wanted = (number_of_bytes - result.len()) as u64;
+                    let copied = (&mut self.reader)
+                        .take(wanted)
+                        .read_to_end(&mut result)
+                        .await
+                        .map_err(Error::io_at(self.bits_read))?;
+                    self.bits_read += 8 * copied as u64;
+                    if result.len() < number_of_bytes {
+                        let missing = 8 * (number_of_bytes - result.len()) as u64;
+                        return Err(Error::eof(self.bits_read, missing));
+                    }
+                    return Ok(result);
+                }
+                for _ in 0..number_of_bytes {
+                    result.push(self.read_byte().await?);
+                }
+                Ok(result)
+            }
+
+            pub async fn skip_bits(&mut self, number_of_bits: u64) -> Result<(), Error> {
+                let from_cache = number_of_bits.min(self.cached as u64) as usize;
+                if from_cache > 0 {
+                    self.take_cached(from_cache);
+                }
+                let remaining = number_of_bits - from_cache as u64;
+                let whole_bytes = remaining / 8;
+                let skipped = skip_bytes(&mut self.reader, whole_bytes)
+                    .await
+                    .map_err(Error::io_at(self.bits_read))?;
+                self.bits_read += skipped * 8;
+                if skipped < whole_bytes {
+                    let missing = (whole_bytes - skipped) * 8 + remaining % 8;
+                    return Err(Error::eof(self.bits_read, missing));
+                }
+                self.read_bits((remaining % 8) as usize).await?;
+                Ok(())
+            }
+
+            // Drops the rest of the current byte. With require_zeros the dropped bits have to
+            // be zero padding.
+            pub async fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+                let padding = self.read_bits(self.pending_bits()).await?;
+                if require_zeros && padding != 0 {
+                    return Err(Error::invalid_data(
+                        self.bits_read,
+                        "Padding bits before the byte boundary aren't zero",
+                    ));
+                }
+                Ok(())
+            }
+
+            pub fn get_ref(&self) -> &R {
+                self.reader.get_ref()
+            }
+
+            // Anything read ahead into the buffer is lost
+            pub fn into_inner(self) -> R {
+                self.reader.into_inner()
+            }
+        }
+
+        pub struct AsyncBitWriter<W: AsyncWrite + Unpin> {
+            // Fewer than 8 bits between calls, the rest go straight to buffer as whole bytes
+            cache: u64,
+            cached: usize,
+            bit_order: BitOrder,
+            bits_written: u64,
+            buffer: Vec<u8>,
+            writer: W,
+        }
+
+        impl<W: AsyncWrite + Unpin> AsyncBitWriter<W> {
+            pub fn new(inner_writer: W) -> AsyncBitWriter<W> {
+                AsyncBitWriter::with_bit_order(inner_writer, BitOrder::MsbFirst)
+            }
+
+            pub fn with_bit_order(inner_writer: W, bit_order: BitOrder) -> AsyncBitWriter<W> {
+                AsyncBitWriter {
+                    cache: 0,
+                    cached: 0,
+                    bit_order,
+                    bits_written: 0,
+                    buffer: Vec::with_capacity($crate::async_bits::BUFFER_SIZE),
+                    writer: inner_writer,
+                }
+            }
+
+            pub fn bit_order(&self) -> BitOrder {
+                self.bit_order
+            }
+
+            pub fn is_aligned(&self) -> bool {
+                self.cached == 0
+            }
+
+            pub fn pending_bits(&self) -> usize {
+                self.cached
+            }
+
+            pub fn bits_written(&self) -> u64 {
+                self.bits_written
+            }
+
+            // Adds up to 56 bits from the bottom of bits and moves any whole bytes to the buffer
+            fn push_bits(&mut self, bits: u64, number_of_bits: usize) {
+                match self.bit_order {
+                    BitOrder::MsbFirst => self.cache = self.cache << number_of_bits | bits,
+                    BitOrder::LsbFirst => self.cache |= bits << self.cached,
+                }
+                self.cached += number_of_bits;
+                self.bits_written += number_of_bits as u64;
+                while self.cached >= 8 {
+                    let byte = match self.bit_order {
+                        BitOrder::MsbFirst => {
+                            let byte = self.cache >> (self.cached - 8);
+                            self.cache &= $crate::async_bits::low_mask(self.cached - 8);
+                            byte
+                        }
+                        BitOrder::LsbFirst => {
+                            let byte = self.cache;
+                            self.cache >>= 8;
+                            byte
+                        }
+                    };
+                    self.buffer.push(byte as u8);
+                    self.cached -= 8;
+                }
+            }
+
+            async fn write_buffer(&mut self) -> Result<(), Error> {
+                self.writer
+                    .write_all(&self.buffer)
+                    .await
+                    .map_err(Error::io_at(self.bits_written))?;
+                self.buffer.clear();
+                Ok(())
+            }
+
+            async fn spill_if_full(&mut self) -> Result<(), Error> {
+                if self.buffer.len() >= $crate::async_bits::BUFFER_SIZE {
+                    self.write_buffer().await?;
+                }
+                Ok(())
+            }
+
+            pub async fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+                self.write_bits(write_one as u128, 1).await
+            }
+
+            pub async fn write_bits(
+                &mut self,
+                bits: u128,
+                number_of_bits: usize,
+            ) -> Result<(), Error> {
+                if number_of_bits > 128 {
+                    return Err(Error::too_wide(self.bits_written, number_of_bits, 128));
+                }
+                let mut remaining = number_of_bits;
+                while remaining > 0 {
+                    let count = remaining.min(56);
+                    let piece = match self.bit_order {
+                        BitOrder::MsbFirst => bits >> (remaining - count),
+                        BitOrder::LsbFirst => bits >> (number_of_bits - remaining),
+                    } as u64;
+                    self.push_bits(piece & $crate::async_bits::low_mask(count), count);
+                    remaining -= count;
+                }
+                self.spill_if_full().await
+            }
+
+            pub async fn write_bits_checked(
+                &mut self,
+                bits: u128,
+                number_of_bits: usize,
+            ) -> Result<(), Error> {
+                check_fits(self.bits_written, bits, number_of_bits)?;
+                self.write_bits(bits, number_of_bits).await
+            }
+
+            pub async fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+                self.write_bits(byte as u128, 8).await
+            }
+
+            pub async fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+                if self.is_aligned() {
+                    self.buffer.extend_from_slice(&bytes);
+                    self.bits_written += 8 * bytes.len() as u64;
+                    return self.spill_if_full().await;
+                }
+                for byte in bytes {
+                    self.push_bits(byte as u64, 8);
+                }
+                self.spill_if_full().await
+            }
+
+            // Zero pads to the next byte, giving back how many bits that took
+            pub async fn pad_to_byte(&mut self) -> Result<usize, Error> {
+                if self.is_aligned() {
+                    return Ok(0);
+                }
+                let padding = 8 - self.cached;
+                self.write_bits(0, padding).await?;
+                Ok(padding)
+            }
+
+            // Pads, then writes out and flushes everything buffered
+            pub async fn flush(&mut self) -> Result<usize, Error> {
+                let padding = self.pad_to_byte().await?;
+                self.write_buffer().await?;
+                self.writer
+                    .flush()
+                    .await
+                    .map_err(Error::io_at(self.bits_written))?;
+                Ok(padding)
+            }
+
+            pub fn get_ref(&self) -> &W {
+                &self.writer
+            }
+
+            pub async fn into_inner(mut self) -> Result<W, Error> {
+                self.flush().await?;
+                Ok(self.writer)
+            }
+        }
+    };
+}
+
+pub(crate) use async_bit_types;
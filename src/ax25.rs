@@ -0,0 +1,472 @@
+// AX.25 frames, as amateur packet radio and APRS send them inside HDLC framing: the destination,
+// source and up to 8 digipeater addresses, a control byte, a protocol ID for I and UI frames, the
+// information field and a 16 bit FCS (CRC-16/IBM-SDLC, low byte first). Only modulo 8 sequence
+// numbers are handled, so SABME connections' 2 byte control fields aren't.
+use crate::bit_write::BitWrite;
+use crate::digest::CrcParams;
+use crate::hdlc::{HdlcDeframer, HdlcWriter};
+use crate::{Error, Reader};
+use std::fmt;
+use std::io::Read;
+
+// The protocol ID for no layer 3, which APRS uses
+pub const NO_LAYER_3: u8 = 0xF0;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address {
+    // Up to 6 upper case letters and digits
+    pub callsign: String,
+    // 0 to 15
+    pub ssid: u8,
+    // The C bit of the destination and source, set on the destination of a command and the source
+    // of a response, or the H bit of a digipeater that has repeated the frame
+    pub flag: bool,
+}
+
+impl Address {
+    pub fn new(callsign: &str, ssid: u8) -> Result<Address, Error> {
+        let valid = !callsign.is_empty()
+            && callsign.len() <= 6
+            && callsign
+                .bytes()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+        if !valid || ssid > 15 {
+            return Err(Error::invalid_input(0, "Not an AX.25 callsign and SSID"));
+        }
+        Ok(Address {
+            callsign: callsign.to_string(),
+            ssid,
+            flag: false,
+        })
+    }
+
+    // Shifted a bit left as AX.25 sends it, with the extension bit set on the last address
+    fn push_to(&self, bytes: &mut Vec<u8>, last: bool) {
+        let callsign = self.callsign.as_bytes();
+        bytes.extend((0..6).map(|index| callsign.get(index).unwrap_or(&b' ') << 1));
+        // The two reserved bits are sent as 1s
+        bytes.push((self.flag as u8) << 7 | 0x60 | (self.ssid & 0x0F) << 1 | last as u8);
+    }
+
+    // And whether it's the last
+    fn parse(bytes: &[u8], position: u64) -> Result<(Address, bool), Error> {
+        if bytes[..6].iter().any(|&byte| byte & 1 != 0) {
+            return Err(Error::invalid_data(position, "AX.25 address ends early"));
+        }
+        let callsign: String = bytes[..6].iter().map(|&byte| (byte >> 1) as char).collect();
+        let address = Address {
+            callsign: callsign.trim_end_matches(' ').to_string(),
+            ssid: bytes[6] >> 1 & 0x0F,
+            flag: bytes[6] & 0x80 != 0,
+        };
+        Ok((address, bytes[6] & 1 != 0))
+    }
+}
+
+// N0CALL-7, leaving the SSID off when it's 0
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ssid {
+            0 => write!(f, "{}", self.callsign),
+            ssid => write!(f, "{}-{}", self.callsign, ssid),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SupervisoryKind {
+    ReceiveReady,
+    ReceiveNotReady,
+    Reject,
+    SelectiveReject,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnnumberedKind {
+    Ui,
+    Sabm,
+    Sabme,
+    Disc,
+    Dm,
+    Ua,
+    Frmr,
+    Xid,
+    Test,
+    // The control byte of anything else, without its P/F bit
+    Other(u8),
+}
+
+const UNNUMBERED: [(UnnumberedKind, u8); 9] = [
+    (UnnumberedKind::Ui, 0x03),
+    (UnnumberedKind::Sabm, 0x2F),
+    (UnnumberedKind::Sabme, 0x6F),
+    (UnnumberedKind::Disc, 0x43),
+    (UnnumberedKind::Dm, 0x0F),
+    (UnnumberedKind::Ua, 0x63),
+    (UnnumberedKind::Frmr, 0x87),
+    (UnnumberedKind::Xid, 0xAF),
+    (UnnumberedKind::Test, 0xE3),
+];
+
+// The control field, with sequence numbers 0 to 7
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    Information {
+        send: u8,
+        receive: u8,
+        poll: bool,
+    },
+    Supervisory {
+        kind: SupervisoryKind,
+        receive: u8,
+        poll_final: bool,
+    },
+    Unnumbered {
+        kind: UnnumberedKind,
+        poll_final: bool,
+    },
+}
+
+impl Control {
+    fn to_byte(self) -> u8 {
+        match self {
+            Control::Information {
+                send,
+                receive,
+                poll,
+            } => (receive & 7) << 5 | (poll as u8) << 4 | (send & 7) << 1,
+            Control::Supervisory {
+                kind,
+                receive,
+                poll_final,
+            } => {
+                let kind = match kind {
+                    SupervisoryKind::ReceiveReady => 0,
+                    SupervisoryKind::ReceiveNotReady => 1,
+                    SupervisoryKind::Reject => 2,
+                    SupervisoryKind::SelectiveReject => 3,
+                };
+                (receive & 7) << 5 | (poll_final as u8) << 4 | kind << 2 | 0b01
+            }
+            Control::Unnumbered { kind, poll_final } => {
+                let byte = match kind {
+                    UnnumberedKind::Other(byte) => byte | 0b11,
+                    kind => {
+                        UNNUMBERED
+                            .iter()
+                            .find(|(known, _)| *known == kind)
+                            .unwrap()
+                            .1
+                    }
+                };
+                byte & !0x10 | (poll_final as u8) << 4
+            }
+        }
+    }
+
+    fn from_byte(byte: u8) -> Control {
+        let poll_final = byte & 0x10 != 0;
+        let receive = byte >> 5;
+        if byte & 1 == 0 {
+            return Control::Information {
+                send: byte >> 1 & 7,
+                receive,
+                poll: poll_final,
+            };
+        }
+        if byte & 0b11 == 0b01 {
+            let kind = match byte >> 2 & 0b11 {
+                0 => SupervisoryKind::ReceiveReady,
+                1 => SupervisoryKind::ReceiveNotReady,
+                2 => SupervisoryKind::Reject,
+                _ => SupervisoryKind::SelectiveReject,
+            };
+            return Control::Supervisory {
+                kind,
+                receive,
+                poll_final,
+            };
+        }
+        let byte = byte & !0x10;
+        let kind = UNNUMBERED
+            .iter()
+            .find(|&&(_, known)| known == byte)
+            .map_or(UnnumberedKind::Other(byte), |&(kind, _)| kind);
+        Control::Unnumbered { kind, poll_final }
+    }
+
+    // I and UI frames carry a protocol ID and information
+    fn has_pid(self) -> bool {
+        matches!(
+            self,
+            Control::Information { .. }
+                | Control::Unnumbered {
+                    kind: UnnumberedKind::Ui,
+                    ..
+                }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ax25Frame {
+    pub destination: Address,
+    pub source: Address,
+    pub digipeaters: Vec<Address>,
+    pub control: Control,
+    // Only on I and UI frames
+    pub pid: Option<u8>,
+    pub info: Vec<u8>,
+}
+
+impl Ax25Frame {
+    // A UI command with no layer 3, as APRS sends
+    pub fn ui(destination: Address, source: Address, path: Vec<Address>, info: &[u8]) -> Ax25Frame {
+        Ax25Frame {
+            destination: Address {
+                flag: true,
+                ..destination
+            },
+            source: Address {
+                flag: false,
+                ..source
+            },
+            digipeaters: path,
+            control: Control::Unnumbered {
+                kind: UnnumberedKind::Ui,
+                poll_final: false,
+            },
+            pid: Some(NO_LAYER_3),
+            info: info.to_vec(),
+        }
+    }
+
+    // The frame between its flags, FCS included
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        if self.digipeaters.len() > 8 {
+            return Err(Error::invalid_input(
+                0,
+                "AX.25 allows at most 8 digipeaters",
+            ));
+        }
+        if self.pid.is_some() != self.control.has_pid() {
+            return Err(Error::invalid_input(
+                0,
+                "Only AX.25 I and UI frames have a PID",
+            ));
+        }
+        let mut bytes = Vec::with_capacity(18 + 7 * self.digipeaters.len() + self.info.len());
+        self.destination.push_to(&mut bytes, false);
+        self.source.push_to(&mut bytes, self.digipeaters.is_empty());
+        for (index, digipeater) in self.digipeaters.iter().enumerate() {
+            digipeater.push_to(&mut bytes, index + 1 == self.digipeaters.len());
+        }
+        bytes.push(self.control.to_byte());
+        bytes.extend(self.pid);
+        bytes.extend_from_slice(&self.info);
+        let fcs = CrcParams::CRC_16_IBM_SDLC.checksum(&bytes)? as u16;
+        bytes.extend_from_slice(&fcs.to_le_bytes());
+        Ok(bytes)
+    }
+
+    // Parses a frame from between its flags, failing with InvalidData at the bit of the frame that
+    // doesn't fit or if the FCS doesn't match
+    pub fn from_bytes(bytes: &[u8]) -> Result<Ax25Frame, Error> {
+        // Two addresses, the control byte and the FCS
+        if bytes.len() < 17 {
+            return Err(Error::invalid_data(0, "AX.25 frame is too short"));
+        }
+        let (body, fcs) = bytes.split_at(bytes.len() - 2);
+        let expected = CrcParams::CRC_16_IBM_SDLC.checksum(body)? as u16;
+        if u16::from_le_bytes([fcs[0], fcs[1]]) != expected {
+            return Err(Error::invalid_data(
+                8 * body.len() as u64,
+                "AX.25 FCS doesn't match",
+            ));
+        }
+        let mut addresses = Vec::new();
+        let mut offset = 0;
+        loop {
+            if addresses.len() == 10 || offset + 7 > body.len() {
+                let position = 8 * offset as u64;
+                return Err(Error::invalid_data(
+                    position,
+                    "AX.25 address field doesn't end",
+                ));
+            }
+            let (address, last) = Address::parse(&body[offset..offset + 7], 8 * offset as u64)?;
+            addresses.push(address);
+            offset += 7;
+            if last {
+                break;
+            }
+        }
+        if addresses.len() < 2 {
+            return Err(Error::invalid_data(48, "AX.25 frame has no source address"));
+        }
+        if offset == body.len() {
+            let position = 8 * offset as u64;
+            return Err(Error::invalid_data(
+                position,
+                "AX.25 frame has no control field",
+            ));
+        }
+        let control = Control::from_byte(body[offset]);
+        offset += 1;
+        let pid = match control.has_pid() {
+            true if offset == body.len() => {
+                let position = 8 * offset as u64;
+                return Err(Error::invalid_data(position, "AX.25 frame has no PID"));
+            }
+            true => {
+                offset += 1;
+                Some(body[offset - 1])
+            }
+            false => None,
+        };
+        let mut addresses = addresses.into_iter();
+        Ok(Ax25Frame {
+            destination: addresses.next().unwrap(),
+            source: addresses.next().unwrap(),
+            digipeaters: addresses.collect(),
+            control,
+            pid,
+            info: body[offset..].to_vec(),
+        })
+    }
+
+    // Sends the frame between flags, stuffed
+    pub fn write<W: BitWrite>(&self, writer: &mut HdlcWriter<W>) -> Result<(), Error> {
+        writer.write_frame(&self.to_bytes()?)
+    }
+
+    // The next frame the deframer finds, or None at the end of the stream. A frame that fails its
+    // FCS or doesn't parse fails with InvalidData, and the next call carries on after it.
+    pub fn read<R: Read>(
+        deframer: &mut HdlcDeframer,
+        reader: &mut Reader<R>,
+    ) -> Result<Option<Ax25Frame>, Error> {
+        match deframer.next_frame(reader)? {
+            Some(bytes) => Ax25Frame::from_bytes(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// The TNC2 monitor format APRS tools pass around: SOURCE>DEST,DIGI*:info, with a * after the last
+// digipeater that has repeated the frame
+impl fmt::Display for Ax25Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+        let repeated = self
+            .digipeaters
+            .iter()
+            .rposition(|digipeater| digipeater.flag);
+        for (index, digipeater) in self.digipeaters.iter().enumerate() {
+            write!(f, ",{}", digipeater)?;
+            if Some(index) == repeated {
+                write!(f, "*")?;
+            }
+        }
+        write!(f, ":{}", String::from_utf8_lossy(&self.info))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitOrder, Writer};
+    use std::io::Cursor;
+
+    fn aprs_frame() -> Ax25Frame {
+        let mut digipeater = Address::new("WIDE1", 1).unwrap();
+        digipeater.flag = true;
+        Ax25Frame::ui(
+            Address::new("APRS", 0).unwrap(),
+            Address::new("N0CALL", 7).unwrap(),
+            vec![digipeater, Address::new("WIDE2", 1).unwrap()],
+            b"!4903.50N/07201.75W-",
+        )
+    }
+
+    #[test]
+    pub fn packs_addresses_and_fcs() {
+        let frame = aprs_frame();
+        let bytes = frame.to_bytes().unwrap();
+        assert_eq!(bytes[..7], [0x82, 0xA0, 0xA4, 0xA6, 0x40, 0x40, 0xE0]);
+        assert_eq!(bytes[7..14], [0x9C, 0x60, 0x86, 0x82, 0x98, 0x98, 0x6E]);
+        assert_eq!(bytes[20], 0xE2);
+        assert_eq!(bytes[27], 0x63);
+        assert_eq!(bytes[28..30], [0x03, 0xF0]);
+        // Run over the FCS too, the CRC comes to its good frame residue
+        let mut crc = CrcParams::CRC_16_IBM_SDLC;
+        crc.xorout = 0;
+        assert_eq!(crc.checksum(&bytes).unwrap(), 0xF0B8);
+        assert_eq!(Ax25Frame::from_bytes(&bytes).unwrap(), frame);
+        assert_eq!(
+            frame.to_string(),
+            "N0CALL-7>APRS,WIDE1-1*,WIDE2-1:!4903.50N/07201.75W-"
+        );
+
+        let mut damaged = bytes;
+        damaged[40] ^= 1;
+        assert!(Ax25Frame::from_bytes(&damaged).is_err());
+        assert!(Address::new("n0call", 0).is_err());
+        assert!(Address::new("N0CALL", 16).is_err());
+    }
+
+    #[test]
+    pub fn control_fields_round_trip() {
+        for byte in 0..=255u8 {
+            let control = Control::from_byte(byte);
+            assert_eq!(control.to_byte(), byte);
+        }
+        let frame = Ax25Frame {
+            destination: Address::new("N0CALL", 0).unwrap(),
+            source: Address::new("N1CALL", 15).unwrap(),
+            digipeaters: Vec::new(),
+            control: Control::Supervisory {
+                kind: SupervisoryKind::Reject,
+                receive: 5,
+                poll_final: true,
+            },
+            pid: None,
+            info: Vec::new(),
+        };
+        assert_eq!(
+            Ax25Frame::from_bytes(&frame.to_bytes().unwrap()).unwrap(),
+            frame
+        );
+        let with_pid = Ax25Frame {
+            pid: Some(NO_LAYER_3),
+            ..frame
+        };
+        assert!(with_pid.to_bytes().is_err());
+    }
+
+    #[test]
+    pub fn round_trips_through_hdlc() {
+        let frame = aprs_frame();
+        let mut output = HdlcWriter::new(Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst));
+        for _ in 0..4 {
+            output.write_flag().unwrap();
+        }
+        frame.write(&mut output).unwrap();
+        frame.write(&mut output).unwrap();
+        output.pad_to_byte().unwrap();
+        let mut bytes = output.into_inner().into_inner().unwrap();
+        // Somewhere in the second frame's information field
+        let length = bytes.len();
+        bytes[length - 8] ^= 0x10;
+
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        let mut deframer = HdlcDeframer::new();
+        let read = Ax25Frame::read(&mut deframer, &mut reader);
+        assert_eq!(read.unwrap(), Some(frame));
+        assert!(matches!(
+            Ax25Frame::read(&mut deframer, &mut reader),
+            Err(Error::InvalidData { .. })
+        ));
+        assert_eq!(Ax25Frame::read(&mut deframer, &mut reader).unwrap(), None);
+    }
+}
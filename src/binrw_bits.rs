@@ -0,0 +1,175 @@
+// Types deriving binrw's BinRead and BinWrite, read off a Reader and written to a Writer wherever
+// they are in the stream, so a format's byte structured sections can be binrw types while the bit
+// packed parts around them use this crate. Part way through a byte, the value's bytes are the ones
+// read_byte and write_byte would see. Reading runs the type over a window of bytes peeked from the
+// reader, growing it while binrw runs out, and only what it used is read. The window stops growing
+// at MAX_WINDOW bytes (or the limit given to read_binrw_with), and a value binrw still runs out on
+// there fails with InvalidData. Seeks in binrw attributes are within that window, so they're
+// relative to where the value starts.
+use crate::{Error, Reader, Writer};
+use binrw::{BinRead, BinWrite, Endian};
+use std::io::{Cursor, Read, Write};
+
+// Bytes peeked for a value's first try, doubled every time binrw hits the end of the window
+const FIRST_WINDOW: usize = 64;
+// Most bytes peeked for one value, so a value that never ends can't take the whole stream
+const MAX_WINDOW: usize = 1 << 20;
+
+impl<R: Read> Reader<R> {
+    pub fn read_binrw<'a, T>(&mut self, endian: Endian, args: T::Args<'a>) -> Result<T, Error>
+    where
+        T: BinRead,
+        T::Args<'a>: Clone,
+    {
+        self.read_binrw_with(endian, args, MAX_WINDOW)
+    }
+
+    // Like read_binrw, peeking at most max_window bytes
+    pub fn read_binrw_with<'a, T>(
+        &mut self,
+        endian: Endian,
+        args: T::Args<'a>,
+        max_window: usize,
+    ) -> Result<T, Error>
+    where
+        T: BinRead,
+        T::Args<'a>: Clone,
+    {
+        let mut window = FIRST_WINDOW.min(max_window);
+        loop {
+            let bytes = self.peek_aligned(window)?;
+            let at_end = bytes.len() < window;
+            let mut cursor = Cursor::new(&bytes[..]);
+            match T::read_options(&mut cursor, endian, args.clone()) {
+                Ok(value) => {
+                    self.skip_bits(8 * cursor.position())?;
+                    return Ok(value);
+                }
+                Err(error) if error.is_eof() && !at_end && window >= max_window => {
+                    let message = format!("binrw wanted more than {} bytes", max_window);
+                    return Err(Error::invalid_data(self.bits_read(), &message));
+                }
+                Err(error) if error.is_eof() && !at_end => {
+                    window = window.saturating_mul(2).min(max_window)
+                }
+                // binrw doesn't say how much more it wanted
+                Err(error) if error.is_eof() => {
+                    return Err(Error::eof(self.bits_read() + 8 * bytes.len() as u64, 8));
+                }
+                Err(error) => {
+                    let message = format!("binrw couldn't read the value: {}", error);
+                    return Err(Error::invalid_data(self.bits_read(), &message));
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn write_binrw<'a, T>(
+        &mut self,
+        value: &T,
+        endian: Endian,
+        args: T::Args<'a>,
+    ) -> Result<(), Error>
+    where
+        T: BinWrite,
+    {
+        let mut cursor = Cursor::new(Vec::new());
+        value
+            .write_options(&mut cursor, endian, args)
+            .map_err(|error| {
+                let message = format!("binrw couldn't write the value: {}", error);
+                Error::invalid_input(self.bits_written(), &message)
+            })?;
+        self.write_bytes(cursor.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+    use binrw::binrw;
+    // binrw's count attribute calls usize::try_from, which edition 2018 doesn't have in scope
+    use std::convert::TryFrom;
+
+    #[binrw]
+    #[brw(magic = b"HD")]
+    #[derive(Debug, PartialEq)]
+    struct Header {
+        version: u16,
+        #[bw(calc = name.len() as u8)]
+        length: u8,
+        #[br(count = length)]
+        name: Vec<u8>,
+    }
+
+    #[test]
+    pub fn mixes_with_bit_fields() {
+        let header = Header {
+            version: 0x0102,
+            name: b"a long enough name to need a second window, and then some more".to_vec(),
+        };
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bits(0b101, 3).unwrap();
+            writer.write_binrw(&header, Endian::Big, ()).unwrap();
+            writer.write_bits(0x1F, 5).unwrap();
+            writer.write_binrw(&0xABCDu16, Endian::Little, ()).unwrap();
+            let bytes = writer.into_inner().unwrap();
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes), bit_order);
+            assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+            let read: Header = reader.read_binrw(Endian::Big, ()).unwrap();
+            assert_eq!(read, header);
+            assert_eq!(reader.bits_read(), 3 + 8 * (5 + header.name.len() as u64));
+            assert_eq!(reader.read_bits(5).unwrap(), 0x1F);
+            assert_eq!(
+                reader.read_binrw::<u16>(Endian::Little, ()).unwrap(),
+                0xABCD
+            );
+        }
+    }
+
+    #[test]
+    pub fn reports_failures() {
+        let mut reader = Reader::new(Cursor::new(b"XX\x00\x01".to_vec()));
+        let error = reader.read_binrw::<Header>(Endian::Big, ()).unwrap_err();
+        assert!(matches!(error, Error::InvalidData { .. }));
+        assert_eq!(reader.bits_read(), 0);
+
+        let mut reader = Reader::new(Cursor::new(b"HD\x00\x01\x05ab".to_vec()));
+        let error = reader.read_binrw::<Header>(Endian::Big, ()).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEof { .. }));
+    }
+
+    #[test]
+    pub fn caps_the_window() {
+        let header = Header {
+            version: 0x0102,
+            name: vec![b'x'; 63],
+        };
+        let mut writer = Writer::new(Vec::new());
+        writer.write_binrw(&header, Endian::Big, ()).unwrap();
+        writer.write_binrw(&header, Endian::Big, ()).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        // Each header is 68 bytes
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let error = reader
+            .read_binrw_with::<Header>(Endian::Big, (), 64)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 0,
+                ..
+            }
+        ));
+        assert_eq!(reader.bits_read(), 0);
+        let read: Header = reader.read_binrw_with(Endian::Big, (), 68).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(reader.bits_read(), 8 * 68);
+    }
+}
@@ -0,0 +1,141 @@
+// Random access to bit fields in a buffer, for poking at headers in place without streaming the
+// whole thing through a Reader and Writer. Positions count bits from the start of the buffer and
+// fields are laid out the way a Writer with the same bit order would write them.
+use crate::{BitOrder, Error};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitCursor<T> {
+    buffer: T,
+    bit_order: BitOrder,
+}
+
+impl<T: AsRef<[u8]>> BitCursor<T> {
+    pub fn new(buffer: T) -> BitCursor<T> {
+        BitCursor::with_bit_order(buffer, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(buffer: T, bit_order: BitOrder) -> BitCursor<T> {
+        BitCursor { buffer, bit_order }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    // Number of bits in the buffer
+    pub fn len(&self) -> usize {
+        self.buffer.as_ref().len() * 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.as_ref().is_empty()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.buffer
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    // Byte index and the shift to the bit inside it
+    fn locate(&self, position: usize) -> (usize, usize) {
+        let shift = match self.bit_order {
+            BitOrder::MsbFirst => 7 - position % 8,
+            BitOrder::LsbFirst => position % 8,
+        };
+        (position / 8, shift)
+    }
+
+    fn check_range(&self, position: usize, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(position as u64, number_of_bits, 128));
+        }
+        match position.checked_add(number_of_bits) {
+            Some(end) if end <= self.len() => Ok(()),
+            _ => {
+                let missing =
+                    (position as u64).saturating_add(number_of_bits as u64) - self.len() as u64;
+                Err(Error::eof(position as u64, missing))
+            }
+        }
+    }
+
+    pub fn get_bit(&self, position: usize) -> Result<bool, Error> {
+        Ok(self.get_bits(position, 1)? == 1)
+    }
+
+    pub fn get_bits(&self, position: usize, number_of_bits: usize) -> Result<u128, Error> {
+        self.check_range(position, number_of_bits)?;
+        let bytes = self.buffer.as_ref();
+        let mut output: u128 = 0;
+        for index in 0..number_of_bits {
+            let (byte, shift) = self.locate(position + index);
+            let bit = (bytes[byte] >> shift & 1) as u128;
+            match self.bit_order {
+                BitOrder::MsbFirst => output = output << 1 | bit,
+                BitOrder::LsbFirst => output |= bit << index,
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> BitCursor<T> {
+    pub fn set_bit(&mut self, position: usize, one: bool) -> Result<(), Error> {
+        self.set_bits(position, one as u128, 1)
+    }
+
+    // Overwrites just the field, leaving the bits around it alone
+    pub fn set_bits(
+        &mut self,
+        position: usize,
+        bits: u128,
+        number_of_bits: usize,
+    ) -> Result<(), Error> {
+        self.check_range(position, number_of_bits)?;
+        for index in 0..number_of_bits {
+            let bit = match self.bit_order {
+                BitOrder::MsbFirst => bits >> (number_of_bits - 1 - index) & 1,
+                BitOrder::LsbFirst => bits >> index & 1,
+            } as u8;
+            let (byte, shift) = self.locate(position + index);
+            let bytes = self.buffer.as_mut();
+            bytes[byte] = bytes[byte] & !(1 << shift) | bit << shift;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::Writer;
+
+    #[test]
+    pub fn get_and_set_fields() {
+        // 1111_1011 0101_0101
+        let mut cursor = BitCursor::new(vec![251, 85]);
+        assert_eq!(cursor.get_bits(5, 6).unwrap(), 0b011_010);
+        assert!(cursor.get_bit(9).unwrap());
+        cursor.set_bits(6, 0b0000, 4).unwrap();
+        assert_eq!(cursor.get_ref(), &[0b1111_1000, 0b0001_0101]);
+        assert!(cursor.get_bits(10, 7).is_err());
+        assert!(cursor.set_bits(usize::MAX, 0, 2).is_err());
+
+        // Matches what a Writer puts down, in either bit order
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bits(0b101, 3).unwrap();
+            writer.write_bits(0x1234, 13).unwrap();
+            let written = writer.into_inner().unwrap();
+
+            let mut cursor = BitCursor::with_bit_order([0u8; 2], bit_order);
+            cursor.set_bits(0, 0b101, 3).unwrap();
+            cursor.set_bits(3, 0x1234, 13).unwrap();
+            assert_eq!(cursor.get_ref()[..], written[..]);
+            assert_eq!(cursor.get_bits(3, 13).unwrap(), 0x1234);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Fieldless enums stored as their variant index in as few bits as tell the variants apart, with
+// reads refusing indexes past the last variant. bit_enum! declares the enum and writes the
+// BitEnum impl, so nothing counts variants by hand:
+//   bit_enum! {
+//       #[derive(Debug, PartialEq)]
+//       pub enum State { Idle, Running, Done }
+//   }
+//   writer.write_enum(&State::Running)?;   // 2 bits
+// Variants can't be given explicit discriminants, since the index is the discriminant.
+#[cfg(feature = "std")]
+use crate::{Error, Reader, Writer};
+#[cfg(feature = "std")]
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+
+pub trait BitEnum: Sized {
+    const VARIANT_COUNT: usize;
+
+    // Position of the variant in declaration order
+    fn to_index(&self) -> usize;
+
+    fn from_index(index: usize) -> Option<Self>;
+
+    fn bit_width() -> usize {
+        enum_width(Self::VARIANT_COUNT)
+    }
+}
+
+// Bits needed to tell variant_count values apart
+pub fn enum_width(variant_count: usize) -> usize {
+    if variant_count <= 1 {
+        0
+    } else {
+        (usize::BITS - (variant_count - 1).leading_zeros()) as usize
+    }
+}
+
+#[macro_export]
+macro_rules! bit_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant),*
+        }
+
+        impl $crate::BitEnum for $name {
+            const VARIANT_COUNT: usize = [$(stringify!($variant)),*].len();
+
+            fn to_index(&self) -> usize {
+                match self {
+                    $($name::$variant => $name::$variant as usize),*
+                }
+            }
+
+            fn from_index(index: usize) -> Option<$name> {
+                $(
+                    if index == $name::$variant as usize {
+                        return Some($name::$variant);
+                    }
+                )*
+                None
+            }
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Writer<W> {
+    pub fn write_enum<E: BitEnum>(&mut self, value: &E) -> Result<(), Error> {
+        let index = value.to_index();
+        if index >= E::VARIANT_COUNT {
+            return Err(Error::invalid_input(
+                self.bits_written(),
+                "Enum index is out of range for its variant count",
+            ));
+        }
+        self.write_bits(index as u128, E::bit_width())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Reader<R> {
+    pub fn read_enum<E: BitEnum>(&mut self) -> Result<E, Error> {
+        let position = self.bits_read();
+        let index = self.read_bits(E::bit_width())?;
+        usize::try_from(index)
+            .ok()
+            .and_then(E::from_index)
+            .ok_or_else(|| Error::invalid_data(position, "Not a variant of the enum"))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    bit_enum! {
+        #[derive(Debug, PartialEq, Clone, Copy)]
+        enum State {
+            Idle,
+            Starting,
+            Running,
+            Stopping,
+            Done,
+        }
+    }
+
+    bit_enum! {
+        #[derive(Debug, PartialEq)]
+        enum Only {
+            One,
+        }
+    }
+
+    #[test]
+    pub fn minimal_width() {
+        assert_eq!(State::VARIANT_COUNT, 5);
+        assert_eq!(State::bit_width(), 3);
+        assert_eq!(Only::bit_width(), 0);
+        assert_eq!(enum_width(2), 1);
+        assert_eq!(enum_width(9), 4);
+
+        let states = [State::Done, State::Idle, State::Running, State::Stopping];
+        let mut writer = Writer::new(Vec::new());
+        for state in &states {
+            writer.write_enum(state).unwrap();
+        }
+        writer.write_enum(&Only::One).unwrap();
+        assert_eq!(writer.bits_written(), 12);
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes, [0b1000_0001, 0b0011_0000]);
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        for state in &states {
+            assert_eq!(reader.read_enum::<State>().unwrap(), *state);
+        }
+        assert_eq!(reader.read_enum::<Only>().unwrap(), Only::One);
+    }
+
+    #[test]
+    pub fn rejects_unknown_variants() {
+        // 5, 6 and 7 fit in 3 bits but aren't variants
+        let mut reader = Reader::new(Cursor::new(vec![0b0011_0100]));
+        assert_eq!(reader.read_enum::<State>().unwrap(), State::Starting);
+        let error = reader.read_enum::<State>().unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 3,
+                ..
+            }
+        ));
+    }
+}
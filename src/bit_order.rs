@@ -0,0 +1,10 @@
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitOrder {
+    // Bits fill each byte starting at the most significant bit, and multi-bit values are written
+    // MSB first. This is the default.
+    #[default]
+    MsbFirst,
+    // Bits fill each byte starting at the least significant bit, and multi-bit values are written
+    // LSB first (GIF, DEFLATE)
+    LsbFirst,
+}
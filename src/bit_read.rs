@@ -0,0 +1,64 @@
+use crate::byte_io::ByteSource;
+use crate::{Error, Reader};
+use alloc::vec::Vec;
+
+// The reading side of BitWrite, for the readers that undo a code on the way through (parity,
+// Hamming, descrambling, ...), so they all take the same calls as a Reader. Everything but
+// read_bits and bits_read comes for free.
+pub trait BitRead {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error>;
+
+    // Bits consumed so far
+    fn bits_read(&self) -> u64;
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(number_of_bytes.min(crate::PREALLOCATE_LIMIT));
+        for _ in 0..number_of_bytes {
+            bytes.push(self.read_byte()?);
+        }
+        Ok(bytes)
+    }
+
+    // Drops bits up to the next multiple of 8 in bits_read, the reading side of
+    // BitWrite::pad_to_byte. With require_zeros they have to be zero.
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        let padding = (8 - self.bits_read() % 8) as usize % 8;
+        if self.read_bits(padding)? != 0 && require_zeros {
+            return Err(Error::invalid_data(
+                self.bits_read(),
+                "Padding bits before the byte boundary aren't zero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<R: ByteSource> BitRead for Reader<R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        Reader::read_bits(self, number_of_bits)
+    }
+
+    fn bits_read(&self) -> u64 {
+        Reader::bits_read(self)
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        Reader::read_bit(self)
+    }
+
+    fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+        Reader::read_bytes(self, number_of_bytes)
+    }
+
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        Reader::align_to_byte(self, require_zeros)
+    }
+}
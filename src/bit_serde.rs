@@ -0,0 +1,1036 @@
+// A serde data format packed at the bit level. It isn't self-describing, so the reading side has to
+// know the type, like bincode:
+//   bool                   1 bit
+//   integers               their own width, signed ones two's complement
+//   f32, f64               their IEEE bits
+//   char                   32 bits
+//   str, bytes             a length then the bytes, not aligned
+//   Option                 a 1 bit flag then the value if it's set
+//   seq, map               a length then the elements (map keys and values alternating)
+//   tuple, struct          the fields in order, nothing else
+//   enum                   the variant index then the variant's fields
+// Lengths and variant indexes default to 32 and 8 bits and can be changed on the serializer and
+// deserializer (both sides have to agree). A field that doesn't need its whole type can be
+// declared as UInt<N> or Int<N> to take N bits.
+use crate::bit_write::BitWrite;
+use crate::{Error, Reader};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+
+// UInt<N> and Int<N> go through serde as one element tuple structs with the width in the name,
+// looked up by N since a const generic can't be put into a &'static str. Width 0 stands in for
+// every width there's no name for, which the (de)serializer then rejects.
+const UINT_TOKEN: &str = "$bit_streamer::UInt";
+const INT_TOKEN: &str = "$bit_streamer::Int";
+
+macro_rules! width_names {
+    ($($width:literal)*) => {
+        const UINT_NAMES: [&str; 65] = [$(concat!("$bit_streamer::UInt", $width)),*];
+        const INT_NAMES: [&str; 65] = [$(concat!("$bit_streamer::Int", $width)),*];
+    };
+}
+
+width_names!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+    33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64
+);
+
+fn width_name(names: &[&'static str; 65], width: usize) -> &'static str {
+    names.get(width).unwrap_or(&names[0])
+}
+
+// Whether a tuple struct is a UInt (false) or Int (true), and its width
+fn parse_width_name(name: &str) -> Option<(bool, usize)> {
+    if let Some(width) = name.strip_prefix(UINT_TOKEN) {
+        return width.parse().ok().map(|width| (false, width));
+    }
+    if let Some(width) = name.strip_prefix(INT_TOKEN) {
+        return width.parse().ok().map(|width| (true, width));
+    }
+    None
+}
+
+// Where an error from Serialize or Deserialize code is said to be until the (de)serializer it
+// passes through fills in the real position
+const UNKNOWN_POSITION: u64 = u64::MAX;
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Error {
+        Error::invalid_input(UNKNOWN_POSITION, &message.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Error {
+        Error::invalid_data(UNKNOWN_POSITION, &message.to_string())
+    }
+}
+
+fn locate(error: Error, bit_position: u64) -> Error {
+    match error {
+        Error::InvalidData {
+            bit_position: UNKNOWN_POSITION,
+            message,
+        } => Error::InvalidData {
+            bit_position,
+            message,
+        },
+        Error::InvalidInput {
+            bit_position: UNKNOWN_POSITION,
+            message,
+        } => Error::InvalidInput {
+            bit_position,
+            message,
+        },
+        error => error,
+    }
+}
+
+// An unsigned value stored in N bits (1 to 64). Other serde formats see a one element tuple struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UInt<const N: usize>(pub u64);
+
+// A signed value stored in N bits (1 to 64), two's complement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Int<const N: usize>(pub i64);
+
+impl<const N: usize> Serialize for UInt<N> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeTupleStruct;
+        let mut state = serializer.serialize_tuple_struct(width_name(&UINT_NAMES, N), 1)?;
+        state.serialize_field(&self.0)?;
+        state.end()
+    }
+}
+
+impl<const N: usize> Serialize for Int<N> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeTupleStruct;
+        let mut state = serializer.serialize_tuple_struct(width_name(&INT_NAMES, N), 1)?;
+        state.serialize_field(&self.0)?;
+        state.end()
+    }
+}
+
+struct WidthVisitor<T>(std::marker::PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for WidthVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a one element tuple struct")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for UInt<N> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let visitor = WidthVisitor::<u64>(std::marker::PhantomData);
+        Ok(UInt(deserializer.deserialize_tuple_struct(
+            width_name(&UINT_NAMES, N),
+            1,
+            visitor,
+        )?))
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for Int<N> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let visitor = WidthVisitor::<i64>(std::marker::PhantomData);
+        Ok(Int(deserializer.deserialize_tuple_struct(
+            width_name(&INT_NAMES, N),
+            1,
+            visitor,
+        )?))
+    }
+}
+
+fn check_width(bit_position: u64, width: usize) -> Result<(), Error> {
+    if width == 0 || width > 64 {
+        return Err(Error::invalid_input(
+            bit_position,
+            "UInt and Int widths have to be 1 to 64",
+        ));
+    }
+    Ok(())
+}
+
+// A fixed width field that's been started but not written yet
+#[derive(Clone, Copy)]
+enum Pending {
+    Unsigned(usize),
+    Signed(usize),
+}
+
+pub struct BitSerializer<'a, W: BitWrite> {
+    output: &'a mut W,
+    length_width: usize,
+    variant_width: usize,
+    pending: Option<Pending>,
+}
+
+impl<'a, W: BitWrite> BitSerializer<'a, W> {
+    pub fn new(output: &'a mut W) -> BitSerializer<'a, W> {
+        BitSerializer {
+            output,
+            length_width: 32,
+            variant_width: 8,
+            pending: None,
+        }
+    }
+
+    pub fn with_length_width(mut self, width: usize) -> BitSerializer<'a, W> {
+        self.length_width = width;
+        self
+    }
+
+    pub fn with_variant_width(mut self, width: usize) -> BitSerializer<'a, W> {
+        self.variant_width = width;
+        self
+    }
+
+    fn nested<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value
+            .serialize(&mut *self)
+            .map_err(|error| locate(error, self.output.bits_written()))
+    }
+
+    fn write_length(&mut self, length: usize) -> Result<(), Error> {
+        self.output
+            .write_bits_checked(length as u128, self.length_width)
+    }
+
+    // Integers go through here so one inside a UInt or Int gets its width instead
+    fn write_unsigned(&mut self, value: u128, width: usize) -> Result<(), Error> {
+        match self.pending.take() {
+            Some(Pending::Unsigned(width)) => self.output.write_bits_checked(value, width),
+            Some(Pending::Signed(_)) => Err(self.mismatch()),
+            None => self.output.write_bits(value, width),
+        }
+    }
+
+    fn write_signed(&mut self, value: i128, width: usize) -> Result<(), Error> {
+        match self.pending.take() {
+            Some(Pending::Signed(width)) => {
+                let bits = value as u128 & (u128::MAX >> (128 - width));
+                // Has to come back out the same after sign extending
+                if (((bits << (128 - width)) as i128) >> (128 - width)) != value {
+                    return Err(Error::too_wide(
+                        self.output.bits_written(),
+                        (129 - value.leading_zeros().max(value.leading_ones())) as usize,
+                        width,
+                    ));
+                }
+                self.output.write_bits(bits, width)
+            }
+            Some(Pending::Unsigned(_)) => Err(self.mismatch()),
+            None => self
+                .output
+                .write_bits(value as u128 & (u128::MAX >> (128 - width)), width),
+        }
+    }
+
+    fn mismatch(&self) -> Error {
+        Error::invalid_input(
+            self.output.bits_written(),
+            "UInt has to hold a u64 and Int an i64",
+        )
+    }
+
+    fn check_no_pending(&mut self) -> Result<(), Error> {
+        match self.pending.take() {
+            Some(_) => Err(self.mismatch()),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn to_writer<T: Serialize + ?Sized, W: BitWrite>(
+    value: &T,
+    output: &mut W,
+) -> Result<(), Error> {
+    BitSerializer::new(output).nested(value)
+}
+
+impl<'a, 'b, W: BitWrite> ser::Serializer for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, value: bool) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bit(value)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), Error> {
+        self.write_signed(value as i128, 8)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), Error> {
+        self.write_signed(value as i128, 16)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), Error> {
+        self.write_signed(value as i128, 32)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), Error> {
+        self.write_signed(value as i128, 64)
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<(), Error> {
+        self.write_signed(value, 128)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), Error> {
+        self.write_unsigned(value as u128, 8)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), Error> {
+        self.write_unsigned(value as u128, 16)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), Error> {
+        self.write_unsigned(value as u128, 32)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), Error> {
+        self.write_unsigned(value as u128, 64)
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<(), Error> {
+        self.write_unsigned(value, 128)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bits(value.to_bits() as u128, 32)
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bits(value.to_bits() as u128, 64)
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bits(value as u128, 32)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Error> {
+        self.serialize_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.write_length(value.len())?;
+        self.output.write_bytes(value.to_vec())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bit(false)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output.write_bit(true)?;
+        self.nested(value)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.check_no_pending()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.check_no_pending()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.output
+            .write_bits_checked(variant_index as u128, self.variant_width)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.check_no_pending()?;
+        self.nested(value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        self.nested(value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        self.check_no_pending()?;
+        match len {
+            Some(len) => {
+                self.write_length(len)?;
+                Ok(self)
+            }
+            None => Err(Error::invalid_input(
+                self.output.bits_written(),
+                "Sequences need a known length",
+            )),
+        }
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, Error> {
+        self.check_no_pending()?;
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, _len: usize) -> Result<Self, Error> {
+        self.check_no_pending()?;
+        if let Some((signed, width)) = parse_width_name(name) {
+            check_width(self.output.bits_written(), width)?;
+            self.pending = Some(match signed {
+                false => Pending::Unsigned(width),
+                true => Pending::Signed(width),
+            });
+        }
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        self.serialize_seq(len)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        self.check_no_pending()?;
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeSeq for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeTuple for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeTupleStruct for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        // A UInt or Int whose field never reached an integer
+        self.check_no_pending()
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeTupleVariant for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeMap for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.nested(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeStruct for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, W: BitWrite> ser::SerializeStructVariant for &'b mut BitSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.nested(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub struct BitDeserializer<'a, R: Read> {
+    input: &'a mut Reader<R>,
+    length_width: usize,
+    variant_width: usize,
+}
+
+impl<'a, R: Read> BitDeserializer<'a, R> {
+    pub fn new(input: &'a mut Reader<R>) -> BitDeserializer<'a, R> {
+        BitDeserializer {
+            input,
+            length_width: 32,
+            variant_width: 8,
+        }
+    }
+
+    pub fn with_length_width(mut self, width: usize) -> BitDeserializer<'a, R> {
+        self.length_width = width;
+        self
+    }
+
+    pub fn with_variant_width(mut self, width: usize) -> BitDeserializer<'a, R> {
+        self.variant_width = width;
+        self
+    }
+
+    fn nested<'de, T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(&mut *self)
+            .map_err(|error| locate(error, self.input.bits_read()))
+    }
+
+    fn read_length(&mut self) -> Result<usize, Error> {
+        let position = self.input.bits_read();
+        let length = self.input.read_bits(self.length_width)?;
+        usize::try_from(length)
+            .map_err(|_| Error::invalid_data(position, "Length doesn't fit in a usize"))
+    }
+
+    fn read_signed(&mut self, width: usize) -> Result<i128, Error> {
+        let bits = self.input.read_bits(width)?;
+        Ok(((bits << (128 - width)) as i128) >> (128 - width))
+    }
+
+    fn read_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let length = self.read_length()?;
+        self.input.read_bytes(length)
+    }
+
+    fn not_self_describing(&self) -> Error {
+        Error::invalid_input(
+            self.input.bits_read(),
+            "The bit format isn't self-describing, so the type has to say what comes next",
+        )
+    }
+}
+
+pub fn from_reader<T: DeserializeOwned, R: Read>(input: &mut Reader<R>) -> Result<T, Error> {
+    BitDeserializer::new(input).nested(std::marker::PhantomData)
+}
+
+impl<'de, 'a, 'b, R: Read> de::Deserializer<'de> for &'b mut BitDeserializer<'a, R> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(self.not_self_describing())
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bool(self.input.read_bit()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i8(self.read_signed(8)? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i16(self.read_signed(16)? as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i32(self.read_signed(32)? as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.read_signed(64)? as i64)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i128(self.read_signed(128)?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u8(self.input.read_bits(8)? as u8)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u16(self.input.read_bits(16)? as u16)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u32(self.input.read_bits(32)? as u32)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.input.read_bits(64)? as u64)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u128(self.input.read_bits(128)?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(f32::from_bits(self.input.read_bits(32)? as u32))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(f64::from_bits(self.input.read_bits(64)? as u64))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let position = self.input.bits_read();
+        let value = self.input.read_bits(32)? as u32;
+        match char::from_u32(value) {
+            Some(value) => visitor.visit_char(value),
+            None => Err(Error::invalid_data(position, "Not a valid char")),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let position = self.input.bits_read();
+        match String::from_utf8(self.read_bytes()?) {
+            Ok(value) => visitor.visit_string(value),
+            Err(_) => Err(Error::invalid_data(position, "String isn't valid UTF-8")),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.read_bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.input.read_bit()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let remaining = self.read_length()?;
+        visitor.visit_seq(Elements {
+            deserializer: self,
+            remaining,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match parse_width_name(name) {
+            Some((false, width)) => {
+                check_width(self.input.bits_read(), width)?;
+                let value = self.input.read_bits(width)? as u64;
+                visitor.visit_seq(de::value::SeqDeserializer::new(std::iter::once(value)))
+            }
+            Some((true, width)) => {
+                check_width(self.input.bits_read(), width)?;
+                let value = self.read_signed(width)? as i64;
+                visitor.visit_seq(de::value::SeqDeserializer::new(std::iter::once(value)))
+            }
+            None => self.deserialize_tuple(len, visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let remaining = self.read_length()?;
+        visitor.visit_map(Elements {
+            deserializer: self,
+            remaining,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(self.not_self_describing())
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(self.not_self_describing())
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// The elements of a seq, tuple, struct or map, remaining counting map entries rather than keys
+// and values
+struct Elements<'b, 'a, R: Read> {
+    deserializer: &'b mut BitDeserializer<'a, R>,
+    remaining: usize,
+}
+
+impl<'de, 'b, 'a, R: Read> de::SeqAccess<'de> for Elements<'b, 'a, R> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.deserializer.nested(seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.min(crate::PREALLOCATE_LIMIT))
+    }
+}
+
+impl<'de, 'b, 'a, R: Read> de::MapAccess<'de> for Elements<'b, 'a, R> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.deserializer.nested(seed).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.deserializer.nested(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.min(crate::PREALLOCATE_LIMIT))
+    }
+}
+
+impl<'de, 'a, 'b, R: Read> de::EnumAccess<'de> for &'b mut BitDeserializer<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let position = self.input.bits_read();
+        let index = self.input.read_bits(self.variant_width)?;
+        let index = u32::try_from(index)
+            .map_err(|_| Error::invalid_data(position, "Variant index doesn't fit in a u32"))?;
+        let variant = seed
+            .deserialize(index.into_deserializer())
+            .map_err(|error: Error| locate(error, position))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, 'b, R: Read> de::VariantAccess<'de> for &'b mut BitDeserializer<'a, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        self.nested(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitCounter, Writer};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(UInt<12>),
+        Line(Int<9>, Int<9>),
+        Box { width: u8, height: u8 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Scene {
+        visible: bool,
+        depth: Int<5>,
+        name: String,
+        label: Option<char>,
+        scale: f32,
+        shapes: Vec<Shape>,
+        tags: BTreeMap<u8, bool>,
+        id: (u16, i64),
+    }
+
+    fn scene() -> Scene {
+        Scene {
+            visible: true,
+            depth: Int(-7),
+            name: "ünïcode".to_string(),
+            label: None,
+            scale: -1.5,
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(UInt(4000)),
+                Shape::Line(Int(-256), Int(255)),
+                Shape::Box {
+                    width: 3,
+                    height: 200,
+                },
+            ],
+            tags: vec![(1, true), (9, false)].into_iter().collect(),
+            id: (0xBEEF, -2),
+        }
+    }
+
+    #[test]
+    pub fn round_trips() {
+        let mut writer = Writer::new(Vec::new());
+        to_writer(&scene(), &mut writer).unwrap();
+        writer.write_bit(true).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let back: Scene = from_reader(&mut reader).unwrap();
+        assert_eq!(back, scene());
+        assert!(reader.read_bit().unwrap());
+
+        // Exactly as many bits as the layout says
+        let mut counter = BitCounter::new();
+        to_writer(&scene(), &mut counter).unwrap();
+        let expected = 1
+            + 5
+            + (32 + 9 * 8)
+            + 1
+            + 32
+            + (32 + 8 + (8 + 12) + (8 + 18) + (8 + 16))
+            + (32 + 2 * 9)
+            + (16 + 64);
+        assert_eq!(counter.bits_written(), expected);
+    }
+
+    #[test]
+    pub fn configured_widths() {
+        let value = (vec![Shape::Empty, Shape::Circle(UInt(1))], "hi".to_string());
+        let mut writer = Writer::new(Vec::new());
+        let mut serializer = BitSerializer::new(&mut writer)
+            .with_length_width(4)
+            .with_variant_width(2);
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(writer.bits_written(), 4 + 2 + (2 + 12) + 4 + 16);
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut deserializer = BitDeserializer::new(&mut reader)
+            .with_length_width(4)
+            .with_variant_width(2);
+        let back: (Vec<Shape>, String) = Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(back, value);
+
+        // A length that doesn't fit its width
+        let mut writer = Writer::new(Vec::new());
+        let mut serializer = BitSerializer::new(&mut writer).with_length_width(2);
+        assert!(vec![0u8; 4].serialize(&mut serializer).is_err());
+    }
+
+    #[test]
+    pub fn rejects_bad_values() {
+        let mut writer = Writer::new(Vec::new());
+        assert!(matches!(
+            to_writer(&UInt::<4>(16), &mut writer),
+            Err(Error::ValueTooWide { .. })
+        ));
+        assert!(matches!(
+            to_writer(&Int::<4>(8), &mut writer),
+            Err(Error::ValueTooWide { .. })
+        ));
+        assert!(to_writer(&Int::<4>(-8), &mut writer).is_ok());
+        assert!(to_writer(&UInt::<65>(0), &mut writer).is_err());
+        assert_eq!(width_name(&INT_NAMES, 12), "$bit_streamer::Int12");
+        assert_eq!(
+            parse_width_name(width_name(&UINT_NAMES, 64)),
+            Some((false, 64))
+        );
+        assert_eq!(
+            parse_width_name(width_name(&UINT_NAMES, 65)),
+            Some((false, 0))
+        );
+
+        // Unknown variant, reported where its index starts
+        let mut reader = Reader::new(Cursor::new(vec![0, 9]));
+        reader.read_byte().unwrap();
+        let error = from_reader::<Shape, _>(&mut reader).unwrap_err();
+        assert_eq!(error.bit_position(), 8);
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut reader = Reader::new(Cursor::new(vec![0xFF; 4]));
+        assert!(from_reader::<char, _>(&mut reader).is_err());
+    }
+}
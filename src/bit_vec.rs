@@ -0,0 +1,62 @@
+// Moving bits between bitvec's in-memory types and a stream. Bit 0 of a slice is the first bit in
+// the stream, whatever bit order either side uses.
+use crate::{Error, Reader, Writer};
+use bitvec::order::BitOrder as Ordering;
+use bitvec::slice::BitSlice;
+use bitvec::store::BitStore;
+use bitvec::vec::BitVec;
+use std::io::{Read, Write};
+
+impl<W: Write> Writer<W> {
+    pub fn write_bitslice<T: BitStore, O: Ordering>(
+        &mut self,
+        bits: &BitSlice<T, O>,
+    ) -> Result<(), Error> {
+        self.write_bits_from_iter(bits.iter().by_vals())?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Reader<R> {
+    pub fn read_to_bitvec<T: BitStore, O: Ordering>(
+        &mut self,
+        number_of_bits: usize,
+    ) -> Result<BitVec<T, O>, Error> {
+        Ok(self.read_bools(number_of_bits)?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+    use bitvec::prelude::{bitvec, Lsb0, Msb0};
+    use std::io::Cursor;
+
+    #[test]
+    pub fn round_trips() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let bits = bitvec![u8, Lsb0; 1, 0, 1, 1, 0, 0, 0, 1, 1, 1, 0];
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bit(true).unwrap();
+            writer.write_bitslice(&bits).unwrap();
+            writer.write_bitslice(&bits[2..]).unwrap();
+            let bytes = writer.into_inner().unwrap();
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes), bit_order);
+            assert!(reader.read_bit().unwrap());
+            let first: BitVec<u8, Lsb0> = reader.read_to_bitvec(bits.len()).unwrap();
+            assert_eq!(first, bits);
+            let second: BitVec<u16, Msb0> = reader.read_to_bitvec(bits.len() - 2).unwrap();
+            assert_eq!(second, bits[2..]);
+            assert!(reader.read_to_bitvec::<u8, Msb0>(8).is_err());
+        }
+
+        // Wider than one read_bits
+        let long: BitVec<u64, Msb0> = (0..200).map(|index| index % 7 == 0).collect();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_bitslice(&long).unwrap();
+        let mut reader = Reader::new(Cursor::new(writer.into_inner().unwrap()));
+        assert_eq!(reader.read_to_bitvec::<u64, Msb0>(200).unwrap(), long);
+    }
+}
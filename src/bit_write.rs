@@ -0,0 +1,318 @@
+use crate::byte_io::ByteSink;
+use crate::writer::fill_bits;
+use crate::{BitOrder, Error, Writer};
+use alloc::vec::Vec;
+
+// ValueTooWide (with the width the value needs) when bits doesn't fit in number_of_bits
+pub(crate) fn check_fits(
+    bit_position: u64,
+    bits: u128,
+    number_of_bits: usize,
+) -> Result<(), Error> {
+    let needed = 128 - bits.leading_zeros() as usize;
+    if needed > number_of_bits {
+        return Err(Error::too_wide(bit_position, needed, number_of_bits));
+    }
+    Ok(())
+}
+
+// What an encoder needs from wherever its bits go, so the same code can write for real or just
+// measure. Everything but write_bits and bits_written comes for free.
+pub trait BitWrite {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error>;
+
+    // Bits produced so far, padding included
+    fn bits_written(&self) -> u64;
+
+    // Which end of a value goes first, so a wrapper that splits values up can split them the way
+    // the writer under it lays them out. MSB first unless the implementation says otherwise.
+    fn bit_order(&self) -> BitOrder {
+        BitOrder::MsbFirst
+    }
+
+    // What pad_to_byte fills with, as in Writer::set_pad_fill, so a wrapper can copy the padding
+    // somewhere else. Zeros unless the implementation says otherwise.
+    fn pad_fill(&self) -> u8 {
+        0
+    }
+
+    fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+        self.write_bits(write_one as u128, 1)
+    }
+
+    // write_bits that fails instead of dropping set bits above number_of_bits
+    fn write_bits_checked(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        check_fits(self.bits_written(), bits, number_of_bits)?;
+        self.write_bits(bits, number_of_bits)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+        self.write_bits(byte as u128, 8)
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        for byte in bytes {
+            self.write_byte(byte)?;
+        }
+        Ok(())
+    }
+
+    // Gives back how many padding bits it added. Zeros unless the implementation says otherwise.
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        let padding = (8 - self.bits_written() % 8) as usize % 8;
+        self.write_bits(0, padding)?;
+        Ok(padding)
+    }
+}
+
+impl<W: ByteSink> BitWrite for Writer<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        Writer::write_bits(self, bits, number_of_bits)
+    }
+
+    fn bits_written(&self) -> u64 {
+        Writer::bits_written(self)
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        Writer::bit_order(self)
+    }
+
+    fn pad_fill(&self) -> u8 {
+        Writer::pad_fill(self)
+    }
+
+    fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+        Writer::write_bit(self, write_one)
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        Writer::write_bytes(self, bytes)
+    }
+
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        Writer::pad_to_byte(self)
+    }
+}
+
+// So a wrapper like TeeWriter can write through a borrowed sink and hand it back when it's done
+impl<B: BitWrite + ?Sized> BitWrite for &mut B {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        (**self).write_bits(bits, number_of_bits)
+    }
+
+    fn bits_written(&self) -> u64 {
+        (**self).bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        (**self).bit_order()
+    }
+
+    fn pad_fill(&self) -> u8 {
+        (**self).pad_fill()
+    }
+
+    fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+        (**self).write_bit(write_one)
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        (**self).pad_to_byte()
+    }
+}
+
+// Writes nowhere and only counts, for sizing a length-prefixed section before writing it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BitCounter {
+    bits: u64,
+}
+
+impl BitCounter {
+    pub fn new() -> BitCounter {
+        BitCounter::default()
+    }
+
+    // Whole bytes the bits so far would take up
+    pub fn bytes_written(&self) -> u64 {
+        self.bits.div_ceil(8)
+    }
+}
+
+impl BitWrite for BitCounter {
+    fn write_bits(&mut self, _bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits, number_of_bits, 128));
+        }
+        self.bits += number_of_bits as u64;
+        Ok(())
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.bits
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.bits += 8 * bytes.len() as u64;
+        Ok(())
+    }
+}
+
+// Sends every bit to two places, say a file and a BitCounter or checksum sink. Both see exactly
+// the same bits, so padding lands in the same spot in each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TeeWriter<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: BitWrite, B: BitWrite> TeeWriter<A, B> {
+    pub fn new(first: A, second: B) -> TeeWriter<A, B> {
+        TeeWriter { first, second }
+    }
+
+    pub fn get_ref(&self) -> (&A, &B) {
+        (&self.first, &self.second)
+    }
+
+    pub fn get_mut(&mut self) -> (&mut A, &mut B) {
+        (&mut self.first, &mut self.second)
+    }
+
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: BitWrite, B: BitWrite> BitWrite for TeeWriter<A, B> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        self.first.write_bits(bits, number_of_bits)?;
+        self.second.write_bits(bits, number_of_bits)
+    }
+
+    // Counted by the first sink, and laid out like it
+    fn bits_written(&self) -> u64 {
+        self.first.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.first.bit_order()
+    }
+
+    fn pad_fill(&self) -> u8 {
+        self.first.pad_fill()
+    }
+
+    fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+        self.first.write_bit(write_one)?;
+        self.second.write_bit(write_one)
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.first.write_bytes(bytes.clone())?;
+        self.second.write_bytes(bytes)
+    }
+
+    // The first pads and the second gets the same bits, fill and all, even when it was at another
+    // place in its byte
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        let place = (self.first.bits_written() % 8) as usize;
+        let padding = self.first.pad_to_byte()?;
+        let fill = fill_bits(
+            self.first.pad_fill(),
+            place,
+            padding,
+            self.first.bit_order(),
+        );
+        self.second.write_bits(fill, padding)?;
+        Ok(padding)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::Reader;
+    use std::io::Cursor;
+
+    fn section<B: BitWrite>(output: &mut B) -> Result<(), Error> {
+        output.write_bits(0b101, 3)?;
+        output.write_bytes(b"payload".to_vec())?;
+        output.write_bit(true)?;
+        Ok(())
+    }
+
+    #[test]
+    pub fn sizes_a_section() {
+        let mut counter = BitCounter::new();
+        section(&mut counter).unwrap();
+        assert_eq!(counter.bits_written(), 60);
+        assert_eq!(counter.pad_to_byte().unwrap(), 4);
+        assert_eq!(counter.bytes_written(), 8);
+        assert!(counter.write_bits(0, 129).is_err());
+
+        // A 16 bit length, then the section for real
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer
+            .write_bits(counter.bits_written() as u128, 16)
+            .unwrap();
+        section(&mut writer).unwrap();
+        BitWrite::pad_to_byte(&mut writer).unwrap();
+        assert_eq!(writer.bits_written(), 16 + 64);
+
+        let bytes = writer.into_inner().unwrap().into_inner();
+        let mut reader = Reader::new(Cursor::new(bytes));
+        assert_eq!(reader.read_bits(16).unwrap(), 64);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bytes(7).unwrap(), b"payload");
+    }
+
+    #[test]
+    pub fn tee_to_two_writers() {
+        let mut tee = TeeWriter::new(
+            Writer::new(Vec::new()),
+            Writer::with_bit_order(Vec::new(), crate::BitOrder::LsbFirst),
+        );
+        tee.write_bits(0b101, 3).unwrap();
+        assert_eq!(tee.pad_to_byte().unwrap(), 5);
+        tee.write_bytes(vec![0xAB]).unwrap();
+        assert_eq!(tee.bits_written(), 16);
+
+        let (first, second) = tee.into_inner();
+        assert_eq!(first.into_inner().unwrap(), [0b1010_0000, 0xAB]);
+        assert_eq!(second.into_inner().unwrap(), [0b0000_0101, 0xAB]);
+
+        let mut counted = TeeWriter::new(Writer::new(Vec::new()), BitCounter::new());
+        section(&mut counted).unwrap();
+        assert_eq!(counted.get_ref().1.bits_written(), 60);
+
+        // A second sink that started part way into a byte still gets the first's padding
+        let mut counter = BitCounter::new();
+        counter.write_bits(0, 6).unwrap();
+        let mut tee = TeeWriter::new(Writer::new(Vec::new()), counter);
+        tee.write_bits(0b101, 3).unwrap();
+        assert_eq!(tee.pad_to_byte().unwrap(), 5);
+        assert_eq!(tee.get_ref().1.bits_written(), 6 + 8);
+
+        // Fill bits go to both, so a checksum of the second matches what the first wrote
+        // 0x5A is 0101_1010, and the padding takes places 3 to 7 of it
+        for (bit_order, expected) in [
+            (crate::BitOrder::MsbFirst, 0b1011_1010),
+            (crate::BitOrder::LsbFirst, 0b0101_1101),
+        ] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.set_pad_fill(0x5A);
+            let mut tee = TeeWriter::new(writer, Writer::with_bit_order(Vec::new(), bit_order));
+            tee.write_bits(0b101, 3).unwrap();
+            assert_eq!(tee.pad_to_byte().unwrap(), 5);
+            let (first, second) = tee.into_inner();
+            let first = first.into_inner().unwrap();
+            assert_eq!(first, [expected]);
+            assert_eq!(second.into_inner().unwrap(), first);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+pub use crate::bit_enum::enum_width;
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Longest run of empty boards one marker can cover, counts are stored minus one in 6 bits
+const MAX_EMPTY_RUN: usize = 64;
+
+pub fn write_small_enum<W: Write>(
+    writer: &mut Writer<W>,
+    value: usize,
+    variant_count: usize,
+) -> Result<(), Error> {
+    if value >= variant_count {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Enum value is out of range for its variant count",
+        ));
+    }
+    Ok(writer.write_bits(value as u128, enum_width(variant_count))?)
+}
+
+pub fn read_small_enum<R: Read>(
+    reader: &mut Reader<R>,
+    variant_count: usize,
+) -> Result<usize, Error> {
+    let value = reader.read_bits(enum_width(variant_count))? as usize;
+    if value >= variant_count {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Enum value is out of range for its variant count",
+        ));
+    }
+    Ok(value)
+}
+
+// Without compression every board is 64 bits. With it, a 1 bit is followed by a non-empty board
+// and a 0 bit by 6 bits giving how many empty boards (minus one) come next.
+pub fn write_bitboards<W: Write>(
+    writer: &mut Writer<W>,
+    boards: &[u64],
+    compress_empty: bool,
+) -> Result<(), Error> {
+    if !compress_empty {
+        for &board in boards {
+            writer.write_bits(board as u128, 64)?;
+        }
+        return Ok(());
+    }
+
+    let mut position = 0;
+    while position < boards.len() {
+        let empty = boards[position..]
+            .iter()
+            .take(MAX_EMPTY_RUN)
+            .take_while(|&&board| board == 0)
+            .count();
+        if empty > 0 {
+            writer.write_bit(false)?;
+            writer.write_bits((empty - 1) as u128, 6)?;
+            position += empty;
+        } else {
+            writer.write_bit(true)?;
+            writer.write_bits(boards[position] as u128, 64)?;
+            position += 1;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_bitboards<R: Read>(
+    reader: &mut Reader<R>,
+    count: usize,
+    compress_empty: bool,
+) -> Result<Vec<u64>, Error> {
+    let mut boards = Vec::with_capacity(count.min(u16::MAX as usize));
+    while boards.len() < count {
+        if !compress_empty || reader.read_bit()? {
+            boards.push(reader.read_bits(64)? as u64);
+            continue;
+        }
+        let empty = reader.read_bits(6)? as usize + 1;
+        if empty > count - boards.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Run of empty bitboards goes past the board count",
+            ));
+        }
+        boards.extend(std::iter::repeat_n(0, empty));
+    }
+    Ok(boards)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn enum_widths() {
+        assert_eq!(enum_width(1), 0);
+        assert_eq!(enum_width(2), 1);
+        assert_eq!(enum_width(6), 3);
+        assert_eq!(enum_width(8), 3);
+        assert_eq!(enum_width(9), 4);
+    }
+
+    #[test]
+    pub fn small_enums() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        // Side to move, piece kind, castling rights
+        write_small_enum(&mut writer, 1, 2).unwrap();
+        write_small_enum(&mut writer, 5, 6).unwrap();
+        write_small_enum(&mut writer, 9, 16).unwrap();
+        assert!(write_small_enum(&mut writer, 6, 6).is_err());
+        writer.flush().unwrap();
+
+        // 1 101 1001
+        assert_eq!(*writer.get_ref().get_ref(), [0b1101_1001]);
+
+        let mut reader = Reader::new(Cursor::new(vec![0b1101_1001, 0b1110_0000]));
+        assert_eq!(read_small_enum(&mut reader, 2).unwrap(), 1);
+        assert_eq!(read_small_enum(&mut reader, 6).unwrap(), 5);
+        assert_eq!(read_small_enum(&mut reader, 16).unwrap(), 9);
+        // 111 is not a valid piece kind
+        assert!(read_small_enum(&mut reader, 6).is_err());
+    }
+
+    #[test]
+    pub fn compressed_bitboards() {
+        // White pawns, then 10 empty boards, then the black king
+        let mut boards = vec![0x0000_0000_0000_FF00];
+        boards.extend(vec![0; 10]);
+        boards.push(0x1000_0000_0000_0000);
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_bitboards(&mut writer, &boards, true).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        // 65 + 7 + 65 bits
+        assert_eq!(encoded.len(), 18);
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(
+            read_bitboards(&mut reader, boards.len(), true).unwrap(),
+            boards
+        );
+    }
+
+    #[test]
+    pub fn long_empty_runs() {
+        let mut boards = vec![0; 100];
+        boards.push(u64::MAX);
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_bitboards(&mut writer, &boards, true).unwrap();
+        write_bitboards(&mut writer, &boards[95..], false).unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        assert_eq!(read_bitboards(&mut reader, 101, true).unwrap(), boards);
+        assert_eq!(
+            read_bitboards(&mut reader, 6, false).unwrap(),
+            &boards[95..]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitShuffle {
+    elem_size: usize,
+    block_size: usize,
+}
+
+impl BitShuffle {
+    pub fn new(elem_size: usize) -> BitShuffle {
+        // Same default as the bitshuffle library: blocks of about 8 KiB
+        let block_size = (8192 / elem_size.max(1)) / 8 * 8;
+        BitShuffle::with_block_size(elem_size, block_size.max(8))
+    }
+
+    // block_size is counted in elements and must be a multiple of 8
+    pub fn with_block_size(elem_size: usize, block_size: usize) -> BitShuffle {
+        BitShuffle {
+            elem_size,
+            block_size,
+        }
+    }
+
+    fn check(&self, data: &[u8]) -> Result<(), Error> {
+        if self.elem_size == 0 || self.block_size == 0 || !self.block_size.is_multiple_of(8) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Bitshuffle block size must be a non-zero multiple of 8 elements",
+            ));
+        }
+        if !data.len().is_multiple_of(self.elem_size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Bitshuffle data is not a whole number of elements",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn shuffle(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check(data)?;
+        // Leftover elements that don't make a group of 8 stay as they are
+        let mut output = data.to_vec();
+        self.for_each_block(data.len(), |start, elements| {
+            let end = start + elements * self.elem_size;
+            shuffle_block(&data[start..end], &mut output[start..end], self.elem_size);
+        });
+        Ok(output)
+    }
+
+    pub fn unshuffle(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check(data)?;
+        // Leftover elements that don't make a group of 8 stay as they are
+        let mut output = data.to_vec();
+        self.for_each_block(data.len(), |start, elements| {
+            let end = start + elements * self.elem_size;
+            unshuffle_block(&data[start..end], &mut output[start..end], self.elem_size);
+        });
+        Ok(output)
+    }
+
+    // Calls back with (byte offset, element count) for every block
+    fn for_each_block<F: FnMut(usize, usize)>(&self, length: usize, mut f: F) {
+        let count = length / self.elem_size;
+        let mut element = 0;
+        while count - element >= 8 {
+            let elements = self.block_size.min((count - element) / 8 * 8);
+            f(element * self.elem_size, elements);
+            element += elements;
+        }
+    }
+
+    pub fn encode<W: Write>(&self, data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+        let shuffled = self.shuffle(data)?;
+        Ok(writer.write_bytes(shuffled)?)
+    }
+
+    pub fn decode<R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        number_of_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let shuffled = reader.read_bytes(number_of_bytes)?;
+        self.unshuffle(&shuffled)
+    }
+}
+
+// Transposes an 8x8 bit matrix held one row per byte
+fn transpose_8x8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^= t ^ (t << 28);
+    x
+}
+
+fn shuffle_block(input: &[u8], output: &mut [u8], elem_size: usize) {
+    let count = input.len() / elem_size;
+    let plane_len = count / 8;
+    let mut row = vec![0u8; count];
+    for byte in 0..elem_size {
+        // Gather the same byte out of every element
+        for (element, value) in row.iter_mut().enumerate() {
+            *value = input[element * elem_size + byte];
+        }
+        let planes = &mut output[byte * 8 * plane_len..(byte + 1) * 8 * plane_len];
+        let done = simd::transpose_row(&row, planes, plane_len);
+        for group in done..plane_len {
+            let mut chunk = [0u8; 8];
+            chunk.copy_from_slice(&row[group * 8..group * 8 + 8]);
+            let transposed = transpose_8x8(u64::from_le_bytes(chunk)).to_le_bytes();
+            for (bit, &value) in transposed.iter().enumerate() {
+                planes[bit * plane_len + group] = value;
+            }
+        }
+    }
+}
+
+fn unshuffle_block(input: &[u8], output: &mut [u8], elem_size: usize) {
+    let count = input.len() / elem_size;
+    let plane_len = count / 8;
+    for byte in 0..elem_size {
+        let planes = &input[byte * 8 * plane_len..(byte + 1) * 8 * plane_len];
+        for group in 0..plane_len {
+            let mut chunk = [0u8; 8];
+            for (bit, value) in chunk.iter_mut().enumerate() {
+                *value = planes[bit * plane_len + group];
+            }
+            let transposed = transpose_8x8(u64::from_le_bytes(chunk)).to_le_bytes();
+            for (offset, &value) in transposed.iter().enumerate() {
+                output[(group * 8 + offset) * elem_size + byte] = value;
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::{__m128i, _mm_add_epi8, _mm_loadu_si128, _mm_movemask_epi8};
+
+    // Splits 16 bytes at a time into bit planes: movemask grabs the top bit of every byte and
+    // adding the vector to itself shifts the next bit up. Returns how many groups of 8 were done.
+    pub fn transpose_row(row: &[u8], planes: &mut [u8], plane_len: usize) -> usize {
+        let chunks = row.len() / 16;
+        for chunk in 0..chunks {
+            // SSE2 is part of the x86_64 baseline and the load is unaligned and in bounds
+            unsafe {
+                let mut v = _mm_loadu_si128(row[chunk * 16..].as_ptr() as *const __m128i);
+                for bit in (0..8).rev() {
+                    let mask = _mm_movemask_epi8(v) as u16;
+                    let [low, high] = mask.to_le_bytes();
+                    planes[bit * plane_len + chunk * 2] = low;
+                    planes[bit * plane_len + chunk * 2 + 1] = high;
+                    v = _mm_add_epi8(v, v);
+                }
+            }
+        }
+        chunks * 2
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod simd {
+    pub fn transpose_row(_row: &[u8], _planes: &mut [u8], _plane_len: usize) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    // Bit k of element i ends up as bit (i % 8) of plane k
+    fn naive_shuffle(data: &[u8], elem_size: usize) -> Vec<u8> {
+        let count = data.len() / elem_size;
+        let mut output = vec![0u8; data.len()];
+        for element in 0..count {
+            for bit in 0..elem_size * 8 {
+                if data[element * elem_size + bit / 8] >> (bit % 8) & 1 != 0 {
+                    output[bit * count / 8 + element / 8] |= 1 << (element % 8);
+                }
+            }
+        }
+        output
+    }
+
+    #[test]
+    pub fn matches_naive_transpose() {
+        // 56 elements covers both the vector and the scalar transpose
+        let data: Vec<u8> = (0..4 * 56u32).map(|i| (i * 37 + i / 5) as u8).collect();
+        let shuffle = BitShuffle::with_block_size(4, 56);
+
+        assert_eq!(shuffle.shuffle(&data).unwrap(), naive_shuffle(&data, 4));
+    }
+
+    #[test]
+    pub fn round_trip_with_leftovers() {
+        // 3 blocks of 16, then 8 more, then 5 leftover elements copied as is
+        let data: Vec<u8> = (0..2 * 61u32).map(|i| (i * 91) as u8).collect();
+        let shuffle = BitShuffle::with_block_size(2, 16);
+        let shuffled = shuffle.shuffle(&data).unwrap();
+
+        assert_eq!(shuffled[2 * 56..], data[2 * 56..]);
+        assert_eq!(shuffle.unshuffle(&shuffled).unwrap(), data);
+    }
+
+    #[test]
+    pub fn groups_small_values() {
+        // Small u16 values leave the upper bit planes all zero
+        let values: Vec<u8> = (0..64u16).flat_map(|i| (i % 4).to_le_bytes()).collect();
+        let shuffled = BitShuffle::new(2).shuffle(&values).unwrap();
+
+        assert!(shuffled[2 * 8..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    pub fn stream_round_trip() {
+        let data: Vec<u8> = (0..8 * 40u32).map(|i| (i * 13) as u8).collect();
+        let shuffle = BitShuffle::new(8);
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        shuffle.encode(&data, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(shuffle.decode(&mut reader, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    pub fn rejects_partial_elements() {
+        assert!(BitShuffle::new(4).shuffle(&[1, 2, 3]).is_err());
+        assert!(BitShuffle::with_block_size(4, 12)
+            .shuffle(&[0; 48])
+            .is_err());
+    }
+}
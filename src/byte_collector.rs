@@ -0,0 +1,84 @@
+// Bits packed into bytes the way a Writer with the same bit order would pack them, for sinks that
+// work a byte at a time (checksums, Reed-Solomon blocks) but are written to a bit at a time
+use crate::{BitOrder, Error};
+
+// Packs bits into bytes in either bit order and hands each byte on once it's full
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ByteCollector {
+    bit_order: BitOrder,
+    byte: u8,
+    filled: usize,
+    // Bits pushed so far
+    pub(crate) bits: u64,
+}
+
+impl ByteCollector {
+    pub(crate) fn new(bit_order: BitOrder) -> ByteCollector {
+        ByteCollector {
+            bit_order,
+            byte: 0,
+            filled: 0,
+            bits: 0,
+        }
+    }
+
+    pub(crate) fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    pub(crate) fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub(crate) fn is_aligned(&self) -> bool {
+        self.filled == 0
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        bits: u128,
+        number_of_bits: usize,
+        mut emit: impl FnMut(u8),
+    ) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits, number_of_bits, 128));
+        }
+        let mut remaining = number_of_bits;
+        while remaining > 0 {
+            let count = remaining.min(8 - self.filled);
+            let mask = (1u128 << count) - 1;
+            match self.bit_order {
+                BitOrder::MsbFirst => {
+                    let piece = (bits >> (remaining - count) & mask) as u8;
+                    self.byte |= piece << (8 - self.filled - count);
+                }
+                BitOrder::LsbFirst => {
+                    let piece = (bits >> (number_of_bits - remaining) & mask) as u8;
+                    self.byte |= piece << self.filled;
+                }
+            }
+            self.filled += count;
+            self.bits += count as u64;
+            remaining -= count;
+            if self.filled == 8 {
+                emit(self.byte);
+                self.byte = 0;
+                self.filled = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Whole bytes, taking the fast path when there's no partial byte in the way
+    pub(crate) fn push_bytes(&mut self, bytes: &[u8], mut emit: impl FnMut(&[u8])) {
+        if self.is_aligned() {
+            self.bits += 8 * bytes.len() as u64;
+            emit(bytes);
+            return;
+        }
+        for &byte in bytes {
+            // Eight bits always fit
+            let _ = self.push(byte as u128, 8, |full| emit(&[full]));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// The byte streams under Reader and Writer. std's Read and Write get these through the blanket
+// impls here and embedded-io's through the EmbeddedIo wrapper, so the bit level code is written
+// once for both. A failure is reported at the bit position the Reader or Writer passes in.
+use crate::Error;
+
+pub trait ByteSource {
+    // Bytes a Reader takes from the source at once and holds until they're read
+    const BUFFER_SIZE: usize;
+
+    // Reads at least one byte into buffer, or none at the end of the stream. Interrupted reads are
+    // tried again.
+    fn pull(&mut self, buffer: &mut [u8], bit_position: u64) -> Result<usize, Error>;
+}
+
+pub trait ByteSink {
+    // Bytes a Writer holds before it hands them to the sink
+    const BUFFER_SIZE: usize;
+
+    fn push(&mut self, bytes: &[u8], bit_position: u64) -> Result<(), Error>;
+
+    fn flush_out(&mut self, bit_position: u64) -> Result<(), Error>;
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> ByteSource for R {
+    // What BufReader and BufWriter default to
+    const BUFFER_SIZE: usize = 8 * 1024;
+
+    fn pull(&mut self, buffer: &mut [u8], bit_position: u64) -> Result<usize, Error> {
+        loop {
+            match self.read(buffer) {
+                Ok(n) => return Ok(n),
+                // A signal landing mid read (EINTR on pipes and sockets) just means try again
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::io(bit_position, e)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> ByteSink for W {
+    const BUFFER_SIZE: usize = 8 * 1024;
+
+    fn push(&mut self, bytes: &[u8], bit_position: u64) -> Result<(), Error> {
+        self.write_all(bytes).map_err(Error::io_at(bit_position))
+    }
+
+    fn flush_out(&mut self, bit_position: u64) -> Result<(), Error> {
+        self.flush().map_err(Error::io_at(bit_position))
+    }
+}
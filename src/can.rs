@@ -0,0 +1,490 @@
+// CAN frames at the bit level, as a logic analyzer sees them on the bus: classic CAN 2.0A/B and
+// ISO CAN FD (ISO 11898-1:2015), with each field sent most significant bit first.
+// From the start of frame on, a transmitter puts the opposite bit in after five the same, through
+// the CRC for classic frames and the data for FD. FD then counts those stuff bits (mod 8, Gray
+// coded, with even parity) into a field of its own, and sends that and the CRC with a fixed stuff
+// bit, the opposite of the bit before, ahead of every fourth bit. FD's CRC covers the dynamic stuff
+// bits and the stuff count too, and its register starts with the top bit set.
+use crate::bit_write::BitWrite;
+use crate::{Error, Reader};
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanId {
+    // 11 bits
+    Standard(u16),
+    // 29 bits
+    Extended(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanFormat {
+    Classic,
+    Fd {
+        bit_rate_switch: bool,
+        error_passive: bool,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanFrame {
+    pub id: CanId,
+    pub format: CanFormat,
+    // A classic remote frame only sends data.len() as its DLC, and reads back with that many zeros
+    pub remote: bool,
+    pub data: Vec<u8>,
+}
+
+// Data lengths of FD's DLCs 9 to 15. Classic frames with those DLCs carry 8 bytes.
+const FD_LENGTHS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
+
+const CRC_15: (u32, usize) = (0x4599, 15);
+const CRC_17: (u32, usize) = (0x1_685B, 17);
+const CRC_21: (u32, usize) = (0x10_2899, 21);
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, width: usize) {
+    bits.extend((0..width).rev().map(|shift| value >> shift & 1 == 1));
+}
+
+fn crc(bits: &[bool], (polynomial, width): (u32, usize), init: u32) -> u32 {
+    let mask = (1 << width) - 1;
+    bits.iter().fold(init, |crc, &bit| {
+        let top = crc >> (width - 1) & 1 == 1;
+        let crc = crc << 1 & mask;
+        if top ^ bit {
+            crc ^ polynomial
+        } else {
+            crc
+        }
+    })
+}
+
+// The 3 bit Gray coded stuff count and its even parity bit
+fn stuff_count_field(stuff_bits: usize) -> u32 {
+    let count = stuff_bits as u32 % 8;
+    let gray = count ^ count >> 1;
+    gray << 1 | (gray.count_ones() % 2)
+}
+
+fn fd_crc(data_length: usize) -> (u32, usize) {
+    if data_length <= 16 {
+        CRC_17
+    } else {
+        CRC_21
+    }
+}
+
+impl CanFrame {
+    fn dlc(&self) -> Result<u32, Error> {
+        let length = self.data.len();
+        match self.format {
+            _ if length <= 8 => Ok(length as u32),
+            CanFormat::Fd { .. } => match FD_LENGTHS.iter().position(|&fd| fd == length) {
+                Some(index) => Ok(9 + index as u32),
+                None => Err(Error::invalid_input(0, "CAN FD data can't be that length")),
+            },
+            CanFormat::Classic => Err(Error::invalid_input(0, "Classic CAN data is 0 to 8 bytes")),
+        }
+    }
+
+    // Start of frame to the end of the data, before stuffing
+    fn fields(&self) -> Result<Vec<bool>, Error> {
+        let dlc = self.dlc()?;
+        let fd = match self.format {
+            CanFormat::Classic => None,
+            CanFormat::Fd { .. } if self.remote => {
+                return Err(Error::invalid_input(0, "CAN FD has no remote frames"));
+            }
+            CanFormat::Fd {
+                bit_rate_switch,
+                error_passive,
+            } => Some((bit_rate_switch, error_passive)),
+        };
+        // RTR in classic frames, RRS (always 0) in FD
+        let request = self.remote as u32;
+        let mut bits = vec![false];
+        match self.id {
+            CanId::Standard(id) if id <= 0x7FF => {
+                push_bits(&mut bits, id as u32, 11);
+                push_bits(&mut bits, request, 1);
+                // IDE
+                push_bits(&mut bits, 0, 1);
+            }
+            CanId::Extended(id) if id <= 0x1FFF_FFFF => {
+                push_bits(&mut bits, id >> 18, 11);
+                // SRR and IDE
+                push_bits(&mut bits, 0b11, 2);
+                push_bits(&mut bits, id & 0x3_FFFF, 18);
+                push_bits(&mut bits, request, 1);
+                // r1
+                if fd.is_none() {
+                    push_bits(&mut bits, 0, 1);
+                }
+            }
+            _ => return Err(Error::invalid_input(0, "CAN identifier is too wide")),
+        }
+        match fd {
+            // r0
+            None => push_bits(&mut bits, 0, 1),
+            // FDF, res, BRS and ESI
+            Some((bit_rate_switch, error_passive)) => {
+                push_bits(&mut bits, 0b10, 2);
+                push_bits(&mut bits, bit_rate_switch as u32, 1);
+                push_bits(&mut bits, error_passive as u32, 1);
+            }
+        }
+        push_bits(&mut bits, dlc, 4);
+        if !self.remote {
+            for &byte in &self.data {
+                push_bits(&mut bits, byte as u32, 8);
+            }
+        }
+        Ok(bits)
+    }
+
+    // Writes the frame from its start of frame to the end of its end of frame, as its transmitter
+    // sends it (so with the ACK slot recessive)
+    pub fn write<W: BitWrite>(&self, writer: &mut W) -> Result<(), Error> {
+        let fields = self.fields()?;
+        let mut stuffed = Vec::with_capacity(2 * fields.len());
+        let mut run = (false, 0);
+        let mut stuff = |stuffed: &mut Vec<bool>, bit: bool| {
+            // A stuff bit goes in before a sixth bit the same, so none trails the last bit
+            if run.1 == 5 {
+                stuffed.push(!run.0);
+                run = (!run.0, 1);
+            }
+            stuffed.push(bit);
+            run = if run.0 == bit {
+                (bit, run.1 + 1)
+            } else {
+                (bit, 1)
+            };
+        };
+        for &bit in &fields {
+            stuff(&mut stuffed, bit);
+        }
+        match self.format {
+            CanFormat::Classic => {
+                let mut checked = Vec::new();
+                push_bits(&mut checked, crc(&fields, CRC_15, 0), 15);
+                for bit in checked {
+                    stuff(&mut stuffed, bit);
+                }
+                if run.1 == 5 {
+                    stuffed.push(!run.0);
+                }
+            }
+            CanFormat::Fd { .. } => {
+                let mut tail = Vec::new();
+                push_bits(
+                    &mut tail,
+                    stuff_count_field(stuffed.len() - fields.len()),
+                    4,
+                );
+                let (polynomial, width) = fd_crc(self.data.len());
+                let covered = [&stuffed[..], &tail[..]].concat();
+                let init = 1 << (width - 1);
+                push_bits(&mut tail, crc(&covered, (polynomial, width), init), width);
+                for (index, bit) in tail.into_iter().enumerate() {
+                    if index % 4 == 0 {
+                        let last = stuffed[stuffed.len() - 1];
+                        stuffed.push(!last);
+                    }
+                    stuffed.push(bit);
+                }
+            }
+        }
+        // CRC delimiter, ACK slot, ACK delimiter and end of frame
+        stuffed.extend([true; 10]);
+        for bit in stuffed {
+            writer.write_bit(bit)?;
+        }
+        Ok(())
+    }
+
+    // Skips the recessive bits of an idle bus and reads the frame that starts at the next dominant
+    // one, or None if the stream ends first. Stuff, form and CRC errors fail with InvalidData, and
+    // the ACK slot can be either.
+    pub fn read<R: Read>(reader: &mut Reader<R>) -> Result<Option<CanFrame>, Error> {
+        loop {
+            match reader.read_bit_opt()? {
+                Some(true) => {}
+                Some(false) => break,
+                None => return Ok(None),
+            }
+        }
+        let mut bits = Destuffer {
+            raw: vec![false],
+            fields: vec![false],
+            run: (false, 1),
+        };
+        let id_a = bits.take(reader, 11)?;
+        let request = bits.take(reader, 1)? == 1;
+        let id = match bits.take(reader, 1)? {
+            0 => CanId::Standard(id_a as u16),
+            _ => {
+                let id_b = bits.take(reader, 18)?;
+                CanId::Extended(id_a << 18 | id_b)
+            }
+        };
+        // RTR for an extended classic frame, where the bit before was SRR
+        let request = match id {
+            CanId::Extended(_) => bits.take(reader, 1)? == 1,
+            CanId::Standard(_) => request,
+        };
+        // FDF, which is r1 of an extended classic frame or r0 of a standard one. Receivers ignore
+        // the reserved bits, along with SRR and FD's RRS.
+        let format = match bits.take(reader, 1)? {
+            0 => {
+                if let CanId::Extended(_) = id {
+                    bits.take(reader, 1)?;
+                }
+                CanFormat::Classic
+            }
+            _ => {
+                // res, BRS and ESI
+                let flags = bits.take(reader, 3)?;
+                CanFormat::Fd {
+                    bit_rate_switch: flags & 0b10 != 0,
+                    error_passive: flags & 0b01 != 0,
+                }
+            }
+        };
+        let remote = request && format == CanFormat::Classic;
+        let dlc = bits.take(reader, 4)? as usize;
+        let length = match format {
+            CanFormat::Classic => dlc.min(8),
+            CanFormat::Fd { .. } if dlc <= 8 => dlc,
+            CanFormat::Fd { .. } => FD_LENGTHS[dlc - 9],
+        };
+        let mut data = vec![0; length];
+        if !remote {
+            for byte in &mut data {
+                *byte = bits.take(reader, 8)? as u8;
+            }
+        }
+        let position = reader.bits_read();
+        match format {
+            CanFormat::Classic => {
+                let expected = crc(&bits.fields, CRC_15, 0);
+                let sent = bits.take(reader, 15)?;
+                // A stuff bit after the CRC is still checked
+                if bits.run.1 == 5 && reader.read_bit()? == bits.run.0 {
+                    return Err(Error::invalid_data(
+                        reader.bits_read() - 1,
+                        "CAN stuff error",
+                    ));
+                }
+                if sent != expected {
+                    return Err(Error::invalid_data(position, "CAN CRC doesn't match"));
+                }
+            }
+            CanFormat::Fd { .. } => {
+                let (polynomial, width) = fd_crc(length);
+                let mut last = bits.raw[bits.raw.len() - 1];
+                let mut tail = 0;
+                for index in 0..4 + width {
+                    if index % 4 == 0 && reader.read_bit()? == last {
+                        let position = reader.bits_read() - 1;
+                        return Err(Error::invalid_data(position, "CAN fixed stuff error"));
+                    }
+                    last = reader.read_bit()?;
+                    tail = tail << 1 | last as u32;
+                    if index == 3 {
+                        bits.raw
+                            .extend((0..4).rev().map(|shift| tail >> shift & 1 == 1));
+                    }
+                }
+                let stuff_bits = bits.raw.len() - 4 - bits.fields.len();
+                if tail >> width != stuff_count_field(stuff_bits) {
+                    return Err(Error::invalid_data(
+                        position,
+                        "CAN FD stuff count doesn't match",
+                    ));
+                }
+                let expected = crc(&bits.raw, (polynomial, width), 1 << (width - 1));
+                if tail & ((1 << width) - 1) != expected {
+                    return Err(Error::invalid_data(position, "CAN CRC doesn't match"));
+                }
+            }
+        }
+        let delimiter = reader.read_bit()?;
+        let _ack_slot = reader.read_bit()?;
+        let ack_delimiter = reader.read_bit()?;
+        if !delimiter || !ack_delimiter || reader.read_bits(7)? != 0x7F {
+            return Err(Error::invalid_data(
+                position,
+                "CAN frame ends with a form error",
+            ));
+        }
+        Ok(Some(CanFrame {
+            id,
+            format,
+            remote,
+            data,
+        }))
+    }
+}
+
+// Takes dynamic stuff bits back out while a frame is read
+struct Destuffer {
+    // Everything read, stuff bits included
+    raw: Vec<bool>,
+    fields: Vec<bool>,
+    // The last bit and how many of it in a row
+    run: (bool, usize),
+}
+
+impl Destuffer {
+    fn take<R: Read>(&mut self, reader: &mut Reader<R>, width: usize) -> Result<u32, Error> {
+        let mut value = 0;
+        for _ in 0..width {
+            if self.run.1 == 5 {
+                let stuff = reader.read_bit()?;
+                if stuff == self.run.0 {
+                    return Err(Error::invalid_data(
+                        reader.bits_read() - 1,
+                        "CAN stuff error",
+                    ));
+                }
+                self.raw.push(stuff);
+                self.run = (stuff, 1);
+            }
+            let bit = reader.read_bit()?;
+            self.raw.push(bit);
+            self.fields.push(bit);
+            self.run = if self.run.0 == bit {
+                (bit, self.run.1 + 1)
+            } else {
+                (bit, 1)
+            };
+            value = value << 1 | bit as u32;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitOrder, Writer};
+    use std::io::Cursor;
+
+    fn bits(frame: &CanFrame) -> Vec<bool> {
+        let mut writer = Writer::new(Vec::new());
+        frame.write(&mut writer).unwrap();
+        let length = writer.bits_written() as usize;
+        writer.pad_to_byte().unwrap();
+        let bytes = writer.into_inner().unwrap();
+        (0..length)
+            .map(|bit| bytes[bit / 8] >> (7 - bit % 8) & 1 == 1)
+            .collect()
+    }
+
+    #[test]
+    pub fn stuffs_runs_of_five() {
+        // 19 zeros from the start of frame to the DLC, and a CRC of 15 more
+        let frame = CanFrame {
+            id: CanId::Standard(0),
+            format: CanFormat::Classic,
+            remote: false,
+            data: Vec::new(),
+        };
+        let mut expected = Vec::new();
+        for _ in 0..6 {
+            expected.extend([false, false, false, false, false, true]);
+        }
+        expected.extend([false; 4]);
+        expected.extend([true; 10]);
+        assert_eq!(bits(&frame), expected);
+    }
+
+    #[test]
+    pub fn round_trips_classic_and_fd_frames() {
+        let frames = [
+            CanFrame {
+                id: CanId::Standard(0x7E0),
+                format: CanFormat::Classic,
+                remote: false,
+                data: vec![0x02, 0x01, 0x0C, 0, 0, 0, 0, 0],
+            },
+            CanFrame {
+                id: CanId::Extended(0x18DA_F110),
+                format: CanFormat::Classic,
+                remote: true,
+                data: vec![0; 3],
+            },
+            CanFrame {
+                id: CanId::Standard(0x123),
+                format: CanFormat::Fd {
+                    bit_rate_switch: true,
+                    error_passive: false,
+                },
+                remote: false,
+                data: (0..12).collect(),
+            },
+            CanFrame {
+                id: CanId::Extended(0x1FFF_FFFF),
+                format: CanFormat::Fd {
+                    bit_rate_switch: false,
+                    error_passive: true,
+                },
+                remote: false,
+                data: vec![0xFF; 64],
+            },
+        ];
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            for frame in &frames {
+                // Idle bus between frames
+                writer.write_bits(0b111, 3).unwrap();
+                frame.write(&mut writer).unwrap();
+            }
+            writer.pad_to_byte().unwrap();
+            let bytes = writer.into_inner().unwrap();
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes), bit_order);
+            for frame in &frames {
+                assert_eq!(CanFrame::read(&mut reader).unwrap().as_ref(), Some(frame));
+            }
+        }
+        let mut too_long = frames[0].clone();
+        too_long.data.push(0);
+        assert!(too_long.write(&mut Writer::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    pub fn catches_crc_and_stuff_errors() {
+        let frame = CanFrame {
+            id: CanId::Standard(0x555),
+            format: CanFormat::Fd {
+                bit_rate_switch: true,
+                error_passive: false,
+            },
+            remote: false,
+            data: b"stuffing".to_vec(),
+        };
+        let sent = bits(&frame);
+        let read = |bits: &[bool]| {
+            let mut writer = Writer::new(Vec::new());
+            for &bit in bits {
+                writer.write_bit(bit).unwrap();
+            }
+            // An idle bus after it, for a DLC made longer to run into
+            writer.write_bits(u128::MAX, 128).unwrap();
+            let bytes = writer.into_inner().unwrap();
+            CanFrame::read(&mut Reader::new(Cursor::new(bytes)))
+        };
+        assert_eq!(read(&sent).unwrap(), Some(frame));
+        // Every single flipped bit up to the CRC delimiter is caught one way or another
+        for index in 1..sent.len() - 10 {
+            let mut damaged = sent.clone();
+            damaged[index] = !damaged[index];
+            assert!(
+                matches!(read(&damaged), Err(Error::InvalidData { .. })),
+                "{}",
+                index
+            );
+        }
+    }
+}
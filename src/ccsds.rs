@@ -0,0 +1,208 @@
+// CCSDS telemetry synchronization (CCSDS 131.0-B): every transfer frame goes out behind the
+// attached sync marker, and everything after the marker is XORed with the pseudo-randomizer's
+// sequence, restarted at each frame, so the receiver keeps bit lock through runs of flat data.
+// Bits are handled in the order they're sent, with each value's most significant bit first.
+use crate::bit_write::BitWrite;
+use crate::scrambler::{Scrambler, Scrambling};
+use crate::{Error, Reader, PREALLOCATE_LIMIT};
+use std::io::Read;
+
+pub const ASM: u32 = 0x1ACF_FC1D;
+
+// The randomizer at the start of a frame. CCSDS gives h(x) = x^8 + x^7 + x^5 + x^3 + 1 numbering
+// the stages from the other end, which is 0x12B here, and seeding it with all ones makes a
+// sequence that starts with those ones: 0xFF, 0x48, 0x0E, 0xC0, ...
+pub fn randomizer() -> Scrambler {
+    // Both are in range, so this can't fail
+    Scrambler::new(Scrambling::Additive, 0x12B, 0x58).unwrap()
+}
+
+// Randomizes a frame in place, or takes the randomization back off since it's only an XOR
+pub fn randomize(frame: &mut [u8]) {
+    let mut randomizer = randomizer();
+    for byte in frame {
+        for shift in (0..8).rev() {
+            *byte ^= (randomizer.next_bit() as u8) << shift;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CcsdsFrame {
+    pub data: Vec<u8>,
+    // Of the first bit of its sync marker
+    pub bit_position: u64,
+    // Bits of the sync marker that didn't match
+    pub marker_errors: usize,
+    // Found as the inverse of the sync marker, as after a BPSK demodulator locks on 180 degrees
+    // out, and turned back over
+    pub inverted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CcsdsFramer {
+    frame_length: usize,
+    randomize: bool,
+    max_marker_errors: usize,
+}
+
+impl CcsdsFramer {
+    // Frames of frame_length bytes, randomized, with sync markers only found exactly
+    pub fn new(frame_length: usize) -> Result<CcsdsFramer, Error> {
+        if frame_length == 0 {
+            return Err(Error::invalid_input(0, "CCSDS frames can't be empty"));
+        }
+        Ok(CcsdsFramer {
+            frame_length,
+            randomize: true,
+            max_marker_errors: 0,
+        })
+    }
+
+    // The randomizer is a managed parameter of the link, on unless both ends agree otherwise
+    pub fn with_randomizer(mut self, randomize: bool) -> CcsdsFramer {
+        self.randomize = randomize;
+        self
+    }
+
+    // Accepts sync markers with up to this many bits wrong. A few make a noisy link lose far fewer
+    // frames, while a false match in the data stays unlikely until about 4. It's capped at 15,
+    // past which a marker and its inverse can't be told apart.
+    pub fn with_max_marker_errors(mut self, max_errors: usize) -> CcsdsFramer {
+        self.max_marker_errors = max_errors.min(15);
+        self
+    }
+
+    pub fn frame_length(&self) -> usize {
+        self.frame_length
+    }
+
+    pub fn write_frame<W: BitWrite>(&self, writer: &mut W, frame: &[u8]) -> Result<(), Error> {
+        if frame.len() != self.frame_length {
+            return Err(Error::invalid_input(
+                writer.bits_written(),
+                "Frame isn't the CCSDS frame length",
+            ));
+        }
+        for shift in (0..32).rev() {
+            writer.write_bit(ASM >> shift & 1 == 1)?;
+        }
+        let mut frame = frame.to_vec();
+        if self.randomize {
+            randomize(&mut frame);
+        }
+        for byte in frame {
+            for shift in (0..8).rev() {
+                writer.write_bit(byte >> shift & 1 == 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Searches from the reader's position for the next sync marker and takes the frame behind it,
+    // or None once the stream runs out, including part way through a frame
+    pub fn next_frame<R: Read>(&self, reader: &mut Reader<R>) -> Result<Option<CcsdsFrame>, Error> {
+        let mut window = 0u32;
+        let mut filled = 0;
+        let (marker_errors, inverted) = loop {
+            let bit = match reader.read_bit_opt()? {
+                Some(bit) => bit,
+                None => return Ok(None),
+            };
+            window = window << 1 | bit as u32;
+            filled += 1;
+            if filled < 32 {
+                continue;
+            }
+            let errors = (window ^ ASM).count_ones() as usize;
+            if errors <= self.max_marker_errors {
+                break (errors, false);
+            }
+            if 32 - errors <= self.max_marker_errors {
+                break (32 - errors, true);
+            }
+        };
+        let bit_position = reader.bits_read() - 32;
+        let mut data = Vec::with_capacity(self.frame_length.min(PREALLOCATE_LIMIT));
+        for _ in 0..self.frame_length {
+            let mut byte = 0;
+            for _ in 0..8 {
+                match reader.read_bit_opt()? {
+                    Some(bit) => byte = byte << 1 | (bit ^ inverted) as u8,
+                    None => return Ok(None),
+                }
+            }
+            data.push(byte);
+        }
+        if self.randomize {
+            randomize(&mut data);
+        }
+        Ok(Some(CcsdsFrame {
+            data,
+            bit_position,
+            marker_errors,
+            inverted,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitOrder, Writer};
+    use std::io::Cursor;
+
+    #[test]
+    pub fn randomizer_sequence() {
+        let mut sequence = [0; 10];
+        randomize(&mut sequence);
+        assert_eq!(
+            sequence,
+            [0xFF, 0x48, 0x0E, 0xC0, 0x9A, 0x0D, 0x70, 0xBC, 0x8E, 0x2C]
+        );
+        // It repeats every 255 bits
+        let mut randomizer = randomizer();
+        let bits: Vec<bool> = (0..510).map(|_| randomizer.next_bit()).collect();
+        assert_eq!(bits[..255], bits[255..]);
+    }
+
+    #[test]
+    pub fn finds_frames_behind_damaged_and_inverted_markers() {
+        let framer = CcsdsFramer::new(6).unwrap().with_max_marker_errors(2);
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bits(0b101, 3).unwrap();
+            framer.write_frame(&mut writer, b"\0\0\0\0\0\0").unwrap();
+            framer.write_frame(&mut writer, b"frame2").unwrap();
+            framer.write_frame(&mut writer, b"frame3").unwrap();
+            assert!(framer.write_frame(&mut writer, b"short").is_err());
+            writer.pad_to_byte().unwrap();
+            let mut bytes = writer.into_inner().unwrap();
+            // Zeros don't go out as zeros
+            assert_ne!(bytes[4..10], [0; 6]);
+            let mut flip = |bit: usize| {
+                bytes[bit / 8] ^= match bit_order {
+                    BitOrder::MsbFirst => 0x80 >> (bit % 8),
+                    BitOrder::LsbFirst => 1 << (bit % 8),
+                }
+            };
+            // Two bits of the second marker, then all of the third frame turned over
+            flip(83 + 5);
+            flip(83 + 20);
+            (163..243).for_each(flip);
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes), bit_order);
+            let frame = framer.next_frame(&mut reader).unwrap().unwrap();
+            assert_eq!(frame.data, [0; 6]);
+            assert_eq!(frame.bit_position, 3);
+            let frame = framer.next_frame(&mut reader).unwrap().unwrap();
+            assert_eq!(frame.data, b"frame2");
+            assert_eq!(frame.marker_errors, 2);
+            let frame = framer.next_frame(&mut reader).unwrap().unwrap();
+            assert_eq!(frame.data, b"frame3");
+            assert!(frame.inverted);
+            assert_eq!(frame.bit_position, 3 + 2 * 80);
+            assert_eq!(framer.next_frame(&mut reader).unwrap(), None);
+        }
+    }
+}
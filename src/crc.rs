@@ -0,0 +1,46 @@
+// Checksums shared by the gzip and zlib containers, frame scanning and the digest sinks, kept
+// outside them so codecs and framing can each be built without the other
+// Bitwise CRC-32 (IEEE, reflected), continuing from a previous value
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// The two running sums both mod modulus, over at most chunk bytes at a time before reducing, the
+// most that can be summed before the second could overflow a u32
+pub fn running_sums(sums: &mut (u32, u32), bytes: &[u8], modulus: u32, chunk: usize) {
+    for chunk in bytes.chunks(chunk) {
+        for &byte in chunk {
+            sums.0 += byte as u32;
+            sums.1 += sums.0;
+        }
+        sums.0 %= modulus;
+        sums.1 %= modulus;
+    }
+}
+
+// Adler-32 as in zlib, continuing from a previous value (1 to start)
+pub fn adler32_update(adler: u32, data: &[u8]) -> u32 {
+    let mut sums = (adler & 0xFFFF, adler >> 16);
+    running_sums(&mut sums, data, 65521, 5552);
+    sums.1 << 16 | sums.0
+}
+
+#[cfg(feature = "codecs")]
+pub fn adler32(data: &[u8]) -> u32 {
+    adler32_update(1, data)
+}
@@ -0,0 +1,300 @@
+use crate::{BitOrder, Writer};
+use std::io::{Error, ErrorKind, Write};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: usize = 15;
+const MAX_STORED: usize = 65535;
+
+// (base length, extra bits) for length codes 257..=285
+const LENGTHS: [(usize, usize); 29] = [
+    (3, 0),
+    (4, 0),
+    (5, 0),
+    (6, 0),
+    (7, 0),
+    (8, 0),
+    (9, 0),
+    (10, 0),
+    (11, 1),
+    (13, 1),
+    (15, 1),
+    (17, 1),
+    (19, 2),
+    (23, 2),
+    (27, 2),
+    (31, 2),
+    (35, 3),
+    (43, 3),
+    (51, 3),
+    (59, 3),
+    (67, 4),
+    (83, 4),
+    (99, 4),
+    (115, 4),
+    (131, 5),
+    (163, 5),
+    (195, 5),
+    (227, 5),
+    (258, 0),
+];
+
+// (base distance, extra bits) for distance codes 0..=29
+const DISTANCES: [(usize, usize); 30] = [
+    (1, 0),
+    (2, 0),
+    (3, 0),
+    (4, 0),
+    (5, 1),
+    (7, 1),
+    (9, 2),
+    (13, 2),
+    (17, 3),
+    (25, 3),
+    (33, 4),
+    (49, 4),
+    (65, 5),
+    (97, 5),
+    (129, 6),
+    (193, 6),
+    (257, 7),
+    (385, 7),
+    (513, 8),
+    (769, 8),
+    (1025, 9),
+    (1537, 9),
+    (2049, 10),
+    (3073, 10),
+    (4097, 11),
+    (6145, 11),
+    (8193, 12),
+    (12289, 12),
+    (16385, 13),
+    (24577, 13),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockType {
+    Stored,
+    FixedHuffman,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deflate {
+    block_type: BlockType,
+}
+
+impl Deflate {
+    pub fn new(block_type: BlockType) -> Deflate {
+        Deflate { block_type }
+    }
+
+    // Writes a complete raw DEFLATE stream (RFC 1951). The writer has to be LSB first.
+    pub fn encode<W: Write>(&self, data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+        if writer.bit_order() != BitOrder::LsbFirst {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "DEFLATE streams need an LSB first writer",
+            ));
+        }
+        match self.block_type {
+            BlockType::Stored => write_stored(data, writer),
+            BlockType::FixedHuffman => write_fixed(data, writer),
+        }
+    }
+}
+
+fn write_stored<W: Write>(data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+    let mut chunks = data.chunks(MAX_STORED).peekable();
+    if chunks.peek().is_none() {
+        // Empty input still needs a final block
+        return write_stored_block(&[], true, writer);
+    }
+    while let Some(chunk) = chunks.next() {
+        write_stored_block(chunk, chunks.peek().is_none(), writer)?;
+    }
+    Ok(())
+}
+
+fn write_stored_block<W: Write>(
+    chunk: &[u8],
+    is_final: bool,
+    writer: &mut Writer<W>,
+) -> Result<(), Error> {
+    writer.write_bit(is_final)?;
+    writer.write_bits(0b00, 2)?;
+    writer.pad_to_byte()?;
+    writer.write_bits(chunk.len() as u128, 16)?;
+    writer.write_bits(!chunk.len() as u128 & 0xFFFF, 16)?;
+    for &byte in chunk {
+        writer.write_byte(byte)?;
+    }
+    Ok(())
+}
+
+// Huffman codes are packed starting from their most significant bit, the opposite of every other
+// field, so flip them before handing them to the LSB first writer
+fn write_code<W: Write>(code: u32, length: usize, writer: &mut Writer<W>) -> Result<(), Error> {
+    let reversed = code.reverse_bits() >> (32 - length);
+    Ok(writer.write_bits(reversed as u128, length)?)
+}
+
+fn write_fixed_symbol<W: Write>(symbol: usize, writer: &mut Writer<W>) -> Result<(), Error> {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => write_code(0x30 + symbol, 8, writer),
+        144..=255 => write_code(0x190 + symbol - 144, 9, writer),
+        256..=279 => write_code(symbol - 256, 7, writer),
+        _ => write_code(0xC0 + symbol - 280, 8, writer),
+    }
+}
+
+fn write_match<W: Write>(
+    length: usize,
+    distance: usize,
+    writer: &mut Writer<W>,
+) -> Result<(), Error> {
+    let length_code = LENGTHS
+        .iter()
+        .rposition(|&(base, _)| base <= length)
+        .unwrap();
+    let (base, extra) = LENGTHS[length_code];
+    write_fixed_symbol(257 + length_code, writer)?;
+    writer.write_bits((length - base) as u128, extra)?;
+
+    let distance_code = DISTANCES
+        .iter()
+        .rposition(|&(base, _)| base <= distance)
+        .unwrap();
+    let (base, extra) = DISTANCES[distance_code];
+    write_code(distance_code as u32, 5, writer)?;
+    Ok(writer.write_bits((distance - base) as u128, extra)?)
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    // In u32 so it's the same hash whatever the width of usize
+    let value = (data[position] as u32) << 16
+        | (data[position + 1] as u32) << 8
+        | data[position + 2] as u32;
+    (value.wrapping_mul(2_654_435_761) >> 8) as usize & ((1 << HASH_BITS) - 1)
+}
+
+fn write_fixed<W: Write>(data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+    writer.write_bit(true)?;
+    writer.write_bits(0b01, 2)?;
+
+    // Greedy LZ77 with hash chains over the last 32K
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+    let insert = |position: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>| {
+        if position + MIN_MATCH <= data.len() {
+            let h = hash(data, position);
+            previous[position] = head[h];
+            head[h] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if position + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, position)];
+            let mut chain = 0;
+            while candidate != usize::MAX
+                && position - candidate <= WINDOW_SIZE
+                && chain < MAX_CHAIN
+            {
+                let limit = (data.len() - position).min(MAX_MATCH);
+                let length = (0..limit)
+                    .take_while(|&i| data[candidate + i] == data[position + i])
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(best_length, best_distance, writer)?;
+            for offset in 0..best_length {
+                insert(position + offset, &mut head, &mut previous);
+            }
+            position += best_length;
+        } else {
+            write_fixed_symbol(data[position] as usize, writer)?;
+            insert(position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    // End of block
+    write_fixed_symbol(256, writer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use std::io::{Cursor, Read};
+
+    fn round_trip(block_type: BlockType, data: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), BitOrder::LsbFirst);
+        Deflate::new(block_type).encode(data, &mut writer).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.get_ref().get_ref().clone();
+
+        let mut decoded = Vec::new();
+        DeflateDecoder::new(&encoded[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        encoded
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut data = b"It was the best of times, it was the worst of times. ".repeat(40);
+        data.extend((0..70_000u32).map(|i| (i * 31 + i / 7) as u8));
+        data
+    }
+
+    #[test]
+    pub fn stored_blocks() {
+        let data = sample();
+        let encoded = round_trip(BlockType::Stored, &data);
+        // Two 64K blocks with 5 byte headers each
+        assert_eq!(encoded.len(), data.len() + 10);
+    }
+
+    #[test]
+    pub fn fixed_huffman_blocks() {
+        let data = sample();
+        let encoded = round_trip(BlockType::FixedHuffman, &data);
+        assert!(encoded.len() < data.len());
+    }
+
+    #[test]
+    pub fn empty_input() {
+        assert_eq!(round_trip(BlockType::Stored, &[]), [1, 0, 0, 255, 255]);
+        assert_eq!(round_trip(BlockType::FixedHuffman, &[]), [3, 0]);
+    }
+
+    #[test]
+    pub fn long_runs() {
+        round_trip(BlockType::FixedHuffman, &[7; 5000]);
+        round_trip(BlockType::FixedHuffman, b"a");
+        round_trip(BlockType::FixedHuffman, b"abababababababababab");
+    }
+
+    #[test]
+    pub fn needs_lsb_first() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        let deflate = Deflate::new(BlockType::Stored);
+        assert!(deflate.encode(b"abc", &mut writer).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+// Types deriving deku's DekuRead and DekuWrite, read off a Reader and written to a Writer at any
+// bit position, so a deku struct can sit in the middle of a stream this crate handles the rest of.
+// Reading runs the type over a window of bytes peeked from the reader starting at the current bit,
+// growing it while deku runs out, and only the bits it used are read. The window stops growing at
+// MAX_WINDOW bytes (or the limit given to read_deku_with), and a value deku still runs out on there
+// fails with InvalidData. deku's bits are MSB first, so the reader and writer have to be too.
+use crate::{BitOrder, Error, Reader, Writer};
+use deku::{DekuContainerRead, DekuContainerWrite, DekuError};
+use std::io::{Read, Write};
+
+// Bytes peeked for a value's first try, doubled every time deku runs out
+const FIRST_WINDOW: usize = 64;
+// Most bytes peeked for one value, so a value that never ends can't take the whole stream
+const MAX_WINDOW: usize = 1 << 20;
+
+impl<R: Read> Reader<R> {
+    pub fn read_deku<T>(&mut self) -> Result<T, Error>
+    where
+        T: for<'a> DekuContainerRead<'a>,
+    {
+        self.read_deku_with(MAX_WINDOW)
+    }
+
+    // Like read_deku, peeking at most max_window bytes
+    pub fn read_deku_with<T>(&mut self, max_window: usize) -> Result<T, Error>
+    where
+        T: for<'a> DekuContainerRead<'a>,
+    {
+        if self.bit_order() != BitOrder::MsbFirst {
+            return Err(Error::invalid_input(
+                self.bits_read(),
+                "deku needs an MSB first reader",
+            ));
+        }
+        let mut window = FIRST_WINDOW.min(max_window);
+        loop {
+            let (bytes, offset) = self.peek_bytes(window)?;
+            let at_end = bytes.len() < window;
+            match T::from_bytes((&bytes, offset)) {
+                Ok(((rest, rest_offset), value)) => {
+                    let consumed = 8 * (bytes.len() - rest.len()) + rest_offset - offset;
+                    self.skip_bits(consumed as u64)?;
+                    return Ok(value);
+                }
+                Err(DekuError::Incomplete(_)) if !at_end && window >= max_window => {
+                    let message = format!("deku wanted more than {} bytes", max_window);
+                    return Err(Error::invalid_data(self.bits_read(), &message));
+                }
+                Err(DekuError::Incomplete(_)) if !at_end => {
+                    window = window.saturating_mul(2).min(max_window)
+                }
+                Err(DekuError::Incomplete(needed)) => {
+                    return Err(Error::eof(
+                        self.bits_read() + 8 * bytes.len() as u64 - offset as u64,
+                        needed.bit_size() as u64,
+                    ));
+                }
+                Err(error) => {
+                    let message = format!("deku couldn't read the value: {}", error);
+                    return Err(Error::invalid_data(self.bits_read(), &message));
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn write_deku<T: DekuContainerWrite>(&mut self, value: &T) -> Result<(), Error> {
+        if self.bit_order() != BitOrder::MsbFirst {
+            return Err(Error::invalid_input(
+                self.bits_written(),
+                "deku needs an MSB first writer",
+            ));
+        }
+        let bits = value.to_bits().map_err(|error| {
+            let message = format!("deku couldn't write the value: {}", error);
+            Error::invalid_input(self.bits_written(), &message)
+        })?;
+        self.write_bits_from_iter(bits.iter().by_vals())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use deku::{DekuRead, DekuWrite};
+    use std::io::Cursor;
+
+    #[derive(Debug, PartialEq, DekuRead, DekuWrite)]
+    #[deku(endian = "big")]
+    struct Packet {
+        #[deku(bits = 3)]
+        kind: u8,
+        #[deku(bits = 9)]
+        length: u16,
+        #[deku(count = "length")]
+        payload: Vec<u8>,
+        #[deku(bits = 1)]
+        last: u8,
+    }
+
+    #[test]
+    pub fn mixes_with_bit_fields() {
+        let packet = Packet {
+            kind: 5,
+            length: 100,
+            payload: (0..100).collect(),
+            last: 1,
+        };
+        let mut writer = Writer::new(Vec::new());
+        writer.write_bits(0b11, 2).unwrap();
+        writer.write_deku(&packet).unwrap();
+        writer.write_bits(0x15, 5).unwrap();
+        assert_eq!(writer.bits_written(), 2 + 12 + 800 + 1 + 5);
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        assert_eq!(reader.read_deku::<Packet>().unwrap(), packet);
+        assert_eq!(reader.bits_read(), 2 + 12 + 800 + 1);
+        assert_eq!(reader.read_bits(5).unwrap(), 0x15);
+
+        // Cut short
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_0000, 0xFF, 1, 2]));
+        let error = reader.read_deku::<Packet>().unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEof { .. }));
+        assert_eq!(reader.bits_read(), 0);
+        let mut writer = Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst);
+        assert!(writer.write_deku(&packet).is_err());
+    }
+
+    #[test]
+    pub fn caps_the_window() {
+        let packet = Packet {
+            kind: 5,
+            length: 100,
+            payload: (0..100).collect(),
+            last: 1,
+        };
+        let mut writer = Writer::new(Vec::new());
+        writer.write_bits(0b11, 2).unwrap();
+        writer.write_deku(&packet).unwrap();
+        writer.write_bits(0x15, 5).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        // The packet ends in the 102nd byte, counting the one it starts part way through
+        let mut reader = Reader::new(Cursor::new(bytes));
+        reader.read_bits(2).unwrap();
+        let error = reader.read_deku_with::<Packet>(80).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 2,
+                ..
+            }
+        ));
+        assert_eq!(reader.bits_read(), 2);
+        assert_eq!(reader.read_deku_with::<Packet>(102).unwrap(), packet);
+        assert_eq!(reader.read_bits(5).unwrap(), 0x15);
+    }
+}
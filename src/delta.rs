@@ -0,0 +1,262 @@
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaEncoding {
+    // Two's complement deltas of this many bits (2 to 64). The most negative value is an escape
+    // followed by the value itself at full width, for jumps too big for the delta.
+    Fixed(usize),
+    // Zigzag encoded groups of 7 bits, each led by a continuation bit, least significant first
+    Varint,
+}
+
+fn check_widths(value_width: usize, encoding: DeltaEncoding) -> Result<(), Error> {
+    if value_width == 0 || value_width > 64 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Delta value width must be between 1 and 64 bits",
+        ));
+    }
+    if let DeltaEncoding::Fixed(width) = encoding {
+        if !(2..=64).contains(&width) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Fixed delta width must be between 2 and 64 bits",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn fits(value: i128, width: usize) -> bool {
+    let limit = 1i128 << (width - 1);
+    value >= -limit && value < limit
+}
+
+fn to_signed(raw: u128, width: usize) -> i128 {
+    // Sign extend from width bits
+    let shift = 128 - width;
+    ((raw << shift) as i128) >> shift
+}
+
+fn mask(width: usize) -> u128 {
+    u128::MAX >> (128 - width)
+}
+
+pub struct DeltaWriter<'a, W: Write> {
+    writer: &'a mut Writer<W>,
+    value_width: usize,
+    encoding: DeltaEncoding,
+    previous: Option<i64>,
+}
+
+impl<'a, W: Write> DeltaWriter<'a, W> {
+    pub fn new(
+        writer: &'a mut Writer<W>,
+        value_width: usize,
+        encoding: DeltaEncoding,
+    ) -> Result<DeltaWriter<'a, W>, Error> {
+        check_widths(value_width, encoding)?;
+        Ok(DeltaWriter {
+            writer,
+            value_width,
+            encoding,
+            previous: None,
+        })
+    }
+
+    fn write_absolute(&mut self, value: i64) -> Result<(), Error> {
+        Ok(self.writer.write_bits(
+            value as i128 as u128 & mask(self.value_width),
+            self.value_width,
+        )?)
+    }
+
+    pub fn write_value(&mut self, value: i64) -> Result<(), Error> {
+        if !fits(value as i128, self.value_width) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Value does not fit in the delta value width",
+            ));
+        }
+        let previous = match self.previous.replace(value) {
+            None => return self.write_absolute(value),
+            Some(previous) => previous,
+        };
+        let delta = value as i128 - previous as i128;
+        match self.encoding {
+            DeltaEncoding::Fixed(width) => {
+                let escape = -(1i128 << (width - 1));
+                if fits(delta, width) && delta != escape {
+                    Ok(self.writer.write_bits(delta as u128 & mask(width), width)?)
+                } else {
+                    self.writer
+                        .write_bits(escape as u128 & mask(width), width)?;
+                    self.write_absolute(value)
+                }
+            }
+            DeltaEncoding::Varint => {
+                let mut zigzag = ((delta << 1) ^ (delta >> 127)) as u128;
+                loop {
+                    let group = zigzag & 0x7F;
+                    zigzag >>= 7;
+                    self.writer.write_bit(zigzag != 0)?;
+                    self.writer.write_bits(group, 7)?;
+                    if zigzag == 0 {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn write_values(&mut self, values: &[i64]) -> Result<(), Error> {
+        for &value in values {
+            self.write_value(value)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DeltaReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    value_width: usize,
+    encoding: DeltaEncoding,
+    previous: Option<i64>,
+}
+
+impl<'a, R: Read> DeltaReader<'a, R> {
+    pub fn new(
+        reader: &'a mut Reader<R>,
+        value_width: usize,
+        encoding: DeltaEncoding,
+    ) -> Result<DeltaReader<'a, R>, Error> {
+        check_widths(value_width, encoding)?;
+        Ok(DeltaReader {
+            reader,
+            value_width,
+            encoding,
+            previous: None,
+        })
+    }
+
+    fn read_absolute(&mut self) -> Result<i64, Error> {
+        let raw = self.reader.read_bits(self.value_width)?;
+        Ok(to_signed(raw, self.value_width) as i64)
+    }
+
+    pub fn read_value(&mut self) -> Result<i64, Error> {
+        let previous = match self.previous {
+            None => {
+                let value = self.read_absolute()?;
+                self.previous = Some(value);
+                return Ok(value);
+            }
+            Some(previous) => previous,
+        };
+        let delta = match self.encoding {
+            DeltaEncoding::Fixed(width) => {
+                let delta = to_signed(self.reader.read_bits(width)?, width);
+                if delta == -(1i128 << (width - 1)) {
+                    let value = self.read_absolute()?;
+                    self.previous = Some(value);
+                    return Ok(value);
+                }
+                delta
+            }
+            DeltaEncoding::Varint => {
+                let mut zigzag: u128 = 0;
+                let mut shift = 0;
+                loop {
+                    let more = self.reader.read_bit()?;
+                    let group = self.reader.read_bits(7)?;
+                    if shift >= 72 {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Delta varint is too long",
+                        ));
+                    }
+                    zigzag |= group << shift;
+                    shift += 7;
+                    if !more {
+                        break;
+                    }
+                }
+                (zigzag >> 1) as i128 ^ -((zigzag & 1) as i128)
+            }
+        };
+        let value = previous as i128 + delta;
+        if !fits(value, self.value_width) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Delta takes the value out of its width",
+            ));
+        }
+        self.previous = Some(value as i64);
+        Ok(value as i64)
+    }
+
+    pub fn read_values(&mut self, count: usize) -> Result<Vec<i64>, Error> {
+        let mut values = Vec::with_capacity(count.min(u16::MAX as usize));
+        for _ in 0..count {
+            values.push(self.read_value()?);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn round_trip(values: &[i64], value_width: usize, encoding: DeltaEncoding) -> Vec<u8> {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        DeltaWriter::new(&mut writer, value_width, encoding)
+            .unwrap()
+            .write_values(values)
+            .unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.get_ref().get_ref().clone();
+
+        let mut reader = Reader::new(Cursor::new(encoded.clone()));
+        let mut delta_reader = DeltaReader::new(&mut reader, value_width, encoding).unwrap();
+        assert_eq!(delta_reader.read_values(values.len()).unwrap(), values);
+        encoded
+    }
+
+    #[test]
+    pub fn fixed_deltas() {
+        // 16 bit 1000, then +3 -> 011, -2 -> 110
+        let encoded = round_trip(&[1000, 1003, 1001], 16, DeltaEncoding::Fixed(3));
+        assert_eq!(encoded, [0b0000_0011, 0b1110_1000, 0b0111_1000]);
+    }
+
+    #[test]
+    pub fn fixed_escape() {
+        // A jump of 4 doesn't fit in 3 bits, so 100 is followed by the full value
+        let encoded = round_trip(&[0, 4, 3], 8, DeltaEncoding::Fixed(3));
+        assert_eq!(encoded, [0, 0b1000_0000, 0b1001_1100]);
+        round_trip(&[i64::MIN, i64::MAX, 0, -1], 64, DeltaEncoding::Fixed(8));
+    }
+
+    #[test]
+    pub fn varint_deltas() {
+        // +1 -> zigzag 2 -> 0 0000010, -100 -> zigzag 199 -> 1 1000111 0 0000001
+        let encoded = round_trip(&[-5, -4, -104], 8, DeltaEncoding::Varint);
+        assert_eq!(
+            encoded,
+            [0b1111_1011, 0b0000_0010, 0b1100_0111, 0b0000_0001]
+        );
+        round_trip(&[i64::MAX, i64::MIN, 7, 7, 7], 64, DeltaEncoding::Varint);
+    }
+
+    #[test]
+    pub fn rejects_bad_widths() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(DeltaWriter::new(&mut writer, 65, DeltaEncoding::Varint).is_err());
+        assert!(DeltaWriter::new(&mut writer, 8, DeltaEncoding::Fixed(1)).is_err());
+        let mut delta_writer = DeltaWriter::new(&mut writer, 4, DeltaEncoding::Fixed(2)).unwrap();
+        assert!(delta_writer.write_value(8).is_err());
+    }
+}
@@ -0,0 +1,946 @@
+// Checksums kept over everything going through a Writer or Reader. Each one is a BitWrite that
+// packs the bits it gets into bytes the same way a Writer with the same bit order would, so on the
+// writing side it goes next to the real writer in a TeeWriter and on the reading side it's the sink
+// of Reader::tee:
+//   let mut output = TeeWriter::new(Writer::new(file), Crc32::new());
+//   ...
+//   let crc = output.get_ref().1.value();
+// Padding has to go through the tee as well (pad_to_byte rather than the Writer's own flush) for
+// the checksum to cover it. Bits that don't make up a whole byte yet aren't in the value, except
+// for a Crc fed in its own bit order.
+use crate::bit_write::BitWrite;
+use crate::byte_collector::ByteCollector;
+use crate::crc::{adler32_update, crc32_update, running_sums};
+use crate::{BitOrder, Error};
+use std::hash::Hasher;
+
+// CRC-32 as used by Ethernet, zip and PNG (IEEE, reflected, 0xFFFFFFFF in and out)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crc32 {
+    crc: u32,
+    collector: ByteCollector,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32::with_bit_order(BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(bit_order: BitOrder) -> Crc32 {
+        Crc32 {
+            crc: 0,
+            collector: ByteCollector::new(bit_order),
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn update(&mut self, bytes: &[u8]) {
+        let crc = &mut self.crc;
+        self.collector
+            .push_bytes(bytes, |bytes| *crc = crc32_update(*crc, bytes));
+    }
+
+    // Over the whole bytes so far
+    pub fn value(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn reset(&mut self) {
+        *self = Crc32::with_bit_order(self.bit_order());
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl BitWrite for Crc32 {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let crc = &mut self.crc;
+        self.collector.push(bits, number_of_bits, |byte| {
+            *crc = crc32_update(*crc, &[byte]);
+        })
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.update(&bytes);
+        Ok(())
+    }
+}
+
+// A CRC in the Rocksoft model: width, polynomial (without the top bit), starting register, whether
+// bytes go in and the result comes out reflected, and what's xor-ed into the result. The presets
+// are from the CRC catalogue, named the way it names them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CrcParams {
+    pub width: usize,
+    pub poly: u64,
+    pub init: u64,
+    pub reflect_in: bool,
+    pub reflect_out: bool,
+    pub xorout: u64,
+}
+
+impl CrcParams {
+    pub const CRC_8_SMBUS: CrcParams = CrcParams::plain(8, 0x07, 0, 0);
+    // CAN's, over the frame's bits from start of frame up to the CRC field
+    pub const CRC_15_CAN: CrcParams = CrcParams::plain(15, 0x4599, 0, 0);
+    // Also known as CCITT-FALSE
+    pub const CRC_16_IBM_3740: CrcParams = CrcParams::plain(16, 0x1021, 0xFFFF, 0);
+    pub const CRC_16_ARC: CrcParams = CrcParams::reflected(16, 0x8005, 0, 0);
+    pub const CRC_16_KERMIT: CrcParams = CrcParams::reflected(16, 0x1021, 0, 0);
+    // HDLC's and AX.25's frame check sequence
+    pub const CRC_16_IBM_SDLC: CrcParams = CrcParams::reflected(16, 0x1021, 0xFFFF, 0xFFFF);
+    pub const CRC_24_OPENPGP: CrcParams = CrcParams::plain(24, 0x86_4CFB, 0xB7_04CE, 0);
+    // RTCM 3's, also known as CRC-24Q
+    pub const CRC_24_LTE_A: CrcParams = CrcParams::plain(24, 0x86_4CFB, 0, 0);
+    pub const CRC_32_ISO_HDLC: CrcParams =
+        CrcParams::reflected(32, 0x04C1_1DB7, 0xFFFF_FFFF, 0xFFFF_FFFF);
+    pub const CRC_32_ISCSI: CrcParams =
+        CrcParams::reflected(32, 0x1EDC_6F41, 0xFFFF_FFFF, 0xFFFF_FFFF);
+    pub const CRC_32_BZIP2: CrcParams = CrcParams::plain(32, 0x04C1_1DB7, 0xFFFF_FFFF, 0xFFFF_FFFF);
+    pub const CRC_64_ECMA_182: CrcParams = CrcParams::plain(64, 0x42F0_E1EB_A9EA_3693, 0, 0);
+    pub const CRC_64_XZ: CrcParams =
+        CrcParams::reflected(64, 0x42F0_E1EB_A9EA_3693, u64::MAX, u64::MAX);
+
+    const fn plain(width: usize, poly: u64, init: u64, xorout: u64) -> CrcParams {
+        CrcParams {
+            width,
+            poly,
+            init,
+            reflect_in: false,
+            reflect_out: false,
+            xorout,
+        }
+    }
+
+    const fn reflected(width: usize, poly: u64, init: u64, xorout: u64) -> CrcParams {
+        CrcParams {
+            width,
+            poly,
+            init,
+            reflect_in: true,
+            reflect_out: true,
+            xorout,
+        }
+    }
+
+    // The CRC of bytes in one go
+    pub fn checksum(&self, bytes: &[u8]) -> Result<u64, Error> {
+        let mut crc = Crc::new(*self)?;
+        crc.update(bytes);
+        Ok(crc.value())
+    }
+
+    // The order bits go through the register in: a byte's top bit first unless it's reflected
+    fn bit_order(&self) -> BitOrder {
+        if self.reflect_in {
+            BitOrder::LsbFirst
+        } else {
+            BitOrder::MsbFirst
+        }
+    }
+}
+
+// The low width bits of value, back to front
+fn reflect(value: u64, width: usize) -> u64 {
+    value.reverse_bits() >> (64 - width)
+}
+
+// A table driven CRC of any width from 1 to 64 bits. Written to in the CRC's own bit order (MSB
+// first, or LSB first if it's reflected), which is the default, every bit goes into the register
+// as soon as it's written, so the CRC can cover fields that don't end on a byte boundary, like
+// CAN's. In the other order bits are packed into bytes first and only whole bytes count, like
+// Crc32.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crc {
+    params: CrcParams,
+    table: [u64; 256],
+    // Unreflected CRCs keep the register in the top width bits, so bits always come in at bit 63
+    // and narrow ones need no special casing. Reflected ones keep it in the bottom width bits.
+    register: u64,
+    collector: ByteCollector,
+}
+
+impl Crc {
+    pub fn new(params: CrcParams) -> Result<Crc, Error> {
+        Crc::with_bit_order(params, params.bit_order())
+    }
+
+    pub fn with_bit_order(params: CrcParams, bit_order: BitOrder) -> Result<Crc, Error> {
+        if params.width == 0 || params.width > 64 {
+            return Err(Error::invalid_input(
+                0,
+                "CRC width must be between 1 and 64 bits",
+            ));
+        }
+        let mask = u64::MAX >> (64 - params.width);
+        if params.poly & !mask != 0 || params.init & !mask != 0 || params.xorout & !mask != 0 {
+            return Err(Error::invalid_input(
+                0,
+                "CRC polynomial, init and xorout must fit in its width",
+            ));
+        }
+        let mut crc = Crc {
+            params,
+            table: [0; 256],
+            register: 0,
+            collector: ByteCollector::new(bit_order),
+        };
+        for index in 0..256 {
+            crc.register = 0;
+            for shift in 0..8 {
+                match params.reflect_in {
+                    true => crc.update_bit(index >> shift & 1 == 1),
+                    false => crc.update_bit(index >> (7 - shift) & 1 == 1),
+                }
+            }
+            crc.table[index] = crc.register;
+        }
+        crc.reset();
+        Ok(crc)
+    }
+
+    pub fn params(&self) -> CrcParams {
+        self.params
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    // Back to the starting value, as if nothing had been written
+    pub fn reset(&mut self) {
+        let params = self.params;
+        self.register = if params.reflect_in {
+            reflect(params.init, params.width)
+        } else {
+            params.init << (64 - params.width)
+        };
+        self.collector = ByteCollector::new(self.bit_order());
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn update(&mut self, bytes: &[u8]) {
+        if self.bit_order() == self.params.bit_order() {
+            self.collector.bits += 8 * bytes.len() as u64;
+            for &byte in bytes {
+                self.update_byte(byte);
+            }
+            return;
+        }
+        let mut collector = self.collector.clone();
+        collector.push_bytes(bytes, |bytes| {
+            for &byte in bytes {
+                self.update_byte(byte);
+            }
+        });
+        self.collector = collector;
+    }
+
+    pub fn value(&self) -> u64 {
+        let params = self.params;
+        let mut value = if params.reflect_in {
+            self.register
+        } else {
+            self.register >> (64 - params.width)
+        };
+        if params.reflect_out != params.reflect_in {
+            value = reflect(value, params.width);
+        }
+        value ^ params.xorout
+    }
+
+    fn poly(&self) -> u64 {
+        if self.params.reflect_in {
+            reflect(self.params.poly, self.params.width)
+        } else {
+            self.params.poly << (64 - self.params.width)
+        }
+    }
+
+    fn update_byte(&mut self, byte: u8) {
+        self.register = if self.params.reflect_in {
+            (self.register >> 8) ^ self.table[(self.register as u8 ^ byte) as usize]
+        } else {
+            (self.register << 8) ^ self.table[((self.register >> 56) as u8 ^ byte) as usize]
+        };
+    }
+
+    fn update_bit(&mut self, bit: bool) {
+        let poly = self.poly();
+        self.register = if self.params.reflect_in {
+            let feedback = (self.register & 1 == 1) != bit;
+            (self.register >> 1) ^ if feedback { poly } else { 0 }
+        } else {
+            let feedback = (self.register >> 63 == 1) != bit;
+            (self.register << 1) ^ if feedback { poly } else { 0 }
+        };
+    }
+}
+
+impl BitWrite for Crc {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if self.bit_order() != self.params.bit_order() {
+            let mut collector = self.collector.clone();
+            collector.push(bits, number_of_bits, |byte| self.update_byte(byte))?;
+            self.collector = collector;
+            return Ok(());
+        }
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        // Whole bytes of the field through the table, then what's left a bit at a time, each in
+        // the order the bits are in the stream
+        let whole = number_of_bits / 8 * 8;
+        match self.bit_order() {
+            BitOrder::MsbFirst => {
+                for shift in (number_of_bits - whole..number_of_bits).rev().step_by(8) {
+                    self.update_byte((bits >> (shift - 7)) as u8);
+                }
+                for shift in (0..number_of_bits - whole).rev() {
+                    self.update_bit(bits >> shift & 1 == 1);
+                }
+            }
+            BitOrder::LsbFirst => {
+                for shift in (0..whole).step_by(8) {
+                    self.update_byte((bits >> shift) as u8);
+                }
+                for shift in whole..number_of_bits {
+                    self.update_bit(bits >> shift & 1 == 1);
+                }
+            }
+        }
+        self.collector.bits += number_of_bits as u64;
+        Ok(())
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.update(&bytes);
+        Ok(())
+    }
+}
+
+// Adler-32 as in zlib
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Adler32 {
+    value: u32,
+    collector: ByteCollector,
+}
+
+impl Adler32 {
+    pub fn new() -> Adler32 {
+        Adler32::with_bit_order(BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(bit_order: BitOrder) -> Adler32 {
+        Adler32 {
+            value: 1,
+            collector: ByteCollector::new(bit_order),
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn update(&mut self, bytes: &[u8]) {
+        let value = &mut self.value;
+        self.collector
+            .push_bytes(bytes, |bytes| *value = adler32_update(*value, bytes));
+    }
+
+    // Over the whole bytes so far
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn reset(&mut self) {
+        *self = Adler32::with_bit_order(self.bit_order());
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+impl BitWrite for Adler32 {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let value = &mut self.value;
+        self.collector.push(bits, number_of_bits, |byte| {
+            *value = adler32_update(*value, &[byte]);
+        })
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.update(&bytes);
+        Ok(())
+    }
+}
+
+// Fletcher-16, sums of bytes mod 255
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fletcher16 {
+    sums: (u32, u32),
+    collector: ByteCollector,
+}
+
+impl Fletcher16 {
+    pub fn new() -> Fletcher16 {
+        Fletcher16::with_bit_order(BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(bit_order: BitOrder) -> Fletcher16 {
+        Fletcher16 {
+            sums: (0, 0),
+            collector: ByteCollector::new(bit_order),
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn update(&mut self, bytes: &[u8]) {
+        let sums = &mut self.sums;
+        self.collector
+            .push_bytes(bytes, |bytes| running_sums(sums, bytes, 255, 5802));
+    }
+
+    // The second sum in the high byte. Over the whole bytes so far.
+    pub fn value(&self) -> u16 {
+        (self.sums.1 << 8 | self.sums.0) as u16
+    }
+
+    pub fn reset(&mut self) {
+        *self = Fletcher16::with_bit_order(self.bit_order());
+    }
+}
+
+impl Default for Fletcher16 {
+    fn default() -> Fletcher16 {
+        Fletcher16::new()
+    }
+}
+
+impl BitWrite for Fletcher16 {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let sums = &mut self.sums;
+        self.collector.push(bits, number_of_bits, |byte| {
+            running_sums(sums, &[byte], 255, 5802);
+        })
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.update(&bytes);
+        Ok(())
+    }
+}
+
+// Fletcher-32, sums of 16 bit words mod 65535. Words are little endian unless set otherwise, and a
+// byte left over at the end counts as a word with a zero byte after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fletcher32 {
+    sums: (u32, u32),
+    // First byte of a word that isn't finished yet
+    pending: Option<u8>,
+    big_endian: bool,
+    collector: ByteCollector,
+}
+
+impl Fletcher32 {
+    pub fn new() -> Fletcher32 {
+        Fletcher32::with_bit_order(BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(bit_order: BitOrder) -> Fletcher32 {
+        Fletcher32 {
+            sums: (0, 0),
+            pending: None,
+            big_endian: false,
+            collector: ByteCollector::new(bit_order),
+        }
+    }
+
+    pub fn with_big_endian(mut self, big_endian: bool) -> Fletcher32 {
+        self.big_endian = big_endian;
+        self
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    // Like write_bytes without needing a Vec
+    pub fn update(&mut self, bytes: &[u8]) {
+        let mut collector = self.collector.clone();
+        collector.push_bytes(bytes, |bytes| self.add_bytes(bytes));
+        self.collector = collector;
+    }
+
+    fn add_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let first = match self.pending.take() {
+                Some(first) => first,
+                None => {
+                    self.pending = Some(byte);
+                    continue;
+                }
+            };
+            let word = match self.big_endian {
+                true => u16::from_be_bytes([first, byte]),
+                false => u16::from_le_bytes([first, byte]),
+            };
+            self.sums.0 = (self.sums.0 + word as u32) % 65535;
+            self.sums.1 = (self.sums.1 + self.sums.0) % 65535;
+        }
+    }
+
+    // Over the whole bytes so far
+    pub fn value(&self) -> u32 {
+        let mut finished = self.clone();
+        if self.pending.is_some() {
+            finished.add_bytes(&[0]);
+        }
+        finished.sums.1 << 16 | finished.sums.0
+    }
+
+    pub fn reset(&mut self) {
+        *self = Fletcher32::with_bit_order(self.bit_order()).with_big_endian(self.big_endian);
+    }
+}
+
+impl Default for Fletcher32 {
+    fn default() -> Fletcher32 {
+        Fletcher32::new()
+    }
+}
+
+impl BitWrite for Fletcher32 {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let mut collector = self.collector.clone();
+        collector.push(bits, number_of_bits, |byte| self.add_bytes(&[byte]))?;
+        self.collector = collector;
+        Ok(())
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.update(&bytes);
+        Ok(())
+    }
+}
+
+// Anything that hashes bytes, for hooking up a hasher from another crate (or one of your own) with
+// ByteDigest. With the digest feature every RustCrypto hasher (sha2, blake2, ...) is one.
+pub trait DigestSink {
+    fn update(&mut self, bytes: &[u8]);
+}
+
+#[cfg(feature = "digest")]
+impl<D: ::digest::Update> DigestSink for D {
+    fn update(&mut self, bytes: &[u8]) {
+        ::digest::Update::update(self, bytes);
+    }
+}
+
+// A std Hasher as a DigestSink, for the ones like xxhash's that take a stream through write
+pub struct HasherSink<H: Hasher>(pub H);
+
+impl<H: Hasher> DigestSink for HasherSink<H> {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+}
+
+// Feeds a DigestSink the bytes a Writer with the same bit order would produce, as a BitWrite for
+// a TeeWriter or Reader::tee, so the hash is taken as the stream goes by rather than over a copy
+// of it afterwards. Bits that don't make up a whole byte yet aren't fed until it's finished.
+pub struct ByteDigest<D: DigestSink> {
+    sink: D,
+    collector: ByteCollector,
+}
+
+impl<D: DigestSink> ByteDigest<D> {
+    pub fn new(sink: D) -> ByteDigest<D> {
+        ByteDigest::with_bit_order(sink, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(sink: D, bit_order: BitOrder) -> ByteDigest<D> {
+        ByteDigest {
+            sink,
+            collector: ByteCollector::new(bit_order),
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.collector.is_aligned()
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.sink
+    }
+
+    // A partly written byte is dropped
+    pub fn into_inner(self) -> D {
+        self.sink
+    }
+}
+
+impl<D: DigestSink> BitWrite for ByteDigest<D> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let sink = &mut self.sink;
+        self.collector
+            .push(bits, number_of_bits, |byte| sink.update(&[byte]))
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.collector.bits()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.collector.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        let sink = &mut self.sink;
+        self.collector
+            .push_bytes(&bytes, |bytes| sink.update(bytes));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crc::crc32;
+    use crate::{Reader, TeeWriter, Writer};
+    use std::collections::hash_map::DefaultHasher;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn check_value() {
+        let mut digest = Crc32::new();
+        digest.update(b"1234");
+        digest.write_bytes(b"56789".to_vec()).unwrap();
+        assert_eq!(digest.value(), 0xCBF4_3926);
+        digest.reset();
+        assert_eq!(digest.value(), 0);
+        assert_eq!(digest.bits_written(), 0);
+    }
+
+    #[test]
+    pub fn follows_a_writer() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let writer = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut output = TeeWriter::new(writer, Crc32::with_bit_order(bit_order));
+            output.write_bits(0b101, 3).unwrap();
+            output.write_bytes(b"unaligned".to_vec()).unwrap();
+            output.write_bits(0x1_2345_6789, 37).unwrap();
+            // Part of a byte isn't counted yet
+            let before = output.get_ref().1.value();
+            output.write_bit(true).unwrap();
+            assert_eq!(output.get_ref().1.value(), before);
+            output.pad_to_byte().unwrap();
+            output.write_bytes(b"aligned".to_vec()).unwrap();
+
+            let (writer, digest) = output.into_inner();
+            assert_eq!(digest.bits_written(), writer.bits_written());
+            let bytes = writer.into_inner().unwrap();
+            assert_eq!(digest.value(), crc32(&bytes));
+        }
+    }
+
+    #[test]
+    pub fn checks_a_trailer() {
+        let mut output = TeeWriter::new(Writer::new(Vec::new()), Crc32::new());
+        output.write_bits(0xA, 4).unwrap();
+        output.write_bytes(b"payload".to_vec()).unwrap();
+        output.pad_to_byte().unwrap();
+        let (mut writer, digest) = output.into_inner();
+        writer.write_bits(digest.value() as u128, 32).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.tee(Crc32::new());
+        assert_eq!(input.read_bits(4).unwrap(), 0xA);
+        assert_eq!(input.read_bytes(7).unwrap(), b"payload");
+        input.align_to_byte(true).unwrap();
+        let digest = input.into_sink();
+        assert_eq!(reader.read_bits(32).unwrap(), digest.value() as u128);
+        assert!(reader.read_bit().is_err());
+    }
+
+    #[test]
+    pub fn catalogue_check_values() {
+        let presets = [
+            (CrcParams::CRC_8_SMBUS, 0xF4),
+            (CrcParams::CRC_15_CAN, 0x059E),
+            (CrcParams::CRC_16_IBM_3740, 0x29B1),
+            (CrcParams::CRC_16_ARC, 0xBB3D),
+            (CrcParams::CRC_16_KERMIT, 0x2189),
+            (CrcParams::CRC_16_IBM_SDLC, 0x906E),
+            (CrcParams::CRC_24_OPENPGP, 0x21_CF02),
+            (CrcParams::CRC_24_LTE_A, 0xCD_E703),
+            (CrcParams::CRC_32_ISO_HDLC, 0xCBF4_3926),
+            (CrcParams::CRC_32_ISCSI, 0xE306_9283),
+            (CrcParams::CRC_32_BZIP2, 0xFC89_1918),
+            (CrcParams::CRC_64_ECMA_182, 0x6C40_DF5F_0B49_7347),
+            (CrcParams::CRC_64_XZ, 0x995D_C9BB_DF19_39FA),
+            // Unreflected in, reflected out (CRC-12/UMTS)
+            (
+                CrcParams {
+                    width: 12,
+                    poly: 0x80F,
+                    init: 0,
+                    reflect_in: false,
+                    reflect_out: true,
+                    xorout: 0,
+                },
+                0xDAF,
+            ),
+        ];
+        for (params, check) in presets {
+            assert_eq!(params.checksum(b"123456789").unwrap(), check);
+            // The same a bit at a time, and through the other bit order
+            let mut crc = Crc::new(params).unwrap();
+            for &byte in b"123456789" {
+                for index in 0..8 {
+                    let bit = match params.reflect_in {
+                        true => byte >> index & 1,
+                        false => byte >> (7 - index) & 1,
+                    };
+                    crc.write_bit(bit == 1).unwrap();
+                }
+            }
+            assert_eq!(crc.value(), check);
+            let other = match params.reflect_in {
+                true => BitOrder::MsbFirst,
+                false => BitOrder::LsbFirst,
+            };
+            let mut crc = Crc::with_bit_order(params, other).unwrap();
+            crc.write_bits(0x31, 8).unwrap();
+            crc.write_bytes(b"23456789".to_vec()).unwrap();
+            assert_eq!(crc.value(), check);
+        }
+        assert!(Crc::new(CrcParams::plain(0, 0, 0, 0)).is_err());
+        assert!(Crc::new(CrcParams::plain(8, 0x107, 0, 0)).is_err());
+    }
+
+    #[test]
+    pub fn covers_unaligned_fields() {
+        // CAN's CRC over a 19 bit field, against the CRC done by hand one bit at a time
+        let field = 0b101_1100_1010_0011_0110u128;
+        let mut register = 0u16;
+        for shift in (0..19).rev() {
+            let feedback = (register >> 14 & 1) as u128 != field >> shift & 1;
+            register = (register << 1) & 0x7FFF;
+            if feedback {
+                register ^= 0x4599;
+            }
+        }
+        for width in [19, 1] {
+            let mut crc = Crc::new(CrcParams::CRC_15_CAN).unwrap();
+            for start in (0..19).step_by(width) {
+                let count = width.min(19 - start);
+                crc.write_bits(field >> (19 - start - count), count)
+                    .unwrap();
+            }
+            assert_eq!(crc.bits_written(), 19);
+            assert_eq!(crc.value(), register as u64);
+        }
+
+        // Following a writer in both bit orders, with fields of all widths
+        for params in [CrcParams::CRC_16_IBM_3740, CrcParams::CRC_32_ISO_HDLC] {
+            let bit_order = params.bit_order();
+            let writer = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut output = TeeWriter::new(writer, Crc::new(params).unwrap());
+            for index in 0..60u128 {
+                let width = (index % 30 + 1) as usize;
+                output.write_bits(index * 0x9E37_79B9, width).unwrap();
+            }
+            output.pad_to_byte().unwrap();
+            let (writer, crc) = output.into_inner();
+            let bytes = writer.into_inner().unwrap();
+            assert_eq!(crc.value(), params.checksum(&bytes).unwrap());
+        }
+    }
+
+    #[test]
+    pub fn sum_check_values() {
+        let mut adler = Adler32::new();
+        adler.update(b"Wiki");
+        adler.write_bytes(b"pedia".to_vec()).unwrap();
+        assert_eq!(adler.value(), 0x11E6_0398);
+        // Long enough to need reducing part way
+        let long = vec![0xFF; 20_000];
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &long {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        adler.reset();
+        adler.update(&long);
+        assert_eq!(adler.value(), b << 16 | a);
+
+        let mut fletcher = Fletcher16::new();
+        fletcher.update(b"abcde");
+        assert_eq!(fletcher.value(), 0xC8F0);
+        fletcher.update(b"f");
+        assert_eq!(fletcher.value(), 0x2057);
+
+        let mut fletcher = Fletcher32::new();
+        fletcher.update(b"abcde");
+        assert_eq!(fletcher.value(), 0xF04F_C729);
+        fletcher.update(b"f");
+        assert_eq!(fletcher.value(), 0x5650_2D2A);
+        fletcher.update(b"gh");
+        assert_eq!(fletcher.value(), 0xEBE1_9591);
+        let mut swapped = Fletcher32::new().with_big_endian(true);
+        swapped.update(b"badcfehg");
+        assert_eq!(swapped.value(), 0xEBE1_9591);
+    }
+
+    #[test]
+    pub fn sums_read_through() {
+        let payload: Vec<u8> = (0..300).map(|index| (index * 13) as u8).collect();
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bits(0b11, 2).unwrap();
+            writer.write_bytes(payload.clone()).unwrap();
+            writer.write_bits(0x2A, 6).unwrap();
+            let bytes = writer.into_inner().unwrap();
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes.clone()), bit_order);
+            let mut input = reader.tee(TeeWriter::new(
+                Adler32::with_bit_order(bit_order),
+                TeeWriter::new(
+                    Fletcher16::with_bit_order(bit_order),
+                    Fletcher32::with_bit_order(bit_order),
+                ),
+            ));
+            input.read_bits(2).unwrap();
+            assert_eq!(input.read_bytes(300).unwrap(), payload);
+            input.read_bits(6).unwrap();
+            let (adler, rest) = input.into_sink().into_inner();
+            let (fletcher16, fletcher32) = rest.into_inner();
+
+            let mut expected = (Adler32::new(), Fletcher16::new(), Fletcher32::new());
+            expected.0.update(&bytes);
+            expected.1.update(&bytes);
+            expected.2.update(&bytes);
+            assert_eq!(adler.value(), expected.0.value());
+            assert_eq!(fletcher16.value(), expected.1.value());
+            assert_eq!(fletcher32.value(), expected.2.value());
+        }
+    }
+
+    // Keeps everything it's given, to check it's exactly the stream
+    #[derive(Default)]
+    struct Recorder(Vec<u8>);
+
+    impl DigestSink for Recorder {
+        fn update(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+    }
+
+    #[test]
+    pub fn feeds_the_exact_stream() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let writer = Writer::with_bit_order(Vec::new(), bit_order);
+            let sink = ByteDigest::with_bit_order(Recorder::default(), bit_order);
+            let mut output = TeeWriter::new(writer, sink);
+            output.write_bits(0b1_0110, 5).unwrap();
+            output.write_bytes(b"some bytes".to_vec()).unwrap();
+            output.write_bits(0xABC, 12).unwrap();
+            assert!(!output.get_ref().1.is_aligned());
+            output.pad_to_byte().unwrap();
+            output.write_bytes(b"aligned".to_vec()).unwrap();
+            let (writer, sink) = output.into_inner();
+            let bytes = writer.into_inner().unwrap();
+            assert_eq!(sink.into_inner().0, bytes);
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes.clone()), bit_order);
+            let sink = ByteDigest::with_bit_order(HasherSink(DefaultHasher::new()), bit_order);
+            let mut input = reader.tee(sink);
+            input.skip_bits(8 * bytes.len() as u64).unwrap();
+            let mut expected = DefaultHasher::new();
+            expected.write(&bytes);
+            assert_eq!(input.into_sink().into_inner().0.finish(), expected.finish());
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    pub fn hashes_with_sha2() {
+        use sha2::{Digest, Sha256};
+
+        let mut output = TeeWriter::new(Writer::new(Vec::new()), ByteDigest::new(Sha256::new()));
+        output.write_bits(0b101, 3).unwrap();
+        output
+            .write_bytes(b"hashed as it's written".to_vec())
+            .unwrap();
+        output.pad_to_byte().unwrap();
+        let (writer, sink) = output.into_inner();
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(sink.into_inner().finalize(), Sha256::digest(&bytes));
+    }
+}
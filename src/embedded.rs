@@ -0,0 +1,165 @@
+// Reader and Writer over embedded-io's Read and Write, for UART, SPI and the like on targets
+// without std. A driver goes in an EmbeddedIo, which is all the Reader or Writer needs to know
+// about it. The Reader never asks the device for a byte before a call needs it, so a driver can be
+// taken back with into_inner part way through a stream and only the rest of the current byte has
+// to be handed over with it. The Writer holds whole bytes in a small buffer and only writes them
+// out when it fills up or on flush.
+use crate::byte_io::{ByteSink, ByteSource};
+use crate::{Error, LeftoverBits, Reader, Writer};
+use alloc::vec::Vec;
+use embedded_io::{Error as _, ErrorKind, Read, Write};
+
+// An embedded-io driver to read or write bits through
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedIo<T>(pub T);
+
+pub type EmbeddedBitReader<R> = Reader<EmbeddedIo<R>>;
+pub type EmbeddedBitWriter<W> = Writer<EmbeddedIo<W>>;
+
+impl<R: Read> ByteSource for EmbeddedIo<R> {
+    // A byte at a time, so nothing is read ahead
+    const BUFFER_SIZE: usize = 1;
+
+    fn pull(&mut self, buffer: &mut [u8], bit_position: u64) -> Result<usize, Error> {
+        loop {
+            match self.0.read(buffer) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::device(bit_position, e.kind())),
+            }
+        }
+    }
+}
+
+impl<W: Write> ByteSink for EmbeddedIo<W> {
+    const BUFFER_SIZE: usize = 32;
+
+    fn push(&mut self, bytes: &[u8], bit_position: u64) -> Result<(), Error> {
+        self.0
+            .write_all(bytes)
+            .map_err(|e| Error::device(bit_position, e.kind()))
+    }
+
+    fn flush_out(&mut self, bit_position: u64) -> Result<(), Error> {
+        self.0
+            .flush()
+            .map_err(|e| Error::device(bit_position, e.kind()))
+    }
+}
+
+impl<R: Read> Reader<EmbeddedIo<R>> {
+    // Gives back the rest of the current byte, any bytes a peek took from the device that haven't
+    // been read yet, and the device
+    pub fn into_inner(self) -> (LeftoverBits, Vec<u8>, R) {
+        let (leftover, buffered, inner) = self.into_parts();
+        (leftover, buffered, inner.0)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+
+    // A device that hands over one byte per read, like a UART
+    struct Trickle<'a>(&'a [u8]);
+
+    impl embedded_io::ErrorType for Trickle<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ErrorKind> {
+            match self.0.split_first() {
+                Some((&byte, rest)) if !buffer.is_empty() => {
+                    buffer[0] = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn value(index: u128) -> (u128, usize) {
+        let width = (index % 100 + 1) as usize;
+        let value = index.wrapping_mul(0x9E37_79B9_7F4A_7C15) & (u128::MAX >> (128 - width));
+        (value, width)
+    }
+
+    fn read_back<R: Read>(reader: &mut EmbeddedBitReader<R>, length: usize) {
+        for index in 0..300 {
+            let (value, width) = value(index);
+            assert_eq!(reader.read_bits(width).unwrap(), value);
+        }
+        assert_eq!(reader.read_bytes(3).unwrap(), [1, 2, 3]);
+        reader.align_to_byte(true).unwrap();
+        assert_eq!(reader.bits_read(), length as u64 * 8);
+    }
+
+    #[test]
+    pub fn matches_sync_types() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut output = [0u8; 4096];
+            let mut writer =
+                EmbeddedBitWriter::with_bit_order(EmbeddedIo(&mut output[..]), bit_order);
+            for index in 0..300 {
+                let (value, width) = value(index);
+                expected.write_bits(value, width).unwrap();
+                writer.write_bits(value, width).unwrap();
+            }
+            writer.write_bytes(vec![1, 2, 3]).unwrap();
+            expected.write_bytes(vec![1, 2, 3]).unwrap();
+            assert_eq!(writer.bits_written(), expected.bits_written());
+            writer.flush().unwrap();
+            let expected = expected.into_inner().unwrap();
+            assert_eq!(output[..expected.len()], expected[..]);
+
+            read_back(
+                &mut EmbeddedBitReader::with_bit_order(EmbeddedIo(&expected[..]), bit_order),
+                expected.len(),
+            );
+            read_back(
+                &mut EmbeddedBitReader::with_bit_order(EmbeddedIo(Trickle(&expected)), bit_order),
+                expected.len(),
+            );
+        }
+    }
+
+    #[test]
+    pub fn leaves_the_rest_to_the_device() {
+        let bytes = [0xA5, 0x0F, 0xFF, 0x42];
+        let mut reader = EmbeddedBitReader::new(EmbeddedIo(&bytes[..]));
+        assert_eq!(reader.read_bits(12).unwrap(), 0xA50);
+        assert_eq!(reader.pending_bits(), 4);
+        assert_eq!(reader.get_ref().0, [0xFF, 0x42]);
+        reader.align_to_byte(false).unwrap();
+        assert_eq!(reader.read_byte().unwrap(), 0xFF);
+        let error = reader.read_bits(12).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnexpectedEof {
+                bit_position: 32,
+                bits_missing: 4
+            }
+        ));
+
+        let (leftover, buffered, rest) = reader.into_inner();
+        assert_eq!((leftover.count, buffered.len(), rest), (0, 0, &[][..]));
+
+        // A device that fails once the buffer goes out to it
+        let mut output = [0u8; 32];
+        let mut writer = EmbeddedBitWriter::new(EmbeddedIo(&mut output[..]));
+        writer.write_bytes(vec![0; 32]).unwrap();
+        writer.write_bits(1, 3).unwrap();
+        let error = writer.flush().unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Device {
+                kind: ErrorKind::WriteZero,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+// What the core readers and writers fail with. Every variant says how far into the stream (in
+// bits, counted like Reader::bits_read or Writer::bits_written) it went wrong, which is the part a
+// bare io::Error can't carry. Converts into an io::Error of the matching kind, with this as its
+// inner error, so the rest of the crate and anything built on Read and Write can use it with `?`.
+// Without std there's no inner reader or writer to fail, so no Io variant either.
+use alloc::string::{String, ToString};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, ErrorKind};
+
+#[derive(Debug)]
+pub enum Error {
+    // The inner reader or writer failed
+    #[cfg(feature = "std")]
+    Io {
+        bit_position: u64,
+        source: io::Error,
+    },
+    // An embedded-io reader or writer failed. Their errors are only required to give a kind.
+    #[cfg(feature = "embedded-io")]
+    Device {
+        bit_position: u64,
+        kind: embedded_io::ErrorKind,
+    },
+    // The stream ended bits_missing bits short of what was asked for
+    UnexpectedEof {
+        bit_position: u64,
+        bits_missing: u64,
+    },
+    // More bits at once than the call can handle, like a 129 bit read_bits or a value that
+    // doesn't fit the width it's being written at
+    ValueTooWide {
+        bit_position: u64,
+        number_of_bits: usize,
+        max_bits: usize,
+    },
+    // The stream holds something it shouldn't, like padding that isn't or trailing data
+    InvalidData {
+        bit_position: u64,
+        message: String,
+    },
+    // The call itself was bad, like a seek before the start
+    InvalidInput {
+        bit_position: u64,
+        message: String,
+    },
+}
+
+impl Error {
+    #[cfg(feature = "std")]
+    pub(crate) fn io(bit_position: u64, source: io::Error) -> Error {
+        Error::Io {
+            bit_position,
+            source,
+        }
+    }
+
+    // For map_err on calls to the inner reader or writer
+    #[cfg(feature = "std")]
+    pub(crate) fn io_at(bit_position: u64) -> impl FnOnce(io::Error) -> Error {
+        move |source| Error::io(bit_position, source)
+    }
+
+    #[cfg(feature = "embedded-io")]
+    pub(crate) fn device(bit_position: u64, kind: embedded_io::ErrorKind) -> Error {
+        Error::Device { bit_position, kind }
+    }
+
+    pub(crate) fn eof(bit_position: u64, bits_missing: u64) -> Error {
+        Error::UnexpectedEof {
+            bit_position,
+            bits_missing,
+        }
+    }
+
+    pub(crate) fn too_wide(bit_position: u64, number_of_bits: usize, max_bits: usize) -> Error {
+        Error::ValueTooWide {
+            bit_position,
+            number_of_bits,
+            max_bits,
+        }
+    }
+
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn invalid_data(bit_position: u64, message: &str) -> Error {
+        Error::InvalidData {
+            bit_position,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn invalid_input(bit_position: u64, message: &str) -> Error {
+        Error::InvalidInput {
+            bit_position,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn bit_position(&self) -> u64 {
+        match *self {
+            #[cfg(feature = "std")]
+            Error::Io { bit_position, .. } => bit_position,
+            #[cfg(feature = "embedded-io")]
+            Error::Device { bit_position, .. } => bit_position,
+            Error::UnexpectedEof { bit_position, .. }
+            | Error::ValueTooWide { bit_position, .. }
+            | Error::InvalidData { bit_position, .. }
+            | Error::InvalidInput { bit_position, .. } => bit_position,
+        }
+    }
+
+    // The io::ErrorKind this turns into, so code written against io::Error keeps working
+    #[cfg(feature = "std")]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io { source, .. } => source.kind(),
+            #[cfg(feature = "embedded-io")]
+            Error::Device { kind, .. } => (*kind).into(),
+            Error::UnexpectedEof { .. } => ErrorKind::UnexpectedEof,
+            Error::ValueTooWide { .. } | Error::InvalidData { .. } => ErrorKind::InvalidData,
+            Error::InvalidInput { .. } => ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io {
+                bit_position,
+                source,
+            } => write!(f, "{} at bit {}", source, bit_position),
+            #[cfg(feature = "embedded-io")]
+            Error::Device { bit_position, kind } => {
+                write!(f, "Device error {:?} at bit {}", kind, bit_position)
+            }
+            Error::UnexpectedEof {
+                bit_position,
+                bits_missing,
+            } => write!(
+                f,
+                "Unexpected EOF at bit {}, {} bits short",
+                bit_position, bits_missing
+            ),
+            Error::ValueTooWide {
+                bit_position,
+                number_of_bits,
+                max_bits,
+            } => write!(
+                f,
+                "{} bits is more than the {} allowed at bit {}",
+                number_of_bits, max_bits, bit_position
+            ),
+            Error::InvalidData {
+                bit_position,
+                message,
+            }
+            | Error::InvalidInput {
+                bit_position,
+                message,
+            } => write!(f, "{} at bit {}", message, bit_position),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        io::Error::new(error.kind(), error)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::Reader;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn carries_bit_position() {
+        let mut reader = Reader::new(Cursor::new(vec![0xAB, 0xCD]));
+        reader.read_bits(12).unwrap();
+        let error = reader.read_bits(10).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnexpectedEof {
+                bit_position: 16,
+                bits_missing: 6
+            }
+        ));
+        assert_eq!(error.to_string(), "Unexpected EOF at bit 16, 6 bits short");
+
+        // Through io::Error and back
+        let error = io::Error::from(error);
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let inner = error.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(inner.bit_position(), 16);
+    }
+}
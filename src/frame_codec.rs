@@ -0,0 +1,390 @@
+use crate::{Reader, Writer, PREALLOCATE_LIMIT};
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDefinition {
+    pub name: String,
+    pub width: usize,
+    pub signed: bool,
+    // Set when the field is written as a difference from the previous record in a batch
+    pub delta_width: Option<usize>,
+}
+
+impl FieldDefinition {
+    fn min(&self) -> i128 {
+        if self.signed {
+            -(1i128 << (self.width - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed {
+            (1i128 << (self.width - 1)) - 1
+        } else {
+            (1i128 << self.width) - 1
+        }
+    }
+
+    fn mask(&self) -> u128 {
+        u128::MAX >> (128 - self.width)
+    }
+
+    fn encode_value(&self, value: i64) -> Result<u128, Error> {
+        if (value as i128) < self.min() || (value as i128) > self.max() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Value {} does not fit in field {}", value, self.name),
+            ));
+        }
+        Ok(value as i128 as u128 & self.mask())
+    }
+
+    pub(crate) fn decode_value(&self, raw: u128) -> i64 {
+        if self.signed {
+            // Sign extend from the field width
+            let shift = 128 - self.width;
+            (((raw << shift) as i128) >> shift) as i64
+        } else {
+            raw as i64
+        }
+    }
+
+    // Deltas are two's complement, with the most negative value escaping to the absolute value
+    fn encode_delta<W: Write>(
+        &self,
+        previous: i64,
+        value: i64,
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        let raw = self.encode_value(value)?;
+        let delta_width = match self.delta_width {
+            None => return Ok(writer.write_bits(raw, self.width)?),
+            Some(delta_width) => delta_width,
+        };
+        let delta = value as i128 - previous as i128;
+        let limit = 1i128 << (delta_width - 1);
+        let mask = u128::MAX >> (128 - delta_width);
+        if delta > -limit && delta < limit {
+            Ok(writer.write_bits(delta as u128 & mask, delta_width)?)
+        } else {
+            writer.write_bits(-limit as u128 & mask, delta_width)?;
+            Ok(writer.write_bits(raw, self.width)?)
+        }
+    }
+
+    fn decode_delta<R: Read>(&self, previous: i64, reader: &mut Reader<R>) -> Result<i64, Error> {
+        let delta_width = match self.delta_width {
+            None => return Ok(self.decode_value(reader.read_bits(self.width)?)),
+            Some(delta_width) => delta_width,
+        };
+        let shift = 128 - delta_width;
+        let delta = ((reader.read_bits(delta_width)? << shift) as i128) >> shift;
+        if delta == -(1i128 << (delta_width - 1)) {
+            return Ok(self.decode_value(reader.read_bits(self.width)?));
+        }
+        let value = previous as i128 + delta;
+        if value < self.min() || value > self.max() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Delta takes field {} out of range", self.name),
+            ));
+        }
+        Ok(value as i64)
+    }
+}
+
+// Records are a value per field, in the order the fields were added. Batches are a 32 bit record
+// count followed by the records packed back to back.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameCodec {
+    fields: Vec<FieldDefinition>,
+    record_width: usize,
+}
+
+impl FrameCodec {
+    pub fn new() -> FrameCodec {
+        FrameCodec::default()
+    }
+
+    // Unsigned fields go up to 63 bits so every value fits in an i64
+    pub fn add_field(&mut self, name: &str, width: usize, signed: bool) -> Result<(), Error> {
+        let max_width = if signed { 64 } else { 63 };
+        if width == 0 || width > max_width {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Field width must be between 1 and 64 bits (63 when unsigned)",
+            ));
+        }
+        if self.field_index(name).is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Field {} is already defined", name),
+            ));
+        }
+        self.fields.push(FieldDefinition {
+            name: name.to_string(),
+            width,
+            signed,
+            delta_width: None,
+        });
+        self.record_width += width;
+        Ok(())
+    }
+
+    // Writes the field as a delta_width bit difference from the previous record of a batch
+    // (the first record is always absolute). Slowly changing fields only need a few bits.
+    pub fn set_delta_width(&mut self, name: &str, delta_width: usize) -> Result<(), Error> {
+        let index = self.field_index(name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Field {} is not defined", name),
+            )
+        })?;
+        if !(2..=64).contains(&delta_width) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Delta width must be between 2 and 64 bits",
+            ));
+        }
+        self.fields[index].delta_width = Some(delta_width);
+        Ok(())
+    }
+
+    pub fn fields(&self) -> &[FieldDefinition] {
+        &self.fields
+    }
+
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field.name == name)
+    }
+
+    // Bits taken by one record
+    pub fn record_width(&self) -> usize {
+        self.record_width
+    }
+
+    pub fn encode_record<W: Write>(
+        &self,
+        record: &[i64],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        if record.len() != self.fields.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Record doesn't have one value per field",
+            ));
+        }
+        for (field, &value) in self.fields.iter().zip(record) {
+            writer.write_bits(field.encode_value(value)?, field.width)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode_record<R: Read>(&self, reader: &mut Reader<R>) -> Result<Vec<i64>, Error> {
+        let mut record = Vec::with_capacity(self.fields.len());
+        self.decode_record_into(reader, &mut record)?;
+        Ok(record)
+    }
+
+    fn decode_record_into<R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        values: &mut Vec<i64>,
+    ) -> Result<(), Error> {
+        for field in &self.fields {
+            values.push(field.decode_value(reader.read_bits(field.width)?));
+        }
+        Ok(())
+    }
+
+    fn encode_record_against<W: Write>(
+        &self,
+        previous: &[i64],
+        record: &[i64],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        for ((field, &previous), &value) in self.fields.iter().zip(previous).zip(record) {
+            field.encode_delta(previous, value, writer)?;
+        }
+        Ok(())
+    }
+
+    // records holds the values of every record one after the other
+    pub fn encode_batch<W: Write>(
+        &self,
+        records: &[i64],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        if self.fields.is_empty() || !records.len().is_multiple_of(self.fields.len()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Batch isn't a whole number of records",
+            ));
+        }
+        let count = records.len() / self.fields.len();
+        if count > u32::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Too many records for one batch",
+            ));
+        }
+        // Every value is checked before the count goes out, so one that doesn't fit can't leave a
+        // count with part of a batch after it
+        for (field, &value) in self.fields.iter().cycle().zip(records) {
+            field.encode_value(value)?;
+        }
+        writer.write_bits(count as u128, 32)?;
+        let mut previous: Option<&[i64]> = None;
+        for record in records.chunks(self.fields.len()) {
+            match previous {
+                None => self.encode_record(record, writer)?,
+                Some(previous) => self.encode_record_against(previous, record, writer)?,
+            }
+            previous = Some(record);
+        }
+        Ok(())
+    }
+
+    // Gives back the values of every record one after the other
+    pub fn decode_batch<R: Read>(&self, reader: &mut Reader<R>) -> Result<Vec<i64>, Error> {
+        let count = reader.read_usize(32)?;
+        let mut records = Vec::with_capacity(
+            count
+                .saturating_mul(self.fields.len())
+                .min(PREALLOCATE_LIMIT),
+        );
+        self.decode_records(reader, count, |value| records.push(value))?;
+        Ok(records)
+    }
+
+    // Decodes the records of a batch after its count, handing each value over as it comes
+    pub(crate) fn decode_records<R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        count: usize,
+        mut push: impl FnMut(i64),
+    ) -> Result<(), Error> {
+        // Records that take no bits would let a corrupt count spin for billions of them
+        if self.fields.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Codec has no fields to decode",
+            ));
+        }
+        let mut previous = Vec::with_capacity(self.fields.len());
+        for index in 0..count {
+            for (field_index, field) in self.fields.iter().enumerate() {
+                if index == 0 {
+                    let value = field.decode_value(reader.read_bits(field.width)?);
+                    previous.push(value);
+                    push(value);
+                } else {
+                    let value = field.decode_delta(previous[field_index], reader)?;
+                    previous[field_index] = value;
+                    push(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sensor_codec() -> FrameCodec {
+        let mut codec = FrameCodec::new();
+        codec.add_field("id", 4, false).unwrap();
+        codec.add_field("temperature", 8, true).unwrap();
+        codec.add_field("humidity", 7, false).unwrap();
+        codec
+    }
+
+    #[test]
+    pub fn field_registration() {
+        let mut codec = sensor_codec();
+        assert_eq!(codec.record_width(), 19);
+        assert_eq!(codec.field_index("humidity"), Some(2));
+        assert!(codec.add_field("id", 3, false).is_err());
+        assert!(codec.add_field("wide", 64, false).is_err());
+        assert!(codec.add_field("wide", 64, true).is_ok());
+    }
+
+    #[test]
+    pub fn record_layout() {
+        let codec = sensor_codec();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        codec.encode_record(&[3, -2, 100], &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        // 0011 1111_1110 1100100
+        assert_eq!(
+            *writer.get_ref().get_ref(),
+            [0b0011_1111, 0b1110_1100, 0b1000_0000]
+        );
+    }
+
+    #[test]
+    pub fn batch_round_trip() {
+        let codec = sensor_codec();
+        let records = vec![1, 21, 40, 2, -40, 99, 15, 127, 0];
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        codec.encode_batch(&records, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        // 32 bit count and 3 records of 19 bits
+        assert_eq!(encoded.len(), (32 + 3 * 19usize).div_ceil(8));
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(codec.decode_batch(&mut reader).unwrap(), records);
+    }
+
+    #[test]
+    pub fn differential_batch() {
+        let mut codec = sensor_codec();
+        codec.set_delta_width("temperature", 3).unwrap();
+        codec.set_delta_width("humidity", 2).unwrap();
+        assert!(codec.set_delta_width("pressure", 2).is_err());
+        assert!(codec.set_delta_width("id", 1).is_err());
+
+        let records = vec![5, 20, 50, 5, 21, 50, 5, 23, 49, 5, -10, 49];
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        codec.encode_batch(&records, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        // The first record is absolute, then deltas of 4 + 3 + 2 bits, and the last temperature
+        // escapes to 3 + 8 bits
+        assert_eq!(encoded.len(), (32 + 19 + 9 + 9 + 9 + 8usize).div_ceil(8));
+        assert_eq!(encoded[6..8], [0b0100_1010, 0b0100_0101]);
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(codec.decode_batch(&mut reader).unwrap(), records);
+    }
+
+    #[test]
+    pub fn rejects_bad_records() {
+        let codec = sensor_codec();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(codec.encode_record(&[16, 0, 0], &mut writer).is_err());
+        assert!(codec.encode_record(&[0, -129, 0], &mut writer).is_err());
+        assert!(codec.encode_record(&[0, 0], &mut writer).is_err());
+        assert!(codec.encode_batch(&[0, 0, 0, 0], &mut writer).is_err());
+
+        // Nothing at all is written for a batch with a bad value in it
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(codec
+            .encode_batch(&[0, 0, 0, 1, 2, 200], &mut writer)
+            .is_err());
+        assert_eq!(writer.bits_written(), 0);
+
+        // A count that would otherwise loop over empty records
+        let mut reader = Reader::new(Cursor::new(vec![0xFF; 4]));
+        assert!(FrameCodec::new().decode_batch(&mut reader).is_err());
+    }
+}
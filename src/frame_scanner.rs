@@ -0,0 +1,450 @@
+use crate::crc::crc32;
+use crate::watchdog::Watchdog;
+use crate::{BitOrder, Reader, Writer, PREALLOCATE_LIMIT};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameLength {
+    // Every payload is this many bytes
+    Fixed(usize),
+    // The payload is led by a length field of this many bits (1 to 32) giving its size in bytes
+    Prefixed(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    None,
+    // CRC-32 of the payload in the 32 bits after it
+    Crc32,
+}
+
+// Where the bit that's index bits into a value of width bits is, in stream order
+fn shift(width: usize, index: usize, bit_order: BitOrder) -> usize {
+    match bit_order {
+        BitOrder::MsbFirst => width - 1 - index,
+        BitOrder::LsbFirst => index,
+    }
+}
+
+pub type BadFrameCallback = Box<dyn FnMut(&[u8]) -> bool>;
+
+// What to do with a frame whose checksum doesn't match
+pub enum BadFramePolicy {
+    // Go back to searching from the bit after the bad frame's sync word
+    Skip,
+    // Give back an InvalidData error
+    Stop,
+    // Gets the bad frame's payload, and keeps scanning (like Skip) when it returns true
+    Callback(BadFrameCallback),
+}
+
+enum Body {
+    // The payload and whether it passed its checksum
+    Frame(Vec<u8>, bool),
+    // Bigger than the lookahead allows
+    TooLong,
+}
+
+// Finds frames in a bit stream that can start at any bit: a sync word, then the payload, then an
+// optional checksum. Bits taken by a frame that fails its checksum are scanned again, so a false
+// sync match can't swallow a real frame behind it.
+pub struct FrameScanner {
+    sync: u128,
+    sync_width: usize,
+    length: FrameLength,
+    checksum: Checksum,
+    policy: BadFramePolicy,
+    // Bits to scan before reading more from the reader
+    replay: VecDeque<bool>,
+    // Bits taken since the current frame's sync word
+    taken: Vec<bool>,
+    watchdog: Option<Watchdog>,
+    max_lookahead: Option<usize>,
+}
+
+impl FrameScanner {
+    pub fn new(sync: u128, sync_width: usize, length: FrameLength) -> Result<FrameScanner, Error> {
+        if sync_width == 0 || sync_width > 128 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Sync word must be between 1 and 128 bits",
+            ));
+        }
+        if sync_width < 128 && sync >> sync_width != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Sync word doesn't fit in its width",
+            ));
+        }
+        if let FrameLength::Prefixed(width) = length {
+            if width == 0 || width > 32 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Length field must be between 1 and 32 bits",
+                ));
+            }
+        }
+        Ok(FrameScanner {
+            sync,
+            sync_width,
+            length,
+            checksum: Checksum::None,
+            policy: BadFramePolicy::Skip,
+            replay: VecDeque::new(),
+            taken: Vec::new(),
+            watchdog: None,
+            max_lookahead: None,
+        })
+    }
+
+    pub fn with_checksum(mut self, checksum: Checksum) -> FrameScanner {
+        self.checksum = checksum;
+        self
+    }
+
+    // Errors with TimedOut once this many bits are scanned (rescans included) without a good frame
+    pub fn with_watchdog(mut self, max_bits: u64) -> FrameScanner {
+        self.watchdog = Some(Watchdog::new(max_bits));
+        self
+    }
+
+    // Holds at most this many bits past a sync word (plus the sync word) for rescanning, so memory
+    // stays bounded on live streams. Frames longer than that are passed over like a false match.
+    pub fn with_max_lookahead(mut self, max_bits: usize) -> FrameScanner {
+        self.max_lookahead = Some(max_bits);
+        self
+    }
+
+    // Bits held for rescanning right now
+    pub fn buffered_bits(&self) -> usize {
+        self.replay.len() + self.taken.len()
+    }
+
+    pub fn on_bad_frame(mut self, policy: BadFramePolicy) -> FrameScanner {
+        self.policy = policy;
+        self
+    }
+
+    // Writes the sync word, length and checksum around the payload
+    pub fn write_frame<W: Write>(
+        &self,
+        payload: &[u8],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        writer.write_bits(self.sync, self.sync_width)?;
+        match self.length {
+            FrameLength::Fixed(length) if length != payload.len() => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Payload isn't the fixed frame length",
+                ));
+            }
+            FrameLength::Fixed(_) => {}
+            FrameLength::Prefixed(width) => {
+                if (payload.len() as u64) >> width != 0 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Payload is too long for the length field",
+                    ));
+                }
+                writer.write_bits(payload.len() as u128, width)?;
+            }
+        }
+        for &byte in payload {
+            writer.write_byte(byte)?;
+        }
+        if self.checksum == Checksum::Crc32 {
+            writer.write_bits(crc32(payload) as u128, 32)?;
+        }
+        Ok(())
+    }
+
+    // None once the stream runs out, including part way through a frame
+    fn next_bit<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<Option<bool>, Error> {
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.consume(1)?;
+        }
+        if let Some(bit) = self.replay.pop_front() {
+            return Ok(Some(bit));
+        }
+        Ok(reader.read_bit_opt()?)
+    }
+
+    fn take_bits<R: Read>(
+        &mut self,
+        reader: &mut Reader<R>,
+        number_of_bits: usize,
+    ) -> Result<Option<u64>, Error> {
+        let bit_order = reader.bit_order();
+        let mut value = 0;
+        for index in 0..number_of_bits {
+            let bit = match self.next_bit(reader)? {
+                Some(bit) => bit,
+                None => return Ok(None),
+            };
+            self.taken.push(bit);
+            value |= (bit as u64) << shift(number_of_bits, index, bit_order);
+        }
+        Ok(Some(value))
+    }
+
+    fn find_sync<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<bool, Error> {
+        let mask = u128::MAX >> (128 - self.sync_width);
+        let bit_order = reader.bit_order();
+        let mut window: u128 = 0;
+        let mut filled = 0;
+        while filled < self.sync_width || window != self.sync {
+            let bit = match self.next_bit(reader)? {
+                Some(bit) => bit as u128,
+                None => return Ok(false),
+            };
+            // The newest bit goes in at the end the sync word's last bit is sent from
+            window = match bit_order {
+                BitOrder::MsbFirst => ((window << 1) | bit) & mask,
+                BitOrder::LsbFirst => (window >> 1) | bit << (self.sync_width - 1),
+            };
+            filled += 1;
+        }
+        Ok(true)
+    }
+
+    // Reads the payload and checksum after a sync word, None if the stream ends first
+    fn read_body<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<Option<Body>, Error> {
+        let length = match self.length {
+            FrameLength::Fixed(length) => length,
+            FrameLength::Prefixed(width) => match self.take_bits(reader, width)? {
+                Some(length) => usize::try_from(length).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        "Frame length doesn't fit in a usize on this target",
+                    )
+                })?,
+                None => return Ok(None),
+            },
+        };
+        if let Some(max_lookahead) = self.max_lookahead {
+            let checksum_width = match self.checksum {
+                Checksum::None => 0,
+                Checksum::Crc32 => 32,
+            };
+            let body_width = length
+                .saturating_mul(8)
+                .saturating_add(self.taken.len() + checksum_width);
+            if body_width > max_lookahead {
+                return Ok(Some(Body::TooLong));
+            }
+        }
+        let mut payload = Vec::with_capacity(length.min(PREALLOCATE_LIMIT));
+        for _ in 0..length {
+            match self.take_bits(reader, 8)? {
+                Some(byte) => payload.push(byte as u8),
+                None => return Ok(None),
+            }
+        }
+        let valid = match self.checksum {
+            Checksum::None => true,
+            Checksum::Crc32 => match self.take_bits(reader, 32)? {
+                Some(crc) => crc as u32 == crc32(&payload),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Body::Frame(payload, valid)))
+    }
+
+    // The next payload with a good checksum, or None at the end of the stream
+    pub fn next_frame<R: Read>(
+        &mut self,
+        reader: &mut Reader<R>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            if !self.find_sync(reader)? {
+                return Ok(None);
+            }
+            self.taken.clear();
+            match self.read_body(reader)? {
+                None => return Ok(None),
+                Some(Body::Frame(payload, true)) => {
+                    if let Some(watchdog) = &mut self.watchdog {
+                        watchdog.progress();
+                    }
+                    return Ok(Some(payload));
+                }
+                Some(Body::Frame(payload, false)) => {
+                    let keep_going = match &mut self.policy {
+                        BadFramePolicy::Skip => true,
+                        BadFramePolicy::Stop => false,
+                        BadFramePolicy::Callback(callback) => callback(&payload),
+                    };
+                    if !keep_going {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Frame failed its checksum",
+                        ));
+                    }
+                }
+                // Can't be held onto for a rescan, so it's taken as a false sync match
+                Some(Body::TooLong) => {}
+            }
+            // Search again from one bit into the sync word
+            let bit_order = reader.bit_order();
+            let mut rescan: VecDeque<bool> = (1..self.sync_width)
+                .map(|index| (self.sync >> shift(self.sync_width, index, bit_order)) & 1 != 0)
+                .collect();
+            rescan.extend(self.taken.drain(..));
+            rescan.append(&mut self.replay);
+            self.replay = rescan;
+        }
+    }
+
+    // Every good frame up to the end of the stream
+    pub fn scan<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<Vec<Vec<u8>>, Error> {
+        let mut frames = Vec::new();
+        while let Some(frame) = self.next_frame(reader)? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    fn scanner() -> FrameScanner {
+        FrameScanner::new(0xEB90, 16, FrameLength::Prefixed(8))
+            .unwrap()
+            .with_checksum(Checksum::Crc32)
+    }
+
+    // Noise, a good frame, a frame with a flipped payload bit, then another good frame, none of
+    // them byte aligned
+    fn noisy_capture() -> Vec<u8> {
+        let scanner = scanner();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.write_bits(0b101, 3).unwrap();
+        scanner.write_frame(b"first", &mut writer).unwrap();
+        writer.write_bits(0x3A, 7).unwrap();
+
+        let mut bad = Writer::new(Cursor::new(Vec::new()));
+        scanner.write_frame(b"broken", &mut bad).unwrap();
+        bad.flush().unwrap();
+        let mut bad = bad.get_ref().get_ref().clone();
+        bad[4] ^= 0b0001_0000;
+        writer.write_bytes(bad).unwrap();
+
+        scanner.write_frame(b"second", &mut writer).unwrap();
+        writer.write_bits(0b11, 2).unwrap();
+        writer.flush().unwrap();
+        writer.get_ref().get_ref().clone()
+    }
+
+    #[test]
+    pub fn skips_bad_frames() {
+        let mut reader = Reader::new(Cursor::new(noisy_capture()));
+        let frames = scanner().scan(&mut reader).unwrap();
+        assert_eq!(frames, [b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    pub fn scans_lsb_first() {
+        let scanner = scanner();
+        let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), BitOrder::LsbFirst);
+        writer.write_bits(0b101, 3).unwrap();
+        scanner.write_frame(b"first", &mut writer).unwrap();
+        // A false match on the sync word, whose length runs past the end of the stream unless the
+        // lookahead cuts it short
+        writer.write_bits(0xEB90, 16).unwrap();
+        writer.write_bits(0x3A, 7).unwrap();
+        scanner.write_frame(b"second", &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let bytes = writer.get_ref().get_ref().clone();
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        let frames = scanner.with_max_lookahead(200).scan(&mut reader).unwrap();
+        assert_eq!(frames, [b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    pub fn stops_on_bad_frame() {
+        let mut reader = Reader::new(Cursor::new(noisy_capture()));
+        let mut scanner = scanner().on_bad_frame(BadFramePolicy::Stop);
+        assert_eq!(scanner.next_frame(&mut reader).unwrap().unwrap(), b"first");
+        assert!(scanner.next_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    pub fn reports_bad_frames() {
+        let bad_frames = Rc::new(Cell::new(0));
+        let counter = bad_frames.clone();
+        let mut scanner = scanner().on_bad_frame(BadFramePolicy::Callback(Box::new(move |_| {
+            counter.set(counter.get() + 1);
+            true
+        })));
+
+        let mut reader = Reader::new(Cursor::new(noisy_capture()));
+        assert_eq!(scanner.scan(&mut reader).unwrap().len(), 2);
+        assert_eq!(bad_frames.get(), 1);
+    }
+
+    #[test]
+    pub fn resyncs_inside_false_match() {
+        // A false sync word whose bogus frame overlaps the real one
+        let scanner = FrameScanner::new(0b1011, 4, FrameLength::Fixed(1))
+            .unwrap()
+            .with_checksum(Checksum::Crc32);
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.write_bits(0b1011, 4).unwrap();
+        scanner.write_frame(&[0x42], &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(writer.get_ref().get_ref().clone()));
+        let mut scanner = scanner;
+        assert_eq!(scanner.scan(&mut reader).unwrap(), [vec![0x42]]);
+    }
+
+    #[test]
+    pub fn watchdog_stops_endless_noise() {
+        let mut reader = Reader::new(Cursor::new(vec![0x55; 4096]));
+        let error = scanner()
+            .with_watchdog(1000)
+            .next_frame(&mut reader)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+
+        // Good frames keep resetting it
+        let mut reader = Reader::new(Cursor::new(noisy_capture()));
+        let frames = scanner().with_watchdog(400).scan(&mut reader).unwrap();
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    pub fn bounded_lookahead() {
+        let scanner = scanner();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        scanner.write_frame(&[0xEB; 200], &mut writer).unwrap();
+        scanner.write_frame(b"small", &mut writer).unwrap();
+        writer.write_bits(0xEB9, 12).unwrap();
+        writer.flush().unwrap();
+
+        // The small frame is 8 + 40 + 32 bits after its sync word
+        let mut scanner = scanner.with_max_lookahead(80);
+        let mut reader = Reader::new(Cursor::new(writer.get_ref().get_ref().clone()));
+        assert_eq!(scanner.next_frame(&mut reader).unwrap().unwrap(), b"small");
+        assert!(scanner.buffered_bits() <= 80 + 16);
+        assert!(scanner.next_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn rejects_bad_settings() {
+        assert!(FrameScanner::new(0x1FF, 8, FrameLength::Fixed(4)).is_err());
+        assert!(FrameScanner::new(0x7E, 8, FrameLength::Prefixed(33)).is_err());
+        let scanner = FrameScanner::new(0x7E, 8, FrameLength::Fixed(4)).unwrap();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(scanner.write_frame(b"abc", &mut writer).is_err());
+    }
+}
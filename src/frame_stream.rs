@@ -0,0 +1,195 @@
+use crate::PREALLOCATE_LIMIT;
+use futures::io::AsyncRead;
+use futures::stream::Stream;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// A frame and the number of bytes it took, or None when more bytes are needed
+pub type Decoded<T> = Result<Option<(T, usize)>, Error>;
+
+// Frames decoded from an async byte source, one per poll. Nothing is read from the source until a
+// frame is asked for, and never more than max_buffer bytes are held, so a slow consumer slows the
+// reads down instead of frames piling up in memory.
+//
+// decode gets the buffered bytes and gives back a frame along with how many bytes it took.
+pub struct FrameStream<R, D> {
+    inner: R,
+    decode: D,
+    buffer: Vec<u8>,
+    max_buffer: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R, D> FrameStream<R, D> {
+    pub fn new(inner: R, max_buffer: usize, decode: D) -> Result<FrameStream<R, D>, Error> {
+        if max_buffer == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Frame stream buffer can't be empty",
+            ));
+        }
+        Ok(FrameStream {
+            inner,
+            decode,
+            buffer: Vec::with_capacity(max_buffer.min(PREALLOCATE_LIMIT)),
+            max_buffer,
+            eof: false,
+            done: false,
+        })
+    }
+
+    // Bytes read from the source that haven't made it into a frame yet
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, D, T> FrameStream<R, D>
+where
+    R: AsyncRead + Unpin,
+    D: FnMut(&[u8]) -> Decoded<T> + Unpin,
+{
+    fn fail(&mut self, error: Error) -> Poll<Option<Result<T, Error>>> {
+        self.done = true;
+        Poll::Ready(Some(Err(error)))
+    }
+}
+
+impl<R, D, T> Stream for FrameStream<R, D>
+where
+    R: AsyncRead + Unpin,
+    D: FnMut(&[u8]) -> Decoded<T> + Unpin,
+{
+    type Item = Result<T, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            if !this.buffer.is_empty() {
+                match (this.decode)(&this.buffer) {
+                    Ok(Some((frame, used))) => {
+                        this.buffer.drain(..used.min(this.buffer.len()));
+                        return Poll::Ready(Some(Ok(frame)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => return this.fail(e),
+                }
+            }
+            if this.eof {
+                if this.buffer.is_empty() {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                return this.fail(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Stream ended part way through a frame",
+                ));
+            }
+            if this.buffer.len() >= this.max_buffer {
+                return this.fail(Error::new(
+                    ErrorKind::InvalidData,
+                    "Frame is bigger than the stream buffer",
+                ));
+            }
+
+            let mut chunk = [0; 512];
+            let room = chunk.len().min(this.max_buffer - this.buffer.len());
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk[..room]) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return this.fail(e),
+                Poll::Ready(Ok(0)) => this.eof = true,
+                Poll::Ready(Ok(n)) => this.buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "framing"))]
+mod test {
+    use super::*;
+    use crate::frame_codec::FrameCodec;
+    use crate::{Reader, Writer};
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::stream::StreamExt;
+
+    // Sensor records, each padded out to 3 bytes
+    fn records() -> (FrameCodec, Vec<u8>) {
+        let mut codec = FrameCodec::new();
+        codec.add_field("id", 4, false).unwrap();
+        codec.add_field("temperature", 8, true).unwrap();
+        codec.add_field("humidity", 7, false).unwrap();
+
+        let mut writer = Writer::new(std::io::Cursor::new(Vec::new()));
+        for id in 0..10 {
+            codec
+                .encode_record(&[id, -id, 2 * id], &mut writer)
+                .unwrap();
+            writer.pad_to_byte().unwrap();
+        }
+        writer.flush().unwrap();
+        let bytes = writer.get_ref().get_ref().clone();
+        (codec, bytes)
+    }
+
+    fn record_decoder(codec: FrameCodec) -> impl FnMut(&[u8]) -> Decoded<Vec<i64>> {
+        move |buffer: &[u8]| {
+            let size = codec.record_width().div_ceil(8);
+            if buffer.len() < size {
+                return Ok(None);
+            }
+            let mut reader = Reader::new(&buffer[..size]);
+            Ok(Some((codec.decode_record(&mut reader)?, size)))
+        }
+    }
+
+    #[test]
+    pub fn decodes_frames() {
+        let (codec, bytes) = records();
+        let stream = FrameStream::new(Cursor::new(bytes), 64, record_decoder(codec)).unwrap();
+        let frames: Vec<_> = block_on(stream.collect());
+        assert_eq!(frames.len(), 10);
+        assert_eq!(*frames[7].as_ref().unwrap(), [7, -7, 14]);
+    }
+
+    #[test]
+    pub fn reads_only_what_it_can_hold() {
+        let (codec, bytes) = records();
+        let mut stream = FrameStream::new(Cursor::new(bytes), 8, record_decoder(codec)).unwrap();
+        let first = block_on(stream.next()).unwrap().unwrap();
+        assert_eq!(first, [0, 0, 0]);
+        assert_eq!(stream.get_ref().position(), 8);
+        assert_eq!(stream.buffered_bytes(), 5);
+    }
+
+    #[test]
+    pub fn errors_on_oversized_and_truncated_frames() {
+        let (codec, bytes) = records();
+        let mut stream =
+            FrameStream::new(Cursor::new(bytes.clone()), 2, record_decoder(codec.clone())).unwrap();
+        assert!(block_on(stream.next()).unwrap().is_err());
+        assert!(block_on(stream.next()).is_none());
+
+        let truncated = bytes[..4].to_vec();
+        let stream = FrameStream::new(Cursor::new(truncated), 64, record_decoder(codec)).unwrap();
+        let frames: Vec<_> = block_on(stream.collect());
+        assert!(frames[0].is_ok());
+        assert_eq!(
+            frames[1].as_ref().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+}
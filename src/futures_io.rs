@@ -0,0 +1,56 @@
+// AsyncBitReader and AsyncBitWriter over the futures AsyncRead and AsyncWrite traits, for smol,
+// async-std and anything else that isn't tokio. They work the same as the tokio ones.
+use crate::async_bits::async_bit_types;
+use crate::bit_write::check_fits;
+use crate::{BitOrder, Error};
+use futures_util::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use std::io::{self, ErrorKind};
+
+fn consume<R: AsyncRead + Unpin>(reader: &mut BufReader<R>, amount: usize) {
+    reader.consume_unpin(amount);
+}
+
+async fn skip_bytes<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    count: u64,
+) -> io::Result<u64> {
+    futures_util::io::copy(reader.take(count), &mut futures_util::io::sink()).await
+}
+
+async_bit_types!();
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use futures::executor::block_on;
+
+    #[test]
+    pub fn round_trips() {
+        block_on(async {
+            for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+                let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+                let mut writer = AsyncBitWriter::with_bit_order(Vec::new(), bit_order);
+                for width in 1..=128 {
+                    let value = (u128::MAX / 3) >> (128 - width);
+                    expected.write_bits(value, width).unwrap();
+                    writer.write_bits(value, width).await.unwrap();
+                }
+                writer.write_bytes(vec![0xAB; 3]).await.unwrap();
+                expected.write_bytes(vec![0xAB; 3]).unwrap();
+                let bytes = writer.into_inner().await.unwrap();
+                assert_eq!(bytes, expected.into_inner().unwrap());
+
+                let mut reader = AsyncBitReader::with_bit_order(&bytes[..], bit_order);
+                for width in 1..=128 {
+                    let value = (u128::MAX / 3) >> (128 - width);
+                    assert_eq!(reader.read_bits(width).await.unwrap(), value);
+                }
+                assert_eq!(reader.read_bytes(3).await.unwrap(), [0xAB; 3]);
+                assert!(reader.skip_bits(1).await.is_err());
+            }
+        });
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{Reader, Writer, PREALLOCATE_LIMIT};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Bases take 2 bits: A = 00, C = 01, G = 10, T = 11
+pub fn base_code(base: u8) -> Option<u8> {
+    match base {
+        b'A' | b'a' => Some(0b00),
+        b'C' | b'c' => Some(0b01),
+        b'G' | b'g' => Some(0b10),
+        b'T' | b't' => Some(0b11),
+        _ => None,
+    }
+}
+
+pub fn code_base(code: u8) -> u8 {
+    [b'A', b'C', b'G', b'T'][(code & 0b11) as usize]
+}
+
+// Layout:
+//   64 bits: number of bases
+//   64 bits: number of N runs, then 64 bits start and 64 bits length for each run
+//   2 bits per base, N written as A
+pub fn write_dna<W: Write>(writer: &mut Writer<W>, sequence: &[u8]) -> Result<(), Error> {
+    let mut n_runs: Vec<(usize, usize)> = Vec::new();
+    for (position, &base) in sequence.iter().enumerate() {
+        if base == b'N' || base == b'n' {
+            match n_runs.last_mut() {
+                Some((start, length)) if *start + *length == position => *length += 1,
+                _ => n_runs.push((position, 1)),
+            }
+        } else if base_code(base).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Sequence has a base other than A, C, G, T or N",
+            ));
+        }
+    }
+
+    writer.write_bits(sequence.len() as u128, 64)?;
+    writer.write_bits(n_runs.len() as u128, 64)?;
+    for (start, length) in n_runs {
+        writer.write_bits(start as u128, 64)?;
+        writer.write_bits(length as u128, 64)?;
+    }
+    for &base in sequence {
+        writer.write_bits(base_code(base).unwrap_or(0) as u128, 2)?;
+    }
+    Ok(())
+}
+
+// The N runs of a sequence written by write_dna, read up to the start of the packed bases
+pub fn read_dna_header<R: Read>(
+    reader: &mut Reader<R>,
+) -> Result<(usize, Vec<(usize, usize)>), Error> {
+    let length = reader.read_usize(64)?;
+    let number_of_runs = reader.read_usize(64)?;
+    let mut n_runs = Vec::new();
+    for _ in 0..number_of_runs {
+        let start = reader.read_usize(64)?;
+        let run_length = reader.read_usize(64)?;
+        if start.checked_add(run_length).is_none_or(|end| end > length) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "N run goes past the sequence",
+            ));
+        }
+        n_runs.push((start, run_length));
+    }
+    Ok((length, n_runs))
+}
+
+pub fn read_dna<R: Read>(reader: &mut Reader<R>) -> Result<Vec<u8>, Error> {
+    let (length, n_runs) = read_dna_header(reader)?;
+    let mut sequence = Vec::with_capacity(length.min(PREALLOCATE_LIMIT));
+    for _ in 0..length {
+        sequence.push(code_base(reader.read_bits(2)? as u8));
+    }
+    for (start, run_length) in n_runs {
+        for base in &mut sequence[start..start + run_length] {
+            *base = b'N';
+        }
+    }
+    Ok(sequence)
+}
+
+// Rolling k-mers straight from the 2 bit codes, yielding (start position, k-mer). The first base
+// ends up in the most significant bits and windows that touch an N are skipped.
+pub struct Kmers<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    k: usize,
+    mask: u64,
+    length: usize,
+    n_runs: Vec<(usize, usize)>,
+    next_run: usize,
+    position: usize,
+    kmer: u64,
+    valid: usize,
+}
+
+pub fn kmers<R: Read>(reader: &mut Reader<R>, k: usize) -> Result<Kmers<'_, R>, Error> {
+    if k == 0 || k > 32 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "k-mers have to be between 1 and 32 bases to fit in a u64",
+        ));
+    }
+    let (length, mut n_runs) = read_dna_header(reader)?;
+    n_runs.sort_unstable();
+    Ok(Kmers {
+        reader,
+        k,
+        mask: u64::MAX >> (64 - 2 * k),
+        length,
+        n_runs,
+        next_run: 0,
+        position: 0,
+        kmer: 0,
+        valid: 0,
+    })
+}
+
+impl<'a, R: Read> Kmers<'a, R> {
+    fn is_n(&mut self, position: usize) -> bool {
+        while let Some(&(start, run_length)) = self.n_runs.get(self.next_run) {
+            if position < start {
+                return false;
+            }
+            if position < start + run_length {
+                return true;
+            }
+            self.next_run += 1;
+        }
+        false
+    }
+}
+
+impl<'a, R: Read> Iterator for Kmers<'a, R> {
+    type Item = Result<(usize, u64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.length {
+            let code = match self.reader.read_bits(2) {
+                Ok(code) => code as u64,
+                Err(e) => {
+                    // Nothing more can be read after a failure
+                    self.position = self.length;
+                    return Some(Err(e.into()));
+                }
+            };
+            let position = self.position;
+            self.position += 1;
+            if self.is_n(position) {
+                self.valid = 0;
+                continue;
+            }
+            self.kmer = (self.kmer << 2 | code) & self.mask;
+            self.valid += 1;
+            if self.valid >= self.k {
+                return Some(Ok((position + 1 - self.k, self.kmer)));
+            }
+        }
+        None
+    }
+}
+
+// Maps Phred quality scores onto a small set of bins
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QualityBinning {
+    lower_bounds: Vec<u8>,
+    representatives: Vec<u8>,
+    bits: usize,
+}
+
+impl QualityBinning {
+    // lower_bounds has to start at 0 and go up, each bin decodes to its representative
+    pub fn new(lower_bounds: Vec<u8>, representatives: Vec<u8>) -> Result<QualityBinning, Error> {
+        if lower_bounds.is_empty()
+            || lower_bounds[0] != 0
+            || lower_bounds.len() != representatives.len()
+            || lower_bounds.windows(2).any(|pair| pair[0] >= pair[1])
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Quality bins need increasing lower bounds starting at 0, one per representative",
+            ));
+        }
+        let bits = (usize::BITS - (lower_bounds.len() - 1).leading_zeros()) as usize;
+        Ok(QualityBinning {
+            lower_bounds,
+            representatives,
+            bits: bits.max(1),
+        })
+    }
+
+    // The 8 level binning used by Illumina's reduced quality scores, which fits in 3 bits
+    pub fn illumina() -> QualityBinning {
+        QualityBinning::new(
+            vec![0, 2, 10, 20, 25, 30, 35, 40],
+            vec![2, 6, 15, 22, 27, 33, 37, 40],
+        )
+        .unwrap()
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    pub fn bin(&self, quality: u8) -> usize {
+        self.lower_bounds
+            .iter()
+            .rposition(|&bound| bound <= quality)
+            .unwrap_or(0)
+    }
+
+    pub fn representative(&self, bin: usize) -> u8 {
+        self.representatives[bin]
+    }
+
+    // Writes a 64 bit count and then one bin index per score
+    pub fn encode<W: Write>(&self, qualities: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_bits(qualities.len() as u128, 64)?;
+        for &quality in qualities {
+            writer.write_bits(self.bin(quality) as u128, self.bits)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode<R: Read>(&self, reader: &mut Reader<R>) -> Result<Vec<u8>, Error> {
+        let count = reader.read_usize(64)?;
+        let mut qualities = Vec::with_capacity(count.min(PREALLOCATE_LIMIT));
+        for _ in 0..count {
+            let bin = reader.read_bits(self.bits)? as usize;
+            if bin >= self.representatives.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "Unknown quality bin"));
+            }
+            qualities.push(self.representatives[bin]);
+        }
+        Ok(qualities)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn dna_round_trip() {
+        let sequence = b"ACGTNNNacgtTTGAN".to_vec();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_dna(&mut writer, &sequence).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        // 16 byte header, 2 runs of 16 bytes, then 32 bits of bases
+        assert_eq!(encoded.len(), 16 + 32 + 4);
+        assert_eq!(
+            encoded[48..],
+            [0b0001_1011, 0b0000_0000, 0b0110_1111, 0b1110_0000]
+        );
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(read_dna(&mut reader).unwrap(), b"ACGTNNNACGTTTGAN".to_vec());
+    }
+
+    #[test]
+    pub fn dna_rejects_other_bases() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(write_dna(&mut writer, b"ACGR").is_err());
+    }
+
+    #[test]
+    pub fn rolling_kmers() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_dna(&mut writer, b"ACGTANCGGT").unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        let found: Vec<(usize, u64)> = kmers(&mut reader, 3)
+            .unwrap()
+            .map(|kmer| kmer.unwrap())
+            .collect();
+
+        // ACG, CGT, GTA, then the N resets the window: CGG, GGT
+        assert_eq!(
+            found,
+            vec![
+                (0, 0b00_01_10),
+                (1, 0b01_10_11),
+                (2, 0b10_11_00),
+                (6, 0b01_10_10),
+                (7, 0b10_10_11)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn full_width_kmers() {
+        let sequence: Vec<u8> = (0..40).map(|i| b"ACGT"[i % 4]).collect();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_dna(&mut writer, &sequence).unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        let found: Vec<u64> = kmers(&mut reader, 32)
+            .unwrap()
+            .map(|kmer| kmer.unwrap().1)
+            .collect();
+        assert_eq!(found.len(), 9);
+        assert_eq!(found[0], 0x1B1B_1B1B_1B1B_1B1B);
+        assert_eq!(found[1], 0x6C6C_6C6C_6C6C_6C6C);
+        assert!(kmers(&mut reader, 33).is_err());
+    }
+
+    #[test]
+    pub fn illumina_bins() {
+        let binning = QualityBinning::illumina();
+        assert_eq!(binning.bits(), 3);
+        assert_eq!(binning.bin(0), 0);
+        assert_eq!(binning.bin(9), 1);
+        assert_eq!(binning.bin(24), 3);
+        assert_eq!(binning.bin(41), 7);
+    }
+
+    #[test]
+    pub fn quality_round_trip() {
+        let binning = QualityBinning::illumina();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        binning.encode(&[38, 12, 2, 41], &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        // 64 bit count then 110 010 001 111
+        assert_eq!(encoded[8..], [0b1100_1000, 0b1111_0000]);
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert_eq!(binning.decode(&mut reader).unwrap(), [37, 15, 6, 40]);
+    }
+
+    #[test]
+    pub fn custom_bins() {
+        assert!(QualityBinning::new(vec![0, 20], vec![10, 30]).is_ok());
+        assert!(QualityBinning::new(vec![5, 20], vec![10, 30]).is_err());
+        assert!(QualityBinning::new(vec![0, 20, 20], vec![10, 20, 30]).is_err());
+    }
+}
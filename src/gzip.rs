@@ -0,0 +1,285 @@
+pub use crate::crc::{crc32, crc32_update};
+use crate::deflate::{BlockType, Deflate};
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+const FTEXT: u8 = 0b0000_0001;
+const FHCRC: u8 = 0b0000_0010;
+const FEXTRA: u8 = 0b0000_0100;
+const FNAME: u8 = 0b0000_1000;
+const FCOMMENT: u8 = 0b0001_0000;
+
+// Operating system byte for "unknown"
+pub const OS_UNKNOWN: u8 = 255;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GzipHeader {
+    pub text: bool,
+    // Seconds since the epoch, 0 if there is none
+    pub mtime: u32,
+    pub extra_flags: u8,
+    pub os: u8,
+    pub extra: Option<Vec<u8>>,
+    // Stored zero terminated, so can't contain zeros
+    pub name: Option<Vec<u8>>,
+    pub comment: Option<Vec<u8>>,
+    // Whether a CRC16 of the header follows it
+    pub header_crc: bool,
+}
+
+impl GzipHeader {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut flags = 0;
+        if self.text {
+            flags |= FTEXT;
+        }
+        if self.header_crc {
+            flags |= FHCRC;
+        }
+        if self.extra.is_some() {
+            flags |= FEXTRA;
+        }
+        if self.name.is_some() {
+            flags |= FNAME;
+        }
+        if self.comment.is_some() {
+            flags |= FCOMMENT;
+        }
+
+        let mut bytes = vec![0x1F, 0x8B, 8, flags];
+        bytes.extend_from_slice(&self.mtime.to_le_bytes());
+        bytes.push(self.extra_flags);
+        bytes.push(self.os);
+        if let Some(extra) = &self.extra {
+            if extra.len() > 0xFFFF {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "gzip extra field is longer than 65535 bytes",
+                ));
+            }
+            bytes.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(extra);
+        }
+        for text in [&self.name, &self.comment].iter().copied().flatten() {
+            if text.contains(&0) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "gzip name and comment can't contain zero bytes",
+                ));
+            }
+            bytes.extend_from_slice(text);
+            bytes.push(0);
+        }
+        if self.header_crc {
+            let crc16 = crc32(&bytes) as u16;
+            bytes.extend_from_slice(&crc16.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        Ok(writer.write_bytes(self.to_bytes()?)?)
+    }
+
+    pub fn read<R: Read>(reader: &mut Reader<R>) -> Result<GzipHeader, Error> {
+        // Keep every byte so the header CRC can be checked
+        let mut bytes = reader.read_bytes(10)?;
+        if bytes[0] != 0x1F || bytes[1] != 0x8B {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a gzip member"));
+        }
+        if bytes[2] != 8 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "gzip member is not DEFLATE compressed",
+            ));
+        }
+        let flags = bytes[3];
+        let mut header = GzipHeader {
+            text: flags & FTEXT != 0,
+            mtime: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            extra_flags: bytes[8],
+            os: bytes[9],
+            header_crc: flags & FHCRC != 0,
+            ..GzipHeader::default()
+        };
+
+        if flags & FEXTRA != 0 {
+            let length = reader.read_bytes(2)?;
+            bytes.extend_from_slice(&length);
+            let extra = reader.read_bytes(u16::from_le_bytes([length[0], length[1]]) as usize)?;
+            bytes.extend_from_slice(&extra);
+            header.extra = Some(extra);
+        }
+        if flags & FNAME != 0 {
+            let name = read_zero_terminated(reader, &mut bytes)?;
+            header.name = Some(name);
+        }
+        if flags & FCOMMENT != 0 {
+            let comment = read_zero_terminated(reader, &mut bytes)?;
+            header.comment = Some(comment);
+        }
+        if header.header_crc {
+            let stored = reader.read_bytes(2)?;
+            if u16::from_le_bytes([stored[0], stored[1]]) != crc32(&bytes) as u16 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "gzip header CRC16 mismatch",
+                ));
+            }
+        }
+        Ok(header)
+    }
+}
+
+fn read_zero_terminated<R: Read>(
+    reader: &mut Reader<R>,
+    bytes: &mut Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    let mut text = Vec::new();
+    loop {
+        let byte = reader.read_byte()?;
+        bytes.push(byte);
+        if byte == 0 {
+            return Ok(text);
+        }
+        text.push(byte);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GzipTrailer {
+    pub crc32: u32,
+    // Uncompressed size modulo 2^32
+    pub size: u32,
+}
+
+impl GzipTrailer {
+    pub fn for_data(data: &[u8]) -> GzipTrailer {
+        GzipTrailer {
+            crc32: crc32(data),
+            size: data.len() as u32,
+        }
+    }
+
+    // Aligns to the next byte first, like the end of a DEFLATE stream
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.pad_to_byte()?;
+        let mut bytes = self.crc32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.size.to_le_bytes());
+        Ok(writer.write_bytes(bytes)?)
+    }
+
+    pub fn read<R: Read>(reader: &mut Reader<R>) -> Result<GzipTrailer, Error> {
+        let bytes = reader.read_bytes(8)?;
+        Ok(GzipTrailer {
+            crc32: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            size: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+
+    pub fn verify(&self, data: &[u8]) -> Result<(), Error> {
+        if *self != GzipTrailer::for_data(data) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "gzip CRC32 or size mismatch",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// A whole gzip member. The writer has to be LSB first.
+pub fn write_member<W: Write>(
+    writer: &mut Writer<W>,
+    header: &GzipHeader,
+    data: &[u8],
+    block_type: BlockType,
+) -> Result<(), Error> {
+    header.write(writer)?;
+    Deflate::new(block_type).encode(data, writer)?;
+    GzipTrailer::for_data(data).write(writer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+    use flate2::read::GzDecoder;
+    use flate2::{Compression, GzBuilder};
+    use std::io::Cursor;
+
+    #[test]
+    pub fn crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    pub fn reads_flate2_header() {
+        let mut encoder = GzBuilder::new()
+            .filename("data.bin")
+            .comment("hello")
+            .extra(vec![1, 2, 3])
+            .mtime(1_234_567)
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(b"payload").unwrap();
+        let encoded = encoder.finish().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        let header = GzipHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, Some(b"data.bin".to_vec()));
+        assert_eq!(header.comment, Some(b"hello".to_vec()));
+        assert_eq!(header.extra, Some(vec![1, 2, 3]));
+        assert_eq!(header.mtime, 1_234_567);
+    }
+
+    #[test]
+    pub fn header_crc_round_trip() {
+        let header = GzipHeader {
+            text: true,
+            name: Some(b"notes.txt".to_vec()),
+            header_crc: true,
+            os: OS_UNKNOWN,
+            ..GzipHeader::default()
+        };
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        header.write(&mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let mut encoded = writer.get_ref().get_ref().clone();
+        let mut reader = Reader::new(Cursor::new(encoded.clone()));
+        assert_eq!(GzipHeader::read(&mut reader).unwrap(), header);
+
+        // Corrupt the name
+        encoded[11] ^= 1;
+        let mut reader = Reader::new(Cursor::new(encoded));
+        assert!(GzipHeader::read(&mut reader).is_err());
+    }
+
+    #[test]
+    pub fn member_decodes_with_flate2() {
+        let data = b"gzip wraps deflate, gzip wraps deflate, gzip wraps deflate".to_vec();
+        let header = GzipHeader {
+            name: Some(b"data.txt".to_vec()),
+            mtime: 42,
+            header_crc: true,
+            os: OS_UNKNOWN,
+            ..GzipHeader::default()
+        };
+        let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), BitOrder::LsbFirst);
+        write_member(&mut writer, &header, &data, BlockType::FixedHuffman).unwrap();
+        writer.flush().unwrap();
+
+        let encoded = writer.get_ref().get_ref().clone();
+        let mut decoder = GzDecoder::new(&encoded[..]);
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(decoder.header().unwrap().filename(), Some(&b"data.txt"[..]));
+
+        let mut reader = Reader::new(Cursor::new(encoded[encoded.len() - 8..].to_vec()));
+        let trailer = GzipTrailer::read(&mut reader).unwrap();
+        trailer.verify(&data).unwrap();
+        assert!(trailer.verify(b"other").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+// Extended Hamming codes (SECDED) over blocks of up to 120 data bits: Hamming(13,8) for bytes,
+// Hamming(72,64) for ECC memory words, or any size between. A block's codeword is sent as Hamming
+// positions 1 to n - 1, with the parity bits at the powers of two and the data bits in order in the
+// rest, then the parity bit over all of them. HammingWriter encodes whatever goes through it a
+// block at a time and Reader::hamming decodes, fixing single bit errors and failing on double ones.
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Hamming {
+    data_bits: usize,
+    // Hamming parity bits, not counting the overall one
+    parity_bits: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Decoded {
+    Clean(u128),
+    // bit is the index of the flipped bit in the codeword, in the order it's sent
+    Corrected { data: u128, bit: usize },
+    // Two bits (or an even number more) flipped
+    Uncorrectable,
+}
+
+impl Hamming {
+    pub fn new(data_bits: usize) -> Result<Hamming, Error> {
+        if data_bits == 0 || data_bits > 120 {
+            return Err(Error::invalid_input(
+                0,
+                "Hamming blocks must have between 1 and 120 data bits",
+            ));
+        }
+        let mut parity_bits = 2;
+        while (1 << parity_bits) < data_bits + parity_bits + 1 {
+            parity_bits += 1;
+        }
+        Ok(Hamming {
+            data_bits,
+            parity_bits,
+        })
+    }
+
+    pub fn data_bits(&self) -> usize {
+        self.data_bits
+    }
+
+    // Codeword size, the overall parity bit included
+    pub fn code_bits(&self) -> usize {
+        self.data_bits + self.parity_bits + 1
+    }
+
+    // Data and codewords are in the order they're sent, from bit 0 up
+    pub fn encode(&self, data: u128) -> u128 {
+        let mut codeword = 0u128;
+        let mut syndrome = 0;
+        let mut position = 1usize;
+        for index in 0..self.data_bits {
+            position += 1;
+            while position.is_power_of_two() {
+                position += 1;
+            }
+            if data >> index & 1 == 1 {
+                codeword |= 1 << (position - 1);
+                syndrome ^= position;
+            }
+        }
+        for parity in 0..self.parity_bits {
+            if syndrome >> parity & 1 == 1 {
+                codeword |= 1 << ((1 << parity) - 1);
+            }
+        }
+        let overall = codeword.count_ones() % 2;
+        codeword | (overall as u128) << (self.code_bits() - 1)
+    }
+
+    pub fn decode(&self, codeword: u128) -> Decoded {
+        let last = self.code_bits() - 1;
+        let mut syndrome = 0;
+        for position in 1..=last {
+            if codeword >> (position - 1) & 1 == 1 {
+                syndrome ^= position;
+            }
+        }
+        let odd = codeword.count_ones() % 2 == 1;
+        let (codeword, flipped) = match (syndrome, odd) {
+            (0, false) => (codeword, None),
+            // Only the overall parity bit
+            (0, true) => (codeword, Some(last)),
+            (syndrome, true) if syndrome <= last => {
+                (codeword ^ 1 << (syndrome - 1), Some(syndrome - 1))
+            }
+            _ => return Decoded::Uncorrectable,
+        };
+        let mut data = 0;
+        let mut position = 1usize;
+        for index in 0..self.data_bits {
+            position += 1;
+            while position.is_power_of_two() {
+                position += 1;
+            }
+            data |= (codeword >> (position - 1) & 1) << index;
+        }
+        match flipped {
+            None => Decoded::Clean(data),
+            Some(bit) => Decoded::Corrected { data, bit },
+        }
+    }
+}
+
+// Between a value and its bits in the order they're sent, bit 0 first
+fn to_sent_order(bits: u128, number_of_bits: usize, bit_order: BitOrder) -> u128 {
+    if number_of_bits == 0 {
+        return 0;
+    }
+    match bit_order {
+        BitOrder::MsbFirst => bits.reverse_bits() >> (128 - number_of_bits),
+        BitOrder::LsbFirst => bits & u128::MAX >> (128 - number_of_bits),
+    }
+}
+
+pub struct HammingWriter<W: BitWrite> {
+    inner: W,
+    code: Hamming,
+    // Data bits of the current block in the order they were written
+    block: u128,
+    filled: usize,
+}
+
+impl<W: BitWrite> HammingWriter<W> {
+    pub fn new(inner: W, code: Hamming) -> HammingWriter<W> {
+        HammingWriter {
+            inner,
+            code,
+            block: 0,
+            filled: 0,
+        }
+    }
+
+    // Fills a started block with zeros and writes it
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if self.filled > 0 {
+            let codeword = self.code.encode(self.block);
+            let code_bits = self.code.code_bits();
+            let bits = to_sent_order(codeword, code_bits, self.inner.bit_order());
+            self.inner.write_bits(bits, code_bits)?;
+            self.block = 0;
+            self.filled = 0;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Anything since the last full block is dropped unless end_block is called first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for HammingWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let sent = to_sent_order(bits, number_of_bits, self.inner.bit_order());
+        for index in 0..number_of_bits {
+            self.block |= (sent >> index & 1) << self.filled;
+            self.filled += 1;
+            if self.filled == self.code.data_bits() {
+                self.end_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Codewords written so far, so data in a started block isn't counted yet
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    // Ends the block first, so the padding goes after its codeword, and gives back the padding
+    // that took
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.end_block()?;
+        self.inner.pad_to_byte()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data written through a HammingWriter with the same code, correcting single bit errors
+    // and failing with InvalidData (at the block's first bit) for double ones. Reading carries on
+    // with the next block after a failure.
+    pub fn hamming(&mut self, code: Hamming) -> HammingReader<'_, R> {
+        HammingReader {
+            reader: self,
+            code,
+            block: 0,
+            used: code.data_bits(),
+            corrected: Vec::new(),
+        }
+    }
+}
+
+pub struct HammingReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    code: Hamming,
+    // Decoded data bits in the order they were written, and how many have been handed out. All of
+    // them once the block's done with, so the next read starts a new one.
+    block: u128,
+    used: usize,
+    corrected: Vec<u64>,
+}
+
+impl<'a, R: Read> HammingReader<'a, R> {
+    // Positions of the bits fixed so far
+    pub fn corrected(&self) -> &[u64] {
+        &self.corrected
+    }
+
+    fn next_block(&mut self) -> Result<(), Error> {
+        let position = self.reader.bits_read();
+        let code_bits = self.code.code_bits();
+        let bits = self.reader.read_bits(code_bits)?;
+        let codeword = to_sent_order(bits, code_bits, self.reader.bit_order());
+        self.used = 0;
+        self.block = match self.code.decode(codeword) {
+            Decoded::Clean(data) => data,
+            Decoded::Corrected { data, bit } => {
+                self.corrected.push(position + bit as u64);
+                data
+            }
+            Decoded::Uncorrectable => {
+                self.used = self.code.data_bits();
+                return Err(Error::invalid_data(
+                    position,
+                    "Hamming block has more than one bit error",
+                ));
+            }
+        };
+        Ok(())
+    }
+
+    // Drops the rest of a block that was only partly read, like the zeros end_block filled it with
+    pub fn end_block(&mut self) {
+        self.used = self.code.data_bits();
+    }
+}
+
+impl<'a, R: Read> BitRead for HammingReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let mut sent = 0;
+        for index in 0..number_of_bits {
+            if self.used == self.code.data_bits() {
+                self.next_block()?;
+            }
+            sent |= (self.block >> self.used & 1) << index;
+            self.used += 1;
+        }
+        Ok(to_sent_order(sent, number_of_bits, self.reader.bit_order()))
+    }
+
+    // Codeword bits included
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Drops the rest of the block and then the padding after it, like HammingWriter's
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        self.end_block();
+        self.reader.align_to_byte(require_zeros)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn corrects_every_single_error() {
+        for data_bits in [1, 4, 8, 11, 57, 64, 120] {
+            let code = Hamming::new(data_bits).unwrap();
+            let data = 0xA5C3_0F96_1E2D_3C4B_5A69_7887_9695_A4B3 & (u128::MAX >> (128 - data_bits));
+            let codeword = code.encode(data);
+            assert_eq!(code.decode(codeword), Decoded::Clean(data));
+            for bit in 0..code.code_bits() {
+                let corrupted = codeword ^ 1 << bit;
+                assert_eq!(code.decode(corrupted), Decoded::Corrected { data, bit });
+                for second in bit + 1..code.code_bits() {
+                    assert_eq!(code.decode(corrupted ^ 1 << second), Decoded::Uncorrectable);
+                }
+            }
+        }
+        assert_eq!(Hamming::new(8).unwrap().code_bits(), 13);
+        assert_eq!(Hamming::new(64).unwrap().code_bits(), 72);
+        assert_eq!(Hamming::new(120).unwrap().code_bits(), 128);
+        assert!(Hamming::new(121).is_err());
+    }
+
+    #[test]
+    pub fn protects_a_stream() {
+        let code = Hamming::new(8).unwrap();
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let writer = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut output = HammingWriter::new(writer, code);
+            output.write_bytes(b"ECC!".to_vec()).unwrap();
+            output.write_bits(0x1234, 13).unwrap();
+            output.end_block().unwrap();
+            assert_eq!(output.bits_written(), 6 * 13);
+            output.pad_to_byte().unwrap();
+            let mut bytes = output.into_inner().into_inner().unwrap();
+            // One error in the first block and two in the third, which is bits 26 to 38
+            bytes[0] ^= 0b0100_0000;
+            bytes[4] ^= 0b0001_0100;
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes), bit_order);
+            let mut input = reader.hamming(code);
+            assert_eq!(input.read_bytes(2).unwrap(), b"EC");
+            let error = input.read_byte().unwrap_err();
+            assert!(matches!(
+                error,
+                Error::InvalidData {
+                    bit_position: 26,
+                    ..
+                }
+            ));
+            assert_eq!(input.read_byte().unwrap(), b'!');
+            assert_eq!(input.read_bits(13).unwrap(), 0x1234);
+            let flipped = match bit_order {
+                BitOrder::MsbFirst => 1,
+                BitOrder::LsbFirst => 6,
+            };
+            assert_eq!(input.corrected(), [flipped]);
+            input.end_block();
+            input.align_to_byte(true).unwrap();
+            assert_eq!(input.bits_read(), 80);
+        }
+    }
+
+    #[test]
+    pub fn pads_in_the_middle_of_a_block() {
+        let code = Hamming::new(8).unwrap();
+        let mut output = HammingWriter::new(Writer::new(Vec::new()), code);
+        output.write_bits(0b101, 3).unwrap();
+        assert_eq!(output.pad_to_byte().unwrap(), 3);
+        output.write_bits(0b1_0110, 5).unwrap();
+        output.write_byte(0xC3).unwrap();
+        output.end_block().unwrap();
+        let bytes = output.into_inner().into_inner().unwrap();
+        assert_eq!(bytes.len(), 6);
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.hamming(code);
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        input.align_to_byte(true).unwrap();
+        assert_eq!(input.bits_read(), 16);
+        assert_eq!(input.read_bits(5).unwrap(), 0b1_0110);
+        assert_eq!(input.read_byte().unwrap(), 0xC3);
+        assert!(input.corrected().is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+// HDLC framing as used by synchronous PPP, X.25, AX.25 and friends. Frames sit between 0x7E flags,
+// and a 0 goes in after every five 1s of the frame so six 1s in a row only ever start a flag. Seven
+// or more aborts the frame. HDLC sends bytes least significant bit first, so the Writer and Reader
+// are normally LsbFirst.
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader, PREALLOCATE_LIMIT};
+use std::io::Read;
+
+pub const FLAG: u8 = 0x7E;
+
+pub struct HdlcWriter<W: BitWrite> {
+    inner: W,
+    // 1s in a row since the last 0
+    ones: usize,
+}
+
+impl<W: BitWrite> HdlcWriter<W> {
+    pub fn new(inner: W) -> HdlcWriter<W> {
+        HdlcWriter { inner, ones: 0 }
+    }
+
+    // Unstuffed, so it's also for filling idle time between frames
+    pub fn write_flag(&mut self) -> Result<(), Error> {
+        self.ones = 0;
+        self.inner.write_bits(FLAG as u128, 8)
+    }
+
+    // Writes the payload stuffed between an opening and a closing flag
+    pub fn write_frame(&mut self, payload: &[u8]) -> Result<(), Error> {
+        self.write_flag()?;
+        for &byte in payload {
+            self.write_byte(byte)?;
+        }
+        self.write_flag()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for HdlcWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let bit_order = self.inner.bit_order();
+        for index in 0..number_of_bits {
+            let shift = match bit_order {
+                BitOrder::MsbFirst => number_of_bits - 1 - index,
+                BitOrder::LsbFirst => index,
+            };
+            let bit = bits >> shift & 1 == 1;
+            self.inner.write_bit(bit)?;
+            self.ones = if bit { self.ones + 1 } else { 0 };
+            if self.ones == 5 {
+                self.inner.write_bit(false)?;
+                self.ones = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Stuffed bits included
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    // Zeros between frames are ignored by the other end
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.ones = 0;
+        self.inner.pad_to_byte()
+    }
+}
+
+// Finds frames between flags in a bit stream, taking the stuffed bits back out. Frames that are
+// aborted, aren't a whole number of bytes or run past the maximum length are dropped and counted.
+pub struct HdlcDeframer {
+    max_length: usize,
+    // Whether the last flag could be opening a frame
+    in_frame: bool,
+    ones: usize,
+    bits: Vec<bool>,
+    dropped: u64,
+}
+
+impl Default for HdlcDeframer {
+    fn default() -> HdlcDeframer {
+        HdlcDeframer::new()
+    }
+}
+
+impl HdlcDeframer {
+    pub fn new() -> HdlcDeframer {
+        HdlcDeframer {
+            max_length: usize::MAX,
+            in_frame: false,
+            ones: 0,
+            bits: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    // Drops frames longer than this many bytes as soon as they pass it, so memory stays bounded
+    // on live streams
+    pub fn with_max_length(mut self, max_bytes: usize) -> HdlcDeframer {
+        self.max_length = max_bytes;
+        self
+    }
+
+    // Frames dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn drop_frame(&mut self) {
+        if !self.bits.is_empty() {
+            self.dropped += 1;
+            self.bits.clear();
+        }
+        self.in_frame = false;
+    }
+
+    // Packs a finished frame into bytes, None if it's empty (as between two flags in a row) or
+    // isn't a whole number of bytes
+    fn finish_frame(&mut self, bit_order: BitOrder) -> Option<Vec<u8>> {
+        if self.bits.is_empty() {
+            return None;
+        }
+        if !self.bits.len().is_multiple_of(8) {
+            self.drop_frame();
+            return None;
+        }
+        let mut frame = Vec::with_capacity((self.bits.len() / 8).min(PREALLOCATE_LIMIT));
+        for chunk in self.bits.chunks(8) {
+            frame.push(chunk.iter().enumerate().fold(0, |byte, (index, &bit)| {
+                let shift = match bit_order {
+                    BitOrder::MsbFirst => 7 - index,
+                    BitOrder::LsbFirst => index,
+                };
+                byte | (bit as u8) << shift
+            }));
+        }
+        self.bits.clear();
+        Some(frame)
+    }
+
+    // The next frame's payload, or None at the end of the stream. A frame cut off by the end of
+    // the stream is dropped.
+    pub fn next_frame<R: Read>(
+        &mut self,
+        reader: &mut Reader<R>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            let bit = match reader.read_bit_opt()? {
+                Some(bit) => bit,
+                None => {
+                    self.drop_frame();
+                    return Ok(None);
+                }
+            };
+            if bit {
+                self.ones += 1;
+                match self.ones {
+                    7 => self.drop_frame(),
+                    ones if ones < 7 && self.in_frame => self.bits.push(true),
+                    _ => {}
+                }
+            } else {
+                let ones = std::mem::replace(&mut self.ones, 0);
+                match ones {
+                    // Stuffed
+                    5 => continue,
+                    6 => {
+                        // The flag's own bits were taken as data until the sixth 1
+                        let length = self.bits.len().saturating_sub(7);
+                        self.bits.truncate(length);
+                        let frame = match self.in_frame {
+                            true => self.finish_frame(reader.bit_order()),
+                            false => None,
+                        };
+                        self.in_frame = true;
+                        if frame.is_some() {
+                            return Ok(frame);
+                        }
+                        continue;
+                    }
+                    _ if self.in_frame => self.bits.push(false),
+                    _ => {}
+                }
+            }
+            if self.bits.len() > self.max_length.saturating_mul(8).saturating_add(7) {
+                self.drop_frame();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn stuffs_after_five_ones() {
+        let mut output = HdlcWriter::new(Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst));
+        output.write_bits(0b1111_1111, 8).unwrap();
+        output.write_bits(0b1_1111, 5).unwrap();
+        assert_eq!(output.bits_written(), 15);
+        output.write_flag().unwrap();
+        output.pad_to_byte().unwrap();
+        // 11111 0 111 11 0 111, then the flag
+        let bytes = output.into_inner().into_inner().unwrap();
+        assert_eq!(bytes, [0b1101_1111, 0b0111_0111, 0b0011_1111]);
+    }
+
+    #[test]
+    pub fn round_trips_frames() {
+        let frames: [&[u8]; 3] = [b"\x7E\x7E\xFF\xFF", b"plain text", b"\x7D\x5E\xFE\x01"];
+        let writer = Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst);
+        let mut output = HdlcWriter::new(writer);
+        output.write_bits(0b1101, 4).unwrap();
+        output.write_frame(frames[0]).unwrap();
+        // Idle flags, then a frame that's aborted, one that's 3 bits short and one too long
+        output.write_flag().unwrap();
+        output.write_bits(0x5A, 8).unwrap();
+        output.get_mut().write_bits(0x7F, 7).unwrap();
+        output.write_flag().unwrap();
+        output.write_bits(0x5A, 5).unwrap();
+        output.write_flag().unwrap();
+        output.write_frame(&[0; 20]).unwrap();
+        output.write_frame(frames[1]).unwrap();
+        output.write_frame(frames[2]).unwrap();
+        output.write_flag().unwrap();
+        output.write_bits(0x5A, 8).unwrap();
+        output.pad_to_byte().unwrap();
+
+        let bytes = output.into_inner().into_inner().unwrap();
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        let mut deframer = HdlcDeframer::new().with_max_length(16);
+        for frame in frames {
+            assert_eq!(deframer.next_frame(&mut reader).unwrap().unwrap(), frame);
+        }
+        assert_eq!(deframer.next_frame(&mut reader).unwrap(), None);
+        assert_eq!(deframer.dropped(), 4);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::morton;
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Skilling's "Programming the Hilbert curve": turns coordinates into the transposed Hilbert
+// index, which only has to be bit interleaved to get the index itself
+fn axes_to_transpose(axes: &mut [u32], bits: usize) {
+    // Inverse undo
+    for shift in (1..bits).rev() {
+        let q = 1u32 << shift;
+        let p = q - 1;
+        for i in 0..axes.len() {
+            if axes[i] & q != 0 {
+                axes[0] ^= p;
+            } else {
+                let t = (axes[0] ^ axes[i]) & p;
+                axes[0] ^= t;
+                axes[i] ^= t;
+            }
+        }
+    }
+
+    // Gray encode
+    for i in 1..axes.len() {
+        axes[i] ^= axes[i - 1];
+    }
+    let mut t = 0;
+    for shift in (1..bits).rev() {
+        let q = 1u32 << shift;
+        if axes[axes.len() - 1] & q != 0 {
+            t ^= q - 1;
+        }
+    }
+    for axis in axes.iter_mut() {
+        *axis ^= t;
+    }
+}
+
+fn transpose_to_axes(axes: &mut [u32], bits: usize) {
+    // Gray decode
+    let t = axes[axes.len() - 1] >> 1;
+    for i in (1..axes.len()).rev() {
+        axes[i] ^= axes[i - 1];
+    }
+    axes[0] ^= t;
+
+    // Undo excess work
+    for shift in 1..bits {
+        let q = 1u32 << shift;
+        let p = q - 1;
+        for i in (0..axes.len()).rev() {
+            if axes[i] & q != 0 {
+                axes[0] ^= p;
+            } else {
+                let t = (axes[0] ^ axes[i]) & p;
+                axes[0] ^= t;
+                axes[i] ^= t;
+            }
+        }
+    }
+}
+
+fn check_bits(bits: usize, max_bits: usize) -> Result<(), Error> {
+    if bits == 0 || bits > max_bits {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Unsupported number of bits per coordinate for a Hilbert index",
+        ));
+    }
+    Ok(())
+}
+
+fn check_index(index: u64, index_bits: usize) -> Result<(), Error> {
+    if index_bits < 64 && index >> index_bits != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Hilbert index is too big for bits per coordinate",
+        ));
+    }
+    Ok(())
+}
+
+fn check_coordinates(coordinates: &[u32], bits: usize) -> Result<(), Error> {
+    if coordinates.iter().any(|&c| bits < 32 && c >> bits != 0) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Coordinate does not fit in bits per coordinate",
+        ));
+    }
+    Ok(())
+}
+
+// Index along a curve filling a 2^bits by 2^bits square, bits goes up to 32
+pub fn encode_2d(x: u32, y: u32, bits: usize) -> Result<u64, Error> {
+    check_bits(bits, 32)?;
+    check_coordinates(&[x, y], bits)?;
+    let mut axes = [x, y];
+    axes_to_transpose(&mut axes, bits);
+    // The first axis holds the most significant bit of every pair
+    Ok(morton::encode_2d(axes[1], axes[0]))
+}
+
+pub fn decode_2d(index: u64, bits: usize) -> Result<(u32, u32), Error> {
+    check_bits(bits, 32)?;
+    check_index(index, 2 * bits)?;
+    let (second, first) = morton::decode_2d(index);
+    let mut axes = [first, second];
+    transpose_to_axes(&mut axes, bits);
+    Ok((axes[0], axes[1]))
+}
+
+// Index along a curve filling a 2^bits cube, bits goes up to 21
+pub fn encode_3d(x: u32, y: u32, z: u32, bits: usize) -> Result<u64, Error> {
+    check_bits(bits, 21)?;
+    check_coordinates(&[x, y, z], bits)?;
+    let mut axes = [x, y, z];
+    axes_to_transpose(&mut axes, bits);
+    Ok(morton::encode_3d(axes[2], axes[1], axes[0]))
+}
+
+pub fn decode_3d(index: u64, bits: usize) -> Result<(u32, u32, u32), Error> {
+    check_bits(bits, 21)?;
+    check_index(index, 3 * bits)?;
+    let (third, second, first) = morton::decode_3d(index);
+    let mut axes = [first, second, third];
+    transpose_to_axes(&mut axes, bits);
+    Ok((axes[0], axes[1], axes[2]))
+}
+
+// Writes the index as a 2 * bits bit field
+pub fn write_2d<W: Write>(
+    writer: &mut Writer<W>,
+    x: u32,
+    y: u32,
+    bits: usize,
+) -> Result<(), Error> {
+    let index = encode_2d(x, y, bits)?;
+    Ok(writer.write_bits(index as u128, 2 * bits)?)
+}
+
+pub fn read_2d<R: Read>(reader: &mut Reader<R>, bits: usize) -> Result<(u32, u32), Error> {
+    check_bits(bits, 32)?;
+    decode_2d(reader.read_bits(2 * bits)? as u64, bits)
+}
+
+// Writes the index as a 3 * bits bit field
+pub fn write_3d<W: Write>(
+    writer: &mut Writer<W>,
+    x: u32,
+    y: u32,
+    z: u32,
+    bits: usize,
+) -> Result<(), Error> {
+    let index = encode_3d(x, y, z, bits)?;
+    Ok(writer.write_bits(index as u128, 3 * bits)?)
+}
+
+pub fn read_3d<R: Read>(reader: &mut Reader<R>, bits: usize) -> Result<(u32, u32, u32), Error> {
+    check_bits(bits, 21)?;
+    decode_3d(reader.read_bits(3 * bits)? as u64, bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn distance(a: &[u32], b: &[u32]) -> u32 {
+        a.iter().zip(b).map(|(a, b)| a.max(b) - a.min(b)).sum()
+    }
+
+    #[test]
+    pub fn first_order_curve() {
+        // The basic U shape
+        assert_eq!(encode_2d(0, 0, 1).unwrap(), 0);
+        assert_eq!(encode_2d(0, 1, 1).unwrap(), 1);
+        assert_eq!(encode_2d(1, 1, 1).unwrap(), 2);
+        assert_eq!(encode_2d(1, 0, 1).unwrap(), 3);
+    }
+
+    #[test]
+    pub fn curve_2d_is_continuous() {
+        let bits = 4;
+        let mut previous = [0, 0];
+        for index in 0..1u64 << (2 * bits) {
+            let (x, y) = decode_2d(index, bits).unwrap();
+            assert_eq!(encode_2d(x, y, bits).unwrap(), index);
+            if index > 0 {
+                assert_eq!(distance(&previous, &[x, y]), 1);
+            }
+            previous = [x, y];
+        }
+    }
+
+    #[test]
+    pub fn curve_3d_is_continuous() {
+        let bits = 3;
+        let mut previous = [0, 0, 0];
+        for index in 0..1u64 << (3 * bits) {
+            let (x, y, z) = decode_3d(index, bits).unwrap();
+            assert_eq!(encode_3d(x, y, z, bits).unwrap(), index);
+            if index > 0 {
+                assert_eq!(distance(&previous, &[x, y, z]), 1);
+            }
+            previous = [x, y, z];
+        }
+    }
+
+    #[test]
+    pub fn full_width_round_trip() {
+        let index = encode_2d(u32::MAX, 12_345, 32).unwrap();
+        assert_eq!(decode_2d(index, 32).unwrap(), (u32::MAX, 12_345));
+        let index = encode_3d(0x1F_FFFF, 0, 777, 21).unwrap();
+        assert_eq!(decode_3d(index, 21).unwrap(), (0x1F_FFFF, 0, 777));
+    }
+
+    #[test]
+    pub fn stream_round_trip() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_2d(&mut writer, 3, 5, 3).unwrap();
+        write_3d(&mut writer, 1, 2, 3, 2).unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        assert_eq!(read_2d(&mut reader, 3).unwrap(), (3, 5));
+        assert_eq!(read_3d(&mut reader, 2).unwrap(), (1, 2, 3));
+    }
+
+    #[test]
+    pub fn rejects_wide_coordinates() {
+        assert!(encode_2d(8, 0, 3).is_err());
+        assert!(encode_3d(0, 0, 0, 22).is_err());
+        assert!(decode_2d(0, 0).is_err());
+        assert!(decode_2d(64, 3).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{BitOrder, BitWrite, Reader};
+use std::io::{Error, ErrorKind, Read};
+
+fn check_byte_width(symbol_width: usize) -> Result<(), Error> {
+    if !8usize.is_multiple_of(symbol_width) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Byte histograms need a symbol width that divides 8",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    symbol_width: usize,
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    // Symbols can be 1 to 16 bits wide
+    pub fn new(symbol_width: usize) -> Result<Histogram, Error> {
+        // A count for every symbol has to fit in memory, which 16 bits doesn't on 16 bit targets
+        if symbol_width == 0 || symbol_width > 16 || symbol_width >= usize::BITS as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Histogram symbol width must be between 1 and 16 bits",
+            ));
+        }
+        Ok(Histogram {
+            symbol_width,
+            counts: vec![0; 1 << symbol_width],
+        })
+    }
+
+    // Reads symbols until the stream runs out. Bits at the end that don't make a whole symbol are
+    // dropped.
+    pub fn collect<R: Read>(
+        reader: &mut Reader<R>,
+        symbol_width: usize,
+    ) -> Result<Histogram, Error> {
+        let mut histogram = Histogram::new(symbol_width)?;
+        loop {
+            match reader.read_bits(symbol_width) {
+                Ok(symbol) => histogram.tally(symbol as usize),
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(histogram),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // Symbols are taken from the front of each byte, so symbol_width has to divide 8
+    pub fn from_bytes(data: &[u8], symbol_width: usize) -> Result<Histogram, Error> {
+        let mut histogram = Histogram::new(symbol_width)?;
+        histogram.add_bytes(data)?;
+        Ok(histogram)
+    }
+
+    pub fn add_bytes(&mut self, data: &[u8]) -> Result<(), Error> {
+        check_byte_width(self.symbol_width)?;
+        let mask = (1 << self.symbol_width) - 1;
+        for &byte in data {
+            for shift in (0..8 / self.symbol_width).rev() {
+                self.tally((byte as usize >> (shift * self.symbol_width)) & mask);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, symbol: usize) -> Result<(), Error> {
+        if symbol >= self.counts.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Symbol is wider than the histogram's symbols",
+            ));
+        }
+        self.tally(symbol);
+        Ok(())
+    }
+
+    // For symbols that can't be too wide
+    fn tally(&mut self, symbol: usize) {
+        self.counts[symbol] += 1;
+    }
+
+    pub fn symbol_width(&self) -> usize {
+        self.symbol_width
+    }
+
+    pub fn count(&self, symbol: usize) -> u64 {
+        self.counts.get(symbol).copied().unwrap_or(0)
+    }
+
+    // One count per symbol, indexed by symbol
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // (symbol, count) for every symbol that showed up
+    pub fn nonzero(&self) -> Vec<(usize, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count != 0)
+            .map(|(symbol, &count)| (symbol, count))
+            .collect()
+    }
+}
+
+// Tallies the symbols in the bits written to it, so as the sink of Reader::tee it counts exactly
+// what the Reader reads, while the data is consumed for something else. Bits short of a whole
+// symbol wait for the next write.
+pub struct HistogramSink {
+    histogram: Histogram,
+    bit_order: BitOrder,
+    symbol: usize,
+    filled: usize,
+    bits: u64,
+}
+
+impl HistogramSink {
+    pub fn new(symbol_width: usize) -> Result<HistogramSink, Error> {
+        HistogramSink::with_bit_order(symbol_width, BitOrder::MsbFirst)
+    }
+
+    // Symbols are put together from the bits the way a Reader with this bit order reads them
+    pub fn with_bit_order(
+        symbol_width: usize,
+        bit_order: BitOrder,
+    ) -> Result<HistogramSink, Error> {
+        Ok(HistogramSink {
+            histogram: Histogram::new(symbol_width)?,
+            bit_order,
+            symbol: 0,
+            filled: 0,
+            bits: 0,
+        })
+    }
+
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    pub fn into_histogram(self) -> Histogram {
+        self.histogram
+    }
+}
+
+impl BitWrite for HistogramSink {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), crate::Error> {
+        if number_of_bits > 128 {
+            return Err(crate::Error::too_wide(self.bits, number_of_bits, 128));
+        }
+        let width = self.histogram.symbol_width;
+        for index in 0..number_of_bits {
+            let shift = match self.bit_order {
+                BitOrder::MsbFirst => number_of_bits - 1 - index,
+                BitOrder::LsbFirst => index,
+            };
+            let bit = (bits >> shift & 1) as usize;
+            self.symbol = match self.bit_order {
+                BitOrder::MsbFirst => self.symbol << 1 | bit,
+                BitOrder::LsbFirst => self.symbol | bit << self.filled,
+            };
+            self.filled += 1;
+            if self.filled == width {
+                self.histogram.tally(self.symbol);
+                self.symbol = 0;
+                self.filled = 0;
+            }
+        }
+        self.bits += number_of_bits as u64;
+        Ok(())
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.bits
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn byte_symbols() {
+        let histogram = Histogram::from_bytes(b"abracadabra", 8).unwrap();
+        assert_eq!(histogram.count(b'a' as usize), 5);
+        assert_eq!(histogram.count(b'r' as usize), 2);
+        assert_eq!(histogram.total(), 11);
+        assert_eq!(histogram.nonzero().len(), 5);
+    }
+
+    #[test]
+    pub fn nibble_symbols() {
+        let histogram = Histogram::from_bytes(&[0x12, 0x21, 0xFF], 4).unwrap();
+        assert_eq!(histogram.counts()[1], 2);
+        assert_eq!(histogram.counts()[2], 2);
+        assert_eq!(histogram.counts()[15], 2);
+        assert!(Histogram::from_bytes(&[0], 3).is_err());
+    }
+
+    #[test]
+    pub fn collect_from_reader() {
+        // 101 101 101 111 111 1 -> the last bit doesn't make a symbol
+        let mut reader = Reader::new(Cursor::new(vec![0b1011_0110, 0b1111_1111]));
+        let histogram = Histogram::collect(&mut reader, 3).unwrap();
+        assert_eq!(histogram.count(0b101), 3);
+        assert_eq!(histogram.count(0b111), 2);
+        assert_eq!(histogram.total(), 5);
+    }
+
+    #[test]
+    pub fn counts_only_what_is_read() {
+        let mut reader = Reader::new(Cursor::new(vec![7, 7, 9, 9, 9]));
+        let mut tee = reader.tee(HistogramSink::new(8).unwrap());
+        assert_eq!(tee.read_bytes(3).unwrap(), [7, 7, 9]);
+        let histogram = tee.into_sink().into_histogram();
+        assert_eq!(histogram.count(7), 2);
+        assert_eq!(histogram.count(9), 1);
+
+        // Nibbles split across reads, put together low bits first
+        let mut reader = Reader::with_bit_order(Cursor::new(vec![0x21, 0x43]), BitOrder::LsbFirst);
+        let sink = HistogramSink::with_bit_order(4, BitOrder::LsbFirst).unwrap();
+        let mut tee = reader.tee(sink);
+        tee.read_bits(2).unwrap();
+        tee.read_bits(7).unwrap();
+        assert_eq!(tee.sink().histogram().nonzero(), [(1, 1), (2, 1)]);
+        assert!(Histogram::new(4).unwrap().add(16).is_err());
+    }
+}
@@ -0,0 +1,433 @@
+// Interleaving between a code and the channel, so a burst of errors on the way is spread thin
+// enough across codewords for the code to fix. InterleaveWriter goes between the coding stage and
+// the output writer:
+//   let interleaving = Interleaving::Block { rows: 8, columns: 13 };
+//   let output = InterleaveWriter::new(Writer::new(file), interleaving)?;
+//   let mut coded = HammingWriter::new(output, Hamming::new(8)?);
+// and DeinterleaveReader goes under the Reader the decoding stage reads from:
+//   let mut reader = Reader::new(DeinterleaveReader::new(file, interleaving, BitOrder::MsbFirst)?);
+//   let mut decoded = reader.hamming(Hamming::new(8)?);
+use crate::bit_write::BitWrite;
+use crate::byte_collector::ByteCollector;
+use crate::{BitOrder, Error};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Interleaving {
+    // Bits go into a rows by columns block a row at a time and come out a column at a time, so a
+    // burst of up to rows bits lands in as many different rows
+    Block { rows: usize, columns: usize },
+    // Forney's: bits go round branches in turn, branch i delaying them by i * delay of its own
+    // bits. The same spread as a block interleaver for about half the memory and latency, but the
+    // delay lines have to be flushed at the end.
+    Convolutional { branches: usize, delay: usize },
+}
+
+impl Interleaving {
+    fn check(&self) -> Result<(), Error> {
+        let valid = match *self {
+            Interleaving::Block { rows, columns } => rows > 0 && columns > 0,
+            Interleaving::Convolutional { branches, delay } => branches > 0 && delay > 0,
+        };
+        if !valid {
+            return Err(Error::invalid_input(
+                0,
+                "Interleaver sizes must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+
+    // Bits a convolutional interleaver and deinterleaver hold between them, which the writer
+    // flushes with zeros and the reader drops from the front
+    fn latency(&self) -> usize {
+        match *self {
+            Interleaving::Block { .. } => 0,
+            Interleaving::Convolutional { branches, delay } => branches * (branches - 1) * delay,
+        }
+    }
+}
+
+// One direction of an interleaver, a bit in and whatever bits are ready out
+enum Stage {
+    Block {
+        rows: usize,
+        columns: usize,
+        bits: Vec<bool>,
+    },
+    Convolutional {
+        lines: Vec<VecDeque<bool>>,
+        next: usize,
+        // Bits still to drop from the front
+        skip: usize,
+    },
+}
+
+impl Stage {
+    fn new(interleaving: Interleaving, inverse: bool) -> Stage {
+        match interleaving {
+            // Undoing a transpose is transposing back
+            Interleaving::Block { rows, columns } if inverse => Stage::Block {
+                rows: columns,
+                columns: rows,
+                bits: Vec::new(),
+            },
+            Interleaving::Block { rows, columns } => Stage::Block {
+                rows,
+                columns,
+                bits: Vec::new(),
+            },
+            Interleaving::Convolutional { branches, delay } => {
+                let lines = (0..branches)
+                    .map(|branch| {
+                        let length = match inverse {
+                            true => branches - 1 - branch,
+                            false => branch,
+                        };
+                        vec![false; length * delay].into()
+                    })
+                    .collect();
+                Stage::Convolutional {
+                    lines,
+                    next: 0,
+                    skip: if inverse { interleaving.latency() } else { 0 },
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, bit: bool, output: &mut Vec<bool>) {
+        match self {
+            Stage::Block {
+                rows,
+                columns,
+                bits,
+            } => {
+                bits.push(bit);
+                if bits.len() == *rows * *columns {
+                    for column in 0..*columns {
+                        output.extend((0..*rows).map(|row| bits[row * *columns + column]));
+                    }
+                    bits.clear();
+                }
+            }
+            Stage::Convolutional { lines, next, skip } => {
+                let line = &mut lines[*next];
+                line.push_back(bit);
+                let bit = line.pop_front().unwrap_or(bit);
+                *next = (*next + 1) % lines.len();
+                match *skip {
+                    0 => output.push(bit),
+                    _ => *skip -= 1,
+                }
+            }
+        }
+    }
+
+    // Input bits that would finish the current block or flush the delay lines
+    fn to_flush(&self, interleaving: Interleaving) -> usize {
+        match self {
+            Stage::Block { bits, .. } if bits.is_empty() => 0,
+            Stage::Block {
+                rows,
+                columns,
+                bits,
+            } => rows * columns - bits.len(),
+            Stage::Convolutional { .. } => interleaving.latency(),
+        }
+    }
+}
+
+pub struct InterleaveWriter<W: BitWrite> {
+    inner: W,
+    interleaving: Interleaving,
+    stage: Stage,
+    // Bits written since the last flush, to know whether a convolutional one needs flushing
+    pending: bool,
+    // Bits that have gone into the interleaver, flushes included, which is how far along the
+    // deinterleaved stream is
+    bits_in: u64,
+}
+
+impl<W: BitWrite> InterleaveWriter<W> {
+    pub fn new(inner: W, interleaving: Interleaving) -> Result<InterleaveWriter<W>, Error> {
+        interleaving.check()?;
+        Ok(InterleaveWriter {
+            inner,
+            interleaving,
+            stage: Stage::new(interleaving, false),
+            pending: false,
+            bits_in: 0,
+        })
+    }
+
+    fn write_out(&mut self, bits: &[bool]) -> Result<(), Error> {
+        let bit_order = self.inner.bit_order();
+        for chunk in bits.chunks(128) {
+            let value = chunk
+                .iter()
+                .enumerate()
+                .fold(0u128, |value, (index, &bit)| {
+                    let shift = match bit_order {
+                        BitOrder::MsbFirst => chunk.len() - 1 - index,
+                        BitOrder::LsbFirst => index,
+                    };
+                    value | (bit as u128) << shift
+                });
+            self.inner.write_bits(value, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    // Fills a started block with zeros, or pushes zeros through a convolutional interleaver's
+    // delay lines, so everything written so far is out. The convolutional flush is for the end of
+    // the stream, since its zeros come out of the deinterleaver too.
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if !self.pending {
+            return Ok(());
+        }
+        let mut output = Vec::new();
+        let flush = self.stage.to_flush(self.interleaving);
+        for _ in 0..flush {
+            self.stage.push(false, &mut output);
+        }
+        self.bits_in += flush as u64;
+        self.pending = false;
+        self.write_out(&output)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Anything still in the interleaver is dropped unless end_block is called first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for InterleaveWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let bit_order = self.inner.bit_order();
+        let mut output = Vec::new();
+        for index in 0..number_of_bits {
+            let shift = match bit_order {
+                BitOrder::MsbFirst => number_of_bits - 1 - index,
+                BitOrder::LsbFirst => index,
+            };
+            self.stage.push(bits >> shift & 1 == 1, &mut output);
+        }
+        self.pending |= number_of_bits > 0;
+        self.bits_in += number_of_bits as u64;
+        self.write_out(&output)
+    }
+
+    // Bits that have come out of the interleaver
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    // The padding goes through the interleaver, so it lands where a Reader over the
+    // DeinterleaveReader expects it in align_to_byte
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        let padding = (8 - self.bits_in % 8) as usize % 8;
+        self.write_bits(0, padding)?;
+        Ok(padding)
+    }
+}
+
+// Undoes an InterleaveWriter's interleaving on the raw bytes under a Reader. A block cut short at
+// the end of the stream is dropped, since it can't be put back in order, and a last byte that's
+// only partly filled is padded with zeros.
+pub struct DeinterleaveReader<R: Read> {
+    inner: R,
+    bit_order: BitOrder,
+    stage: Stage,
+    collector: ByteCollector,
+    ready: VecDeque<u8>,
+    done: bool,
+}
+
+impl<R: Read> DeinterleaveReader<R> {
+    pub fn new(
+        inner: R,
+        interleaving: Interleaving,
+        bit_order: BitOrder,
+    ) -> Result<DeinterleaveReader<R>, Error> {
+        interleaving.check()?;
+        Ok(DeinterleaveReader {
+            inner,
+            bit_order,
+            stage: Stage::new(interleaving, true),
+            collector: ByteCollector::new(bit_order),
+            ready: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; 512];
+        while self.ready.is_empty() && !self.done {
+            let count = match self.inner.read(&mut buffer) {
+                Ok(count) => count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            let mut output = Vec::new();
+            for &byte in &buffer[..count] {
+                for index in 0..8 {
+                    let bit = match self.bit_order {
+                        BitOrder::MsbFirst => byte >> (7 - index) & 1,
+                        BitOrder::LsbFirst => byte >> index & 1,
+                    };
+                    self.stage.push(bit == 1, &mut output);
+                }
+            }
+            if count == 0 {
+                self.done = true;
+                if !self.collector.is_aligned() {
+                    let padding = 8 - (self.collector.bits() % 8) as usize;
+                    output.resize(output.len() + padding, false);
+                }
+            }
+            let ready = &mut self.ready;
+            for bit in output {
+                // One bit always fits
+                let _ = self
+                    .collector
+                    .push(bit as u128, 1, |byte| ready.push_back(byte));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DeinterleaveReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let count = buffer.len().min(self.ready.len());
+        for (slot, byte) in buffer.iter_mut().zip(self.ready.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hamming::{Hamming, HammingWriter};
+    use crate::{BitRead, Reader, Writer};
+    use std::io::Cursor;
+
+    #[test]
+    pub fn block_transposes() {
+        let interleaving = Interleaving::Block {
+            rows: 2,
+            columns: 4,
+        };
+        let mut output = InterleaveWriter::new(Writer::new(Vec::new()), interleaving).unwrap();
+        output.write_bits(0b1100_1010, 8).unwrap();
+        // Rows 1100 and 1010 read down the columns
+        assert_eq!(output.into_inner().into_inner().unwrap(), [0b1110_0100]);
+
+        let reader = DeinterleaveReader::new(
+            Cursor::new(vec![0b1110_0100]),
+            interleaving,
+            BitOrder::MsbFirst,
+        )
+        .unwrap();
+        assert_eq!(Reader::new(reader).read_byte().unwrap(), 0b1100_1010);
+        assert!(InterleaveWriter::new(
+            Writer::new(Vec::new()),
+            Interleaving::Block {
+                rows: 0,
+                columns: 4
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn spreads_a_burst_across_codewords() {
+        let code = Hamming::new(8).unwrap();
+        // Four whole 16 by 13 blocks of codewords
+        let data: Vec<u8> = (0..64).map(|index| index * 3).collect();
+        for interleaving in [
+            Interleaving::Block {
+                rows: 16,
+                columns: 13,
+            },
+            Interleaving::Convolutional {
+                branches: 13,
+                delay: 2,
+            },
+        ] {
+            let output = InterleaveWriter::new(Writer::new(Vec::new()), interleaving).unwrap();
+            let mut coded = HammingWriter::new(output, code);
+            coded.write_bytes(data.clone()).unwrap();
+            let mut output = coded.into_inner();
+            output.end_block().unwrap();
+            output.pad_to_byte().unwrap();
+            let mut bytes = output.into_inner().into_inner().unwrap();
+            // A burst of 12 bits, far too many for any one codeword
+            bytes[40] ^= 0xFF;
+            bytes[41] ^= 0xF0;
+
+            let input =
+                DeinterleaveReader::new(Cursor::new(bytes), interleaving, BitOrder::MsbFirst);
+            let mut reader = Reader::new(input.unwrap());
+            let mut decoded = reader.hamming(code);
+            assert_eq!(decoded.read_bytes(data.len()).unwrap(), data);
+            assert_eq!(decoded.corrected().len(), 12);
+        }
+    }
+
+    #[test]
+    pub fn pads_before_deinterleaving() {
+        for interleaving in [
+            Interleaving::Block {
+                rows: 2,
+                columns: 4,
+            },
+            Interleaving::Convolutional {
+                branches: 3,
+                delay: 1,
+            },
+        ] {
+            let mut output = InterleaveWriter::new(Writer::new(Vec::new()), interleaving).unwrap();
+            output.write_bits(0b101, 3).unwrap();
+            assert_eq!(output.pad_to_byte().unwrap(), 5);
+            output.write_byte(0xA5).unwrap();
+            output.end_block().unwrap();
+            let bytes = output.into_inner().into_inner().unwrap();
+
+            let input =
+                DeinterleaveReader::new(Cursor::new(bytes), interleaving, BitOrder::MsbFirst);
+            let mut reader = Reader::new(input.unwrap());
+            assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+            reader.align_to_byte(true).unwrap();
+            assert_eq!(reader.read_byte().unwrap(), 0xA5);
+        }
+    }
+}
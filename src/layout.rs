@@ -0,0 +1,392 @@
+// Unsigned fields packed back to back, described at run time rather than by a struct, for tools
+// that get their formats from config files:
+//   Layout::new().field("version", 3).field("flags", 5)
+// or the same from text, as name:width pairs split by commas or whitespace:
+//   "version:3, flags:5".parse::<Layout>()
+// Fields can also be pinned to where a spec puts them with field_at (name:width@start in text),
+// numbered MSB0 (bit 0 comes first in the stream) or LSB0 (bit 0 is the last bit of the record,
+// as register diagrams number them). Bits no field covers are reserved, skipped when reading and
+// written as zeros. The builder can't fail, so widths, overlaps and duplicate names are checked
+// when the layout is used.
+use crate::bit_write::BitWrite;
+use crate::Reader;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Numbering {
+    #[default]
+    Msb0,
+    Lsb0,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LayoutField {
+    name: String,
+    width: usize,
+    // Where the spec numbers its first bit, None to follow the previous field
+    start: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    fields: Vec<LayoutField>,
+    numbering: Numbering,
+    record_bits: Option<u64>,
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout::default()
+    }
+
+    pub fn field(mut self, name: &str, width: usize) -> Layout {
+        self.fields.push(LayoutField {
+            name: name.to_string(),
+            width,
+            start: None,
+        });
+        self
+    }
+
+    // A field covering bits start to start + width - 1 in the layout's numbering. Under LSB0 that
+    // makes start its least significant bit.
+    pub fn field_at(mut self, name: &str, start: u64, width: usize) -> Layout {
+        self.fields.push(LayoutField {
+            name: name.to_string(),
+            width,
+            start: Some(start),
+        });
+        self
+    }
+
+    pub fn with_numbering(mut self, numbering: Numbering) -> Layout {
+        self.numbering = numbering;
+        self
+    }
+
+    // The whole record's size, for LSB0 layouts whose top bits are reserved. Otherwise a record
+    // ends with its last field.
+    pub fn with_record_bits(mut self, record_bits: u64) -> Layout {
+        self.record_bits = Some(record_bits);
+        self
+    }
+
+    pub fn numbering(&self) -> Numbering {
+        self.numbering
+    }
+
+    // Name and width of each field, in the order they were added
+    pub fn fields(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.width))
+    }
+
+    // Size of the record positioned fields are numbered within. The sums here and in offsets
+    // saturate, and validate turns a field that ran into u64::MAX into an error.
+    fn numbered_bits(&self) -> u64 {
+        self.record_bits.unwrap_or_else(|| {
+            self.fields
+                .iter()
+                .filter_map(|field| Some(field.start?.saturating_add(field.width as u64)))
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    // Each field with the offset of its first bit in the stream, in the order they were added
+    fn offsets(&self) -> Vec<(&LayoutField, u64)> {
+        let numbered_bits = self.numbered_bits();
+        let mut next = 0;
+        let mut placed = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let offset = match (field.start, self.numbering) {
+                (None, _) => next,
+                (Some(start), Numbering::Msb0) => start,
+                (Some(start), Numbering::Lsb0) => {
+                    numbered_bits.saturating_sub(start.saturating_add(field.width as u64))
+                }
+            };
+            next = offset.saturating_add(field.width as u64);
+            placed.push((field, offset));
+        }
+        placed
+    }
+
+    pub fn bit_len(&self) -> u64 {
+        self.offsets()
+            .iter()
+            .map(|(field, offset)| offset.saturating_add(field.width as u64))
+            .chain(self.record_bits)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        for (index, field) in self.fields.iter().enumerate() {
+            if field.width == 0 || field.width > 128 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Field {} must be between 1 and 128 bits", field.name),
+                ));
+            }
+            if self.fields[..index]
+                .iter()
+                .any(|other| other.name == field.name)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Field {} is already defined", field.name),
+                ));
+            }
+            if let Some(start) = field.start {
+                match start.checked_add(field.width as u64) {
+                    Some(end) if end <= self.numbered_bits() => {}
+                    _ => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Field {} runs past the end of the record", field.name),
+                        ));
+                    }
+                }
+            }
+        }
+        let placed = self.stream_order();
+        if let Some((field, _)) = placed
+            .iter()
+            .find(|(field, offset)| offset.checked_add(field.width as u64).is_none())
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Field {} ends past the last bit a stream can have",
+                    field.name
+                ),
+            ));
+        }
+        for pair in placed.windows(2) {
+            let ((first, first_offset), (second, second_offset)) = (pair[0], pair[1]);
+            if first_offset + first.width as u64 > second_offset {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Fields {} and {} overlap", first.name, second.name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn stream_order(&self) -> Vec<(&LayoutField, u64)> {
+        let mut placed = self.offsets();
+        placed.sort_by_key(|(_, offset)| *offset);
+        placed
+    }
+
+    pub fn read<R: Read>(&self, reader: &mut Reader<R>) -> Result<BTreeMap<String, u128>, Error> {
+        self.validate()?;
+        let mut values = BTreeMap::new();
+        let mut position = 0;
+        for (field, offset) in self.stream_order() {
+            reader.skip_bits(offset - position)?;
+            values.insert(field.name.clone(), reader.read_bits(field.width)?);
+            position = offset + field.width as u64;
+        }
+        reader.skip_bits(self.bit_len() - position)?;
+        Ok(values)
+    }
+
+    // values needs one entry per field and nothing else, so a misspelt name isn't dropped quietly
+    pub fn write<W: BitWrite>(
+        &self,
+        writer: &mut W,
+        values: &BTreeMap<String, u128>,
+    ) -> Result<(), Error> {
+        self.validate()?;
+        if let Some(name) = values
+            .keys()
+            .find(|name| !self.fields.iter().any(|field| &field.name == *name))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Field {} is not in the layout", name),
+            ));
+        }
+        let mut position = 0;
+        for (field, offset) in self.stream_order() {
+            let value = values.get(&field.name).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("No value for field {}", field.name),
+                )
+            })?;
+            write_reserved(writer, offset - position)?;
+            writer.write_bits_checked(*value, field.width)?;
+            position = offset + field.width as u64;
+        }
+        write_reserved(writer, self.bit_len() - position)
+    }
+}
+
+fn write_reserved<W: BitWrite>(writer: &mut W, mut count: u64) -> Result<(), Error> {
+    while count > 0 {
+        let chunk = count.min(128);
+        writer.write_bits(0, chunk as usize)?;
+        count -= chunk;
+    }
+    Ok(())
+}
+
+// An optional msb0 or lsb0 first, then name:width or name:width@start entries
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(description: &str) -> Result<Layout, Error> {
+        let mut layout = Layout::new();
+        let mut entries = description
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|entry| !entry.is_empty())
+            .peekable();
+        match entries.peek() {
+            Some(&"msb0") => {
+                entries.next();
+            }
+            Some(&"lsb0") => {
+                entries.next();
+                layout = layout.with_numbering(Numbering::Lsb0);
+            }
+            _ => {}
+        }
+        for entry in entries {
+            let bad_entry = || {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Expected name:width or name:width@start, got {}", entry),
+                )
+            };
+            let (name, rest) = entry.split_once(':').ok_or_else(bad_entry)?;
+            layout = match rest.split_once('@') {
+                None => layout.field(name, rest.parse().map_err(|_| bad_entry())?),
+                Some((width, start)) => layout.field_at(
+                    name,
+                    start.parse().map_err(|_| bad_entry())?,
+                    width.parse().map_err(|_| bad_entry())?,
+                ),
+            };
+        }
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn round_trips() {
+        let layout = Layout::new()
+            .field("version", 3)
+            .field("flags", 5)
+            .field("length", 12);
+        assert_eq!(layout.bit_len(), 20);
+
+        let mut values = BTreeMap::new();
+        values.insert("version".to_string(), 5);
+        values.insert("flags".to_string(), 0b1_0010);
+        values.insert("length".to_string(), 0xABC);
+        let mut writer = Writer::new(Vec::new());
+        layout.write(&mut writer, &values).unwrap();
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes, [0b1011_0010, 0xAB, 0xC0]);
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        assert_eq!(layout.read(&mut reader).unwrap(), values);
+
+        // Bad values
+        values.insert("version".to_string(), 8);
+        assert!(layout.write(&mut Writer::new(Vec::new()), &values).is_err());
+        values.insert("version".to_string(), 0);
+        values.insert("verison".to_string(), 1);
+        assert!(layout.write(&mut Writer::new(Vec::new()), &values).is_err());
+        values.remove("verison");
+        values.remove("length");
+        assert!(layout.write(&mut Writer::new(Vec::new()), &values).is_err());
+    }
+
+    #[test]
+    pub fn parses_descriptions() {
+        let layout: Layout = "version:3, flags:5\n length:12".parse().unwrap();
+        let expected = Layout::new()
+            .field("version", 3)
+            .field("flags", 5)
+            .field("length", 12);
+        assert_eq!(layout, expected);
+        assert_eq!(
+            layout.fields().collect::<Vec<_>>(),
+            [("version", 3), ("flags", 5), ("length", 12)]
+        );
+
+        assert!("version:3 flags".parse::<Layout>().is_err());
+        assert!("version:x".parse::<Layout>().is_err());
+        assert!("version:3 version:4".parse::<Layout>().is_err());
+        assert!("wide:129".parse::<Layout>().is_err());
+        assert!(Layout::new().field("empty", 0).validate().is_err());
+    }
+
+    #[test]
+    pub fn numbering() {
+        // A register diagram: bits 7 to 4 are flags, 1 to 0 the mode, 3 and 2 reserved
+        let lsb0 = Layout::new()
+            .with_numbering(Numbering::Lsb0)
+            .field_at("mode", 0, 2)
+            .field_at("flags", 4, 4);
+        // The same thing as a spec numbering MSB0 would give it
+        let msb0 = Layout::new().field_at("flags", 0, 4).field_at("mode", 6, 2);
+        assert_eq!(lsb0.bit_len(), 8);
+
+        let mut values = BTreeMap::new();
+        values.insert("flags".to_string(), 0b1010);
+        values.insert("mode".to_string(), 0b11);
+        for layout in [&lsb0, &msb0] {
+            let mut writer = Writer::new(Vec::new());
+            layout.write(&mut writer, &values).unwrap();
+            writer.write_bits(0xF, 4).unwrap();
+            let bytes = writer.into_inner().unwrap();
+            assert_eq!(bytes, [0b1010_0011, 0xF0]);
+
+            let mut reader = Reader::new(Cursor::new(bytes));
+            assert_eq!(layout.read(&mut reader).unwrap(), values);
+            assert_eq!(reader.read_bits(4).unwrap(), 0xF);
+        }
+
+        // Reserved top bits only show up with an explicit record size
+        let padded = lsb0.clone().with_record_bits(16);
+        assert_eq!(padded.bit_len(), 16);
+        let mut writer = Writer::new(Vec::new());
+        padded.write(&mut writer, &values).unwrap();
+        assert_eq!(writer.into_inner().unwrap(), [0, 0b1010_0011]);
+
+        // Sequential fields follow the one before them in the stream
+        let mixed = Layout::new().field_at("low", 4, 4).field("after", 4);
+        assert_eq!(mixed.bit_len(), 12);
+
+        assert_eq!("lsb0 mode:2@0, flags:4@4".parse::<Layout>().unwrap(), lsb0);
+        assert!(Layout::new()
+            .field_at("a", 0, 4)
+            .field_at("b", 3, 2)
+            .validate()
+            .is_err());
+        assert!(padded.field_at("high", 15, 2).validate().is_err());
+        assert!("flags:4@x".parse::<Layout>().is_err());
+
+        // Fields whose ends don't fit in a u64
+        assert!("a:4@18446744073709551615".parse::<Layout>().is_err());
+        let past_the_end = Layout::new().field_at("a", u64::MAX - 4, 4).field("b", 4);
+        assert!(past_the_end.validate().is_err());
+        assert_eq!(past_the_end.bit_len(), u64::MAX);
+    }
+}
@@ -0,0 +1,542 @@
+// Low-density parity-check codes from a parity-check matrix, given as the columns set in each row.
+// Encoding is systematic: the matrix is brought to reduced row echelon form once, from the right,
+// so the columns that end up as pivots carry parity and the rest carry the data bits in order.
+// For the usual H = [A | B] with B invertible that's the data first and the parity after it. The
+// elimination is dense, which is fine for codes up to a few thousand bits; the long structured
+// codes of DVB-S2 and 5G want their own encoders, though their matrices decode fine here.
+// Decoding is either bit flipping over hard bits or normalized min-sum belief propagation over
+// log-likelihood ratios (positive for a 0). LdpcWriter and Reader::ldpc do it a block at a time as
+// the stream passes, the reader running min-sum over the bits it got.
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+// Scale on min-sum's check messages, which otherwise overestimate
+const MIN_SUM_SCALE: f32 = 0.75;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ldpc {
+    columns: usize,
+    // The columns set in each row, and the rows set in each column
+    checks: Vec<Vec<usize>>,
+    variables: Vec<Vec<usize>>,
+    // Columns the data goes in, in order, and for every parity column the data bits (by index)
+    // it's the sum of
+    data_columns: Vec<usize>,
+    parity: Vec<(usize, Vec<usize>)>,
+}
+
+impl Ldpc {
+    pub fn from_rows(columns: usize, rows: &[Vec<usize>]) -> Result<Ldpc, Error> {
+        let mut checks = Vec::with_capacity(rows.len());
+        let mut variables = vec![Vec::new(); columns];
+        for (row, set) in rows.iter().enumerate() {
+            let mut set = set.clone();
+            set.sort_unstable();
+            set.dedup();
+            if set.iter().any(|&column| column >= columns) {
+                return Err(Error::invalid_input(
+                    0,
+                    "LDPC parity-check row has a column past the end",
+                ));
+            }
+            for &column in &set {
+                variables[column].push(row);
+            }
+            checks.push(set);
+        }
+
+        // Gaussian elimination over GF(2), picking pivots from the rightmost column down
+        let words = columns.div_ceil(64);
+        let mut dense: Vec<Vec<u64>> = checks
+            .iter()
+            .map(|set| {
+                let mut row = vec![0u64; words];
+                for &column in set {
+                    row[column / 64] |= 1 << (column % 64);
+                }
+                row
+            })
+            .collect();
+        let is_set = |row: &[u64], column: usize| row[column / 64] >> (column % 64) & 1 == 1;
+        let mut pivots = Vec::new();
+        let mut rank = 0;
+        for column in (0..columns).rev() {
+            let found = match (rank..dense.len()).find(|&row| is_set(&dense[row], column)) {
+                Some(row) => row,
+                None => continue,
+            };
+            dense.swap(rank, found);
+            let pivot_row = dense[rank].clone();
+            for (row, other) in dense.iter_mut().enumerate() {
+                if row != rank && is_set(other, column) {
+                    for (word, &pivot_word) in other.iter_mut().zip(&pivot_row) {
+                        *word ^= pivot_word;
+                    }
+                }
+            }
+            pivots.push(column);
+            rank += 1;
+        }
+        if rank == columns {
+            return Err(Error::invalid_input(
+                0,
+                "LDPC parity-check matrix leaves no data bits",
+            ));
+        }
+        let mut is_pivot = vec![false; columns];
+        for &column in &pivots {
+            is_pivot[column] = true;
+        }
+        let data_columns: Vec<usize> = (0..columns).filter(|&column| !is_pivot[column]).collect();
+        let parity = pivots
+            .iter()
+            .zip(&dense)
+            .map(|(&pivot, row)| {
+                let sources = data_columns
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &column)| is_set(row, column))
+                    .map(|(index, _)| index)
+                    .collect();
+                (pivot, sources)
+            })
+            .collect();
+        Ok(Ldpc {
+            columns,
+            checks,
+            variables,
+            data_columns,
+            parity,
+        })
+    }
+
+    pub fn code_bits(&self) -> usize {
+        self.columns
+    }
+
+    pub fn data_bits(&self) -> usize {
+        self.data_columns.len()
+    }
+
+    pub fn encode(&self, data: &[bool]) -> Result<Vec<bool>, Error> {
+        if data.len() != self.data_bits() {
+            return Err(Error::invalid_input(
+                0,
+                "LDPC data must be exactly data_bits long",
+            ));
+        }
+        let mut codeword = vec![false; self.columns];
+        for (&column, &bit) in self.data_columns.iter().zip(data) {
+            codeword[column] = bit;
+        }
+        for (column, sources) in &self.parity {
+            codeword[*column] = sources.iter().fold(false, |sum, &index| sum ^ data[index]);
+        }
+        Ok(codeword)
+    }
+
+    // The data bits of a codeword
+    pub fn data(&self, codeword: &[bool]) -> Vec<bool> {
+        self.data_columns
+            .iter()
+            .map(|&column| codeword[column])
+            .collect()
+    }
+
+    pub fn is_codeword(&self, codeword: &[bool]) -> bool {
+        codeword.len() == self.columns
+            && self.checks.iter().all(|set| {
+                !set.iter()
+                    .fold(false, |sum, &column| sum ^ codeword[column])
+            })
+    }
+
+    // Flips the bits in the most failed checks until they all pass. None if they still don't
+    // after max_iterations.
+    pub fn decode_hard(&self, bits: &[bool], max_iterations: usize) -> Option<Vec<bool>> {
+        if bits.len() != self.columns {
+            return None;
+        }
+        let mut bits = bits.to_vec();
+        for _ in 0..max_iterations {
+            let failed: Vec<bool> = self
+                .checks
+                .iter()
+                .map(|set| set.iter().fold(false, |sum, &column| sum ^ bits[column]))
+                .collect();
+            if !failed.contains(&true) {
+                return Some(bits);
+            }
+            let votes: Vec<usize> = self
+                .variables
+                .iter()
+                .map(|rows| rows.iter().filter(|&&row| failed[row]).count())
+                .collect();
+            let most = votes.iter().copied().max().unwrap_or(0);
+            for (bit, &count) in bits.iter_mut().zip(&votes) {
+                if count == most {
+                    *bit = !*bit;
+                }
+            }
+        }
+        self.is_codeword(&bits).then_some(bits)
+    }
+
+    // Normalized min-sum over log-likelihood ratios, positive meaning a 0 is more likely. None if
+    // the checks don't all pass within max_iterations.
+    pub fn decode_soft(&self, llrs: &[f32], max_iterations: usize) -> Option<Vec<bool>> {
+        if llrs.len() != self.columns {
+            return None;
+        }
+        // Check to variable messages, lined up with each check's columns
+        let mut messages: Vec<Vec<f32>> =
+            self.checks.iter().map(|set| vec![0.0; set.len()]).collect();
+        let mut totals = llrs.to_vec();
+        for _ in 0..max_iterations {
+            let bits: Vec<bool> = totals.iter().map(|&total| total < 0.0).collect();
+            if self.is_codeword(&bits) {
+                return Some(bits);
+            }
+            for (set, check_messages) in self.checks.iter().zip(messages.iter_mut()) {
+                let incoming: Vec<f32> = set
+                    .iter()
+                    .zip(check_messages.iter())
+                    .map(|(&column, &message)| totals[column] - message)
+                    .collect();
+                let negative = incoming.iter().filter(|&&value| value < 0.0).count() % 2 == 1;
+                let (mut smallest, mut second) = (f32::INFINITY, f32::INFINITY);
+                for &value in &incoming {
+                    let magnitude = value.abs();
+                    if magnitude < smallest {
+                        second = smallest;
+                        smallest = magnitude;
+                    } else if magnitude < second {
+                        second = magnitude;
+                    }
+                }
+                for (message, &value) in check_messages.iter_mut().zip(&incoming) {
+                    let magnitude = if value.abs() == smallest {
+                        second
+                    } else {
+                        smallest
+                    };
+                    let sign = if negative != (value < 0.0) { -1.0 } else { 1.0 };
+                    *message = sign * MIN_SUM_SCALE * magnitude;
+                }
+            }
+            totals = llrs.to_vec();
+            for (set, check_messages) in self.checks.iter().zip(&messages) {
+                for (&column, &message) in set.iter().zip(check_messages) {
+                    totals[column] += message;
+                }
+            }
+        }
+        let bits: Vec<bool> = totals.iter().map(|&total| total < 0.0).collect();
+        self.is_codeword(&bits).then_some(bits)
+    }
+}
+
+// Bits in the order they're sent, to and from values of up to 128 bits in a stream's bit order
+fn from_sent(bits: &[bool], bit_order: BitOrder) -> u128 {
+    bits.iter().enumerate().fold(0, |value, (index, &bit)| {
+        value
+            | (bit as u128)
+                << match bit_order {
+                    BitOrder::MsbFirst => bits.len() - 1 - index,
+                    BitOrder::LsbFirst => index,
+                }
+    })
+}
+
+fn to_sent(value: u128, number_of_bits: usize, bit_order: BitOrder) -> impl Iterator<Item = bool> {
+    (0..number_of_bits).map(move |index| {
+        let shift = match bit_order {
+            BitOrder::MsbFirst => number_of_bits - 1 - index,
+            BitOrder::LsbFirst => index,
+        };
+        value >> shift & 1 == 1
+    })
+}
+
+pub struct LdpcWriter<W: BitWrite> {
+    inner: W,
+    code: Ldpc,
+    block: Vec<bool>,
+}
+
+impl<W: BitWrite> LdpcWriter<W> {
+    pub fn new(inner: W, code: Ldpc) -> LdpcWriter<W> {
+        LdpcWriter {
+            inner,
+            code,
+            block: Vec::new(),
+        }
+    }
+
+    // Fills a started block with zeros and writes it
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.block.resize(self.code.data_bits(), false);
+        let codeword = self.code.encode(&self.block)?;
+        self.block.clear();
+        let bit_order = self.inner.bit_order();
+        for chunk in codeword.chunks(128) {
+            self.inner
+                .write_bits(from_sent(chunk, bit_order), chunk.len())?;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Anything since the last full block is dropped unless end_block is called first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for LdpcWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        for bit in to_sent(bits, number_of_bits, self.inner.bit_order()) {
+            self.block.push(bit);
+            if self.block.len() == self.code.data_bits() {
+                self.end_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Blocks written so far, so data in a started block isn't counted yet
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    // Ends the block first, so the padding goes after its codeword, and gives back the padding
+    // that took
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.end_block()?;
+        self.inner.pad_to_byte()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data written through an LdpcWriter with the same code, decoding each block with up to
+    // max_iterations of min-sum. A block that doesn't decode fails with InvalidData at its first
+    // bit, and reading carries on with the next block.
+    pub fn ldpc(&mut self, code: Ldpc, max_iterations: usize) -> LdpcReader<'_, R> {
+        LdpcReader {
+            reader: self,
+            code,
+            max_iterations,
+            block: Vec::new(),
+            used: 0,
+            corrected: Vec::new(),
+        }
+    }
+}
+
+pub struct LdpcReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    code: Ldpc,
+    max_iterations: usize,
+    // The current block's data, and how many of its bits have been handed out
+    block: Vec<bool>,
+    used: usize,
+    corrected: Vec<u64>,
+}
+
+impl<'a, R: Read> LdpcReader<'a, R> {
+    // Positions of the bits fixed so far
+    pub fn corrected(&self) -> &[u64] {
+        &self.corrected
+    }
+
+    fn next_block(&mut self) -> Result<(), Error> {
+        let position = self.reader.bits_read();
+        let bit_order = self.reader.bit_order();
+        let mut received = Vec::with_capacity(self.code.code_bits());
+        while received.len() < self.code.code_bits() {
+            let count = (self.code.code_bits() - received.len()).min(128);
+            let value = self.reader.read_bits(count)?;
+            received.extend(to_sent(value, count, bit_order));
+        }
+        self.block.clear();
+        self.used = 0;
+        let llrs: Vec<f32> = received
+            .iter()
+            .map(|&bit| if bit { -1.0 } else { 1.0 })
+            .collect();
+        let codeword = match self.code.decode_soft(&llrs, self.max_iterations) {
+            Some(codeword) => codeword,
+            None => {
+                return Err(Error::invalid_data(position, "LDPC block didn't decode"));
+            }
+        };
+        for (index, (&got, &fixed)) in received.iter().zip(&codeword).enumerate() {
+            if got != fixed {
+                self.corrected.push(position + index as u64);
+            }
+        }
+        self.block = self.code.data(&codeword);
+        Ok(())
+    }
+
+    // Drops the rest of a block that was only partly read, like the zeros end_block filled it with
+    pub fn end_block(&mut self) {
+        self.used = self.block.len();
+    }
+}
+
+impl<'a, R: Read> BitRead for LdpcReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let mut bits = Vec::with_capacity(number_of_bits);
+        while bits.len() < number_of_bits {
+            if self.used == self.block.len() {
+                self.next_block()?;
+            }
+            let count = (number_of_bits - bits.len()).min(self.block.len() - self.used);
+            bits.extend_from_slice(&self.block[self.used..self.used + count]);
+            self.used += count;
+        }
+        Ok(from_sent(&bits, self.reader.bit_order()))
+    }
+
+    // Parity included
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Drops the rest of the block and then the padding after it, like LdpcWriter's
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        self.end_block();
+        self.reader.align_to_byte(require_zeros)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    // A regular (3, 6) code with 48 bits, data first: each row has three ones in the data half
+    // and a staircase in the parity half, so the parity half is invertible
+    fn code() -> Ldpc {
+        let rows: Vec<Vec<usize>> = (0..24)
+            .map(|row| {
+                let mut set = vec![row % 24, (row * 5 + 7) % 24, (row * 11 + 3) % 24];
+                set.sort_unstable();
+                set.dedup();
+                set.push(24 + row);
+                if row > 0 {
+                    set.push(24 + row - 1);
+                }
+                set
+            })
+            .collect();
+        Ldpc::from_rows(48, &rows).unwrap()
+    }
+
+    #[test]
+    pub fn encodes_systematically() {
+        let code = code();
+        assert_eq!(code.data_bits(), 24);
+        let data: Vec<bool> = (0..24)
+            .map(|index| index % 3 == 0 || index % 7 == 1)
+            .collect();
+        let codeword = code.encode(&data).unwrap();
+        assert!(code.is_codeword(&codeword));
+        assert_eq!(&codeword[..24], &data[..]);
+        assert_eq!(code.data(&codeword), data);
+
+        // A single flipped bit, in the data or the parity, comes back either way
+        for flipped in [0, 13, 30, 47] {
+            let mut received = codeword.clone();
+            received[flipped] = !received[flipped];
+            assert_eq!(code.decode_hard(&received, 20).unwrap(), codeword);
+            let llrs: Vec<f32> = received
+                .iter()
+                .map(|&bit| if bit { -1.0 } else { 1.0 })
+                .collect();
+            assert_eq!(code.decode_soft(&llrs, 20).unwrap(), codeword);
+        }
+        // Soft input knows which bits are shaky
+        let mut llrs: Vec<f32> = codeword
+            .iter()
+            .map(|&bit| if bit { -4.0 } else { 4.0 })
+            .collect();
+        for index in [2, 9, 26, 40] {
+            llrs[index] = -llrs[index] / 8.0;
+        }
+        assert_eq!(code.decode_soft(&llrs, 20).unwrap(), codeword);
+
+        assert!(Ldpc::from_rows(4, &[vec![0, 4]]).is_err());
+        assert!(Ldpc::from_rows(2, &[vec![0], vec![1]]).is_err());
+    }
+
+    #[test]
+    pub fn protects_a_stream() {
+        let mut output = LdpcWriter::new(Writer::new(Vec::new()), code());
+        output.write_bits(0b101, 3).unwrap();
+        output.write_bytes(b"LDPC".to_vec()).unwrap();
+        output.write_bits(0x1_2345, 17).unwrap();
+        output.end_block().unwrap();
+        // 52 data bits in 3 blocks
+        assert_eq!(output.bits_written(), 3 * 48);
+        let mut bytes = output.into_inner().into_inner().unwrap();
+        bytes[1] ^= 0b0010_0000;
+        bytes[10] ^= 0b1000_0000;
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.ldpc(code(), 20);
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        assert_eq!(input.read_bytes(4).unwrap(), b"LDPC");
+        assert_eq!(input.read_bits(17).unwrap(), 0x1_2345);
+        assert_eq!(input.corrected().len(), 2);
+        input.end_block();
+        assert_eq!(input.bits_read(), 3 * 48);
+    }
+
+    #[test]
+    pub fn pads_in_the_middle_of_a_block() {
+        let mut writer = Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst);
+        // Two bits ahead of the blocks, so a codeword doesn't end on a byte
+        writer.write_bits(0b11, 2).unwrap();
+        let mut output = LdpcWriter::new(writer, code());
+        output.write_bits(0b101, 3).unwrap();
+        assert_eq!(output.pad_to_byte().unwrap(), 6);
+        output.write_bits(0x1_2345, 17).unwrap();
+        output.end_block().unwrap();
+        assert_eq!(output.bits_written(), 2 + 48 + 6 + 48);
+        let bytes = output.into_inner().into_inner().unwrap();
+
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        let mut input = reader.ldpc(code(), 20);
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        input.align_to_byte(true).unwrap();
+        assert_eq!(input.bits_read(), 56);
+        assert_eq!(input.read_bits(17).unwrap(), 0x1_2345);
+        assert!(input.corrected().is_empty());
+    }
+}
@@ -1,5 +1,162 @@
+// The core (Reader, Writer, the traits and the in-memory views) is always built. Everything else
+// sits behind a feature so small targets only compile what they use:
+//   std      Reader and Writer over std::io, and everything built on them
+//   codecs   compression and integer coding (deflate, gzip, zlib, LZW, RLE, delta, ...)
+//   framing  frame codecs, runtime layouts, checksum digests, scramblers, scanning for sync words
+//            and HDLC, AX.25, CAN and CCSDS link framing
+//   fec      error detection and correction (parity, Hamming, Reed-Solomon, LDPC, repetition)
+//            and interleaving
+//   capture  importing logic analyzer and audio captures, and exporting annotations
+//   testing  random and fixed pattern sources and checkers
+// Those six are on by default. The rest (futures, futures-io, bumpalo, bitvec, memmap2, nom, binrw,
+// deku, digest, pcap, udp, serialport, rand, simd, serde, tokio, tokio-codec, wasm-bindgen) are opt
+// in, and all of them turn std on.
+// Without std the crate is no_std with alloc. Reader and Writer are still there, but only over
+// embedded-io's Read and Write (wrapped in EmbeddedIo), the one opt in feature that doesn't need
+// std, for a microcontroller's drivers. BitCursor, SliceReader and BitView read and write in
+// memory, next to BitRead, BitWrite with BitCounter, TeeWriter and ArrayBitWriter, and BitEnum.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "capture")]
+pub mod annotation;
+#[cfg(feature = "bumpalo")]
+mod arena;
+mod array_writer;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod async_bits;
+#[cfg(feature = "framing")]
+pub mod ax25;
+#[cfg(feature = "binrw")]
+mod binrw_bits;
+pub mod bit_cursor;
+mod bit_enum;
+mod bit_order;
+mod bit_read;
+#[cfg(feature = "serde")]
+pub mod bit_serde;
+#[cfg(feature = "bitvec")]
+mod bit_vec;
+mod bit_write;
+#[cfg(feature = "codecs")]
+pub mod bitboard;
+#[cfg(feature = "codecs")]
+pub mod bitshuffle;
+#[cfg(any(feature = "fec", feature = "framing"))]
+mod byte_collector;
+mod byte_io;
+#[cfg(feature = "framing")]
+pub mod can;
+#[cfg(feature = "framing")]
+pub mod ccsds;
+#[cfg(any(feature = "codecs", feature = "framing"))]
+mod crc;
+#[cfg(feature = "codecs")]
+pub mod deflate;
+#[cfg(feature = "deku")]
+mod deku_bits;
+#[cfg(feature = "codecs")]
+pub mod delta;
+#[cfg(feature = "framing")]
+pub mod digest;
+#[cfg(feature = "embedded-io")]
+mod embedded;
+mod error;
+#[cfg(feature = "framing")]
+pub mod frame_codec;
+#[cfg(feature = "framing")]
+pub mod frame_scanner;
+#[cfg(feature = "futures")]
+pub mod frame_stream;
+#[cfg(feature = "futures-io")]
+pub mod futures_io;
+#[cfg(feature = "codecs")]
+pub mod genomic;
+#[cfg(feature = "codecs")]
+pub mod gzip;
+#[cfg(feature = "fec")]
+pub mod hamming;
+#[cfg(feature = "framing")]
+pub mod hdlc;
+#[cfg(feature = "codecs")]
+pub mod hilbert;
+#[cfg(feature = "codecs")]
+pub mod histogram;
+#[cfg(feature = "fec")]
+pub mod interleave;
+#[cfg(feature = "framing")]
+pub mod layout;
+#[cfg(feature = "fec")]
+pub mod ldpc;
+#[cfg(any(feature = "udp", feature = "serialport"))]
+pub mod live;
+#[cfg(feature = "capture")]
+pub mod logic_import;
+#[cfg(feature = "codecs")]
+pub mod lzw;
+#[cfg(feature = "memmap2")]
+pub mod mapped;
+#[cfg(feature = "codecs")]
+pub mod morton;
+#[cfg(feature = "codecs")]
+pub mod mtf;
+#[cfg(feature = "nom")]
+mod nom_bits;
+#[cfg(feature = "simd")]
+mod packing;
+#[cfg(feature = "fec")]
+pub mod parity;
+#[cfg(feature = "testing")]
+pub mod pattern;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod prelude;
+#[cfg(feature = "testing")]
+pub mod random;
+#[cfg(feature = "codecs")]
+pub mod rate;
 mod reader;
+#[cfg(feature = "fec")]
+pub mod reed_solomon;
+#[cfg(feature = "fec")]
+pub mod repetition;
+#[cfg(feature = "codecs")]
+pub mod rle;
+#[cfg(feature = "framing")]
+pub mod scrambler;
+pub mod slice_reader;
+#[cfg(feature = "capture")]
+pub mod slicer;
+#[cfg(feature = "tokio-codec")]
+pub mod tokio_codec;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+#[cfg(feature = "framing")]
+pub mod watchdog;
 mod writer;
+#[cfg(feature = "codecs")]
+pub mod zlib;
+
+// Most elements a decoder reserves up front from a length it has read, so a corrupt length can't
+// make it allocate much before the data runs out. Anything that has to build for 16 bit targets
+// (where 1 << 20 doesn't fit in a usize) uses this instead of a literal.
+#[allow(dead_code)]
+pub(crate) const PREALLOCATE_LIMIT: usize = match 1usize.checked_shl(20) {
+    Some(limit) => limit,
+    None => 1 << 12,
+};
 
-pub use reader::Reader;
-pub use writer::Writer;
+pub use array_writer::ArrayBitWriter;
+pub use bit_enum::{enum_width, BitEnum};
+pub use bit_order::BitOrder;
+pub use bit_read::BitRead;
+pub use bit_write::{BitCounter, BitWrite, TeeWriter};
+#[cfg(feature = "embedded-io")]
+pub use embedded::{EmbeddedBitReader, EmbeddedBitWriter, EmbeddedIo};
+pub use error::Error;
+pub use reader::{Bits, Chunks, LeftoverBits, Reader, TakeBits, TeeReader};
+#[cfg(feature = "tokio")]
+pub use tokio_io::{AsyncBitReader, AsyncBitWriter};
+pub use writer::{FinishPolicy, Writer};
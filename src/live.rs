@@ -0,0 +1,104 @@
+// Sources for reading bits straight off hardware or the network
+use crate::Reader;
+use std::io::Error;
+#[cfg(feature = "udp")]
+use std::io::Read;
+#[cfg(feature = "udp")]
+use std::net::UdpSocket;
+#[cfg(feature = "serialport")]
+use std::time::Duration;
+
+// Largest UDP payload
+#[cfg(feature = "udp")]
+const MAX_DATAGRAM: usize = 65_535;
+
+// Reads datagrams off a socket as one byte stream. Each datagram is received whole (so nothing is
+// truncated by a small read buffer) and a single read never mixes bytes from two datagrams.
+#[cfg(feature = "udp")]
+pub struct UdpSource {
+    socket: UdpSocket,
+    datagram: Vec<u8>,
+    position: usize,
+    datagrams: u64,
+}
+
+#[cfg(feature = "udp")]
+impl UdpSource {
+    pub fn new(socket: UdpSocket) -> UdpSource {
+        UdpSource {
+            socket,
+            datagram: Vec::new(),
+            position: 0,
+            datagrams: 0,
+        }
+    }
+
+    // Datagrams received so far
+    pub fn datagrams(&self) -> u64 {
+        self.datagrams
+    }
+
+    // Whether everything from the last datagram has been handed out
+    pub fn at_datagram_end(&self) -> bool {
+        self.position == self.datagram.len()
+    }
+
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+#[cfg(feature = "udp")]
+impl Read for UdpSource {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        // Empty datagrams are skipped so they don't look like the end of the stream. Socket
+        // timeouts come back as errors.
+        while self.at_datagram_end() {
+            self.datagram.resize(MAX_DATAGRAM, 0);
+            let n = self.socket.recv(&mut self.datagram)?;
+            self.datagram.truncate(n);
+            self.position = 0;
+            self.datagrams += 1;
+        }
+        let n = buf.len().min(self.datagram.len() - self.position);
+        buf[..n].copy_from_slice(&self.datagram[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "udp")]
+pub fn udp_reader(socket: UdpSocket) -> Reader<UdpSource> {
+    Reader::new(UdpSource::new(socket))
+}
+
+// Opens a serial port for reading. Reads that go past timeout without data fail with TimedOut.
+#[cfg(feature = "serialport")]
+pub fn serial_reader(
+    path: &str,
+    baud_rate: u32,
+    timeout: Duration,
+) -> Result<Reader<Box<dyn serialport::SerialPort>>, Error> {
+    let port = serialport::new(path, baud_rate).timeout(timeout).open()?;
+    Ok(Reader::new(port))
+}
+
+#[cfg(all(test, feature = "udp"))]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn reads_across_datagrams() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = receiver.local_addr().unwrap();
+        sender.send_to(&[0b1011_0000, 0xAB], address).unwrap();
+        sender.send_to(&[], address).unwrap();
+        sender.send_to(&[0xCD], address).unwrap();
+
+        let mut reader = udp_reader(receiver);
+        assert_eq!(reader.read_bits(4).unwrap(), 0b1011);
+        reader.align_to_byte(true).unwrap();
+        assert_eq!(reader.read_bits(16).unwrap(), 0xABCD);
+    }
+}
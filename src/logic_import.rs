@@ -0,0 +1,253 @@
+// Turns one channel of a logic analyzer capture into a bit stream: VCD dumps from simulators and
+// scopes, and the raw logic data sigrok writes (in .sr archives or with the binary output format)
+use crate::{Reader, Writer};
+use std::io::{BufRead, BufReader, Cursor, Error, ErrorKind, Read};
+
+// Most samples read_vcd will take from one file, 128 MiB once packed
+const MAX_VCD_SAMPLES: u128 = 1 << 30;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// A channel's samples, one bit each, packed MSB first
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Samples {
+    packed: Vec<u8>,
+    count: usize,
+}
+
+impl Samples {
+    pub(crate) fn from_bits(bits: impl Iterator<Item = bool>) -> Result<Samples, Error> {
+        let mut writer = Writer::new(Vec::new());
+        let mut count = 0;
+        for bit in bits {
+            writer.write_bit(bit)?;
+            count += 1;
+        }
+        Ok(Samples {
+            packed: writer.into_inner()?,
+            count,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // The packed samples. The last byte is padded with zeros, so use len for the real count.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.packed
+    }
+
+    pub fn into_reader(self) -> Reader<Cursor<Vec<u8>>> {
+        Reader::new(Cursor::new(self.packed))
+    }
+}
+
+// Femtoseconds in a VCD time unit
+fn unit_femtoseconds(unit: &str) -> Option<u128> {
+    Some(match unit {
+        "s" => 1_000_000_000_000_000,
+        "ms" => 1_000_000_000_000,
+        "us" => 1_000_000_000,
+        "ns" => 1_000_000,
+        "ps" => 1_000,
+        "fs" => 1,
+        _ => return None,
+    })
+}
+
+// Samples a 1 bit VCD signal sample_rate times a second, from time 0 to the last timestamp.
+// Unknown and high impedance values read as 0. Times are in femtoseconds, so the rate can be at
+// most 10^15.
+pub fn read_vcd<R: Read>(input: R, signal: &str, sample_rate: u64) -> Result<Samples, Error> {
+    let period = 1_000_000_000_000_000 / (sample_rate as u128).max(1);
+    if sample_rate == 0 || period == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Sample rate has to be between 1 and 10^15 a second",
+        ));
+    }
+    let mut tokens = Vec::new();
+    for line in BufReader::new(input).lines() {
+        tokens.extend(line?.split_whitespace().map(str::to_string));
+    }
+
+    let mut timescale: u128 = 1_000_000; // 1ns when the file doesn't say
+    let mut identifier = None;
+    let mut index = 0;
+    // Header sections run from a $keyword to $end
+    while index < tokens.len() {
+        let keyword = tokens[index].clone();
+        let end = tokens[index..]
+            .iter()
+            .position(|token| token == "$end")
+            .map(|offset| index + offset)
+            .ok_or_else(|| invalid(format!("{} has no $end", keyword)))?;
+        let body = &tokens[index + 1..end];
+        index = end + 1;
+        match keyword.as_str() {
+            "$timescale" => {
+                let text: String = body.concat();
+                let split = text
+                    .find(|c: char| !c.is_ascii_digit())
+                    .ok_or_else(|| invalid(format!("Bad timescale {}", text)))?;
+                let amount: u128 = text[..split]
+                    .parse()
+                    .map_err(|_| invalid(format!("Bad timescale {}", text)))?;
+                let unit = unit_femtoseconds(&text[split..])
+                    .ok_or_else(|| invalid(format!("Bad timescale {}", text)))?;
+                timescale = amount
+                    .checked_mul(unit)
+                    .ok_or_else(|| invalid(format!("Timescale {} is too long", text)))?;
+            }
+            // $var wire 1 ! name $end
+            "$var" if body.len() >= 4 && body[3] == signal => {
+                if body[1] != "1" {
+                    return Err(invalid(format!("{} is more than 1 bit wide", signal)));
+                }
+                identifier = Some(body[2].clone());
+            }
+            "$enddefinitions" => break,
+            _ => {}
+        }
+    }
+    let identifier =
+        identifier.ok_or_else(|| invalid(format!("No signal named {} in the VCD", signal)))?;
+
+    // Value changes as (time in femtoseconds, value)
+    let mut changes = Vec::new();
+    let mut time: u128 = 0;
+    let mut values = tokens[index..].iter();
+    while let Some(token) = values.next() {
+        if let Some(stamp) = token.strip_prefix('#') {
+            let stamp: u128 = stamp
+                .parse()
+                .map_err(|_| invalid(format!("Bad timestamp {}", token)))?;
+            time = stamp
+                .checked_mul(timescale)
+                .ok_or_else(|| invalid(format!("Timestamp {} is too late", token)))?;
+        } else if token.starts_with(['b', 'B', 'r', 'R']) {
+            // Vector and real values have the identifier as a separate token
+            values.next();
+        } else if !token.starts_with('$') && token.get(1..) == Some(identifier.as_str()) {
+            changes.push((time, token.starts_with('1')));
+        }
+    }
+
+    if time / period >= MAX_VCD_SAMPLES {
+        return Err(invalid(format!(
+            "VCD runs to more than {} samples at this rate",
+            MAX_VCD_SAMPLES
+        )));
+    }
+    let mut value = false;
+    let mut next_change = 0;
+    let mut bits = Vec::new();
+    let mut sample_time = 0;
+    while sample_time <= time {
+        while next_change < changes.len() && changes[next_change].0 <= sample_time {
+            value = changes[next_change].1;
+            next_change += 1;
+        }
+        bits.push(value);
+        sample_time += period;
+    }
+    Samples::from_bits(bits.into_iter())
+}
+
+// Sigrok logic data is unit_size bytes per sample, little endian, channel n in bit n. Every
+// decimation'th sample is kept, so a capture at 8 times the symbol rate takes a decimation of 8
+// (starting from the first sample).
+pub fn read_sigrok_logic<R: Read>(
+    mut input: R,
+    unit_size: usize,
+    channel: usize,
+    decimation: usize,
+) -> Result<Samples, Error> {
+    if unit_size == 0 || channel >= unit_size * 8 || decimation == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Channel must be inside a non-empty sample, and decimation can't be zero",
+        ));
+    }
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let bits = data
+        .chunks_exact(unit_size)
+        .step_by(decimation)
+        .map(|sample| sample[channel / 8] >> (channel % 8) & 1 != 0);
+    Samples::from_bits(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VCD: &str = "$date today $end
+$timescale 10ns $end
+$scope module top $end
+$var wire 1 ! clk $end
+$var wire 1 \" data $end
+$var wire 8 # bus [7:0] $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+1\"
+b00000000 #
+$end
+#10
+1!
+#20
+0!
+0\"
+#25
+1\"
+#40
+x\"
+";
+
+    #[test]
+    pub fn vcd_channel() {
+        // 10ns units, so a sample every 100ns (10 units) at 10MHz
+        let samples = read_vcd(VCD.as_bytes(), "data", 10_000_000).unwrap();
+        assert_eq!(samples.len(), 5);
+        let mut reader = samples.into_reader();
+        assert_eq!(reader.read_bits(5).unwrap(), 0b11010);
+
+        let clock = read_vcd(VCD.as_bytes(), "clk", 20_000_000).unwrap();
+        assert_eq!(clock.as_bytes(), [0b0011_0000, 0b0000_0000]);
+        assert!(read_vcd(VCD.as_bytes(), "bus", 1000).is_err());
+        assert!(read_vcd(VCD.as_bytes(), "missing", 1000).is_err());
+
+        // Rates past a sample per femtosecond, and times past what can be sampled
+        let error = read_vcd(VCD.as_bytes(), "data", 2_000_000_000_000_000).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let late = VCD.replace("#40", "#400000000000000000000000000000000000");
+        let error = read_vcd(late.as_bytes(), "data", 1000).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let endless = VCD.replace("#40", "#40000000000");
+        let error = read_vcd(endless.as_bytes(), "data", 10_000_000).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    pub fn sigrok_channel() {
+        // Two byte samples, channel 9 is bit 1 of the second byte
+        let data = [0, 2, 0xFF, 0, 0, 2, 0, 0, 0, 2];
+        let samples = read_sigrok_logic(&data[..], 2, 9, 1).unwrap();
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples.as_bytes(), [0b1010_1000]);
+
+        let samples = read_sigrok_logic(&data[..], 2, 9, 2).unwrap();
+        assert_eq!(samples.into_reader().read_bits(3).unwrap(), 0b111);
+        assert!(read_sigrok_logic(&data[..], 2, 16, 1).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{Reader, Writer};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lzw {
+    min_code_size: usize,
+    max_code_size: usize,
+    early_change: bool,
+}
+
+impl Lzw {
+    pub fn new(min_code_size: usize, max_code_size: usize, early_change: bool) -> Lzw {
+        Lzw {
+            min_code_size,
+            max_code_size,
+            early_change,
+        }
+    }
+
+    // GIF image data: literals are min_code_size bits wide and codes grow up to 12 bits
+    pub fn gif(min_code_size: usize) -> Lzw {
+        Lzw::new(min_code_size, 12, false)
+    }
+
+    // TIFF and PDF (EarlyChange 1) streams: 8 bit literals, codes grow one code early
+    pub fn tiff() -> Lzw {
+        Lzw::new(8, 12, true)
+    }
+
+    pub fn initial_width(&self) -> usize {
+        self.min_code_size + 1
+    }
+
+    pub fn clear_code(&self) -> usize {
+        1 << self.min_code_size
+    }
+
+    pub fn end_code(&self) -> usize {
+        self.clear_code() + 1
+    }
+
+    fn first_code(&self) -> usize {
+        self.clear_code() + 2
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.min_code_size == 0 || self.min_code_size > 8 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "LZW min code size must be between 1 and 8 bits",
+            ));
+        }
+        if self.max_code_size <= self.min_code_size || self.max_code_size > 16 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "LZW max code size must be above min code size and at most 16 bits",
+            ));
+        }
+        Ok(())
+    }
+
+    // Number of dictionary entries allowed before the table has to be cleared
+    // In u64 and capped, since 16 bit codes don't fit a 16 bit usize
+    fn table_limit(&self) -> usize {
+        let codes = 1u64 << self.max_code_size;
+        let limit = if self.early_change { codes - 1 } else { codes };
+        usize::try_from(limit).unwrap_or(usize::MAX)
+    }
+
+    // Width needed so the biggest code that can come next fits
+    fn width_for(&self, biggest_code: usize) -> usize {
+        let needed = (usize::BITS - biggest_code.leading_zeros()) as usize;
+        needed.max(self.initial_width()).min(self.max_code_size)
+    }
+
+    pub fn encode<W: Write>(&self, data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+        self.check()?;
+        let mut table: HashMap<(usize, u8), usize> = HashMap::new();
+        let mut next_code = self.first_code();
+        let mut width = self.initial_width();
+        writer.write_bits(self.clear_code() as u128, width)?;
+
+        let mut current: Option<usize> = None;
+        for &byte in data {
+            if byte as usize >= self.clear_code() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Symbol does not fit in LZW min code size",
+                ));
+            }
+            let prefix = match current {
+                None => {
+                    current = Some(byte as usize);
+                    continue;
+                }
+                Some(prefix) => prefix,
+            };
+            if let Some(&code) = table.get(&(prefix, byte)) {
+                current = Some(code);
+                continue;
+            }
+
+            writer.write_bits(prefix as u128, width)?;
+            if next_code < self.table_limit() {
+                table.insert((prefix, byte), next_code);
+                next_code += 1;
+            } else {
+                // Table is full, so start over
+                writer.write_bits(self.clear_code() as u128, width)?;
+                table.clear();
+                next_code = self.first_code();
+            }
+            // The decoder lags one entry behind us, so it grows with the code before ours
+            width = if self.early_change {
+                self.width_for(next_code)
+            } else {
+                self.width_for(next_code - 1)
+            };
+            current = Some(byte as usize);
+        }
+
+        if let Some(prefix) = current {
+            writer.write_bits(prefix as u128, width)?;
+            // Account for the entry the decoder adds when it reads the last code
+            if next_code < self.table_limit() {
+                next_code += 1;
+            }
+            width = if self.early_change {
+                self.width_for(next_code)
+            } else {
+                self.width_for(next_code - 1)
+            };
+        }
+        Ok(writer.write_bits(self.end_code() as u128, width)?)
+    }
+
+    pub fn decode<R: Read>(&self, reader: &mut Reader<R>) -> Result<Vec<u8>, Error> {
+        self.check()?;
+        // Each entry is (prefix code, last byte); literals have no prefix
+        let mut table: Vec<(Option<usize>, u8)> = Vec::new();
+        let reset = |table: &mut Vec<(Option<usize>, u8)>| {
+            table.clear();
+            for literal in 0..self.first_code() {
+                table.push((None, literal as u8));
+            }
+        };
+        reset(&mut table);
+
+        let mut output: Vec<u8> = Vec::new();
+        let mut previous: Option<usize> = None;
+        let mut width = self.initial_width();
+        loop {
+            let code = reader.read_bits(width)? as usize;
+            if code == self.clear_code() {
+                reset(&mut table);
+                previous = None;
+                width = self.initial_width();
+                continue;
+            }
+            if code == self.end_code() {
+                return Ok(output);
+            }
+
+            let start = output.len();
+            match previous {
+                None => {
+                    if code >= self.clear_code() {
+                        return Err(Error::new(ErrorKind::InvalidData, "Invalid first LZW code"));
+                    }
+                    output.push(code as u8);
+                }
+                Some(previous) => {
+                    if code < table.len() {
+                        Lzw::expand(&table, code, &mut output);
+                        if table.len() < self.table_limit() {
+                            table.push((Some(previous), output[start]));
+                        }
+                    } else if code == table.len() && table.len() < self.table_limit() {
+                        // The KwKwK case: code refers to the entry being built right now
+                        Lzw::expand(&table, previous, &mut output);
+                        let first = output[start];
+                        output.push(first);
+                        table.push((Some(previous), first));
+                    } else {
+                        return Err(Error::new(ErrorKind::InvalidData, "Invalid LZW code"));
+                    }
+                }
+            }
+            previous = Some(code);
+            width = if self.early_change {
+                self.width_for(table.len() + 1)
+            } else {
+                self.width_for(table.len())
+            };
+        }
+    }
+
+    fn expand(table: &[(Option<usize>, u8)], code: usize, output: &mut Vec<u8>) {
+        let start = output.len();
+        let mut next = Some(code);
+        while let Some(code) = next {
+            let (prefix, byte) = table[code];
+            output.push(byte);
+            next = prefix;
+        }
+        // Entries were walked from the back
+        output[start..].reverse();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+    use std::io::Cursor;
+
+    fn round_trip(lzw: Lzw, bit_order: BitOrder, data: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), bit_order);
+        lzw.encode(data, &mut writer).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.get_ref().get_ref().clone();
+
+        let mut reader = Reader::with_bit_order(Cursor::new(encoded.clone()), bit_order);
+        assert_eq!(lzw.decode(&mut reader).unwrap(), data);
+        encoded
+    }
+
+    #[test]
+    pub fn gif_known_stream() {
+        // 2 bit literals: clear(4) 1 6 6 2 end(5) with 3 bit codes, LSB first
+        let encoded = round_trip(Lzw::gif(2), BitOrder::LsbFirst, &[1, 1, 1, 1, 1, 2]);
+        assert_eq!(encoded, vec![0b1000_1100, 0b0010_1101, 0b0000_0101]);
+    }
+
+    #[test]
+    pub fn tiff_round_trip() {
+        let data = b"TOBEORNOTTOBEORTOBEORNOT#".to_vec();
+        round_trip(Lzw::tiff(), BitOrder::MsbFirst, &data);
+    }
+
+    #[test]
+    pub fn grows_and_clears_table() {
+        // Enough varied data to walk the widths all the way up and force a clear
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        round_trip(Lzw::tiff(), BitOrder::MsbFirst, &data);
+        round_trip(Lzw::gif(8), BitOrder::LsbFirst, &data);
+        round_trip(Lzw::new(8, 9, false), BitOrder::LsbFirst, &data);
+        round_trip(Lzw::new(8, 9, true), BitOrder::MsbFirst, &data);
+    }
+
+    #[test]
+    pub fn empty_input() {
+        round_trip(Lzw::gif(4), BitOrder::LsbFirst, &[]);
+    }
+
+    #[test]
+    pub fn symbol_too_wide() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(Lzw::gif(2).encode(&[4], &mut writer).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+// Reads bits straight out of a memory mapped file, so huge read-mostly files (index segments and
+// the like) aren't copied into memory and seeking anywhere in them is free
+use crate::slice_reader::{BitView, SliceReader};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::Error;
+use std::path::Path;
+
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    // The file mustn't be changed or truncated by anyone while it's mapped. The OS doesn't stop
+    // that, and reads would see it happen (or fault, when the file shrinks).
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedFile, Error> {
+        let file = File::open(path)?;
+        // See above for the contract that makes this sound
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedFile { map })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub fn bits(&self) -> BitView<'_> {
+        BitView::new(&self.map)
+    }
+
+    // A reader over the whole file. Use its seek_bits for random access.
+    pub fn reader(&self) -> SliceReader<'_> {
+        SliceReader::new(&self.map)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    pub fn reads_mapped_file() {
+        let path = std::env::temp_dir().join(format!("bit_streamer_mapped_{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[0b1011_0000, 0xAB, 0xCD]).unwrap();
+        drop(file);
+
+        let mapped = MappedFile::open(&path).unwrap();
+        let mut reader = mapped.reader();
+        reader.seek_bits(8).unwrap();
+        assert_eq!(reader.read_byte_slice(2).unwrap(), [0xAB, 0xCD]);
+        reader.seek_bits(0).unwrap();
+        assert_eq!(reader.read_bits(4).unwrap(), 0b1011);
+        assert_eq!(mapped.bits().len(), 24);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Spreads the bits of a 32 bit value out so there's a zero between each one
+fn spread_2d(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    x = (x | (x << 1)) & 0x5555_5555_5555_5555;
+    x
+}
+
+fn compact_2d(code: u64) -> u32 {
+    let mut x = code & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x >> 16)) & 0x0000_0000_FFFF_FFFF;
+    x as u32
+}
+
+// Spreads the low 21 bits of a value out so there are two zeros between each one
+fn spread_3d(value: u32) -> u64 {
+    let mut x = value as u64 & 0x1F_FFFF;
+    x = (x | (x << 32)) & 0x001F_0000_0000_FFFF;
+    x = (x | (x << 16)) & 0x001F_0000_FF00_00FF;
+    x = (x | (x << 8)) & 0x100F_00F0_0F00_F00F;
+    x = (x | (x << 4)) & 0x10C3_0C30_C30C_30C3;
+    x = (x | (x << 2)) & 0x1249_2492_4924_9249;
+    x
+}
+
+fn compact_3d(code: u64) -> u32 {
+    let mut x = code & 0x1249_2492_4924_9249;
+    x = (x | (x >> 2)) & 0x10C3_0C30_C30C_30C3;
+    x = (x | (x >> 4)) & 0x100F_00F0_0F00_F00F;
+    x = (x | (x >> 8)) & 0x001F_0000_FF00_00FF;
+    x = (x | (x >> 16)) & 0x001F_0000_0000_FFFF;
+    x = (x | (x >> 32)) & 0x0000_0000_001F_FFFF;
+    x as u32
+}
+
+// x takes the even bits and y the odd bits
+pub fn encode_2d(x: u32, y: u32) -> u64 {
+    spread_2d(x) | (spread_2d(y) << 1)
+}
+
+pub fn decode_2d(code: u64) -> (u32, u32) {
+    (compact_2d(code), compact_2d(code >> 1))
+}
+
+// Each coordinate can use up to 21 bits, x takes bit 0, y bit 1 and z bit 2
+pub fn encode_3d(x: u32, y: u32, z: u32) -> u64 {
+    spread_3d(x) | (spread_3d(y) << 1) | (spread_3d(z) << 2)
+}
+
+pub fn decode_3d(code: u64) -> (u32, u32, u32) {
+    (
+        compact_3d(code),
+        compact_3d(code >> 1),
+        compact_3d(code >> 2),
+    )
+}
+
+fn check_coordinates(
+    coordinates: &[u32],
+    bits_per_coordinate: usize,
+    max_bits: usize,
+) -> Result<(), Error> {
+    if bits_per_coordinate > max_bits {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Too many bits per coordinate for a Morton code",
+        ));
+    }
+    if coordinates
+        .iter()
+        .any(|&c| bits_per_coordinate < 32 && c >> bits_per_coordinate != 0)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Coordinate does not fit in bits per coordinate",
+        ));
+    }
+    Ok(())
+}
+
+// Writes the code as a 2 * bits_per_coordinate bit field
+pub fn write_2d<W: Write>(
+    writer: &mut Writer<W>,
+    x: u32,
+    y: u32,
+    bits_per_coordinate: usize,
+) -> Result<(), Error> {
+    check_coordinates(&[x, y], bits_per_coordinate, 32)?;
+    Ok(writer.write_bits(encode_2d(x, y) as u128, 2 * bits_per_coordinate)?)
+}
+
+pub fn read_2d<R: Read>(
+    reader: &mut Reader<R>,
+    bits_per_coordinate: usize,
+) -> Result<(u32, u32), Error> {
+    check_coordinates(&[], bits_per_coordinate, 32)?;
+    Ok(decode_2d(reader.read_bits(2 * bits_per_coordinate)? as u64))
+}
+
+// Writes the code as a 3 * bits_per_coordinate bit field
+pub fn write_3d<W: Write>(
+    writer: &mut Writer<W>,
+    x: u32,
+    y: u32,
+    z: u32,
+    bits_per_coordinate: usize,
+) -> Result<(), Error> {
+    check_coordinates(&[x, y, z], bits_per_coordinate, 21)?;
+    Ok(writer.write_bits(encode_3d(x, y, z) as u128, 3 * bits_per_coordinate)?)
+}
+
+pub fn read_3d<R: Read>(
+    reader: &mut Reader<R>,
+    bits_per_coordinate: usize,
+) -> Result<(u32, u32, u32), Error> {
+    check_coordinates(&[], bits_per_coordinate, 21)?;
+    Ok(decode_3d(reader.read_bits(3 * bits_per_coordinate)? as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn encode_2d_interleaves() {
+        // x = 0b11, y = 0b01 -> y1 x1 y0 x0 = 0111
+        assert_eq!(encode_2d(0b11, 0b01), 0b0111);
+        assert_eq!(encode_2d(u32::MAX, 0), 0x5555_5555_5555_5555);
+        assert_eq!(decode_2d(0b0111), (0b11, 0b01));
+        assert_eq!(decode_2d(encode_2d(123_456, 987_654)), (123_456, 987_654));
+    }
+
+    #[test]
+    pub fn encode_3d_interleaves() {
+        // x = 0b1, y = 0b0, z = 0b1 -> z0 y0 x0 = 101, then x1 = 1 -> 1101
+        assert_eq!(encode_3d(0b11, 0b00, 0b01), 0b1101);
+        assert_eq!(encode_3d(0x1F_FFFF, 0, 0), 0x1249_2492_4924_9249);
+        assert_eq!(
+            decode_3d(encode_3d(1_000_000, 2_000, 0x1F_FFFF)),
+            (1_000_000, 2_000, 0x1F_FFFF)
+        );
+    }
+
+    #[test]
+    pub fn stream_round_trip() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        write_2d(&mut writer, 5, 9, 4).unwrap();
+        write_3d(&mut writer, 3, 1, 2, 2).unwrap();
+        writer.flush().unwrap();
+
+        // 5 = 0101, 9 = 1001 -> 1001_0011, then 101_011 -> 1010_1100
+        assert_eq!(*writer.get_ref().get_ref(), [147, 172]);
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        assert_eq!(read_2d(&mut reader, 4).unwrap(), (5, 9));
+        assert_eq!(read_3d(&mut reader, 2).unwrap(), (3, 1, 2));
+    }
+
+    #[test]
+    pub fn rejects_wide_coordinates() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        assert!(write_2d(&mut writer, 16, 0, 4).is_err());
+        assert!(write_3d(&mut writer, 0, 0, 0, 22).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Reader, Writer, PREALLOCATE_LIMIT};
+use std::io::{Error, Read, Write};
+
+// Move-to-front: every byte is replaced by its position in a list of recently used bytes, and
+// then moved to the front of the list. Runs of a byte turn into runs of zeros, which is what the
+// RLE and entropy coding stages after it want (as in bzip2).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveToFront {
+    order: [u8; 256],
+}
+
+impl Default for MoveToFront {
+    fn default() -> MoveToFront {
+        MoveToFront::new()
+    }
+}
+
+impl MoveToFront {
+    pub fn new() -> MoveToFront {
+        let mut order = [0u8; 256];
+        for (index, value) in order.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+        MoveToFront { order }
+    }
+
+    pub fn reset(&mut self) {
+        *self = MoveToFront::new();
+    }
+
+    fn move_to_front(&mut self, index: usize) -> u8 {
+        let byte = self.order[index];
+        self.order.copy_within(0..index, 1);
+        self.order[0] = byte;
+        byte
+    }
+
+    pub fn encode_byte(&mut self, byte: u8) -> u8 {
+        let index = self.order.iter().position(|&b| b == byte).unwrap();
+        self.move_to_front(index);
+        index as u8
+    }
+
+    pub fn decode_byte(&mut self, index: u8) -> u8 {
+        self.move_to_front(index as usize)
+    }
+
+    pub fn encode(&mut self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|&byte| self.encode_byte(byte)).collect()
+    }
+
+    pub fn decode(&mut self, indices: &[u8]) -> Vec<u8> {
+        indices
+            .iter()
+            .map(|&index| self.decode_byte(index))
+            .collect()
+    }
+
+    // Writes the indices as bytes
+    pub fn encode_to<W: Write>(
+        &mut self,
+        data: &[u8],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        for &byte in data {
+            let index = self.encode_byte(byte);
+            writer.write_byte(index)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode_from<R: Read>(
+        &mut self,
+        reader: &mut Reader<R>,
+        number_of_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(number_of_bytes.min(PREALLOCATE_LIMIT));
+        for _ in 0..number_of_bytes {
+            let index = reader.read_byte()?;
+            data.push(self.decode_byte(index));
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rle::Rle;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn encodes_recent_bytes_as_small_indices() {
+        let mut mtf = MoveToFront::new();
+        assert_eq!(mtf.encode(b"bbbaab"), [98, 0, 0, 98, 0, 1]);
+        mtf.reset();
+        assert_eq!(mtf.decode(&[98, 0, 0, 98, 0, 1]), b"bbbaab");
+    }
+
+    #[test]
+    pub fn stream_round_trip() {
+        let data = b"banana bandana banana".to_vec();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        MoveToFront::new().encode_to(&data, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        let decoded = MoveToFront::new()
+            .decode_from(&mut reader, data.len())
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    pub fn chains_into_rle() {
+        let data = b"aaaaaaaabbbbbbbbaaaaaaaa".to_vec();
+        let indices = MoveToFront::new().encode(&data);
+        let rle = Rle::new(4);
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        rle.encode_bytes(&indices, &mut writer).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.get_ref().get_ref().clone();
+        assert!(encoded.len() < data.len() / 2);
+
+        let mut reader = Reader::new(Cursor::new(encoded));
+        let indices = rle.decode_bytes(&mut reader, data.len()).unwrap();
+        assert_eq!(MoveToFront::new().decode(&indices), data);
+    }
+}
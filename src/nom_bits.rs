@@ -0,0 +1,192 @@
+// Runs nom bit parsers, the ones over (&[u8], usize) input, straight off a Reader so a grammar
+// already written with nom can be used on a stream:
+//   let (kind, length) = reader.parse_nom(|input| {
+//       (take(4usize), take(12usize)).parse(input)
+//   })?;
+// The parser has to work for input of any lifetime, so a combinator on its own goes in a closure
+// like that one, or in a fn. It sees a window of bytes peeked from the reader starting at the
+// current bit, and only the bits it consumed are read. Use nom's streaming bit parsers: their
+// Incomplete grows the window until the stream runs out, while the complete ones fail outright on
+// a window that's too small. The window stops growing at MAX_WINDOW bytes (or the limit given to
+// parse_nom_with), and a parser still Incomplete there fails with InvalidData. nom's bits are MSB
+// first, so the reader has to be too.
+use crate::{BitOrder, Error, Reader};
+use nom::{IResult, Needed};
+use std::io::Read;
+
+// Bytes peeked for a parser's first try, doubled on every Incomplete
+const FIRST_WINDOW: usize = 16;
+// Most bytes peeked for one parse, so a parser that never completes can't take the whole stream
+const MAX_WINDOW: usize = 1 << 20;
+
+impl<R: Read> Reader<R> {
+    pub fn parse_nom<O, P>(&mut self, parser: P) -> Result<O, Error>
+    where
+        P: for<'a> FnMut((&'a [u8], usize)) -> IResult<(&'a [u8], usize), O>,
+    {
+        self.parse_nom_with(parser, MAX_WINDOW)
+    }
+
+    // Like parse_nom, peeking at most max_window bytes
+    pub fn parse_nom_with<O, P>(&mut self, mut parser: P, max_window: usize) -> Result<O, Error>
+    where
+        P: for<'a> FnMut((&'a [u8], usize)) -> IResult<(&'a [u8], usize), O>,
+    {
+        if self.bit_order() != BitOrder::MsbFirst {
+            return Err(Error::invalid_input(
+                self.bits_read(),
+                "nom bit parsers need an MSB first reader",
+            ));
+        }
+        let mut window = FIRST_WINDOW.min(max_window);
+        loop {
+            let (bytes, offset) = self.peek_bytes(window)?;
+            let at_end = bytes.len() < window;
+            // Bits from the reader's position to where the parser got to
+            let consumed = |(rest, rest_offset): (&[u8], usize)| {
+                8 * (bytes.len() - rest.len()) as u64 + rest_offset as u64 - offset as u64
+            };
+            match parser((&bytes, offset)) {
+                Ok((rest, output)) => {
+                    self.skip_bits(consumed(rest))?;
+                    return Ok(output);
+                }
+                Err(nom::Err::Incomplete(_)) if !at_end && window >= max_window => {
+                    let message = format!("nom parser wanted more than {} bytes", max_window);
+                    return Err(Error::invalid_data(self.bits_read(), &message));
+                }
+                Err(nom::Err::Incomplete(_)) if !at_end => {
+                    window = window.saturating_mul(2).min(max_window)
+                }
+                // The most nom says it wanted, which for bit parsers is the width of the field
+                // that ran out
+                Err(nom::Err::Incomplete(needed)) => {
+                    let missing = match needed {
+                        Needed::Size(size) => size.get() as u64,
+                        Needed::Unknown => 1,
+                    };
+                    return Err(Error::eof(
+                        self.bits_read() + 8 * bytes.len() as u64 - offset as u64,
+                        missing,
+                    ));
+                }
+                Err(nom::Err::Error(error)) | Err(nom::Err::Failure(error)) => {
+                    let message = format!("nom parser failed with {:?}", error.code);
+                    return Err(Error::invalid_data(
+                        self.bits_read() + consumed(error.input),
+                        &message,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use nom::bits::streaming::{tag, take};
+    use nom::multi::count;
+    use nom::Parser;
+    use std::io::Cursor;
+
+    type Bits<'a> = (&'a [u8], usize);
+
+    // A 4 bit kind, a 12 bit length and that many 3 bit values
+    fn record(input: Bits) -> IResult<Bits, (u8, Vec<u8>)> {
+        let (input, kind) = take(4usize)(input)?;
+        let (input, length): (_, usize) = take(12usize)(input)?;
+        let (input, values) = count(take(3usize), length).parse(input)?;
+        Ok((input, (kind, values)))
+    }
+
+    #[test]
+    pub fn parses_from_a_stream() {
+        let values: Vec<u8> = (0..200).map(|index| index % 8).collect();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bits(0xA, 4).unwrap();
+        writer.write_bits(values.len() as u128, 12).unwrap();
+        for &value in &values {
+            writer.write_bits(value as u128, 3).unwrap();
+        }
+        writer.write_bits(0x3C, 6).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        // Starting part way through a byte
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.parse_nom(record).unwrap(), (0xA, values));
+        assert_eq!(reader.bits_read(), 3 + 16 + 600);
+        let marker: u8 = reader
+            .parse_nom(|input| tag(0x3Cu8, 6usize)(input))
+            .unwrap();
+        assert_eq!(marker, 0x3C);
+        assert_eq!(reader.read_bits(6).unwrap(), 0);
+    }
+
+    #[test]
+    pub fn reports_failures() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1100_0000, 0xFF]));
+        reader.read_bits(2).unwrap();
+        let error = reader
+            .parse_nom(|input| {
+                let (input, _): (_, u8) = take(4usize)(input)?;
+                tag(0b11u8, 2usize)(input)
+            })
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 6,
+                ..
+            }
+        ));
+        // Nothing was read
+        assert_eq!(reader.bits_read(), 2);
+
+        let error = reader.parse_nom(record).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEof { .. }));
+
+        let mut reader = Reader::with_bit_order(Cursor::new(vec![0]), BitOrder::LsbFirst);
+        assert!(reader
+            .parse_nom(|input| take::<_, u8, _, _>(1usize)(input))
+            .is_err());
+    }
+
+    #[test]
+    pub fn caps_the_window() {
+        let values: Vec<u8> = (0..200).map(|index| index % 8).collect();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_bits(0xA, 4).unwrap();
+        writer.write_bits(values.len() as u128, 12).unwrap();
+        for &value in &values {
+            writer.write_bits(value as u128, 3).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        // 77 bytes of record don't fit in 40
+        let mut reader = Reader::new(Cursor::new(bytes.clone()));
+        let error = reader.parse_nom_with(record, 40).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 0,
+                ..
+            }
+        ));
+        assert_eq!(reader.bits_read(), 0);
+        assert_eq!(reader.parse_nom_with(record, 77).unwrap(), (0xA, values));
+
+        // A parser that never completes stops at the limit rather than reading on forever
+        let mut reader = Reader::new(std::io::repeat(0));
+        let error = reader
+            .parse_nom_with(
+                |input| count(take::<_, u8, _, _>(8usize), 1 << 30).parse(input),
+                1024,
+            )
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidData { .. }));
+    }
+}
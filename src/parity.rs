@@ -0,0 +1,328 @@
+// A parity bit after every block of data bits, the way UARTs and older serial formats send them.
+// ParityWriter adds them to whatever it writes to and Reader::parity checks and strips them, so the
+// code on either side only sees the data. Padding to a byte goes around the blocks rather than
+// into them, on both sides.
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Parity {
+    // The parity bit makes the count of ones in the block and it even
+    Even,
+    Odd,
+}
+
+impl Parity {
+    // The parity bit for a block, given whether it has an odd number of ones
+    fn bit(self, odd_ones: bool) -> bool {
+        match self {
+            Parity::Even => odd_ones,
+            Parity::Odd => !odd_ones,
+        }
+    }
+}
+
+fn check_block(data_bits: usize) -> Result<(), Error> {
+    if data_bits == 0 {
+        return Err(Error::invalid_input(
+            0,
+            "Parity blocks need at least one data bit",
+        ));
+    }
+    Ok(())
+}
+
+// Which bits of a value are in the piece from start (counted in stream order) that's count long
+fn piece(bits: u128, number_of_bits: usize, start: usize, count: usize, order: BitOrder) -> u128 {
+    let shift = match order {
+        BitOrder::MsbFirst => number_of_bits - start - count,
+        BitOrder::LsbFirst => start,
+    };
+    bits >> shift & u128::MAX >> (128 - count)
+}
+
+pub struct ParityWriter<W: BitWrite> {
+    inner: W,
+    parity: Parity,
+    data_bits: usize,
+    // Data bits into the current block, and whether they hold an odd number of ones
+    filled: usize,
+    odd_ones: bool,
+}
+
+impl<W: BitWrite> ParityWriter<W> {
+    pub fn new(inner: W, parity: Parity, data_bits: usize) -> Result<ParityWriter<W>, Error> {
+        check_block(data_bits)?;
+        Ok(ParityWriter {
+            inner,
+            parity,
+            data_bits,
+            filled: 0,
+            odd_ones: false,
+        })
+    }
+
+    // Writes the parity bit for a block cut short, if one's been started
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if self.filled > 0 {
+            self.inner.write_bit(self.parity.bit(self.odd_ones))?;
+            self.filled = 0;
+            self.odd_ones = false;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Anything written since the last full block is left without its parity bit unless end_block
+    // is called first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for ParityWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let bit_order = self.inner.bit_order();
+        let mut written = 0;
+        while written < number_of_bits {
+            let count = (number_of_bits - written).min(self.data_bits - self.filled);
+            let data = piece(bits, number_of_bits, written, count, bit_order);
+            self.inner.write_bits(data, count)?;
+            self.odd_ones ^= data.count_ones() % 2 == 1;
+            self.filled += count;
+            written += count;
+            if self.filled == self.data_bits {
+                self.end_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Parity bits included
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.inner.pad_to_byte()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data with a parity bit after every data_bits bits, checking and dropping them. A wrong
+    // parity bit is an InvalidData error at its position, after which reading carries on with the
+    // next block.
+    pub fn parity(
+        &mut self,
+        parity: Parity,
+        data_bits: usize,
+    ) -> Result<ParityReader<'_, R>, Error> {
+        check_block(data_bits)?;
+        Ok(ParityReader {
+            reader: self,
+            parity,
+            data_bits,
+            filled: 0,
+            odd_ones: false,
+            failures: None,
+        })
+    }
+}
+
+pub struct ParityReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    parity: Parity,
+    data_bits: usize,
+    filled: usize,
+    odd_ones: bool,
+    // Where bad parity bits were, when they're being collected rather than returned as errors
+    failures: Option<Vec<u64>>,
+}
+
+impl<'a, R: Read> ParityReader<'a, R> {
+    // Keeps the positions of bad parity bits for failures instead of failing the read, for lines
+    // where a few flipped bits are expected and the data is wanted anyway
+    pub fn collect_failures(mut self) -> ParityReader<'a, R> {
+        self.failures = Some(Vec::new());
+        self
+    }
+
+    // Positions of the bad parity bits so far, if they're being collected
+    pub fn failures(&self) -> &[u64] {
+        self.failures.as_deref().unwrap_or(&[])
+    }
+
+    // Reads and checks the parity bit for a block cut short, if one's been started
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if self.filled == 0 {
+            return Ok(());
+        }
+        let expected = self.parity.bit(self.odd_ones);
+        self.filled = 0;
+        self.odd_ones = false;
+        let position = self.reader.bits_read();
+        if self.reader.read_bit()? == expected {
+            return Ok(());
+        }
+        match &mut self.failures {
+            Some(failures) => {
+                failures.push(position);
+                Ok(())
+            }
+            None => Err(Error::invalid_data(
+                position,
+                "Parity bit doesn't match its block",
+            )),
+        }
+    }
+}
+
+impl<'a, R: Read> BitRead for ParityReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let bit_order = self.reader.bit_order();
+        let mut bits = 0;
+        let mut read = 0;
+        let mut failure = None;
+        while read < number_of_bits {
+            let count = (number_of_bits - read).min(self.data_bits - self.filled);
+            let data = self.reader.read_bits(count)?;
+            bits |= match bit_order {
+                BitOrder::MsbFirst => data << (number_of_bits - read - count),
+                BitOrder::LsbFirst => data << read,
+            };
+            self.odd_ones ^= data.count_ones() % 2 == 1;
+            self.filled += count;
+            read += count;
+            if self.filled == self.data_bits {
+                if let Err(error) = self.end_block() {
+                    // Finish the value first, so the reader ends up where the caller expects
+                    failure = failure.or(Some(error));
+                }
+            }
+        }
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(bits),
+        }
+    }
+
+    // Parity bits included
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Skips the padding to the next byte boundary, around the blocks like ParityWriter's
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        self.reader.align_to_byte(require_zeros)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn uart_frames() {
+        // 8E1: a start bit, 8 data bits LSB first, even parity and a stop bit
+        let mut writer = Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst);
+        for &byte in b"Ha" {
+            writer.write_bit(false).unwrap();
+            let mut data = ParityWriter::new(&mut writer, Parity::Even, 8).unwrap();
+            data.write_byte(byte).unwrap();
+            writer.write_bit(true).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+        // Even parity is 0 for 'H', with two ones, and 1 for 'a', with three
+        assert_eq!(bytes, [0b1001_0000, 0b0001_0100, 0b0011_0110]);
+
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        for &byte in b"Ha" {
+            assert!(!reader.read_bit().unwrap());
+            assert_eq!(
+                reader.parity(Parity::Even, 8).unwrap().read_byte().unwrap(),
+                byte
+            );
+            assert!(reader.read_bit().unwrap());
+        }
+    }
+
+    #[test]
+    pub fn splits_values_across_blocks() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let writer = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut output = ParityWriter::new(writer, Parity::Odd, 7).unwrap();
+            output.write_bits(0x1_2345, 17).unwrap();
+            output.write_bits(0xDEAD_BEEF_CAFE, 48).unwrap();
+            output.write_bit(true).unwrap();
+            output.end_block().unwrap();
+            // 66 data bits in 10 blocks, the last cut short
+            assert_eq!(output.bits_written(), 66 + 10);
+            output.pad_to_byte().unwrap();
+            let bytes = output.into_inner().into_inner().unwrap();
+
+            let mut reader = Reader::with_bit_order(Cursor::new(bytes.clone()), bit_order);
+            let mut input = reader.parity(Parity::Odd, 7).unwrap();
+            assert_eq!(input.read_bits(17).unwrap(), 0x1_2345);
+            assert_eq!(input.read_bits(48).unwrap(), 0xDEAD_BEEF_CAFE);
+            assert!(input.read_bit().unwrap());
+            input.end_block().unwrap();
+            input.align_to_byte(true).unwrap();
+            assert_eq!(input.bits_read(), 8 * bytes.len() as u64);
+        }
+    }
+
+    #[test]
+    pub fn reports_bad_parity_bits() {
+        let mut writer = ParityWriter::new(Writer::new(Vec::new()), Parity::Even, 4).unwrap();
+        writer.write_bits(0xABCD, 16).unwrap();
+        let mut bytes = writer.into_inner().into_inner().unwrap();
+        // Flip a data bit in the second block and the parity bit of the fourth
+        bytes[0] ^= 0b0000_0100;
+        bytes[2] ^= 0b0001_0000;
+
+        let mut reader = Reader::new(Cursor::new(bytes.clone()));
+        let error = reader
+            .parity(Parity::Even, 4)
+            .unwrap()
+            .read_bits(16)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 9,
+                ..
+            }
+        ));
+        // The whole value was still read
+        assert_eq!(reader.bits_read(), 20);
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.parity(Parity::Even, 4).unwrap().collect_failures();
+        assert_eq!(input.read_bits(16).unwrap(), 0xA3CD);
+        assert_eq!(input.failures(), [9, 19]);
+        assert!(Reader::new(Cursor::new(vec![0]))
+            .parity(Parity::Odd, 0)
+            .is_err());
+    }
+}
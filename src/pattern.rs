@@ -0,0 +1,148 @@
+// Known bit patterns for bringing up links and codecs: a source that sends one forever, and a sink
+// that counts how far what it receives strays from it
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Zeros,
+    Ones,
+    // 1010...
+    Alternating,
+    // 0, 1, 2, ... in this many bits (1 to 64) each, MSB first, wrapping around
+    Counter(usize),
+}
+
+impl Pattern {
+    fn check(self) -> Result<Pattern, Error> {
+        match self {
+            Pattern::Counter(width) if width == 0 || width > 64 => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Counter width has to be 1 to 64 bits",
+            )),
+            _ => Ok(self),
+        }
+    }
+
+    // The index'th bit of the pattern
+    pub fn bit(self, index: u64) -> bool {
+        match self {
+            Pattern::Zeros => false,
+            Pattern::Ones => true,
+            Pattern::Alternating => index.is_multiple_of(2),
+            Pattern::Counter(width) => {
+                let width = width as u64;
+                let count = index / width;
+                count >> (width - 1 - index % width) & 1 == 1
+            }
+        }
+    }
+
+    fn byte(self, index: u64) -> u8 {
+        (0..8).fold(0, |byte, bit| byte << 1 | self.bit(index * 8 + bit) as u8)
+    }
+}
+
+// Never runs out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatternSource {
+    pattern: Pattern,
+    bytes: u64,
+}
+
+impl PatternSource {
+    pub fn new(pattern: Pattern) -> Result<PatternSource, Error> {
+        Ok(PatternSource {
+            pattern: pattern.check()?,
+            bytes: 0,
+        })
+    }
+}
+
+impl Read for PatternSource {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        for byte in buf.iter_mut() {
+            *byte = self.pattern.byte(self.bytes);
+            self.bytes += 1;
+        }
+        Ok(buf.len())
+    }
+}
+
+// Takes everything written to it and compares it against the pattern
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatternChecker {
+    pattern: Pattern,
+    bits_checked: u64,
+    bit_errors: u64,
+    first_error: Option<u64>,
+}
+
+impl PatternChecker {
+    pub fn new(pattern: Pattern) -> Result<PatternChecker, Error> {
+        Ok(PatternChecker {
+            pattern: pattern.check()?,
+            bits_checked: 0,
+            bit_errors: 0,
+            first_error: None,
+        })
+    }
+
+    pub fn bits_checked(&self) -> u64 {
+        self.bits_checked
+    }
+
+    pub fn bit_errors(&self) -> u64 {
+        self.bit_errors
+    }
+
+    // Bit offset of the first bit that didn't match
+    pub fn first_error(&self) -> Option<u64> {
+        self.first_error
+    }
+}
+
+impl Write for PatternChecker {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        for &byte in buf {
+            let wrong = byte ^ self.pattern.byte(self.bits_checked / 8);
+            if wrong != 0 && self.first_error.is_none() {
+                self.first_error = Some(self.bits_checked + wrong.leading_zeros() as u64);
+            }
+            self.bit_errors += wrong.count_ones() as u64;
+            self.bits_checked += 8;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Reader, Writer};
+
+    #[test]
+    pub fn counter_round_trip() {
+        let mut reader = Reader::new(PatternSource::new(Pattern::Counter(4)).unwrap());
+        assert_eq!(reader.read_bits(24).unwrap(), 0x01_2345);
+        let mut alternating = PatternSource::new(Pattern::Alternating).unwrap();
+        let mut byte = [0];
+        alternating.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [0b1010_1010]);
+        assert!(PatternSource::new(Pattern::Counter(65)).is_err());
+
+        let mut writer = Writer::new(PatternChecker::new(Pattern::Counter(12)).unwrap());
+        for count in 0..100 {
+            // One bit flipped in count 50
+            let value = if count == 50 { count ^ 0b100 } else { count };
+            writer.write_bits(value, 12).unwrap();
+        }
+        let checker = writer.into_inner().unwrap();
+        assert_eq!(checker.bits_checked(), 1200);
+        assert_eq!(checker.bit_errors(), 1);
+        assert_eq!(checker.first_error(), Some(50 * 12 + 9));
+    }
+}
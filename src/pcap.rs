@@ -0,0 +1,478 @@
+// Packets out of pcap and pcapng captures, and the bytes picked out of them as a stream to read
+// bits from. Both formats are worked out from the first block of the file.
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read};
+
+const PCAP_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SECTION: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER: u32 = 0x1A2B_3C4D;
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+// Biggest block we'll allocate for, well past any real snap length
+const MAX_BLOCK: usize = 1 << 26;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    // Nanoseconds since the epoch
+    pub timestamp: u64,
+    // Link layer type of the interface it was captured on (1 is Ethernet)
+    pub link_type: u16,
+    // Length on the wire, data can be shorter when the capture had a snap length
+    pub original_length: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Interface {
+    link_type: u16,
+    // Timestamp units in a second, kept whole since binary resolutions aren't a whole number of
+    // nanoseconds
+    ticks_per_second: u64,
+}
+
+impl Interface {
+    fn to_nanos(self, ticks: u64) -> u64 {
+        let nanos = ticks as u128 * 1_000_000_000 / self.ticks_per_second as u128;
+        u64::try_from(nanos).unwrap_or(u64::MAX)
+    }
+}
+
+enum Format {
+    Pcap { nanos: bool, link_type: u16 },
+    Pcapng { interfaces: Vec<Interface> },
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+pub struct PcapPackets<R: Read> {
+    inner: R,
+    format: Format,
+    big_endian: bool,
+}
+
+impl<R: Read> PcapPackets<R> {
+    pub fn new(mut inner: R) -> Result<PcapPackets<R>, Error> {
+        let mut magic = [0; 4];
+        inner.read_exact(&mut magic)?;
+        let big = u32::from_be_bytes(magic);
+        let little = u32::from_le_bytes(magic);
+
+        if big == PCAPNG_SECTION {
+            let mut packets = PcapPackets {
+                inner,
+                format: Format::Pcapng {
+                    interfaces: Vec::new(),
+                },
+                big_endian: false,
+            };
+            let mut length = [0; 4];
+            packets.inner.read_exact(&mut length)?;
+            packets.read_section_header(length)?;
+            return Ok(packets);
+        }
+
+        let (big_endian, nanos) = match (big, little) {
+            (PCAP_MICROS, _) => (true, false),
+            (PCAP_NANOS, _) => (true, true),
+            (_, PCAP_MICROS) => (false, false),
+            (_, PCAP_NANOS) => (false, true),
+            _ => return Err(invalid("Not a pcap or pcapng file")),
+        };
+        // Version, time zone, accuracy and snap length aren't needed
+        let mut header = [0; 20];
+        inner.read_exact(&mut header)?;
+        let mut packets = PcapPackets {
+            inner,
+            format: Format::Pcap {
+                nanos,
+                link_type: 0,
+            },
+            big_endian,
+        };
+        let link_type = packets.u32_at(&header, 16) as u16;
+        packets.format = Format::Pcap { nanos, link_type };
+        Ok(packets)
+    }
+
+    fn u16_at(&self, bytes: &[u8], at: usize) -> u16 {
+        let value = [bytes[at], bytes[at + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(value)
+        } else {
+            u16::from_le_bytes(value)
+        }
+    }
+
+    fn u32_at(&self, bytes: &[u8], at: usize) -> u32 {
+        let value = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        if self.big_endian {
+            u32::from_be_bytes(value)
+        } else {
+            u32::from_le_bytes(value)
+        }
+    }
+
+    // Fills buf, or gives back false when the stream ends before the first byte
+    fn read_or_end(&mut self, buf: &mut [u8]) -> Result<bool, Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(invalid("Capture ends part way through a packet")),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_body(&mut self, length: usize) -> Result<Vec<u8>, Error> {
+        if length > MAX_BLOCK {
+            return Err(invalid("Capture record is too long"));
+        }
+        let mut body = vec![0; length];
+        self.inner.read_exact(&mut body)?;
+        Ok(body)
+    }
+
+    // The rest of a section header after its block type and length. The length's byte order isn't
+    // known until the magic after it has been read.
+    fn read_section_header(&mut self, length: [u8; 4]) -> Result<(), Error> {
+        let mut magic = [0; 4];
+        self.inner.read_exact(&mut magic)?;
+        self.big_endian = if u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER {
+            true
+        } else if u32::from_le_bytes(magic) == PCAPNG_BYTE_ORDER {
+            false
+        } else {
+            return Err(invalid("Bad pcapng byte order magic"));
+        };
+        let length = self.u32_at(&length, 0) as usize;
+        if length < 28 || !length.is_multiple_of(4) {
+            return Err(invalid("Bad pcapng section header length"));
+        }
+        self.read_body(length - 12)?;
+        // Interface ids start over in every section
+        self.format = Format::Pcapng {
+            interfaces: Vec::new(),
+        };
+        Ok(())
+    }
+
+    fn interface(&self, id: u32) -> Result<Interface, Error> {
+        let interface = match &self.format {
+            Format::Pcapng { interfaces } => interfaces.get(id as usize),
+            Format::Pcap { .. } => None,
+        };
+        interface
+            .copied()
+            .ok_or_else(|| invalid("Packet from an unknown pcapng interface"))
+    }
+
+    fn read_interface(&self, body: &[u8]) -> Result<Interface, Error> {
+        if body.len() < 8 {
+            return Err(invalid("pcapng interface block is too short"));
+        }
+        let mut interface = Interface {
+            link_type: self.u16_at(body, 0),
+            ticks_per_second: 1_000_000,
+        };
+        // Options are a code, a length and a value padded to 4 bytes. Code 9 is the timestamp
+        // resolution: a power of 10, or of 2 when the top bit is set.
+        let mut at = 8;
+        while at + 4 <= body.len() {
+            let code = self.u16_at(body, at);
+            let length = self.u16_at(body, at + 2) as usize;
+            if code == 0 {
+                break;
+            }
+            if code == 9 && length == 1 && at + 4 < body.len() {
+                let resolution = body[at + 4];
+                interface.ticks_per_second = if resolution & 0x80 != 0 {
+                    1u64.checked_shl((resolution & 0x7F) as u32)
+                } else {
+                    10u64.checked_pow(resolution as u32)
+                }
+                .ok_or_else(|| invalid("Unsupported pcapng timestamp resolution"))?;
+            }
+            at += 4 + length.next_multiple_of(4);
+        }
+        Ok(interface)
+    }
+
+    fn next_pcap(&mut self, nanos: bool, link_type: u16) -> Result<Option<Packet>, Error> {
+        let mut header = [0; 16];
+        if !self.read_or_end(&mut header)? {
+            return Ok(None);
+        }
+        let seconds = self.u32_at(&header, 0) as u64;
+        let fraction = self.u32_at(&header, 4) as u64;
+        let captured = self.u32_at(&header, 8) as usize;
+        let original_length = self.u32_at(&header, 12);
+        let data = self.read_body(captured)?;
+        let fraction = if nanos { fraction } else { fraction * 1000 };
+        Ok(Some(Packet {
+            timestamp: seconds * 1_000_000_000 + fraction,
+            link_type,
+            original_length,
+            data,
+        }))
+    }
+
+    fn next_pcapng(&mut self) -> Result<Option<Packet>, Error> {
+        loop {
+            let mut start = [0; 8];
+            if !self.read_or_end(&mut start)? {
+                return Ok(None);
+            }
+            // The section header's type reads the same in both byte orders
+            if u32::from_be_bytes([start[0], start[1], start[2], start[3]]) == PCAPNG_SECTION {
+                self.read_section_header([start[4], start[5], start[6], start[7]])?;
+                continue;
+            }
+
+            let block_type = self.u32_at(&start, 0);
+            let length = self.u32_at(&start, 4) as usize;
+            if length < 12 || !length.is_multiple_of(4) {
+                return Err(invalid("Bad pcapng block length"));
+            }
+            let block = self.read_body(length - 8)?;
+            // The length is repeated at the end of every block
+            let body = &block[..block.len() - 4];
+
+            match block_type {
+                PCAPNG_INTERFACE => {
+                    let interface = self.read_interface(body)?;
+                    if let Format::Pcapng { interfaces } = &mut self.format {
+                        interfaces.push(interface);
+                    }
+                }
+                PCAPNG_ENHANCED_PACKET => {
+                    if body.len() < 20 {
+                        return Err(invalid("pcapng packet block is too short"));
+                    }
+                    let interface = self.interface(self.u32_at(body, 0))?;
+                    let ticks = (self.u32_at(body, 4) as u64) << 32 | self.u32_at(body, 8) as u64;
+                    let captured = self.u32_at(body, 12) as usize;
+                    let original_length = self.u32_at(body, 16);
+                    let data = body
+                        .get(20..20 + captured)
+                        .ok_or_else(|| invalid("pcapng packet runs past its block"))?;
+                    return Ok(Some(Packet {
+                        timestamp: interface.to_nanos(ticks),
+                        link_type: interface.link_type,
+                        original_length,
+                        data: data.to_vec(),
+                    }));
+                }
+                PCAPNG_SIMPLE_PACKET => {
+                    if body.len() < 4 {
+                        return Err(invalid("pcapng packet block is too short"));
+                    }
+                    let interface = self.interface(0)?;
+                    let original_length = self.u32_at(body, 0);
+                    let captured = (original_length as usize).min(body.len() - 4);
+                    return Ok(Some(Packet {
+                        timestamp: 0,
+                        link_type: interface.link_type,
+                        original_length,
+                        data: body[4..4 + captured].to_vec(),
+                    }));
+                }
+                // Statistics, name resolution and anything else
+                _ => {}
+            }
+        }
+    }
+
+    pub fn next_packet(&mut self) -> Result<Option<Packet>, Error> {
+        match self.format {
+            Format::Pcap { nanos, link_type } => self.next_pcap(nanos, link_type),
+            Format::Pcapng { .. } => self.next_pcapng(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapPackets<R> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Result<Packet, Error>> {
+        self.next_packet().transpose()
+    }
+}
+
+// Which bytes of each packet go into the stream, e.g. offset 42 skips the Ethernet, IPv4 and UDP
+// headers of a plain UDP packet
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadSelector {
+    pub offset: usize,
+    pub length: Option<usize>,
+}
+
+impl PayloadSelector {
+    pub fn select<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let start = self.offset.min(data.len());
+        let end = match self.length {
+            Some(length) => start.saturating_add(length).min(data.len()),
+            None => data.len(),
+        };
+        &data[start..end]
+    }
+}
+
+// The selected bytes of every packet back to back, to wrap in a Reader
+pub struct PcapSource<R: Read> {
+    packets: PcapPackets<R>,
+    selector: PayloadSelector,
+    payload: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> PcapSource<R> {
+    pub fn new(inner: R, selector: PayloadSelector) -> Result<PcapSource<R>, Error> {
+        Ok(PcapSource {
+            packets: PcapPackets::new(inner)?,
+            selector,
+            payload: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for PcapSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.position == self.payload.len() {
+            match self.packets.next_packet()? {
+                Some(packet) => {
+                    self.payload = self.selector.select(&packet.data).to_vec();
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.payload.len() - self.position);
+        buf[..n].copy_from_slice(&self.payload[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Reader;
+    use std::io::Cursor;
+
+    fn pcap_file(packets: &[&[u8]]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&PCAP_MICROS.to_le_bytes());
+        file.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes());
+        for (index, packet) in packets.iter().enumerate() {
+            file.extend_from_slice(&(index as u32 + 10).to_le_bytes());
+            file.extend_from_slice(&500u32.to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn pcapng_block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+        let length = 12 + body.len().next_multiple_of(4);
+        file.extend_from_slice(&block_type.to_be_bytes());
+        file.extend_from_slice(&(length as u32).to_be_bytes());
+        file.extend_from_slice(body);
+        file.resize(file.len() + body.len().next_multiple_of(4) - body.len(), 0);
+        file.extend_from_slice(&(length as u32).to_be_bytes());
+    }
+
+    #[test]
+    pub fn classic_packets() {
+        let file = pcap_file(&[&[1, 2, 3], &[4, 5]]);
+        let packets: Vec<Packet> = PcapPackets::new(Cursor::new(file))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].data, [4, 5]);
+        assert_eq!(packets[1].link_type, 1);
+        assert_eq!(packets[0].timestamp, 10_000_500_000);
+    }
+
+    #[test]
+    pub fn pcapng_packets() {
+        // Big endian section, an interface with nanosecond timestamps, a statistics block, an
+        // enhanced packet and a simple packet
+        let mut file = Vec::new();
+        let mut section = PCAPNG_BYTE_ORDER.to_be_bytes().to_vec();
+        section.extend_from_slice(&[0, 1, 0, 0]);
+        section.extend_from_slice(&[0xFF; 8]);
+        pcapng_block(&mut file, PCAPNG_SECTION, &section);
+        pcapng_block(
+            &mut file,
+            PCAPNG_INTERFACE,
+            &[0, 1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0],
+        );
+        pcapng_block(&mut file, 5, &[0; 12]);
+        let mut enhanced = vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2];
+        enhanced.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 60, 0xAA, 0xBB, 0xCC]);
+        pcapng_block(&mut file, PCAPNG_ENHANCED_PACKET, &enhanced);
+        pcapng_block(&mut file, PCAPNG_SIMPLE_PACKET, &[0, 0, 0, 2, 0xDD, 0xEE]);
+
+        let mut packets = PcapPackets::new(Cursor::new(file)).unwrap();
+        let packet = packets.next().unwrap().unwrap();
+        assert_eq!(packet.data, [0xAA, 0xBB, 0xCC]);
+        assert_eq!(packet.original_length, 60);
+        assert_eq!(packet.timestamp, (1 << 32) + 2);
+        assert_eq!(packets.next().unwrap().unwrap().data, [0xDD, 0xEE]);
+        assert!(packets.next().is_none());
+    }
+
+    #[test]
+    pub fn binary_timestamp_resolution() {
+        // 2^20 ticks a second, which isn't a whole number of nanoseconds, and 2^30
+        for (resolution, ticks, nanos) in [
+            (0x94, 0x38_0000, 3_500_000_000),
+            (0x9E, 3 << 29, 1_500_000_000),
+        ] {
+            let mut file = Vec::new();
+            let mut section = PCAPNG_BYTE_ORDER.to_be_bytes().to_vec();
+            section.extend_from_slice(&[0, 1, 0, 0]);
+            section.extend_from_slice(&[0xFF; 8]);
+            pcapng_block(&mut file, PCAPNG_SECTION, &section);
+            let interface = [
+                0, 1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, resolution, 0, 0, 0, 0, 0, 0, 0,
+            ];
+            pcapng_block(&mut file, PCAPNG_INTERFACE, &interface);
+            let mut enhanced = vec![0, 0, 0, 0, 0, 0, 0, 0];
+            enhanced.extend_from_slice(&(ticks as u32).to_be_bytes());
+            enhanced.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, 0xAA]);
+            pcapng_block(&mut file, PCAPNG_ENHANCED_PACKET, &enhanced);
+
+            let mut packets = PcapPackets::new(Cursor::new(file)).unwrap();
+            assert_eq!(packets.next().unwrap().unwrap().timestamp, nanos);
+        }
+    }
+
+    #[test]
+    pub fn payload_bit_stream() {
+        // A 2 byte header on each packet, then a 12 bit field split across packets
+        let file = pcap_file(&[&[0, 0, 0xAB], &[0, 0, 0xC0, 0xFF]]);
+        let selector = PayloadSelector {
+            offset: 2,
+            length: Some(1),
+        };
+        let mut reader = Reader::new(PcapSource::new(Cursor::new(file), selector).unwrap());
+        assert_eq!(reader.read_bits(12).unwrap(), 0xABC);
+        assert_eq!(reader.read_bits(4).unwrap(), 0);
+        assert!(reader.read_bit().is_err());
+        assert!(PcapPackets::new(Cursor::new(vec![0; 24])).is_err());
+    }
+}
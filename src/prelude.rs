@@ -0,0 +1,5 @@
+// The core types and traits in one import: `use bit_streamer::prelude::*;`
+pub use crate::bit_cursor::BitCursor;
+pub use crate::slice_reader::{BitView, SliceReader};
+pub use crate::{ArrayBitWriter, BitCounter, BitEnum, BitOrder, BitRead, BitWrite, TeeWriter};
+pub use crate::{FinishPolicy, LeftoverBits, Reader, TakeBits, TeeReader, Writer};
@@ -0,0 +1,96 @@
+// An endless stream of pseudo-random bits, for fuzzing decoders, making up test payloads and
+// dithering. It runs on a small built in generator, or with the rand feature on any rand RNG.
+use crate::Reader;
+use std::io::{Error, Read};
+
+// Where RandomBits gets its bits from, 64 at a time
+pub trait WordSource {
+    fn next_word(&mut self) -> u64;
+}
+
+// SplitMix64, tiny and plenty random for test data. Not for anything that needs to be secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl WordSource for SplitMix64 {
+    fn next_word(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<G: rand_core::RngCore> WordSource for G {
+    fn next_word(&mut self) -> u64 {
+        self.next_u64()
+    }
+}
+
+// Never runs out, so reads always fill the whole buffer
+#[derive(Clone, Debug)]
+pub struct RandomBits<G: WordSource = SplitMix64> {
+    generator: G,
+}
+
+impl RandomBits<SplitMix64> {
+    // The same seed always gives the same bits
+    pub fn new(seed: u64) -> RandomBits<SplitMix64> {
+        RandomBits::with_generator(SplitMix64::new(seed))
+    }
+}
+
+impl<G: WordSource> RandomBits<G> {
+    pub fn with_generator(generator: G) -> RandomBits<G> {
+        RandomBits { generator }
+    }
+
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.generator
+    }
+}
+
+impl<G: WordSource> Read for RandomBits<G> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        for chunk in buf.chunks_mut(8) {
+            let word = self.generator.next_word().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+}
+
+pub fn random_reader(seed: u64) -> Reader<RandomBits> {
+    Reader::new(RandomBits::new(seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn seeded_stream() {
+        let mut first = random_reader(42);
+        let mut second = random_reader(42);
+        let mut other = random_reader(43);
+
+        let bits: Vec<u128> = (0..100).map(|_| first.read_bits(128).unwrap()).collect();
+        let again: Vec<u128> = (0..100).map(|_| second.read_bits(128).unwrap()).collect();
+        assert_eq!(bits, again);
+        assert_ne!(bits[0], other.read_bits(128).unwrap());
+
+        // 12800 bits should come out close to half ones
+        let ones: u32 = bits.iter().map(|word| word.count_ones()).sum();
+        assert!((6000..6800).contains(&ones));
+    }
+}
@@ -0,0 +1,140 @@
+// Keeps an encoder near a target bitrate. The encoder reports how far it has got (bits from
+// Writer::bits_written, and seconds of content) and at the end of each window gets told to spend
+// fewer or more bits.
+use std::io::{Error, ErrorKind};
+
+// Most doublings or halvings one window can ask for, so a window far off target (a scene cut, a
+// stall) doesn't throw the quantizer to one end of its range
+const MAX_DOUBLINGS: f64 = 4.0;
+
+// Whatever the encoder turns to trade quality for size, usually a quantizer
+pub trait RateAdjust {
+    // steps > 0 asks for coarser quantization (fewer bits), steps < 0 for finer
+    fn adjust(&mut self, steps: i32);
+}
+
+impl<F: FnMut(i32)> RateAdjust for F {
+    fn adjust(&mut self, steps: i32) {
+        self(steps)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateController {
+    bits_per_second: f64,
+    window_seconds: f64,
+    tolerance: f64,
+    steps_per_doubling: f64,
+    window_start: u64,
+    window_elapsed: f64,
+}
+
+impl RateController {
+    pub fn new(bits_per_second: u64, window_seconds: f64) -> Result<RateController, Error> {
+        if bits_per_second == 0 || window_seconds.is_nan() || window_seconds <= 0.0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Bitrate and window length have to be above zero",
+            ));
+        }
+        Ok(RateController {
+            bits_per_second: bits_per_second as f64,
+            window_seconds,
+            tolerance: 0.05,
+            steps_per_doubling: 6.0,
+            window_start: 0,
+            window_elapsed: 0.0,
+        })
+    }
+
+    // How far (as a fraction) a window can miss the target before anything is adjusted
+    pub fn with_tolerance(mut self, tolerance: f64) -> RateController {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    // Adjustment steps that double or halve the bitrate. The default of 6 matches the quantizer
+    // scale of H.264 and most codecs after it.
+    pub fn with_steps_per_doubling(mut self, steps: f64) -> RateController {
+        self.steps_per_doubling = steps.max(0.0);
+        self
+    }
+
+    // Call after encoding seconds more content, with the writer's bits_written so far. When that
+    // closes a window, gives back the bits spent over the bits allowed in it and, if that's off by
+    // more than the tolerance, asks control to adjust. A window with no bits in it says nothing
+    // about the quantizer, so it's never adjusted for.
+    pub fn update(
+        &mut self,
+        bits_written: u64,
+        seconds: f64,
+        control: &mut impl RateAdjust,
+    ) -> Option<f64> {
+        self.window_elapsed += seconds;
+        if self.window_elapsed < self.window_seconds {
+            return None;
+        }
+        let spent = bits_written.saturating_sub(self.window_start) as f64;
+        let ratio = spent / (self.bits_per_second * self.window_elapsed);
+        self.window_start = bits_written;
+        self.window_elapsed = 0.0;
+
+        if spent > 0.0 && (ratio - 1.0).abs() > self.tolerance {
+            let doublings = ratio.log2().clamp(-MAX_DOUBLINGS, MAX_DOUBLINGS);
+            let mut steps = (doublings * self.steps_per_doubling).round() as i32;
+            if steps == 0 {
+                // Always move at least a step once outside the tolerance
+                steps = if ratio > 1.0 { 1 } else { -1 };
+            }
+            control.adjust(steps);
+        }
+        Some(ratio)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::sink;
+
+    #[test]
+    pub fn settles_on_target() {
+        // 8 frames a second, 1 second windows, aiming for 40 kbit/s
+        let mut controller = RateController::new(40_000, 1.0).unwrap();
+        let mut writer = Writer::new(sink());
+        let mut quantizer = 0;
+        let mut ratios = Vec::new();
+        for _ in 0..80 {
+            // Each 6 quantizer steps halves the frame size, starting from 16000 bits
+            let frame_bits = (16_000.0 / 2f64.powf(quantizer as f64 / 6.0)) as usize;
+            for _ in 0..frame_bits / 100 {
+                writer.write_bits(0, 100).unwrap();
+            }
+            let mut adjust = |steps| quantizer += steps;
+            if let Some(ratio) = controller.update(writer.bits_written(), 0.125, &mut adjust) {
+                ratios.push(ratio);
+            }
+        }
+
+        assert_eq!(ratios.len(), 10);
+        assert!(ratios[0] > 3.0);
+        assert!(ratios[9] > 0.95 && ratios[9] < 1.05);
+        assert_eq!(quantizer, 10);
+        assert!(RateController::new(0, 1.0).is_err());
+    }
+
+    #[test]
+    pub fn bounds_each_adjustment() {
+        let mut controller = RateController::new(40_000, 1.0).unwrap();
+        let mut steps = Vec::new();
+        let mut adjust = |step| steps.push(step);
+        // A window with nothing written, then one at a thousand times the target
+        assert_eq!(controller.update(0, 1.0, &mut adjust), Some(0.0));
+        assert_eq!(
+            controller.update(40_000_000, 1.0, &mut adjust),
+            Some(1000.0)
+        );
+        assert_eq!(steps, [24]);
+    }
+}
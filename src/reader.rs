@@ -1,67 +1,575 @@
 #![allow(dead_code)]
-use std::io::{BufReader, Error, ErrorKind, Read};
+use crate::byte_io::ByteSource;
+use crate::{BitOrder, BitWrite, Error, FinishPolicy, PREALLOCATE_LIMIT};
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
 
-pub struct Reader<R: Read> {
-    byte: [u8; 1],
-    byte_offset: usize,
-    reader: BufReader<R>,
+// The unread end of the byte a Reader was part way through, as a value like read_bits would give
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LeftoverBits {
+    pub bits: u8,
+    pub count: usize,
 }
 
-impl<R: Read> Reader<R> {
+// LOW_MASKS[n] has the low n bits set, for n up to 64
+const LOW_MASKS: [u64; 65] = {
+    let mut masks = [0; 65];
+    let mut n = 1;
+    while n <= 64 {
+        masks[n] = u64::MAX >> (64 - n);
+        n += 1;
+    }
+    masks
+};
+
+fn low_mask(number_of_bits: usize) -> u64 {
+    LOW_MASKS[number_of_bits]
+}
+
+// No input makes a Reader panic: bad lengths, truncated streams and out of range seeks all come
+// back as errors. reader::test::never_panics throws random operations at random input to hold it
+// to that.
+pub struct Reader<R: ByteSource> {
+    // Bits taken from the reader but not read yet, in the low `cached` bits. The next bit is the
+    // top one of those in MSB first order and the bottom one in LSB first order.
+    cache: u64,
+    cached: usize,
+    bit_order: BitOrder,
+    bits_read: u64,
+    // Bytes taken from the reader by a peek that don't fit in the cache yet
+    peeked: VecDeque<u8>,
+    // What BufReader would hold: bytes from the reader that haven't gone anywhere yet are
+    // buffer[start..end]
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    reader: R,
+}
+
+impl<R: ByteSource> Reader<R> {
     pub fn new(inner_reader: R) -> Reader<R> {
+        Reader::with_bit_order(inner_reader, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(inner_reader: R, bit_order: BitOrder) -> Reader<R> {
         Reader {
-            byte: [0],
-            byte_offset: 8,
-            reader: BufReader::new(inner_reader),
+            cache: 0,
+            cached: 0,
+            bit_order,
+            bits_read: 0,
+            peeked: VecDeque::new(),
+            buffer: alloc::vec![0; R::BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            reader: inner_reader,
         }
     }
 
-    fn extract_bit(&mut self, byte: u8) -> bool {
-        let front_is_one = byte & 0b1000_0000 != 0;
-        self.byte[0] <<= 1; // Pushes the front bit off the buffer
-        self.byte_offset += 1;
-        front_is_one
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
     }
 
-    pub fn read_bit(&mut self) -> Result<bool, Error> {
-        if self.byte_offset == 8 {
-            // Refresh the buffer
-            let n = self.reader.read(&mut self.byte)?;
-            if n == 0 {
-                // Didn't read anything at all
-                return Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected EOF"));
+    // Whether the next bit starts a byte
+    pub fn is_aligned(&self) -> bool {
+        self.cached.is_multiple_of(8)
+    }
+
+    // Bits left unread in the current byte
+    pub fn pending_bits(&self) -> usize {
+        self.cached % 8
+    }
+
+    // Bits consumed so far, skipped ones included. Peeking and seeking don't change it.
+    pub fn bits_read(&self) -> u64 {
+        self.bits_read
+    }
+
+    // How many bytes are buffered, asking the reader for more once there are none. Zero only at
+    // the end of the stream.
+    fn fill_buffer(&mut self) -> Result<usize, Error> {
+        if self.start == self.end {
+            self.end = self.reader.pull(&mut self.buffer, self.bits_read)?;
+            self.start = 0;
+        }
+        Ok(self.end - self.start)
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        match self.bit_order {
+            BitOrder::MsbFirst => self.cache = self.cache << 8 | byte as u64,
+            BitOrder::LsbFirst => self.cache |= (byte as u64) << self.cached,
+        }
+        self.cached += 8;
+    }
+
+    // Tops the cache up with whole bytes, peeked ones first. Only waits on the reader while there
+    // are fewer than wanted bits, so a live source isn't asked for data nobody needs yet. Stops
+    // short at the end of the stream.
+    fn refill(&mut self, wanted: usize) -> Result<(), Error> {
+        while self.cached <= 56 {
+            if let Some(byte) = self.peeked.pop_front() {
+                self.push_byte(byte);
+                continue;
+            }
+            if self.start == self.end && self.cached >= wanted {
+                break;
+            }
+            let available = self.fill_buffer()?;
+            if available == 0 {
+                break;
             }
-            self.byte_offset = 0;
+            let n = available.min((64 - self.cached) / 8);
+            for index in self.start..self.start + n {
+                self.push_byte(self.buffer[index]);
+            }
+            self.start += n;
         }
-        let bit = self.extract_bit(self.byte[0]);
-        Ok(bit)
+        Ok(())
+    }
+
+    // Takes number_of_bits (1 to 64, no more than are cached) off the front of the cache
+    fn take_cached(&mut self, number_of_bits: usize) -> u64 {
+        let bits = match self.bit_order {
+            BitOrder::MsbFirst => {
+                let bits = self.cache >> (self.cached - number_of_bits);
+                self.cache &= low_mask(self.cached - number_of_bits);
+                bits
+            }
+            BitOrder::LsbFirst => {
+                let bits = self.cache & low_mask(number_of_bits);
+                self.cache = self.cache.checked_shr(number_of_bits as u32).unwrap_or(0);
+                bits
+            }
+        };
+        self.cached -= number_of_bits;
+        self.bits_read += number_of_bits as u64;
+        bits
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
     }
 
     pub fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
         if number_of_bits > 128 {
-            // Make sure we're not writing more than 128 bits
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Tried to read more than 128 bits",
-            ));
+            return Err(Error::too_wide(self.bits_read, number_of_bits, 128));
+        }
+        if number_of_bits <= 16 {
+            // Short reads like Huffman codes nearly always come straight out of the cache
+            if number_of_bits == 0 {
+                return Ok(0);
+            }
+            if self.cached < number_of_bits {
+                self.refill(number_of_bits)?;
+            }
+            if self.cached >= number_of_bits {
+                return Ok(self.take_cached(number_of_bits) as u128);
+            }
+        }
+        let (output, done) = self.read_up_to(number_of_bits)?;
+        if done < number_of_bits {
+            let missing = (number_of_bits - done) as u64;
+            return Err(Error::eof(self.bits_read, missing));
+        }
+        Ok(output)
+    }
+
+    // Reads up to number_of_bits, fewer if the stream ends first, and gives back the value of the
+    // ones it got with how many there were. For the last block of a stream that's allowed to be
+    // short.
+    pub fn read_bits_partial(&mut self, number_of_bits: usize) -> Result<(u128, usize), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read, number_of_bits, 128));
         }
+        self.read_up_to(number_of_bits)
+    }
+
+    fn read_up_to(&mut self, number_of_bits: usize) -> Result<(u128, usize), Error> {
         let mut output: u128 = 0;
-        for _ in 0..number_of_bits {
-            // Keep reading from front of buffer and create bufer from that
-            output = output << 1;
-            if self.read_bit()? {
-                output = output | 0b1;
+        let mut done = 0;
+        while done < number_of_bits {
+            if self.cached < number_of_bits - done {
+                self.refill(number_of_bits - done)?;
+                if self.cached == 0 {
+                    break;
+                }
+            }
+            let count = self.cached.min(number_of_bits - done);
+            let bits = self.take_cached(count) as u128;
+            match self.bit_order {
+                // First bits read are the most significant
+                BitOrder::MsbFirst => output = output << count | bits,
+                // First bits read are the least significant
+                BitOrder::LsbFirst => output |= bits << done,
+            }
+            done += count;
+        }
+        Ok((output, done))
+    }
+
+    // Ok(None) when the stream ends cleanly before the read. Running out part way through is still
+    // an UnexpectedEof error.
+    pub fn read_bit_opt(&mut self) -> Result<Option<bool>, Error> {
+        Ok(self.read_bits_opt(1)?.map(|bit| bit == 1))
+    }
+
+    pub fn read_bits_opt(&mut self, number_of_bits: usize) -> Result<Option<u128>, Error> {
+        if number_of_bits > 0 && self.cached == 0 {
+            self.refill(1)?;
+            if self.cached == 0 {
+                return Ok(None);
+            }
+        }
+        self.read_bits(number_of_bits).map(Some)
+    }
+
+    // Reads out.len() values of width bits each, the way bit-packed integer blocks are laid out
+    pub fn read_packed(&mut self, width: usize, out: &mut [u64]) -> Result<(), Error> {
+        if width > 64 {
+            return Err(Error::too_wide(self.bits_read, width, 64));
+        }
+        if width == 0 {
+            out.fill(0);
+            return Ok(());
+        }
+        #[cfg(feature = "simd")]
+        let out = if self.is_aligned() && width <= crate::packing::MAX_WIDTH && out.len() >= 8 {
+            // Whole groups of 8 values end on a byte, so they can be read as bytes and unpacked
+            let (block, rest) = out.split_at_mut(out.len() / 8 * 8);
+            let length = block.len() * width / 8;
+            let mut bytes = vec![0; length + crate::packing::SLACK];
+            self.read_bytes_into(&mut bytes[..length])?;
+            crate::packing::unpack(width, self.bit_order, &bytes, block);
+            rest
+        } else {
+            out
+        };
+        for value in out.iter_mut() {
+            if self.cached < width {
+                self.refill(width)?;
+            }
+            *value = if self.cached >= width {
+                self.take_cached(width)
+            } else {
+                // Straddles the end of the cache, or the stream
+                self.read_bits(width)? as u64
+            };
+        }
+        Ok(())
+    }
+
+    // Reads a count or length, making sure it fits in a usize on this target (16 and 32 bit ones
+    // can't hold every 32 or 64 bit value)
+    pub fn read_usize(&mut self, number_of_bits: usize) -> Result<usize, Error> {
+        let value = self.read_bits(number_of_bits)?;
+        usize::try_from(value).map_err(|_| {
+            let message = format!("{} doesn't fit in a usize on this target", value);
+            Error::invalid_data(self.bits_read, &message)
+        })
+    }
+
+    pub fn peek_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.peek_bits(1)? == 1)
+    }
+
+    // Same as read_bits, but the bits are still there for the next read
+    pub fn peek_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read, number_of_bits, 128));
+        }
+        if self.cached < number_of_bits {
+            self.refill(number_of_bits)?;
+        }
+        // Whatever doesn't fit in the cache waits in peeked
+        while self.cached + 8 * self.peeked.len() < number_of_bits {
+            if self.fill_buffer()? == 0 {
+                let missing = number_of_bits - self.cached - 8 * self.peeked.len();
+                return Err(Error::eof(self.bits_read, missing as u64));
+            }
+            self.peeked.push_back(self.buffer[self.start]);
+            self.start += 1;
+        }
+
+        if number_of_bits <= self.cached {
+            let bits = match self.bit_order {
+                BitOrder::MsbFirst => self
+                    .cache
+                    .checked_shr((self.cached - number_of_bits) as u32)
+                    .unwrap_or(0),
+                BitOrder::LsbFirst => self.cache & low_mask(number_of_bits),
+            };
+            return Ok(bits as u128);
+        }
+        let mut output: u128 = 0;
+        for index in 0..number_of_bits {
+            let bit = if index < self.cached {
+                match self.bit_order {
+                    BitOrder::MsbFirst => self.cache >> (self.cached - 1 - index) & 1,
+                    BitOrder::LsbFirst => self.cache >> index & 1,
+                }
+            } else {
+                let index = index - self.cached;
+                let byte = self.peeked[index / 8] as u64;
+                match self.bit_order {
+                    BitOrder::MsbFirst => byte >> (7 - index % 8) & 1,
+                    BitOrder::LsbFirst => byte >> (index % 8) & 1,
+                }
+            } as u128;
+            match self.bit_order {
+                BitOrder::MsbFirst => output = output << 1 | bit,
+                BitOrder::LsbFirst => output |= bit << index,
             }
         }
         Ok(output)
     }
 
+    // Up to number_of_bytes bytes from the current one on, all still unread, with how many bits at
+    // the start of the first are already read (those come back as zeros). Fewer bytes only at the
+    // end of the stream.
+    pub(crate) fn peek_bytes(&mut self, number_of_bytes: usize) -> Result<(Vec<u8>, usize), Error> {
+        let offset = (8 - self.cached % 8) % 8;
+        let total = self.cached + offset;
+        let cache = self.cache;
+        let bit_order = self.bit_order;
+        let mut bytes: Vec<u8> = (0..total / 8)
+            .map(|index| match bit_order {
+                BitOrder::MsbFirst => (cache >> (total - 8 * (index + 1))) as u8,
+                BitOrder::LsbFirst => ((cache << offset) >> (8 * index)) as u8,
+            })
+            .chain(self.peeked.iter().copied())
+            .take(number_of_bytes)
+            .collect();
+        while bytes.len() < number_of_bytes {
+            let available = self.fill_buffer()?;
+            if available == 0 {
+                break;
+            }
+            let n = available.min(number_of_bytes - bytes.len());
+            let buffered = &self.buffer[self.start..self.start + n];
+            bytes.extend_from_slice(buffered);
+            self.peeked.extend(buffered);
+            self.start += n;
+        }
+        Ok((bytes, offset))
+    }
+
+    // Up to number_of_bytes bytes as read_byte would give them from here, without reading them.
+    // Fewer only at the end of the stream, where a last partial byte is left off.
+    pub(crate) fn peek_aligned(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+        if self.is_aligned() {
+            return Ok(self.peek_bytes(number_of_bytes)?.0);
+        }
+        let (bytes, offset) = self.peek_bytes(number_of_bytes + 1)?;
+        Ok(bytes
+            .windows(2)
+            .map(|pair| match self.bit_order {
+                BitOrder::MsbFirst => pair[0] << offset | pair[1] >> (8 - offset),
+                BitOrder::LsbFirst => pair[0] >> offset | pair[1] << (8 - offset),
+            })
+            .collect())
+    }
+
+    // Throws away bits, going a whole byte at a time once the cache is used up
+    pub fn skip_bits(&mut self, number_of_bits: u64) -> Result<(), Error> {
+        let from_cache = number_of_bits.min(self.cached as u64) as usize;
+        if from_cache > 0 {
+            self.take_cached(from_cache);
+        }
+        let mut remaining = number_of_bits - from_cache as u64;
+        while remaining >= 8 {
+            if self.peeked.pop_front().is_none() {
+                break;
+            }
+            remaining -= 8;
+            self.bits_read += 8;
+        }
+        let whole_bytes = remaining / 8;
+        let mut skipped = 0;
+        while skipped < whole_bytes {
+            let available = self.fill_buffer()?;
+            if available == 0 {
+                break;
+            }
+            let n = (available as u64).min(whole_bytes - skipped);
+            self.start += n as usize;
+            self.bits_read += n * 8;
+            skipped += n;
+        }
+        if skipped < whole_bytes {
+            let missing = (whole_bytes - skipped) * 8 + remaining % 8;
+            return Err(Error::eof(self.bits_read, missing));
+        }
+        self.read_bits((remaining % 8) as usize)?;
+        Ok(())
+    }
+
+    // The rest of the stream a bit at a time, for use with iterator adapters. Ends cleanly at the
+    // end of the stream and stops after the first error.
+    pub fn bits(&mut self) -> Bits<'_, R> {
+        Bits {
+            reader: self,
+            width: 1,
+            done: false,
+        }
+    }
+
+    // Like bits, but width bits (1 to 128) at a time. Bits left over at the end that don't make
+    // a whole group come out as an UnexpectedEof error. Any other width is one error, even at the
+    // end of the stream.
+    pub fn chunks(&mut self, width: usize) -> Chunks<'_, R> {
+        Chunks(Bits {
+            reader: self,
+            width,
+            done: false,
+        })
+    }
+
+    // A reader over the next limit bits only, for a field or nested structure with a declared bit
+    // length. Reads past the limit fail with UnexpectedEof without touching this reader.
+    pub fn take_bits(&mut self, limit: u64) -> TakeBits<'_, R> {
+        TakeBits {
+            end: self.bits_read.saturating_add(limit),
+            reader: self,
+        }
+    }
+
+    // Reads through to sink as well, the reading side of TeeWriter. The sink gets every bit read or
+    // skipped, in order, so a checksum sink with the same bit order sees the stream's bytes.
+    pub fn tee<B: BitWrite>(&mut self, sink: B) -> TeeReader<'_, R, B> {
+        TeeReader { reader: self, sink }
+    }
+
+    // Skips to the next multiple of alignment bits, counted like bits_read, the reading side of
+    // Writer::pad_to_alignment. Gives back how many bits were skipped.
+    pub fn skip_to_alignment(&mut self, alignment: u64) -> Result<u64, Error> {
+        if alignment == 0 {
+            return Err(Error::invalid_input(
+                self.bits_read,
+                "Alignment has to be at least 1 bit",
+            ));
+        }
+        let padding = (alignment - self.bits_read % alignment) % alignment;
+        self.skip_bits(padding)?;
+        Ok(padding)
+    }
+
+    // Drops the rest of the current byte, the reading side of Writer::pad_to_byte. With
+    // require_zeros the dropped bits have to be zero padding.
+    pub fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        let padding = self.read_bits(self.pending_bits())?;
+        if require_zeros && padding != 0 {
+            return Err(Error::invalid_data(
+                self.bits_read,
+                "Padding bits before the byte boundary aren't zero",
+            ));
+        }
+        Ok(())
+    }
+
+    // Fails unless the stream has nothing left, for catching trailing garbage after a decode
+    pub fn ensure_exhausted(&mut self) -> Result<(), Error> {
+        self.refill(1)?;
+        if self.cached > 0 {
+            return Err(Error::invalid_data(
+                self.bits_read,
+                "Trailing data after the end of the stream",
+            ));
+        }
+        Ok(())
+    }
+
+    // The reading side of Writer::finish: checks the rest of the last byte is the padding policy
+    // says it should be, and that nothing comes after it
+    pub fn finish(&mut self, policy: FinishPolicy) -> Result<(), Error> {
+        let pending = self.pending_bits();
+        let ok = match policy {
+            FinishPolicy::StopBit => {
+                let stop = match self.read_bit() {
+                    Err(Error::UnexpectedEof { .. }) => false,
+                    stop => stop?,
+                };
+                stop && self.read_bits(self.pending_bits())? == 0
+            }
+            FinishPolicy::Zeros => self.read_bits(pending)? == 0,
+            FinishPolicy::Ones => self.read_bits(pending)? == low_mask(pending) as u128,
+            FinishPolicy::RequireAligned => pending == 0,
+        };
+        if !ok {
+            let message = format!("Trailing bits don't match {:?} padding", policy);
+            return Err(Error::invalid_data(self.bits_read, &message));
+        }
+        self.ensure_exhausted()
+    }
+
+    // Flag arrays, unpacked from 64 bit reads
+    pub fn read_bools(&mut self, number_of_bools: usize) -> Result<Vec<bool>, Error> {
+        let mut bools = Vec::with_capacity(number_of_bools.min(PREALLOCATE_LIMIT));
+        let mut remaining = number_of_bools;
+        while remaining > 0 {
+            let count = remaining.min(64);
+            let piece = self.read_bits(count)?;
+            match self.bit_order {
+                BitOrder::MsbFirst => {
+                    bools.extend((0..count).rev().map(|shift| piece >> shift & 1 == 1))
+                }
+                BitOrder::LsbFirst => bools.extend((0..count).map(|shift| piece >> shift & 1 == 1)),
+            }
+            remaining -= count;
+        }
+        Ok(bools)
+    }
+
+    // Reads the presence bit Writer::write_optional puts first, then the value through read if
+    // it's set
+    pub fn read_optional<T, E: From<Error>>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        if self.read_bit()? {
+            Ok(Some(read(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn read_byte(&mut self) -> Result<u8, Error> {
         Ok(self.read_bits(8)? as u8)
     }
 
     pub fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
         let mut result: Vec<u8> = Vec::new();
+        if self.is_aligned() {
+            // Whole bytes come out the same in either bit order, so copy them straight across
+            while result.len() < number_of_bytes && self.cached > 0 {
+                result.push(self.take_cached(8) as u8);
+            }
+            while result.len() < number_of_bytes {
+                match self.peeked.pop_front() {
+                    Some(byte) => result.push(byte),
+                    None => break,
+                }
+                self.bits_read += 8;
+            }
+            while result.len() < number_of_bytes {
+                let available = self.fill_buffer()?;
+                if available == 0 {
+                    break;
+                }
+                let n = available.min(number_of_bytes - result.len());
+                result.extend_from_slice(&self.buffer[self.start..self.start + n]);
+                self.start += n;
+                self.bits_read += 8 * n as u64;
+            }
+            if result.len() < number_of_bytes {
+                let missing = 8 * (number_of_bytes - result.len()) as u64;
+                return Err(Error::eof(self.bits_read, missing));
+            }
+            return Ok(result);
+        }
         for _ in 0..number_of_bytes {
             let new_byte = self.read_byte()?;
             result.push(new_byte);
@@ -69,15 +577,367 @@ impl<R: Read> Reader<R> {
         Ok(result)
     }
 
-    pub fn get_ref(&mut self) -> &BufReader<R> {
+    // Fills buf without allocating, for callers reading lots of small chunks
+    pub fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if self.is_aligned() {
+            let mut filled = 0;
+            while filled < buf.len() && self.cached > 0 {
+                buf[filled] = self.take_cached(8) as u8;
+                filled += 1;
+            }
+            let from_peek = (buf.len() - filled).min(self.peeked.len());
+            for (byte, peeked) in buf[filled..].iter_mut().zip(self.peeked.drain(..from_peek)) {
+                *byte = peeked;
+            }
+            filled += from_peek;
+            self.bits_read += 8 * from_peek as u64;
+            // Buffered bytes first, then straight from the reader
+            while filled < buf.len() {
+                let n = if self.start < self.end {
+                    let n = (self.end - self.start).min(buf.len() - filled);
+                    buf[filled..filled + n]
+                        .copy_from_slice(&self.buffer[self.start..self.start + n]);
+                    self.start += n;
+                    n
+                } else {
+                    self.reader.pull(&mut buf[filled..], self.bits_read)?
+                };
+                if n == 0 {
+                    let missing = 8 * (buf.len() - filled) as u64;
+                    return Err(Error::eof(self.bits_read, missing));
+                }
+                filled += n;
+                self.bits_read += 8 * n as u64;
+            }
+            return Ok(());
+        }
+        for byte in buf.iter_mut() {
+            *byte = self.read_byte()?;
+        }
+        Ok(())
+    }
+
+    // The inner reader, which may be further on than bits_read since bytes are taken from it ahead
+    // of being read
+    pub fn get_ref(&self) -> &R {
         &self.reader
     }
+
+    // The rest of the current byte, the whole bytes taken from the inner reader but not read yet
+    // and the inner reader, for into_inner to put back together
+    pub(crate) fn into_parts(mut self) -> (LeftoverBits, Vec<u8>, R) {
+        let count = self.pending_bits();
+        let bits = if count > 0 {
+            self.take_cached(count) as u8
+        } else {
+            0
+        };
+        let mut buffered = Vec::new();
+        while self.cached > 0 {
+            buffered.push(self.take_cached(8) as u8);
+        }
+        buffered.extend(self.peeked.drain(..));
+        buffered.extend_from_slice(&self.buffer[self.start..self.end]);
+        (LeftoverBits { bits, count }, buffered, self.reader)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Reader<R> {
+    // Carries on into next once the inner reader runs out, keeping the bit position. Chaining
+    // again adds more segments.
+    pub fn chain<S: Read>(self, next: S) -> Reader<io::Chain<R, S>> {
+        // Bytes already buffered from the inner reader still come before next
+        let mut peeked = self.peeked;
+        peeked.extend(&self.buffer[self.start..self.end]);
+        Reader {
+            cache: self.cache,
+            cached: self.cached,
+            bit_order: self.bit_order,
+            bits_read: self.bits_read,
+            peeked,
+            buffer: self.buffer,
+            start: 0,
+            end: 0,
+            reader: self.reader.chain(next),
+        }
+    }
+
+    // Gives back the rest of the current byte, and a reader that carries on at the next byte.
+    // That's the inner reader with whatever was already buffered from it put back in front.
+    pub fn into_inner(self) -> (LeftoverBits, io::Chain<io::Cursor<Vec<u8>>, R>) {
+        let (leftover, buffered, inner) = self.into_parts();
+        (leftover, io::Cursor::new(buffered).chain(inner))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek> Reader<R> {
+    // Moves to a bit offset in the inner stream and gives back the new offset from the start.
+    // Positions are in bits, so SeekFrom::End(-3) is 3 bits before the end.
+    pub fn seek_bits(&mut self, position: SeekFrom) -> Result<u64, Error> {
+        let target = match position {
+            SeekFrom::Start(bits) => Some(bits),
+            SeekFrom::Current(bits) => {
+                // The cached, peeked and buffered bytes have already left the inner stream. A
+                // stream that reports a position before them is lying, which is an error rather
+                // than a panic.
+                let taken = self.peeked.len() + self.end - self.start;
+                self.reader
+                    .stream_position()
+                    .map_err(Error::io_at(self.bits_read))?
+                    .checked_sub(taken as u64)
+                    .and_then(|byte_position| byte_position.checked_mul(8))
+                    .and_then(|current| current.checked_sub(self.cached as u64))
+                    .and_then(|current| current.checked_add_signed(bits))
+            }
+            SeekFrom::End(bits) => {
+                // Finding the end moves the inner stream, so it goes back to where the buffer and
+                // cache left it if the target turns out to be out of range
+                let current = self
+                    .reader
+                    .stream_position()
+                    .map_err(Error::io_at(self.bits_read))?;
+                let end = self
+                    .reader
+                    .seek(SeekFrom::End(0))
+                    .map_err(Error::io_at(self.bits_read))?;
+                let target = end
+                    .checked_mul(8)
+                    .and_then(|end| end.checked_add_signed(bits));
+                if target.is_none() {
+                    self.reader
+                        .seek(SeekFrom::Start(current))
+                        .map_err(Error::io_at(self.bits_read))?;
+                }
+                target
+            }
+        };
+        let target = target.ok_or_else(|| {
+            Error::invalid_input(
+                self.bits_read,
+                "Tried to seek to a negative or overflowing bit position",
+            )
+        })?;
+
+        self.reader
+            .seek(SeekFrom::Start(target / 8))
+            .map_err(Error::io_at(self.bits_read))?;
+        self.peeked.clear();
+        self.start = 0;
+        self.end = 0;
+        self.cache = 0;
+        self.cached = 0;
+        let bits_read = self.bits_read;
+        self.read_bits((target % 8) as usize)?;
+        self.bits_read = bits_read;
+        Ok(target)
+    }
+}
+
+pub struct Bits<'a, R: ByteSource> {
+    reader: &'a mut Reader<R>,
+    width: usize,
+    done: bool,
+}
+
+impl<'a, R: ByteSource> Bits<'a, R> {
+    fn next_group(&mut self) -> Option<Result<u128, Error>> {
+        if self.done {
+            return None;
+        }
+        let group = match self.width {
+            // Would be Ok(0) forever
+            0 => Some(Err(Error::invalid_input(
+                self.reader.bits_read,
+                "Chunks have to be at least 1 bit wide",
+            ))),
+            width if width > 128 => Some(Err(Error::too_wide(self.reader.bits_read, width, 128))),
+            width => self.reader.read_bits_opt(width).transpose(),
+        };
+        self.done = !matches!(group, Some(Ok(_)));
+        group
+    }
+}
+
+impl<'a, R: ByteSource> Iterator for Bits<'a, R> {
+    type Item = Result<bool, Error>;
+
+    fn next(&mut self) -> Option<Result<bool, Error>> {
+        Some(self.next_group()?.map(|bit| bit == 1))
+    }
+}
+
+pub struct Chunks<'a, R: ByteSource>(Bits<'a, R>);
+
+impl<'a, R: ByteSource> Iterator for Chunks<'a, R> {
+    type Item = Result<u128, Error>;
+
+    fn next(&mut self) -> Option<Result<u128, Error>> {
+        self.0.next_group()
+    }
+}
+
+pub struct TakeBits<'a, R: ByteSource> {
+    reader: &'a mut Reader<R>,
+    // The limit as a bits_read count, so limits taken inside this one use up this one's too
+    end: u64,
+}
+
+impl<'a, R: ByteSource> TakeBits<'a, R> {
+    pub fn remaining(&self) -> u64 {
+        self.end.saturating_sub(self.reader.bits_read)
+    }
+
+    fn check(&self, number_of_bits: u64) -> Result<(), Error> {
+        let remaining = self.remaining();
+        if number_of_bits > remaining {
+            return Err(Error::eof(
+                self.reader.bits_read,
+                number_of_bits - remaining,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    pub fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return self.reader.read_bits(number_of_bits);
+        }
+        self.check(number_of_bits as u64)?;
+        self.reader.read_bits(number_of_bits)
+    }
+
+    pub fn read_usize(&mut self, number_of_bits: usize) -> Result<usize, Error> {
+        if number_of_bits > 128 {
+            return self.reader.read_usize(number_of_bits);
+        }
+        self.check(number_of_bits as u64)?;
+        self.reader.read_usize(number_of_bits)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    pub fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+        let bits = (number_of_bytes as u64).saturating_mul(8);
+        self.check(bits)?;
+        self.reader.read_bytes(number_of_bytes)
+    }
+
+    pub fn skip_bits(&mut self, number_of_bits: u64) -> Result<(), Error> {
+        self.check(number_of_bits)?;
+        self.reader.skip_bits(number_of_bits)
+    }
+
+    // Skips whatever of the limit is left, so the outer reader ends up just past the field
+    pub fn skip_rest(&mut self) -> Result<(), Error> {
+        self.skip_bits(self.remaining())
+    }
+
+    // A limit inside this one, which can't reach past it
+    pub fn take_bits(&mut self, limit: u64) -> TakeBits<'_, R> {
+        TakeBits {
+            end: self.end.min(self.reader.bits_read.saturating_add(limit)),
+            reader: self.reader,
+        }
+    }
+}
+
+pub struct TeeReader<'a, R: ByteSource, B: BitWrite> {
+    reader: &'a mut Reader<R>,
+    sink: B,
+}
+
+impl<'a, R: ByteSource, B: BitWrite> TeeReader<'a, R, B> {
+    pub fn bits_read(&self) -> u64 {
+        self.reader.bits_read
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    pub fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        let bits = self.reader.read_bits(number_of_bits)?;
+        self.sink.write_bits(bits, number_of_bits)?;
+        Ok(bits)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    pub fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, Error> {
+        let bytes = self.reader.read_bytes(number_of_bytes)?;
+        self.sink.write_bytes(bytes.clone())?;
+        Ok(bytes)
+    }
+
+    // Skipped bits are still read, so the sink sees them
+    pub fn skip_bits(&mut self, mut number_of_bits: u64) -> Result<(), Error> {
+        while number_of_bits > 0 {
+            let count = number_of_bits.min(128);
+            self.read_bits(count as usize)?;
+            number_of_bits -= count;
+        }
+        Ok(())
+    }
+
+    pub fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        let position = self.reader.bits_read;
+        let padding = self.read_bits(self.reader.pending_bits())?;
+        if require_zeros && padding != 0 {
+            return Err(Error::invalid_data(
+                position,
+                "Padding bits before the byte boundary aren't zero",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn sink(&self) -> &B {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut B {
+        &mut self.sink
+    }
+
+    // Back to reading from the reader alone
+    pub fn into_sink(self) -> B {
+        self.sink
+    }
+}
+
+// Bytes at a time from the current bit position. Hits a clean EOF once fewer than 8 bits are left
+// in the limit.
+#[cfg(feature = "std")]
+impl<'a, R: ByteSource> Read for TakeBits<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf
+            .len()
+            .min(usize::try_from(self.remaining() / 8).unwrap_or(usize::MAX));
+        if count == 0 {
+            return Ok(0);
+        }
+        self.check(count as u64 * 8)?;
+        self.reader.read_bytes_into(&mut buf[..count])?;
+        Ok(count)
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
+    use crate::Writer;
     use std::io::Cursor;
+    use std::io::ErrorKind;
 
     #[test]
     pub fn read_bit() {
@@ -152,4 +1012,544 @@ mod test {
 
         assert_eq!(reader.read_bytes(2).unwrap(), vec![251, 85]);
     }
+
+    #[test]
+    pub fn read_lsb_first() {
+        // 251 = 1111_1011
+        let cursor = Cursor::new(vec![251, 85]);
+        let mut reader = Reader::with_bit_order(cursor, BitOrder::LsbFirst);
+
+        // Bits come out starting from the back of the byte
+        assert!(reader.read_bit().unwrap());
+        assert!(reader.read_bit().unwrap());
+        assert!(!reader.read_bit().unwrap());
+        // Remaining 1_1111 is read LSB first
+        assert_eq!(reader.read_bits(5).unwrap(), 0b1_1111);
+        assert_eq!(reader.read_byte().unwrap(), 85);
+    }
+
+    #[test]
+    pub fn peek_bits() {
+        // 1111_1011 0101_0101
+        let cursor = Cursor::new(vec![251, 85]);
+        let mut reader = Reader::new(cursor);
+
+        assert!(reader.peek_bit().unwrap());
+        assert_eq!(reader.peek_bits(4).unwrap(), 0b1111);
+        assert_eq!(reader.read_bits(5).unwrap(), 0b1_1111);
+        // Peeking across the byte boundary
+        assert_eq!(reader.peek_bits(6).unwrap(), 0b011_010);
+        assert_eq!(reader.peek_bits(11).unwrap(), 0b011_0101_0101);
+        assert!(reader.peek_bits(12).is_err());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b011);
+        assert_eq!(reader.read_byte().unwrap(), 85);
+        assert!(reader.peek_bit().is_err());
+    }
+
+    #[test]
+    pub fn skip_bits() {
+        let cursor = Cursor::new(vec![0b1010_0000, 1, 2, 3, 0b0110_0000]);
+        let mut reader = Reader::new(cursor);
+
+        reader.skip_bits(2).unwrap();
+        assert!(reader.read_bit().unwrap());
+        // Rest of the first byte, 3 whole bytes, then a bit
+        reader.skip_bits(5 + 24 + 1).unwrap();
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+
+        let cursor = Cursor::new(vec![1, 2, 3, 4]);
+        let mut reader = Reader::new(cursor);
+        assert_eq!(reader.peek_bits(20).unwrap(), 0x1020);
+        reader.skip_bits(16).unwrap();
+        assert_eq!(reader.read_byte().unwrap(), 3);
+        assert!(reader.skip_bits(9).is_err());
+    }
+
+    #[test]
+    pub fn align_to_byte() {
+        let cursor = Cursor::new(vec![0b1010_0000, 0b1100_0001, 0xFF]);
+        let mut reader = Reader::new(cursor);
+
+        reader.align_to_byte(true).unwrap();
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert!(!reader.is_aligned());
+        assert_eq!(reader.pending_bits(), 5);
+        reader.align_to_byte(true).unwrap();
+        assert!(reader.is_aligned());
+        assert_eq!(reader.pending_bits(), 0);
+        assert_eq!(reader.bits_read(), 8);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        assert!(reader.align_to_byte(true).is_err());
+        assert_eq!(reader.read_byte().unwrap(), 0xFF);
+
+        let mut reader = Reader::new(Cursor::new(vec![0b1000_0001]));
+        reader.read_bit().unwrap();
+        reader.align_to_byte(false).unwrap();
+        assert!(reader.read_bit().is_err());
+    }
+
+    #[test]
+    pub fn into_inner() {
+        let cursor = Cursor::new(vec![0b1011_0110, 1, 2, 3]);
+        let mut reader = Reader::new(cursor);
+
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        reader.peek_bits(12).unwrap();
+        let (leftover, mut inner) = reader.into_inner();
+        assert_eq!(
+            leftover,
+            LeftoverBits {
+                bits: 0b1_0110,
+                count: 5
+            }
+        );
+        let mut rest = Vec::new();
+        inner.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [1, 2, 3]);
+
+        let cursor = Cursor::new(vec![0b1011_0110]);
+        let mut reader = Reader::with_bit_order(cursor, BitOrder::LsbFirst);
+        reader.read_bits(2).unwrap();
+        assert_eq!(
+            reader.into_inner().0,
+            LeftoverBits {
+                bits: 0b10_1101,
+                count: 6
+            }
+        );
+    }
+
+    #[test]
+    pub fn bits_read() {
+        let cursor = Cursor::new(vec![251, 85, 195, 0]);
+        let mut reader = Reader::new(cursor);
+
+        assert_eq!(reader.bits_read(), 0);
+        reader.read_bits(3).unwrap();
+        reader.peek_bits(10).unwrap();
+        assert_eq!(reader.bits_read(), 3);
+        reader.skip_bits(14).unwrap();
+        reader.read_byte().unwrap();
+        assert_eq!(reader.bits_read(), 25);
+    }
+
+    #[test]
+    pub fn seek_bits() {
+        // 1111_1011 0101_0101 1100_0011
+        let cursor = Cursor::new(vec![251, 85, 195]);
+        let mut reader = Reader::new(cursor);
+
+        assert_eq!(reader.seek_bits(SeekFrom::Start(5)).unwrap(), 5);
+        assert_eq!(reader.read_bits(4).unwrap(), 0b0110);
+        assert_eq!(reader.seek_bits(SeekFrom::Current(0)).unwrap(), 9);
+        reader.peek_bits(12).unwrap();
+        assert_eq!(reader.seek_bits(SeekFrom::Current(-2)).unwrap(), 7);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        // A seek out of range leaves the reader where it was, peeked bits and all
+        reader.peek_bits(8).unwrap();
+        assert!(reader.seek_bits(SeekFrom::End(-25)).is_err());
+        assert_eq!(reader.read_bits(6).unwrap(), 0b01_0101);
+        assert_eq!(reader.seek_bits(SeekFrom::End(-3)).unwrap(), 21);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b011);
+        assert!(reader.seek_bits(SeekFrom::Current(-25)).is_err());
+    }
+
+    #[test]
+    pub fn peek_lsb_first() {
+        let cursor = Cursor::new(vec![251, 85]);
+        let mut reader = Reader::with_bit_order(cursor, BitOrder::LsbFirst);
+
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        // 11_1110 from the first byte, then 01 from the back of 0101_0101
+        assert_eq!(reader.peek_bits(10).unwrap(), 0b01_0111_1110);
+        assert_eq!(reader.read_bits(10).unwrap(), 0b01_0111_1110);
+    }
+
+    #[test]
+    pub fn read_bytes_into() {
+        let cursor = Cursor::new(vec![251, 85, 195]);
+        let mut reader = Reader::new(cursor);
+
+        let mut buf = [0; 2];
+        reader.read_bits(4).unwrap();
+        reader.read_bytes_into(&mut buf).unwrap();
+        assert_eq!(buf, [0b1011_0101, 0b0101_1100]);
+        assert!(reader.read_bytes_into(&mut buf).is_err());
+    }
+
+    #[test]
+    pub fn aligned_bulk_reads() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut reader = Reader::with_bit_order(Cursor::new(data.clone()), BitOrder::LsbFirst);
+
+        assert_eq!(reader.peek_bits(12).unwrap(), 0x100);
+        assert_eq!(reader.read_bytes(100).unwrap(), &data[..100]);
+        let mut buf = [0; 100];
+        reader.read_bytes_into(&mut buf).unwrap();
+        assert_eq!(buf[..], data[100..200]);
+        assert_eq!(reader.bits_read(), 1600);
+        assert!(reader.read_bytes(57).is_err());
+    }
+
+    #[test]
+    pub fn reads_across_cache_refills() {
+        // Odd widths so reads straddle the 64 bit cache boundaries
+        let fields: Vec<(u128, usize)> = (0..40u32)
+            .map(|index| {
+                let width = (index as usize * 37) % 128 + 1;
+                let value = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128.rotate_left(index);
+                (value & (u128::MAX >> (128 - width)), width)
+            })
+            .collect();
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = crate::Writer::with_bit_order(Vec::new(), bit_order);
+            for &(value, width) in &fields {
+                writer.write_bits(value, width).unwrap();
+            }
+            let mut reader =
+                Reader::with_bit_order(Cursor::new(writer.into_inner().unwrap()), bit_order);
+            for (index, &(value, width)) in fields.iter().enumerate() {
+                if index % 3 == 0 {
+                    assert_eq!(reader.peek_bits(width).unwrap(), value);
+                }
+                assert_eq!(reader.read_bits(width).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    pub fn short_reads() {
+        // 1111_1011 0101_0101
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut reader = Reader::with_bit_order(Cursor::new(vec![251, 85]), bit_order);
+            let expected = match bit_order {
+                BitOrder::MsbFirst => [0b111, 0b1101, 0b0101_0101],
+                BitOrder::LsbFirst => [0b011, 0b1111, 0b0101_0101],
+            };
+            assert_eq!(reader.read_bits(0).unwrap(), 0);
+            assert_eq!(reader.read_bits(3).unwrap(), expected[0]);
+            assert_eq!(reader.read_bits(4).unwrap(), expected[1]);
+            assert!(reader.peek_bits(16).is_err());
+            reader.read_bit().unwrap();
+            assert_eq!(reader.read_bits(8).unwrap(), expected[2]);
+            assert!(reader.read_bits(1).is_err());
+        }
+    }
+
+    #[test]
+    pub fn read_packed() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = crate::Writer::with_bit_order(Vec::new(), bit_order);
+            let values: Vec<u64> = (0..50).map(|value| value * 37 % 128).collect();
+            for &value in &values {
+                writer.write_bits(value as u128, 7).unwrap();
+            }
+            writer.write_bits(u64::MAX as u128 - 1, 64).unwrap();
+            let mut reader =
+                Reader::with_bit_order(Cursor::new(writer.into_inner().unwrap()), bit_order);
+
+            let mut out = [0; 50];
+            reader.read_packed(7, &mut out).unwrap();
+            assert_eq!(out[..], values[..]);
+            let mut wide = [0; 1];
+            reader.read_packed(64, &mut wide).unwrap();
+            assert_eq!(wide, [u64::MAX - 1]);
+            reader.read_packed(0, &mut out).unwrap();
+            assert_eq!(out, [0; 50]);
+            assert!(reader.read_packed(8, &mut wide).is_err());
+            assert!(reader.read_packed(65, &mut wide).is_err());
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    pub fn never_panics() {
+        let mut random = crate::random::random_reader(7);
+        for _ in 0..500 {
+            let length = random.read_bits(5).unwrap() as usize;
+            let data = random.read_bytes(length).unwrap();
+            let bit_order = if random.read_bit().unwrap() {
+                BitOrder::MsbFirst
+            } else {
+                BitOrder::LsbFirst
+            };
+            let mut reader = Reader::with_bit_order(Cursor::new(data), bit_order);
+            for _ in 0..20 {
+                let amount = random.read_bits(64).unwrap() as u64;
+                let small = (amount % 160) as usize;
+                let _ = match random.read_bits(4).unwrap() {
+                    0 => reader.read_bits(small).map(drop),
+                    1 => reader.peek_bits(small).map(drop),
+                    2 => reader.skip_bits(amount % 300),
+                    3 => reader.skip_bits(amount),
+                    4 => reader.align_to_byte(true),
+                    5 => reader.read_bytes(small).map(drop),
+                    6 => reader.read_bytes_into(&mut vec![0; small % 40]),
+                    7 => reader.read_packed(small % 70, &mut [0; 3]),
+                    8 => reader.seek_bits(SeekFrom::Start(amount)).map(drop),
+                    9 => reader.seek_bits(SeekFrom::Current(amount as i64)).map(drop),
+                    10 => reader.seek_bits(SeekFrom::End(amount as i64)).map(drop),
+                    11 => reader
+                        .seek_bits(SeekFrom::Current(small as i64 - 80))
+                        .map(drop),
+                    _ => reader.read_bit().map(drop),
+                };
+            }
+            reader.into_inner();
+        }
+    }
+
+    #[test]
+    pub fn send_and_sync() {
+        // Readers and writers own their state outright, so they can move to (or be shared with)
+        // another thread whenever the inner stream can
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Reader<Cursor<Vec<u8>>>>();
+        assert_send_sync::<crate::Writer<Vec<u8>>>();
+    }
+
+    #[test]
+    pub fn read_usize() {
+        let cursor = Cursor::new(vec![0xFF; 10]);
+        let mut reader = Reader::new(cursor);
+
+        assert_eq!(reader.read_usize(8).unwrap(), 255);
+        // Wider than any usize
+        assert!(reader.read_usize(72).is_err());
+    }
+
+    #[test]
+    pub fn chain() {
+        // 1111_1011 0101_0101 | 1100_0011 | 0000_1111
+        let mut reader = Reader::new(Cursor::new(vec![251, 85]));
+        assert_eq!(reader.read_bits(4).unwrap(), 0b1111);
+        let mut reader = reader.chain(&[195][..]).chain(&[15][..]);
+
+        assert_eq!(reader.read_bits(16).unwrap(), 0b1011_0101_0101_1100);
+        assert_eq!(reader.bits_read(), 20);
+        assert_eq!(reader.read_bits(12).unwrap(), 0b0011_0000_1111);
+        assert!(reader.read_bit().is_err());
+    }
+
+    #[test]
+    pub fn take_bits() {
+        // 3 bit length, then a 5 bit field holding a 2 bit field, then 1 byte after it all
+        let mut reader = Reader::new(Cursor::new(vec![0b1011_0011, 0xAB, 0xCD]));
+        let length = reader.read_bits(3).unwrap() as u64;
+        {
+            let mut field = reader.take_bits(length);
+            {
+                let mut inner = field.take_bits(2);
+                assert_eq!(inner.read_bits(2).unwrap(), 0b10);
+                assert_eq!(
+                    inner.read_bit().unwrap_err().kind(),
+                    ErrorKind::UnexpectedEof
+                );
+            }
+            assert_eq!(field.remaining(), 3);
+            assert!(field.take_bits(10).read_bits(4).is_err());
+            assert!(field.read_bits(4).is_err());
+            assert_eq!(field.read_bits(3).unwrap(), 0b011);
+            assert!(field.read_bit().is_err());
+        }
+        assert_eq!(reader.bits_read(), 8);
+
+        let mut bytes = Vec::new();
+        reader.take_bits(12).read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, [0xAB]);
+        let mut field = reader.take_bits(4);
+        assert_eq!(field.read(&mut [0; 4]).unwrap(), 0);
+        field.skip_rest().unwrap();
+        assert_eq!(reader.read_bits(4).unwrap(), 0xD);
+    }
+
+    // Fails every other call with Interrupted, like a pipe read that keeps getting signalled
+    struct Interrupting<R> {
+        inner: R,
+        interrupt: bool,
+    }
+
+    impl<R: Read> Read for Interrupting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::new(ErrorKind::Interrupted, "Interrupted"));
+            }
+            let count = buf.len().min(1);
+            self.inner.read(&mut buf[..count])
+        }
+    }
+
+    #[test]
+    pub fn retries_interrupted_reads() {
+        let bytes: Vec<u8> = (0..40).collect();
+        let interrupting = Interrupting {
+            inner: Cursor::new(bytes.clone()),
+            interrupt: false,
+        };
+        let mut reader = Reader::new(interrupting);
+
+        assert_eq!(reader.peek_bits(128).unwrap() >> 120, 0);
+        assert_eq!(reader.read_bits(4).unwrap(), 0);
+        assert_eq!(reader.read_bytes(3).unwrap(), [0x00, 0x10, 0x20]);
+        reader.skip_bits(4).unwrap();
+        let mut rest = [0; 36];
+        reader.read_bytes_into(&mut rest).unwrap();
+        assert_eq!(rest[..], bytes[4..]);
+        assert!(reader.read_bit().is_err());
+    }
+
+    #[test]
+    pub fn skip_to_alignment() {
+        let mut reader = Reader::new(Cursor::new(vec![0xFF, 0x00, 0x00, 0x00, 0x00, 0xAB]));
+        reader.read_bits(3).unwrap();
+        assert_eq!(reader.skip_to_alignment(4).unwrap(), 1);
+        assert_eq!(reader.skip_to_alignment(4).unwrap(), 0);
+        assert_eq!(reader.skip_to_alignment(40).unwrap(), 36);
+        assert_eq!(reader.read_byte().unwrap(), 0xAB);
+        assert!(reader.skip_to_alignment(0).is_err());
+        assert!(reader.skip_to_alignment(64).is_err());
+    }
+
+    #[test]
+    pub fn finish() {
+        let finish = |bytes: Vec<u8>, bits: usize, policy| {
+            let mut reader = Reader::new(Cursor::new(bytes));
+            reader.read_bits(bits).unwrap();
+            reader.finish(policy)
+        };
+
+        assert!(finish(vec![0b0001_0000], 3, FinishPolicy::StopBit).is_ok());
+        assert!(finish(vec![0, 0b1000_0000], 8, FinishPolicy::StopBit).is_ok());
+        assert!(finish(vec![0], 8, FinishPolicy::StopBit).is_err());
+        assert!(finish(vec![0b0001_0001], 3, FinishPolicy::StopBit).is_err());
+        assert!(finish(vec![0b0000_0000], 3, FinishPolicy::Zeros).is_ok());
+        assert!(finish(vec![0b0001_1111], 3, FinishPolicy::Ones).is_ok());
+        assert!(finish(vec![0b0001_1110], 3, FinishPolicy::Ones).is_err());
+        assert!(finish(vec![0, 0], 16, FinishPolicy::RequireAligned).is_ok());
+        assert!(finish(vec![0], 3, FinishPolicy::RequireAligned).is_err());
+
+        let error = finish(vec![0, 0, 7], 8, FinishPolicy::Zeros).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Trailing data after the end of the stream at bit 8"
+        );
+        let mut reader = Reader::new(Cursor::new(vec![0xAB]));
+        assert!(reader.ensure_exhausted().is_err());
+        reader.read_byte().unwrap();
+        assert!(reader.ensure_exhausted().is_ok());
+    }
+
+    #[test]
+    pub fn read_opt() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_0101, 0xFF]));
+        assert_eq!(reader.read_bits_opt(4).unwrap(), Some(0b1010));
+        assert_eq!(reader.peek_bits(12).unwrap(), 0b0101_1111_1111);
+        assert_eq!(reader.read_bits_opt(8).unwrap(), Some(0b0101_1111));
+        assert_eq!(reader.read_bit_opt().unwrap(), Some(true));
+        assert!(reader.read_bits_opt(4).is_err());
+        assert_eq!(reader.read_bits_opt(4).unwrap(), None);
+        assert_eq!(reader.read_bit_opt().unwrap(), None);
+    }
+
+    #[test]
+    pub fn read_bits_partial() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_0101, 0b1100_0011]));
+        assert_eq!(
+            reader.read_bits_partial(12).unwrap(),
+            (0b1010_0101_1100, 12)
+        );
+        assert_eq!(reader.read_bits_partial(12).unwrap(), (0b0011, 4));
+        assert_eq!(reader.read_bits_partial(12).unwrap(), (0, 0));
+        assert!(reader.read_bits_partial(129).is_err());
+
+        let mut reader = Reader::with_bit_order(Cursor::new(vec![0b1010_0101]), BitOrder::LsbFirst);
+        reader.read_bits(2).unwrap();
+        assert_eq!(reader.read_bits_partial(70).unwrap(), (0b10_1001, 6));
+    }
+
+    #[test]
+    pub fn bits_and_chunks() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_0000, 0b0000_0011]));
+        let ones: Vec<usize> = reader
+            .bits()
+            .enumerate()
+            .filter(|(_, bit)| *bit.as_ref().unwrap())
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(ones, [0, 2, 14, 15]);
+        assert_eq!(reader.bits().count(), 0);
+
+        let mut reader = Reader::new(Cursor::new(vec![0x12, 0x34, 0x5]));
+        let mut chunks = reader.chunks(8);
+        assert_eq!(chunks.next().unwrap().unwrap(), 0x12);
+        assert_eq!(chunks.next().unwrap().unwrap(), 0x34);
+        assert_eq!(chunks.next().unwrap().unwrap(), 0x5);
+        assert!(chunks.next().is_none());
+
+        let mut reader = Reader::new(Cursor::new(vec![0xAB]));
+        let chunks: Vec<_> = reader.chunks(3).collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].as_ref().unwrap(), &0b010);
+        assert_eq!(
+            chunks[2].as_ref().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+
+        // Widths that can't be read end the iterator after one error
+        let chunks: Vec<_> = reader.chunks(0).collect();
+        assert!(matches!(chunks[..], [Err(Error::InvalidInput { .. })]));
+        let chunks: Vec<_> = reader.chunks(129).collect();
+        assert!(matches!(chunks[..], [Err(Error::ValueTooWide { .. })]));
+    }
+
+    #[test]
+    pub fn read_bools() {
+        let mut reader = Reader::new(Cursor::new(vec![0b1010_0000; 10]));
+        let bools = reader.read_bools(75).unwrap();
+        assert_eq!(bools.len(), 75);
+        assert!(bools[0] && !bools[1] && bools[2] && !bools[73] && bools[74]);
+        assert!(reader.read_bools(6).is_err());
+
+        let mut reader = Reader::with_bit_order(Cursor::new(vec![0b0000_0101]), BitOrder::LsbFirst);
+        assert_eq!(reader.read_bools(3).unwrap(), [true, false, true]);
+    }
+
+    #[test]
+    pub fn optional() {
+        let mut writer = Writer::new(Vec::new());
+        writer
+            .write_optional(Some(0x2A), |writer, value| writer.write_bits(value, 6))
+            .unwrap();
+        writer
+            .write_optional(None::<u128>, |writer, value| writer.write_bits(value, 6))
+            .unwrap();
+        // A helper that speaks io::Error
+        writer
+            .write_optional(Some(3u8), |writer, value| -> io::Result<()> {
+                Ok(writer.write_bits(value as u128, 2)?)
+            })
+            .unwrap();
+        assert_eq!(writer.bits_written(), 11);
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes, [0b1101_0100, 0b1110_0000]);
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        assert_eq!(
+            reader.read_optional(|reader| reader.read_bits(6)).unwrap(),
+            Some(0x2A)
+        );
+        assert_eq!(
+            reader.read_optional(|reader| reader.read_bits(6)).unwrap(),
+            None
+        );
+        let value: io::Result<Option<u128>> =
+            reader.read_optional(|reader| Ok(reader.read_bits(2)?));
+        assert_eq!(value.unwrap(), Some(3));
+
+        // Present but cut short
+        let mut reader = Reader::new(Cursor::new(vec![0b1000_0000]));
+        assert!(reader.read_optional(|reader| reader.read_bits(8)).is_err());
+    }
 }
@@ -0,0 +1,579 @@
+// Reed-Solomon codes over GF(2^8): RS(n, k) blocks of k data bytes followed by n - k parity bytes,
+// correcting up to (n - k) / 2 bytes in error anywhere in the block. The field polynomial and the
+// first root of the generator are configurable (0x11D and 0 by default), which covers most radio
+// and storage formats. n below 255 is a shortened code, as if the block had leading zero bytes.
+// RsWriter encodes whatever goes through it a block at a time and Reader::reed_solomon decodes.
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::byte_collector::ByteCollector;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReedSolomon {
+    n: usize,
+    k: usize,
+    first_root: usize,
+    // Powers of the primitive element (twice over, so products can index without reducing) and
+    // their logs
+    exp: Vec<u8>,
+    log: Vec<u8>,
+    // Highest power first, without the leading 1
+    generator: Vec<u8>,
+}
+
+impl ReedSolomon {
+    pub fn new(n: usize, k: usize) -> Result<ReedSolomon, Error> {
+        ReedSolomon::with_field(n, k, 0x11D, 0)
+    }
+
+    // field_poly is the 9 bit primitive polynomial, and the generator's roots are the powers of 2
+    // from first_root on
+    pub fn with_field(
+        n: usize,
+        k: usize,
+        field_poly: u16,
+        first_root: u8,
+    ) -> Result<ReedSolomon, Error> {
+        if k == 0 || k >= n || n > 255 {
+            return Err(Error::invalid_input(
+                0,
+                "Reed-Solomon needs 0 < k < n <= 255",
+            ));
+        }
+        if field_poly >> 8 != 1 {
+            return Err(Error::invalid_input(
+                0,
+                "Reed-Solomon field polynomial must be 9 bits",
+            ));
+        }
+        let mut exp = vec![0u8; 512];
+        let mut log = vec![0u8; 256];
+        let mut value = 1u16;
+        for power in 0..255 {
+            if power > 0 && (value == 1 || value == 0) {
+                return Err(Error::invalid_input(
+                    0,
+                    "Reed-Solomon field polynomial isn't primitive",
+                ));
+            }
+            exp[power] = value as u8;
+            exp[power + 255] = value as u8;
+            log[value as usize] = power as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {
+                value ^= field_poly;
+            }
+        }
+        let mut code = ReedSolomon {
+            n,
+            k,
+            first_root: first_root as usize,
+            exp,
+            log,
+            generator: Vec::new(),
+        };
+        // The product of (x - 2^i) over the n - k roots
+        let mut generator = vec![1u8];
+        for root in 0..n - k {
+            let root = code.exp[(code.first_root + root) % 255];
+            let mut next = vec![0u8; generator.len() + 1];
+            for (index, &coefficient) in generator.iter().enumerate() {
+                next[index] ^= coefficient;
+                next[index + 1] ^= code.mul(coefficient, root);
+            }
+            generator = next;
+        }
+        code.generator = generator.split_off(1);
+        Ok(code)
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.n
+    }
+
+    pub fn data_len(&self) -> usize {
+        self.k
+    }
+
+    // Most bytes in error a block can have and still be corrected
+    pub fn max_errors(&self) -> usize {
+        (self.n - self.k) / 2
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+    }
+
+    fn pow(&self, power: usize) -> u8 {
+        self.exp[power % 255]
+    }
+
+    // Lowest power first
+    fn eval_low_first(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter()
+            .rev()
+            .fold(0, |sum, &coefficient| self.mul(sum, x) ^ coefficient)
+    }
+
+    // The block for data, which has to be k bytes: the data then the parity
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if data.len() != self.k {
+            return Err(Error::invalid_input(
+                0,
+                "Reed-Solomon data must be exactly k bytes",
+            ));
+        }
+        // The remainder of data times x^(n - k) divided by the generator
+        let mut parity = vec![0u8; self.n - self.k];
+        for &byte in data {
+            let feedback = byte ^ parity[0];
+            parity.rotate_left(1);
+            let last = parity.len() - 1;
+            parity[last] = 0;
+            for (slot, &coefficient) in parity.iter_mut().zip(&self.generator) {
+                *slot ^= self.mul(feedback, coefficient);
+            }
+        }
+        let mut block = data.to_vec();
+        block.extend_from_slice(&parity);
+        Ok(block)
+    }
+
+    // Corrects block (n bytes) in place and gives back the indexes of the bytes it fixed. More
+    // errors than it can fix is an InvalidData error, with block left as it was.
+    pub fn decode(&self, block: &mut [u8]) -> Result<Vec<usize>, Error> {
+        if block.len() != self.n {
+            return Err(Error::invalid_input(
+                0,
+                "Reed-Solomon blocks must be exactly n bytes",
+            ));
+        }
+        let parity = self.n - self.k;
+        let syndromes: Vec<u8> = (0..parity)
+            .map(|index| {
+                let x = self.pow(self.first_root + index);
+                block.iter().fold(0, |sum, &byte| self.mul(sum, x) ^ byte)
+            })
+            .collect();
+        if syndromes.iter().all(|&syndrome| syndrome == 0) {
+            return Ok(Vec::new());
+        }
+        let uncorrectable =
+            || Error::invalid_data(0, "Reed-Solomon block has more errors than it can correct");
+
+        // Berlekamp-Massey for the error locator, lowest power first
+        let mut locator = vec![1u8];
+        let mut previous = vec![1u8];
+        let mut errors = 0;
+        let mut shift = 1;
+        let mut last_discrepancy = 1u8;
+        for step in 0..parity {
+            let mut discrepancy = syndromes[step];
+            for (index, &coefficient) in locator.iter().enumerate().skip(1).take(errors) {
+                discrepancy ^= self.mul(coefficient, syndromes[step - index]);
+            }
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+            let scale = self.div(discrepancy, last_discrepancy);
+            let mut next = locator.clone();
+            next.resize(next.len().max(previous.len() + shift), 0);
+            for (index, &coefficient) in previous.iter().enumerate() {
+                next[index + shift] ^= self.mul(scale, coefficient);
+            }
+            if 2 * errors <= step {
+                previous = locator;
+                errors = step + 1 - errors;
+                last_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                shift += 1;
+            }
+            locator = next;
+        }
+        locator.truncate(errors + 1);
+        if errors > self.max_errors() {
+            return Err(uncorrectable());
+        }
+
+        // Chien search: the byte at index has error value X = 2^(n - 1 - index), a root of the
+        // locator at X^-1
+        let positions: Vec<usize> = (0..self.n)
+            .filter(|&index| {
+                let inverse = self.pow(255 - (self.n - 1 - index) % 255);
+                self.eval_low_first(&locator, inverse) == 0
+            })
+            .collect();
+        if positions.len() != errors {
+            return Err(uncorrectable());
+        }
+
+        // Forney for the error values, from the evaluator S(x) * locator(x) mod x^(n - k) and the
+        // locator's formal derivative
+        let mut evaluator = vec![0u8; parity];
+        for (index, &syndrome) in syndromes.iter().enumerate() {
+            for (offset, &coefficient) in locator.iter().enumerate() {
+                if index + offset < parity {
+                    evaluator[index + offset] ^= self.mul(syndrome, coefficient);
+                }
+            }
+        }
+        let derivative: Vec<u8> = locator
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, &coefficient)| if index % 2 == 1 { coefficient } else { 0 })
+            .collect();
+        let mut corrected = block.to_vec();
+        for &index in &positions {
+            let power = self.n - 1 - index;
+            let inverse = self.pow(255 - power % 255);
+            let denominator = self.eval_low_first(&derivative, inverse);
+            if denominator == 0 {
+                return Err(uncorrectable());
+            }
+            let numerator = self.eval_low_first(&evaluator, inverse);
+            // X^(1 - first_root), as a power of 2 mod 255
+            let scale = self.pow((power * (256 - self.first_root % 255)) % 255);
+            corrected[index] ^= self.mul(scale, self.div(numerator, denominator));
+        }
+        // A pattern past what the code can see can still look correctable, so check the result
+        let still_wrong = (0..parity).any(|index| {
+            let x = self.pow(self.first_root + index);
+            corrected
+                .iter()
+                .fold(0, |sum, &byte| self.mul(sum, x) ^ byte)
+                != 0
+        });
+        if still_wrong {
+            return Err(uncorrectable());
+        }
+        block.copy_from_slice(&corrected);
+        Ok(positions)
+    }
+}
+
+pub struct RsWriter<W: BitWrite> {
+    inner: W,
+    code: ReedSolomon,
+    collector: ByteCollector,
+    block: Vec<u8>,
+}
+
+impl<W: BitWrite> RsWriter<W> {
+    pub fn new(inner: W, code: ReedSolomon) -> RsWriter<W> {
+        RsWriter {
+            collector: ByteCollector::new(inner.bit_order()),
+            inner,
+            code,
+            block: Vec::new(),
+        }
+    }
+
+    fn add_byte(&mut self, byte: u8) -> Result<(), Error> {
+        self.block.push(byte);
+        if self.block.len() == self.code.data_len() {
+            let block = self.code.encode(&self.block)?;
+            self.block.clear();
+            self.inner.write_bytes(block)?;
+        }
+        Ok(())
+    }
+
+    // Fills a started block with zeros (the last byte first, if it's only partly written) and
+    // writes it
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        if !self.collector.is_aligned() {
+            let padding = 8 - (self.collector.bits() % 8) as usize;
+            self.write_bits(0, padding)?;
+        }
+        while !self.block.is_empty() {
+            self.add_byte(0)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Anything since the last full block is dropped unless end_block is called first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for RsWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        self.collector
+            .push(bits, number_of_bits, |byte| bytes.push(byte))?;
+        for byte in bytes {
+            self.add_byte(byte)?;
+        }
+        Ok(())
+    }
+
+    // Blocks written so far, so data in a started block isn't counted yet
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        if !self.collector.is_aligned() {
+            for byte in bytes {
+                self.write_bits(byte as u128, 8)?;
+            }
+            return Ok(());
+        }
+        self.collector.bits += 8 * bytes.len() as u64;
+        for byte in bytes {
+            self.add_byte(byte)?;
+        }
+        Ok(())
+    }
+
+    // Blocks are whole bytes, so the padding goes in the data, as end_block pads a partly written
+    // byte. It isn't in bits_written until its block is.
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        let padding = (8 - self.collector.bits() % 8) as usize % 8;
+        self.write_bits(0, padding)?;
+        Ok(padding)
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data written through an RsWriter with the same code, correcting what it can. A block
+    // with too many errors fails with InvalidData at its first bit, and reading carries on with
+    // the next block.
+    pub fn reed_solomon(&mut self, code: ReedSolomon) -> RsReader<'_, R> {
+        RsReader {
+            reader: self,
+            used: 8 * code.data_len(),
+            code,
+            block: Vec::new(),
+            corrected: Vec::new(),
+        }
+    }
+}
+
+pub struct RsReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    code: ReedSolomon,
+    // The current block's data, and how many of its bits have been handed out. All of them once
+    // the block's done with, so the next read starts a new one.
+    block: Vec<u8>,
+    used: usize,
+    corrected: Vec<u64>,
+}
+
+impl<'a, R: Read> RsReader<'a, R> {
+    // Positions of the first bits of the bytes fixed so far
+    pub fn corrected(&self) -> &[u64] {
+        &self.corrected
+    }
+
+    fn next_block(&mut self) -> Result<(), Error> {
+        let position = self.reader.bits_read();
+        let mut block = self.reader.read_bytes(self.code.block_len())?;
+        self.used = 8 * self.code.data_len();
+        let fixed = self
+            .code
+            .decode(&mut block)
+            .map_err(|error| Error::invalid_data(position, &error.to_string()))?;
+        self.corrected
+            .extend(fixed.iter().map(|&index| position + 8 * index as u64));
+        block.truncate(self.code.data_len());
+        self.block = block;
+        self.used = 0;
+        Ok(())
+    }
+
+    // Drops the rest of a block that was only partly read, like the zeros end_block filled it with
+    pub fn end_block(&mut self) {
+        self.used = 8 * self.code.data_len();
+    }
+}
+
+impl<'a, R: Read> BitRead for RsReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let bit_order = self.reader.bit_order();
+        let mut bits = 0;
+        for index in 0..number_of_bits {
+            if self.used == 8 * self.code.data_len() {
+                self.next_block()?;
+            }
+            let byte = self.block[self.used / 8];
+            let bit = match bit_order {
+                BitOrder::MsbFirst => byte >> (7 - self.used % 8) & 1,
+                BitOrder::LsbFirst => byte >> (self.used % 8) & 1,
+            };
+            bits |= (bit as u128)
+                << match bit_order {
+                    BitOrder::MsbFirst => number_of_bits - 1 - index,
+                    BitOrder::LsbFirst => index,
+                };
+            self.used += 1;
+        }
+        Ok(bits)
+    }
+
+    // Parity included
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Skips the padding in the data up to the next whole byte, like RsWriter's
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        let position = self.bits_read();
+        let padding = (8 - self.used % 8) % 8;
+        if self.read_bits(padding)? != 0 && require_zeros {
+            return Err(Error::invalid_data(
+                position,
+                "Padding bits before the byte boundary aren't zero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn corrects_up_to_its_limit() {
+        let codes = [
+            ReedSolomon::new(255, 223).unwrap(),
+            ReedSolomon::new(15, 9).unwrap(),
+            // Another field and first root
+            ReedSolomon::with_field(60, 40, 0x187, 112).unwrap(),
+        ];
+        for code in codes {
+            let data: Vec<u8> = (0..code.data_len())
+                .map(|index| (index * 37 + 5) as u8)
+                .collect();
+            let block = code.encode(&data).unwrap();
+            assert_eq!(&block[..code.data_len()], &data[..]);
+            let mut clean = block.clone();
+            assert_eq!(code.decode(&mut clean).unwrap(), []);
+
+            // Errors spread over the block, parity included
+            let errors = code.max_errors();
+            let positions: Vec<usize> = (0..errors)
+                .map(|error| error * 7 % code.block_len())
+                .collect();
+            let mut corrupted = block.clone();
+            for (count, &index) in positions.iter().enumerate() {
+                corrupted[index] ^= count as u8 + 1;
+            }
+            let mut fixed = code.decode(&mut corrupted).unwrap();
+            fixed.sort_unstable();
+            let mut expected = positions.clone();
+            expected.sort_unstable();
+            expected.dedup();
+            assert_eq!(fixed, expected);
+            assert_eq!(corrupted, block);
+
+            // One more than it can take is caught rather than miscorrected
+            let mut corrupted = block.clone();
+            for byte in &mut corrupted[..=errors] {
+                *byte ^= 0x5A;
+            }
+            let before = corrupted.clone();
+            assert!(code.decode(&mut corrupted).is_err());
+            assert_eq!(corrupted, before);
+        }
+        // The example from Wikiversity's Reed-Solomon for coders
+        let block = ReedSolomon::new(21, 11)
+            .unwrap()
+            .encode(b"hello world")
+            .unwrap();
+        assert_eq!(
+            block[11..],
+            [0xED, 0x25, 0x54, 0xC4, 0xFD, 0xFD, 0x89, 0xF3, 0xA8, 0xAA]
+        );
+        assert!(ReedSolomon::new(10, 10).is_err());
+        assert!(ReedSolomon::with_field(255, 223, 0x100, 0).is_err());
+    }
+
+    #[test]
+    pub fn protects_a_stream() {
+        let code = ReedSolomon::new(20, 12).unwrap();
+        let mut output = RsWriter::new(Writer::new(Vec::new()), code.clone());
+        output.write_bits(0b101, 3).unwrap();
+        output.write_bytes(b"reed-solomon".to_vec()).unwrap();
+        output.write_bits(0x1F, 5).unwrap();
+        output.write_bytes(b"archive".to_vec()).unwrap();
+        output.end_block().unwrap();
+        // 20 bytes of data in 2 blocks
+        assert_eq!(output.bits_written(), 2 * 20 * 8);
+        let mut bytes = output.into_inner().into_inner().unwrap();
+        // Four bad bytes in the first block and too many in the second
+        for index in [0, 5, 11, 19, 20, 21, 22, 23, 24] {
+            bytes[index] ^= 0xFF;
+        }
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.reed_solomon(code.clone());
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        assert_eq!(input.read_bytes(11).unwrap(), b"reed-solomo");
+        assert_eq!(input.corrected(), [0, 40, 88, 152]);
+        let error = input.read_bits(13).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 160,
+                ..
+            }
+        ));
+        assert_eq!(input.bits_read(), 320);
+    }
+
+    #[test]
+    pub fn pads_in_the_middle_of_a_block() {
+        let code = ReedSolomon::new(20, 12).unwrap();
+        let writer = Writer::with_bit_order(Vec::new(), BitOrder::LsbFirst);
+        let mut output = RsWriter::new(writer, code.clone());
+        output.write_bits(0b101, 3).unwrap();
+        assert_eq!(output.pad_to_byte().unwrap(), 5);
+        assert_eq!(output.pad_to_byte().unwrap(), 0);
+        output.write_bytes(b"padded".to_vec()).unwrap();
+        output.end_block().unwrap();
+        assert_eq!(output.bits_written(), 20 * 8);
+        let bytes = output.into_inner().into_inner().unwrap();
+        assert_eq!(bytes[..7], *b"\x05padded");
+
+        let mut reader = Reader::with_bit_order(Cursor::new(bytes), BitOrder::LsbFirst);
+        let mut input = reader.reed_solomon(code);
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        input.align_to_byte(true).unwrap();
+        assert_eq!(input.read_bytes(6).unwrap(), b"padded");
+        assert!(input.corrected().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+// The simplest code there is: every bit sent copies times over, and read back by majority vote.
+// With an odd number of copies up to half of them (rounded down) can flip; with an even number a
+// tie can't be settled, and is an error. Slow, but it survives channels the others don't and is a
+// baseline to hold them up against.
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+fn check_copies(copies: usize) -> Result<(), Error> {
+    if copies == 0 || copies > 128 {
+        return Err(Error::invalid_input(
+            0,
+            "Repetition needs between 1 and 128 copies of each bit",
+        ));
+    }
+    Ok(())
+}
+
+// Where the bit that's index bits into a value of number_of_bits is, in stream order
+fn shift(number_of_bits: usize, index: usize, bit_order: BitOrder) -> usize {
+    match bit_order {
+        BitOrder::MsbFirst => number_of_bits - 1 - index,
+        BitOrder::LsbFirst => index,
+    }
+}
+
+pub struct RepetitionWriter<W: BitWrite> {
+    inner: W,
+    copies: usize,
+}
+
+impl<W: BitWrite> RepetitionWriter<W> {
+    pub fn new(inner: W, copies: usize) -> Result<RepetitionWriter<W>, Error> {
+        check_copies(copies)?;
+        Ok(RepetitionWriter { inner, copies })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for RepetitionWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let all = u128::MAX >> (128 - self.copies);
+        let bit_order = self.inner.bit_order();
+        for index in 0..number_of_bits {
+            let bit = bits >> shift(number_of_bits, index, bit_order) & 1;
+            self.inner.write_bits(all * bit, self.copies)?;
+        }
+        Ok(())
+    }
+
+    // Copies included
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.inner.pad_to_byte()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data written through a RepetitionWriter with the same number of copies. A tie fails
+    // with InvalidData at its first copy once the rest of the value's been read.
+    pub fn repetition(&mut self, copies: usize) -> Result<RepetitionReader<'_, R>, Error> {
+        check_copies(copies)?;
+        Ok(RepetitionReader {
+            reader: self,
+            copies,
+            outvoted: Vec::new(),
+        })
+    }
+}
+
+pub struct RepetitionReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    copies: usize,
+    outvoted: Vec<u64>,
+}
+
+impl<'a, R: Read> RepetitionReader<'a, R> {
+    // Positions of the first copy of each bit whose copies didn't all agree
+    pub fn outvoted(&self) -> &[u64] {
+        &self.outvoted
+    }
+}
+
+impl<'a, R: Read> BitRead for RepetitionReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let bit_order = self.reader.bit_order();
+        let mut bits = 0;
+        let mut tie = None;
+        for index in 0..number_of_bits {
+            let position = self.reader.bits_read();
+            let ones = self.reader.read_bits(self.copies)?.count_ones() as usize;
+            if ones != 0 && ones != self.copies {
+                self.outvoted.push(position);
+            }
+            if 2 * ones == self.copies {
+                tie = tie.or(Some(position));
+            } else if 2 * ones > self.copies {
+                bits |= 1 << shift(number_of_bits, index, bit_order);
+            }
+        }
+        match tie {
+            Some(position) => Err(Error::invalid_data(
+                position,
+                "Repeated bit's copies are split evenly",
+            )),
+            None => Ok(bits),
+        }
+    }
+
+    // Copies included
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Skips the padding to the next byte boundary, around the copies like RepetitionWriter's
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        self.reader.align_to_byte(require_zeros)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn outvotes_flipped_copies() {
+        let mut output = RepetitionWriter::new(Writer::new(Vec::new()), 5).unwrap();
+        output.write_bits(0b101, 3).unwrap();
+        output.write_bytes(b"noisy".to_vec()).unwrap();
+        assert_eq!(output.bits_written(), 5 * 43);
+        output.pad_to_byte().unwrap();
+        let mut bytes = output.into_inner().into_inner().unwrap();
+        // Two of the first bit's five copies and one of another's
+        bytes[0] ^= 0b0001_1000;
+        bytes[7] ^= 0b0001_0000;
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut input = reader.repetition(5).unwrap();
+        assert_eq!(input.read_bits(3).unwrap(), 0b101);
+        assert_eq!(input.read_bytes(5).unwrap(), b"noisy");
+        assert_eq!(input.outvoted().len(), 2);
+        assert_eq!(input.outvoted()[0], 0);
+        input.align_to_byte(true).unwrap();
+    }
+
+    #[test]
+    pub fn even_copies_detect_ties() {
+        let mut output = RepetitionWriter::new(Writer::new(Vec::new()), 2).unwrap();
+        output.write_bits(0b1011, 4).unwrap();
+        let mut bytes = output.into_inner().into_inner().unwrap();
+        bytes[0] ^= 0b0001_0000;
+
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let error = reader.repetition(2).unwrap().read_bits(4).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidData {
+                bit_position: 2,
+                ..
+            }
+        ));
+        assert_eq!(reader.bits_read(), 8);
+        assert!(reader.repetition(0).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{Reader, Writer, PREALLOCATE_LIMIT};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Byte runs shorter than this are cheaper to keep as literals
+const MIN_BYTE_RUN: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rle {
+    count_width: usize,
+}
+
+impl Rle {
+    pub fn new(count_width: usize) -> Rle {
+        Rle { count_width }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.count_width == 0 || self.count_width > 32 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "RLE count width must be between 1 and 32 bits",
+            ));
+        }
+        Ok(())
+    }
+
+    // Worked out in u64, since a 32 bit count is all of a 32 bit usize
+    fn max_count(&self) -> usize {
+        usize::try_from((1u64 << self.count_width) - 1).unwrap_or(usize::MAX)
+    }
+
+    // Writes the first bit, then the lengths of alternating runs. A run too long for the count
+    // is split by a zero length run of the other value, like fax makeup codes.
+    pub fn encode_bits<W: Write>(
+        &self,
+        bits: &[bool],
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        let first = match bits.first() {
+            Some(&first) => first,
+            None => return Ok(()),
+        };
+        writer.write_bit(first)?;
+
+        let mut position = 0;
+        while position < bits.len() {
+            let value = bits[position];
+            let mut length = bits[position..]
+                .iter()
+                .take_while(|&&bit| bit == value)
+                .count();
+            position += length;
+            while length > self.max_count() {
+                writer.write_bits(self.max_count() as u128, self.count_width)?;
+                writer.write_bits(0, self.count_width)?;
+                length -= self.max_count();
+            }
+            writer.write_bits(length as u128, self.count_width)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode_bits<R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        number_of_bits: usize,
+    ) -> Result<Vec<bool>, Error> {
+        self.check()?;
+        let mut bits = Vec::with_capacity(number_of_bits.min(PREALLOCATE_LIMIT));
+        if number_of_bits == 0 {
+            return Ok(bits);
+        }
+        let mut value = reader.read_bit()?;
+        while bits.len() < number_of_bits {
+            let length = reader.read_usize(self.count_width)?;
+            if length > number_of_bits - bits.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "RLE run goes past the end of the data",
+                ));
+            }
+            bits.extend(std::iter::repeat_n(value, length));
+            value = !value;
+        }
+        Ok(bits)
+    }
+
+    // Packets start with a flag bit (1 for a run, 0 for literals) and a count minus one. Runs are
+    // followed by the repeated byte and literals by all of their bytes.
+    pub fn encode_bytes<W: Write>(&self, data: &[u8], writer: &mut Writer<W>) -> Result<(), Error> {
+        self.check()?;
+        let max_packet = self.max_count().saturating_add(1);
+        let mut literals: Vec<u8> = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let byte = data[position];
+            let length = data[position..]
+                .iter()
+                .take(max_packet)
+                .take_while(|&&b| b == byte)
+                .count();
+            if length >= MIN_BYTE_RUN.min(max_packet) && length > 1 {
+                self.write_literals(&mut literals, writer)?;
+                writer.write_bit(true)?;
+                writer.write_bits((length - 1) as u128, self.count_width)?;
+                writer.write_byte(byte)?;
+                position += length;
+            } else {
+                literals.push(byte);
+                if literals.len() == max_packet {
+                    self.write_literals(&mut literals, writer)?;
+                }
+                position += 1;
+            }
+        }
+        self.write_literals(&mut literals, writer)
+    }
+
+    fn write_literals<W: Write>(
+        &self,
+        literals: &mut Vec<u8>,
+        writer: &mut Writer<W>,
+    ) -> Result<(), Error> {
+        if literals.is_empty() {
+            return Ok(());
+        }
+        writer.write_bit(false)?;
+        writer.write_bits((literals.len() - 1) as u128, self.count_width)?;
+        for &byte in literals.iter() {
+            writer.write_byte(byte)?;
+        }
+        literals.clear();
+        Ok(())
+    }
+
+    pub fn decode_bytes<R: Read>(
+        &self,
+        reader: &mut Reader<R>,
+        number_of_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.check()?;
+        let mut data = Vec::with_capacity(number_of_bytes.min(PREALLOCATE_LIMIT));
+        while data.len() < number_of_bytes {
+            let is_run = reader.read_bit()?;
+            let count = reader.read_usize(self.count_width)?.saturating_add(1);
+            if count > number_of_bytes - data.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "RLE packet goes past the end of the data",
+                ));
+            }
+            if is_run {
+                let byte = reader.read_byte()?;
+                data.extend(std::iter::repeat_n(byte, count));
+            } else {
+                for _ in 0..count {
+                    data.push(reader.read_byte()?);
+                }
+            }
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encoded<F: FnOnce(&mut Writer<Cursor<Vec<u8>>>)>(f: F) -> Vec<u8> {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        f(&mut writer);
+        writer.flush().unwrap();
+        writer.get_ref().get_ref().clone()
+    }
+
+    #[test]
+    pub fn bit_runs() {
+        // 1111_1110_0000_0000_0000_0001
+        let mut bits = vec![true; 7];
+        bits.extend(vec![false; 16]);
+        bits.push(true);
+        let rle = Rle::new(3);
+        let data = encoded(|writer| rle.encode_bits(&bits, writer).unwrap());
+
+        // 1, then 111, 111 000 111 000 010 (16 as 7 + 0 + 7 + 0 + 2), 001
+        assert_eq!(data, [0b1111_1110, 0b0011_1000, 0b0100_0100]);
+
+        let mut reader = Reader::new(Cursor::new(data));
+        assert_eq!(rle.decode_bits(&mut reader, bits.len()).unwrap(), bits);
+    }
+
+    #[test]
+    pub fn long_bit_runs() {
+        let mut bits = vec![false; 1000];
+        bits.extend(vec![true; 255]);
+        bits.extend(vec![false; 3]);
+        let rle = Rle::new(8);
+        let data = encoded(|writer| rle.encode_bits(&bits, writer).unwrap());
+
+        let mut reader = Reader::new(Cursor::new(data));
+        assert_eq!(rle.decode_bits(&mut reader, bits.len()).unwrap(), bits);
+    }
+
+    #[test]
+    pub fn byte_runs_and_literals() {
+        let bytes = vec![1, 2, 9, 9, 9, 9, 9, 3, 3];
+        let rle = Rle::new(4);
+        let data = encoded(|writer| rle.encode_bytes(&bytes, writer).unwrap());
+
+        // 0 0001 [1] [2], 1 0100 [9], 0 0001 [3] [3]
+        assert_eq!(data.len(), 7);
+
+        let mut reader = Reader::new(Cursor::new(data));
+        assert_eq!(rle.decode_bytes(&mut reader, bytes.len()).unwrap(), bytes);
+    }
+
+    #[test]
+    pub fn long_byte_runs() {
+        let mut bytes = vec![0; 100];
+        bytes.extend((0..40).map(|i| i as u8));
+        bytes.extend(vec![7; 3]);
+        let rle = Rle::new(4);
+        let data = encoded(|writer| rle.encode_bytes(&bytes, writer).unwrap());
+
+        let mut reader = Reader::new(Cursor::new(data));
+        assert_eq!(rle.decode_bytes(&mut reader, bytes.len()).unwrap(), bytes);
+    }
+
+    #[test]
+    pub fn rejects_overlong_runs() {
+        let rle = Rle::new(4);
+        let data = encoded(|writer| rle.encode_bytes(&[5; 10], writer).unwrap());
+        let mut reader = Reader::new(Cursor::new(data));
+        assert!(rle.decode_bytes(&mut reader, 4).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+// Scramblers, which whiten a bit stream with a linear feedback shift register so long runs of the
+// same bit can't starve a receiver's clock recovery or bunch up the spectrum. The polynomial has
+// bit k set for each x^k term, so DVB's 1 + x^14 + x^15 is 0xC001 and 802.11's 1 + x^4 + x^7 is
+// 0x91, and bit k - 1 of the seed is the register stage that x^k taps.
+//   Additive           XORs the data with the register's own sequence, which the descrambler has
+//                      to start from the same seed at the same bit (DVB, 802.11, CCSDS)
+//   SelfSynchronizing  feeds the scrambled bits back into the register, so the descrambler falls
+//                      into step by itself after degree bits (10GBASE-R's 1 + x^39 + x^58, SDI)
+use crate::bit_read::BitRead;
+use crate::bit_write::BitWrite;
+use crate::{BitOrder, Error, Reader};
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scrambling {
+    Additive,
+    SelfSynchronizing,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scrambler {
+    scrambling: Scrambling,
+    // The polynomial without its 1, shifted so x^k is bit k - 1 like the stage it taps
+    taps: u64,
+    mask: u64,
+    seed: u64,
+    state: u64,
+}
+
+impl Scrambler {
+    // Polynomials of degree 1 to 63, which need their 1 term. An additive scrambler's seed can't
+    // be zero, or its sequence would be too.
+    pub fn new(scrambling: Scrambling, polynomial: u64, seed: u64) -> Result<Scrambler, Error> {
+        if polynomial & 1 == 0 || polynomial == 1 || polynomial >> 63 != 0 {
+            return Err(Error::invalid_input(
+                0,
+                "Scrambler polynomial must have a 1 term and a degree from 1 to 63",
+            ));
+        }
+        let degree = 63 - polynomial.leading_zeros();
+        let mask = u64::MAX >> (64 - degree);
+        if seed & !mask != 0 {
+            return Err(Error::invalid_input(
+                0,
+                "Scrambler seed is wider than its polynomial",
+            ));
+        }
+        if scrambling == Scrambling::Additive && seed == 0 {
+            return Err(Error::invalid_input(
+                0,
+                "Additive scrambler seed can't be zero",
+            ));
+        }
+        Ok(Scrambler {
+            scrambling,
+            taps: polynomial >> 1,
+            mask,
+            seed,
+            state: seed,
+        })
+    }
+
+    pub fn scrambling(&self) -> Scrambling {
+        self.scrambling
+    }
+
+    pub fn degree(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    // Back to the seed, for formats that restart the sequence every frame
+    pub fn reset(&mut self) {
+        self.state = self.seed;
+    }
+
+    fn feedback(&self) -> bool {
+        (self.state & self.taps).count_ones() % 2 == 1
+    }
+
+    fn shift_in(&mut self, bit: bool) {
+        self.state = (self.state << 1 | bit as u64) & self.mask;
+    }
+
+    // The next bit of an additive scrambler's sequence on its own
+    pub fn next_bit(&mut self) -> bool {
+        let bit = self.feedback();
+        self.shift_in(bit);
+        bit
+    }
+
+    pub fn scramble_bit(&mut self, bit: bool) -> bool {
+        let feedback = self.feedback();
+        let scrambled = bit ^ feedback;
+        self.shift_in(match self.scrambling {
+            Scrambling::Additive => feedback,
+            Scrambling::SelfSynchronizing => scrambled,
+        });
+        scrambled
+    }
+
+    pub fn descramble_bit(&mut self, bit: bool) -> bool {
+        let feedback = self.feedback();
+        self.shift_in(match self.scrambling {
+            Scrambling::Additive => feedback,
+            Scrambling::SelfSynchronizing => bit,
+        });
+        bit ^ feedback
+    }
+
+    // number_of_bits (up to 128) of a value at a time, taking its bits in stream order
+    fn apply(
+        &mut self,
+        bits: u128,
+        number_of_bits: usize,
+        bit_order: BitOrder,
+        scramble: bool,
+    ) -> u128 {
+        let mut output = 0;
+        for index in 0..number_of_bits {
+            let shift = match bit_order {
+                BitOrder::MsbFirst => number_of_bits - 1 - index,
+                BitOrder::LsbFirst => index,
+            };
+            let bit = bits >> shift & 1 == 1;
+            let bit = match scramble {
+                true => self.scramble_bit(bit),
+                false => self.descramble_bit(bit),
+            };
+            output |= (bit as u128) << shift;
+        }
+        output
+    }
+
+    // In place, for a frame already in memory
+    pub fn scramble_bytes(&mut self, bytes: &mut [u8], bit_order: BitOrder) {
+        for byte in bytes {
+            *byte = self.apply(*byte as u128, 8, bit_order, true) as u8;
+        }
+    }
+
+    pub fn descramble_bytes(&mut self, bytes: &mut [u8], bit_order: BitOrder) {
+        for byte in bytes {
+            *byte = self.apply(*byte as u128, 8, bit_order, false) as u8;
+        }
+    }
+}
+
+pub struct ScramblerWriter<W: BitWrite> {
+    inner: W,
+    scrambler: Scrambler,
+}
+
+impl<W: BitWrite> ScramblerWriter<W> {
+    pub fn new(inner: W, scrambler: Scrambler) -> ScramblerWriter<W> {
+        ScramblerWriter { inner, scrambler }
+    }
+
+    pub fn scrambler(&self) -> &Scrambler {
+        &self.scrambler
+    }
+
+    pub fn scrambler_mut(&mut self) -> &mut Scrambler {
+        &mut self.scrambler
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    // Writes through here go out unscrambled, for sync words and headers between scrambled frames
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: BitWrite> BitWrite for ScramblerWriter<W> {
+    fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written(), number_of_bits, 128));
+        }
+        let bit_order = self.inner.bit_order();
+        let scrambled = self.scrambler.apply(bits, number_of_bits, bit_order, true);
+        self.inner.write_bits(scrambled, number_of_bits)
+    }
+
+    fn bits_written(&self) -> u64 {
+        self.inner.bits_written()
+    }
+
+    fn bit_order(&self) -> BitOrder {
+        self.inner.bit_order()
+    }
+
+    // The padding isn't scrambled
+    fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.inner.pad_to_byte()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    // Reads data written through a ScramblerWriter, from the next bit on. The scrambler is only
+    // borrowed, so it carries on from where it was across unscrambled bits read from the Reader in
+    // between, like those written through a ScramblerWriter's get_mut.
+    pub fn descramble<'a>(&'a mut self, scrambler: &'a mut Scrambler) -> DescrambleReader<'a, R> {
+        DescrambleReader {
+            reader: self,
+            scrambler,
+        }
+    }
+}
+
+pub struct DescrambleReader<'a, R: Read> {
+    reader: &'a mut Reader<R>,
+    scrambler: &'a mut Scrambler,
+}
+
+impl<'a, R: Read> BitRead for DescrambleReader<'a, R> {
+    fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_read(), number_of_bits, 128));
+        }
+        let bits = self.reader.read_bits(number_of_bits)?;
+        let bit_order = self.reader.bit_order();
+        Ok(self.scrambler.apply(bits, number_of_bits, bit_order, false))
+    }
+
+    fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    // Skips the padding to the next byte boundary, which a ScramblerWriter leaves unscrambled
+    fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), Error> {
+        self.reader.align_to_byte(require_zeros)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn matches_the_802_11_sequence() {
+        // The start of the sequence the standard lists for its all ones seed
+        let mut scrambler = Scrambler::new(Scrambling::Additive, 0x91, 0x7F).unwrap();
+        let mut bytes = [0; 4];
+        scrambler.scramble_bytes(&mut bytes, BitOrder::MsbFirst);
+        assert_eq!(bytes, [0b0000_1110, 0b1111_0010, 0b1100_1001, 0b0000_0010]);
+        // It repeats every 127 bits
+        scrambler.reset();
+        let sequence: Vec<bool> = (0..254).map(|_| scrambler.next_bit()).collect();
+        assert_eq!(sequence[..127], sequence[127..]);
+        assert!(Scrambler::new(Scrambling::Additive, 0x91, 0).is_err());
+        assert!(Scrambler::new(Scrambling::Additive, 0x90, 1).is_err());
+        assert!(Scrambler::new(Scrambling::Additive, 0x91, 0x80).is_err());
+    }
+
+    #[test]
+    pub fn round_trips_through_writer_and_reader() {
+        let data = b"\x00\x00\x00\x00 a long run of zeros \xFF\xFF\xFF\xFF".to_vec();
+        for scrambling in [Scrambling::Additive, Scrambling::SelfSynchronizing] {
+            let scrambler = Scrambler::new(scrambling, 0xC001, 0x00A9).unwrap();
+            let mut output = ScramblerWriter::new(Writer::new(Vec::new()), scrambler.clone());
+            output.get_mut().write_bits(0x47, 8).unwrap();
+            output.write_bits(0b101, 3).unwrap();
+            output.write_bytes(data.clone()).unwrap();
+            output.pad_to_byte().unwrap();
+            let bytes = output.into_inner().into_inner().unwrap();
+            assert!(bytes[1..5].iter().all(|&byte| byte != 0));
+
+            let mut reader = Reader::new(Cursor::new(bytes));
+            assert_eq!(reader.read_byte().unwrap(), 0x47);
+            let mut scrambler = scrambler;
+            let mut input = reader.descramble(&mut scrambler);
+            assert_eq!(input.read_bits(3).unwrap(), 0b101);
+            assert_eq!(input.read_bytes(data.len()).unwrap(), data);
+            input.align_to_byte(true).unwrap();
+        }
+    }
+
+    #[test]
+    pub fn self_synchronizing_falls_into_step() {
+        let mut scrambler = Scrambler::new(Scrambling::SelfSynchronizing, 0x211, 0x1FF).unwrap();
+        let mut bytes = *b"serial digital interface";
+        scrambler.scramble_bytes(&mut bytes, BitOrder::LsbFirst);
+        // Started from the wrong state, only the first 9 bits come out wrong
+        let mut descrambler = Scrambler::new(Scrambling::SelfSynchronizing, 0x211, 0).unwrap();
+        descrambler.descramble_bytes(&mut bytes, BitOrder::LsbFirst);
+        assert_eq!(bytes[2..], b"serial digital interface"[2..]);
+        assert_eq!(bytes[1] & 0xFE, b'e' & 0xFE);
+    }
+}
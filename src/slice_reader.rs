@@ -0,0 +1,227 @@
+use crate::Error;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// A run of bits inside a borrowed buffer, MSB first, that doesn't have to start or end on a byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitView<'a> {
+    data: &'a [u8],
+    start: usize,
+    len: usize,
+}
+
+impl<'a> BitView<'a> {
+    pub fn new(data: &'a [u8]) -> BitView<'a> {
+        BitView {
+            data,
+            start: 0,
+            len: data.len() * 8,
+        }
+    }
+
+    // Number of bits
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+        let position = self.start + index;
+        Some(self.data[position / 8] & (0b1000_0000 >> (position % 8)) != 0)
+    }
+
+    pub fn slice(&self, start: usize, len: usize) -> Option<BitView<'a>> {
+        if start.checked_add(len)? > self.len {
+            return None;
+        }
+        Some(BitView {
+            data: self.data,
+            start: self.start + start,
+            len,
+        })
+    }
+
+    // The bytes themselves, when the view starts and ends on byte boundaries
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        if !self.start.is_multiple_of(8) || !self.len.is_multiple_of(8) {
+            return None;
+        }
+        Some(&self.data[self.start / 8..(self.start + self.len) / 8])
+    }
+
+    // Copies the bits out, with the last byte padded with zeros
+    pub fn to_vec(&self) -> Vec<u8> {
+        if let Some(bytes) = self.as_bytes() {
+            return bytes.to_vec();
+        }
+        let mut bytes = vec![0; self.len.div_ceil(8)];
+        for index in 0..self.len {
+            if self.get(index) == Some(true) {
+                bytes[index / 8] |= 0b1000_0000 >> (index % 8);
+            }
+        }
+        bytes
+    }
+
+    pub fn reader(&self) -> SliceReader<'a> {
+        SliceReader {
+            data: self.data,
+            start: self.start,
+            position: self.start,
+            end: self.start + self.len,
+        }
+    }
+}
+
+// Reads MSB first straight out of an in-memory buffer. Payloads come back as views into that
+// buffer rather than copies, so nothing gets allocated per frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    // Bit positions into data
+    start: usize,
+    position: usize,
+    end: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> SliceReader<'a> {
+        BitView::new(data).reader()
+    }
+
+    // Bits read so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.end - self.position
+    }
+
+    // Jumps to a position (as given by position) anywhere in the reader's bits. It's only
+    // arithmetic, so random access costs nothing.
+    pub fn seek_bits(&mut self, position: usize) -> Result<(), Error> {
+        if position < self.start || position > self.end {
+            return Err(Error::invalid_input(
+                self.position as u64,
+                "Tried to seek outside the buffer",
+            ));
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    fn check_remaining(&self, number_of_bits: usize) -> Result<(), Error> {
+        if number_of_bits > self.remaining() {
+            let missing = (number_of_bits - self.remaining()) as u64;
+            return Err(Error::eof(self.position as u64, missing));
+        }
+        Ok(())
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        if self.position == self.end {
+            return Err(Error::eof(self.position as u64, 1));
+        }
+        let bit = self.data[self.position / 8] & (0b1000_0000 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Ok(bit)
+    }
+
+    pub fn read_bits(&mut self, number_of_bits: usize) -> Result<u128, Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.position as u64, number_of_bits, 128));
+        }
+        self.check_remaining(number_of_bits)?;
+        let mut output: u128 = 0;
+        for _ in 0..number_of_bits {
+            output = (output << 1) | self.read_bit()? as u128;
+        }
+        Ok(output)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    // The next number_of_bits as a view, without copying them
+    pub fn read_view(&mut self, number_of_bits: usize) -> Result<BitView<'a>, Error> {
+        self.check_remaining(number_of_bits)?;
+        let view = BitView {
+            data: self.data,
+            start: self.position,
+            len: number_of_bits,
+        };
+        self.position += number_of_bits;
+        Ok(view)
+    }
+
+    // Borrows the next bytes of the buffer. The reader has to be on a byte boundary.
+    pub fn read_byte_slice(&mut self, number_of_bytes: usize) -> Result<&'a [u8], Error> {
+        if !self.position.is_multiple_of(8) {
+            return Err(Error::invalid_input(
+                self.position as u64,
+                "Byte slices can only be read on a byte boundary",
+            ));
+        }
+        let number_of_bits = number_of_bytes.saturating_mul(8);
+        let start = self.position / 8;
+        self.read_view(number_of_bits)?;
+        Ok(&self.data[start..start + number_of_bytes])
+    }
+
+    pub fn skip_to_byte(&mut self) {
+        self.position = self.position.next_multiple_of(8).min(self.end);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn borrowed_payloads() {
+        // Two frames of an 8 bit length followed by the payload
+        let capture = [3, b'a', b'b', b'c', 2, b'h', b'i'];
+        let mut reader = SliceReader::new(&capture);
+        let mut frames = Vec::new();
+        while reader.remaining() > 0 {
+            let length = reader.read_byte().unwrap() as usize;
+            frames.push(reader.read_byte_slice(length).unwrap());
+        }
+        assert_eq!(frames, [&b"abc"[..], &b"hi"[..]]);
+        // Same memory, not a copy
+        assert_eq!(frames[1].as_ptr(), capture[5..].as_ptr());
+    }
+
+    #[test]
+    pub fn unaligned_views() {
+        // 101 then 1100_1010_1 then 0111
+        let data = [0b1011_1001, 0b0101_0111];
+        let mut reader = SliceReader::new(&data);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        let view = reader.read_view(9).unwrap();
+        assert_eq!(view.len(), 9);
+        assert_eq!(view.get(0), Some(true));
+        assert_eq!(view.get(9), None);
+        assert_eq!(view.as_bytes(), None);
+        assert_eq!(view.to_vec(), [0b1100_1010, 0b1000_0000]);
+        assert_eq!(view.reader().read_bits(9).unwrap(), 0b1_1001_0101);
+        assert_eq!(view.slice(1, 8).unwrap().to_vec(), [0b1001_0101]);
+        assert!(reader.read_byte_slice(1).is_err());
+        assert_eq!(reader.read_bits(4).unwrap(), 0b0111);
+        assert!(reader.read_bit().is_err());
+
+        let mut view_reader = view.reader();
+        view_reader.seek_bits(7).unwrap();
+        assert_eq!(view_reader.read_bits(5).unwrap(), 0b1_0101);
+        assert!(view_reader.seek_bits(2).is_err());
+        assert!(view_reader.seek_bits(13).is_err());
+    }
+}
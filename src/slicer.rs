@@ -0,0 +1,221 @@
+// Turns an analog capture (a WAV file from a sound card or SDR, or raw ADC samples) back into the
+// bits it was carrying
+use crate::logic_import::Samples;
+use std::io::{Error, ErrorKind, Read};
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Interleaved when there's more than one channel. 8 bit samples are shifted to be signed.
+    pub samples: Vec<i32>,
+}
+
+impl Wav {
+    pub fn channel(&self, channel: usize) -> Vec<i32> {
+        self.samples
+            .iter()
+            .skip(channel)
+            .step_by(self.channels.max(1) as usize)
+            .copied()
+            .collect()
+    }
+}
+
+// Reads 8 or 16 bit PCM WAV data
+pub fn read_wav<R: Read>(mut input: R) -> Result<Wav, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let id = &data[position..position + 4];
+        let size = u32::from_le_bytes([
+            data[position + 4],
+            data[position + 5],
+            data[position + 6],
+            data[position + 7],
+        ]) as usize;
+        let body = data
+            .get(position + 8..position + 8 + size)
+            .ok_or_else(|| invalid("WAV chunk runs past the end of the file"))?;
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid("WAV format chunk is too short"));
+                }
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if tag != 1 || !(bits == 8 || bits == 16) || channels == 0 {
+                    return Err(invalid("Only 8 and 16 bit PCM WAV files are supported"));
+                }
+                format = Some((channels, sample_rate, bits));
+            }
+            b"data" => {
+                let (channels, sample_rate, bits) =
+                    format.ok_or_else(|| invalid("WAV data comes before its format"))?;
+                let samples = if bits == 8 {
+                    body.iter().map(|&sample| sample as i32 - 128).collect()
+                } else {
+                    body.chunks_exact(2)
+                        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as i32)
+                        .collect()
+                };
+                return Ok(Wav {
+                    sample_rate,
+                    channels,
+                    samples,
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        position += 8 + size + size % 2;
+    }
+    Err(invalid("WAV file has no data"))
+}
+
+// Decides one bit per symbol from samples above (1) or below (0) a threshold. The symbol clock is
+// pulled toward the level transitions as they go by, so a transmitter a little off the nominal
+// rate still gets sampled in the middle of its symbols.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slicer {
+    samples_per_symbol: f64,
+    threshold: i32,
+    loop_gain: f64,
+}
+
+impl Slicer {
+    pub fn new(sample_rate: u32, symbol_rate: u32, threshold: i32) -> Result<Slicer, Error> {
+        if symbol_rate == 0 || sample_rate < 2 * symbol_rate {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Need at least 2 samples per symbol",
+            ));
+        }
+        Ok(Slicer {
+            samples_per_symbol: sample_rate as f64 / symbol_rate as f64,
+            threshold,
+            loop_gain: 0.3,
+        })
+    }
+
+    // How far (0 to 1) the clock moves toward each transition. 0 turns clock recovery off.
+    pub fn with_loop_gain(mut self, loop_gain: f64) -> Slicer {
+        self.loop_gain = loop_gain.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn slice(&self, samples: &[i32]) -> Result<Samples, Error> {
+        let step = 1.0 / self.samples_per_symbol;
+        // Where the current sample falls in its symbol, 0 to 1
+        let mut phase = 0.0;
+        let mut previous = None;
+        let mut bits = Vec::with_capacity((samples.len() as f64 * step) as usize + 1);
+        for &sample in samples {
+            let level = sample > self.threshold;
+            if previous.is_some_and(|previous| previous != level) {
+                // Transitions belong on symbol boundaries (phase 0)
+                if phase < 0.5 {
+                    phase -= self.loop_gain * phase;
+                } else {
+                    phase += self.loop_gain * (1.0 - phase);
+                }
+            }
+            previous = Some(level);
+
+            let next_phase = phase + step;
+            // Decide the bit as the clock passes the middle of the symbol
+            if phase < 0.5 && next_phase >= 0.5 {
+                bits.push(level);
+            }
+            phase = if next_phase >= 1.0 {
+                next_phase - 1.0
+            } else {
+                next_phase
+            };
+        }
+        Samples::from_bits(bits.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern() -> Vec<bool> {
+        // 7 bit LFSR, plenty of transitions with runs up to 7
+        let mut state = 0b100_0001u8;
+        (0..127)
+            .map(|_| {
+                let bit = (state >> 6 ^ state >> 5) & 1;
+                state = (state << 1 | bit) & 0x7F;
+                bit == 1
+            })
+            .collect()
+    }
+
+    // Square wave at a rate a little off from the slicer's
+    fn waveform(bits: &[bool], samples_per_symbol: f64) -> Vec<i32> {
+        let length = (bits.len() as f64 * samples_per_symbol) as usize;
+        (0..length)
+            .map(|index| {
+                let symbol = (index as f64 / samples_per_symbol) as usize;
+                if bits[symbol.min(bits.len() - 1)] {
+                    900
+                } else {
+                    -900
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn recovers_drifting_clock() {
+        let bits = pattern();
+        let samples = waveform(&bits, 10.3);
+        let slicer = Slicer::new(48_000, 4_800, 0).unwrap();
+
+        let recovered = slicer.slice(&samples).unwrap();
+        let mut reader = recovered.clone().into_reader();
+        let decoded: Vec<bool> = (0..bits.len())
+            .map(|_| reader.read_bit().unwrap())
+            .collect();
+        assert_eq!(decoded, bits);
+
+        // Without clock recovery the sampling point walks off the symbols
+        let drifting = slicer.with_loop_gain(0.0).slice(&samples).unwrap();
+        assert_ne!(drifting, recovered);
+    }
+
+    #[test]
+    pub fn reads_wav() {
+        // 16 bit stereo, 3 frames
+        let mut file = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&[1, 0, 2, 0]);
+        file.extend_from_slice(&8000u32.to_le_bytes());
+        file.extend_from_slice(&32000u32.to_le_bytes());
+        file.extend_from_slice(&[4, 0, 16, 0]);
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&12u32.to_le_bytes());
+        for sample in [100i16, -1, -200, -1, 300, -1] {
+            file.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        let wav = read_wav(&file[..]).unwrap();
+        assert_eq!(wav.sample_rate, 8000);
+        assert_eq!(wav.channel(0), [100, -200, 300]);
+        assert!(read_wav(&b"RIFF"[..]).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// A tokio-util Encoder and Decoder for bit-packed messages over a byte transport, so a bit format
+// can go straight into Framed. Each frame is the payload's length in bits as a big endian u32,
+// then the payload zero padded to a whole byte. The decoder fails a message that doesn't use
+// exactly its own bits, or whose padding isn't zeros.
+use crate::{Reader, Writer};
+use bytes::{Buf, BufMut, BytesMut};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+const PREFIX_BYTES: usize = 4;
+
+// A message that knows how to write itself as bits and read itself back
+pub trait BitMessage: Sized {
+    fn encode<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), crate::Error>;
+
+    fn decode<R: Read>(reader: &mut Reader<R>) -> Result<Self, crate::Error>;
+}
+
+pub struct BitFrameCodec<M> {
+    max_frame_bits: u64,
+    message: PhantomData<fn() -> M>,
+}
+
+impl<M> BitFrameCodec<M> {
+    // Frames are limited to 8 MiB of payload unless set otherwise
+    pub fn new() -> BitFrameCodec<M> {
+        BitFrameCodec::with_max_frame_bits(8 * 8 * 1024 * 1024)
+    }
+
+    // A length prefix over max_frame_bits is an error rather than something to buffer for, so a
+    // bad peer can't make the decoder hold gigabytes. Can't go over u32::MAX.
+    pub fn with_max_frame_bits(max_frame_bits: u64) -> BitFrameCodec<M> {
+        BitFrameCodec {
+            max_frame_bits: max_frame_bits.min(u32::MAX as u64),
+            message: PhantomData,
+        }
+    }
+
+    pub fn max_frame_bits(&self) -> u64 {
+        self.max_frame_bits
+    }
+}
+
+impl<M> Default for BitFrameCodec<M> {
+    fn default() -> BitFrameCodec<M> {
+        BitFrameCodec::new()
+    }
+}
+
+impl<M: BitMessage> Encoder<M> for BitFrameCodec<M> {
+    type Error = Error;
+
+    fn encode(&mut self, message: M, dst: &mut BytesMut) -> Result<(), Error> {
+        let mut writer = Writer::new(Vec::new());
+        message.encode(&mut writer)?;
+        let bits = writer.bits_written();
+        if bits > self.max_frame_bits {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Message is {} bits, over the {} bit frame limit",
+                    bits, self.max_frame_bits
+                ),
+            ));
+        }
+        let payload = writer.into_inner()?;
+        dst.reserve(PREFIX_BYTES + payload.len());
+        dst.put_u32(bits as u32);
+        dst.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+impl<M: BitMessage> Decoder for BitFrameCodec<M> {
+    type Item = M;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<M>, Error> {
+        if src.len() < PREFIX_BYTES {
+            return Ok(None);
+        }
+        let bits = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as u64;
+        if bits > self.max_frame_bits {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Frame of {} bits is over the {} bit limit",
+                    bits, self.max_frame_bits
+                ),
+            ));
+        }
+        let frame_bytes = PREFIX_BYTES + bits.div_ceil(8) as usize;
+        if src.len() < frame_bytes {
+            src.reserve(frame_bytes - src.len());
+            return Ok(None);
+        }
+        src.advance(PREFIX_BYTES);
+        let payload = src.split_to(frame_bytes - PREFIX_BYTES);
+
+        let mut reader = Reader::new(&payload[..]);
+        let message = M::decode(&mut reader)?;
+        // Reading into the padding doesn't fail on its own, so check the count
+        if reader.bits_read() != bits {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Message used {} of its frame's {} bits",
+                    reader.bits_read(),
+                    bits
+                ),
+            ));
+        }
+        reader.align_to_byte(true)?;
+        Ok(Some(message))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Reading {
+        sensor: u8,
+        value: i16,
+        valid: bool,
+    }
+
+    impl BitMessage for Reading {
+        fn encode<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), crate::Error> {
+            writer.write_bits(self.sensor as u128, 5)?;
+            writer.write_bits(self.value as u16 as u128, 16)?;
+            writer.write_bit(self.valid)
+        }
+
+        fn decode<R: Read>(reader: &mut Reader<R>) -> Result<Reading, crate::Error> {
+            Ok(Reading {
+                sensor: reader.read_bits(5)? as u8,
+                value: reader.read_bits(16)? as u16 as i16,
+                valid: reader.read_bit()?,
+            })
+        }
+    }
+
+    #[test]
+    pub fn frames_messages() {
+        let readings = vec![
+            Reading {
+                sensor: 17,
+                value: -300,
+                valid: true,
+            },
+            Reading {
+                sensor: 2,
+                value: 42,
+                valid: false,
+            },
+        ];
+        let mut codec = BitFrameCodec::new();
+        let mut buffer = BytesMut::new();
+        for reading in readings.clone() {
+            codec.encode(reading, &mut buffer).unwrap();
+        }
+        // 22 bits is 3 bytes after the prefix
+        assert_eq!(buffer.len(), 2 * (4 + 3));
+        assert_eq!(buffer[..4], [0, 0, 0, 22]);
+
+        // A frame trickling in a byte at a time
+        let mut incoming = BytesMut::new();
+        let mut decoded = Vec::new();
+        for &byte in buffer.iter() {
+            incoming.put_u8(byte);
+            if let Some(reading) = codec.decode(&mut incoming).unwrap() {
+                decoded.push(reading);
+            }
+        }
+        assert!(incoming.is_empty());
+        assert_eq!(decoded, readings);
+    }
+
+    #[test]
+    pub fn rejects_bad_frames() {
+        let mut codec = BitFrameCodec::<Reading>::with_max_frame_bits(64);
+        // Longer than the message uses
+        let mut frame = BytesMut::from(&[0, 0, 0, 30, 0, 0, 0, 0][..]);
+        assert!(codec.decode(&mut frame).is_err());
+        // Shorter than the message needs
+        let mut frame = BytesMut::from(&[0, 0, 0, 20, 0, 0, 0][..]);
+        assert!(codec.decode(&mut frame).is_err());
+        // Set padding
+        let mut frame = BytesMut::from(&[0, 0, 0, 22, 0, 0, 1][..]);
+        assert!(codec.decode(&mut frame).is_err());
+        // Over the limit, before any of it arrives
+        let mut frame = BytesMut::from(&[0, 0, 1, 0][..]);
+        assert!(codec.decode(&mut frame).is_err());
+    }
+}
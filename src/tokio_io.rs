@@ -0,0 +1,80 @@
+// Reader and Writer over tokio's AsyncRead and AsyncWrite, with the same bit calls made async, so
+// a server can decode bit-packed frames without parking a thread on every connection. Bits are
+// cached the same way as the sync types and errors are the same crate Error.
+use crate::async_bits::async_bit_types;
+use crate::bit_write::check_fits;
+use crate::{BitOrder, Error};
+use std::io::{self, ErrorKind};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+fn consume<R: AsyncRead + Unpin>(reader: &mut BufReader<R>, amount: usize) {
+    reader.consume(amount);
+}
+
+async fn skip_bytes<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    count: u64,
+) -> io::Result<u64> {
+    tokio::io::copy(&mut reader.take(count), &mut tokio::io::sink()).await
+}
+
+async_bit_types!();
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Reader, Writer};
+    use std::io::Cursor;
+
+    #[tokio::test]
+    pub async fn matches_sync_types() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+            let mut writer = AsyncBitWriter::with_bit_order(Vec::new(), bit_order);
+            for index in 0..2000u128 {
+                let width = (index % 100 + 1) as usize;
+                let value =
+                    index.wrapping_mul(0x9E37_79B9_7F4A_7C15) & (u128::MAX >> (128 - width));
+                expected.write_bits(value, width).unwrap();
+                writer.write_bits(value, width).await.unwrap();
+            }
+            writer.write_bytes(vec![1, 2, 3]).await.unwrap();
+            expected.write_bytes(vec![1, 2, 3]).unwrap();
+            assert_eq!(writer.bits_written(), expected.bits_written());
+            let bytes = writer.into_inner().await.unwrap();
+            assert_eq!(bytes, expected.into_inner().unwrap());
+
+            let mut sync_reader = Reader::with_bit_order(Cursor::new(bytes.clone()), bit_order);
+            let mut reader = AsyncBitReader::with_bit_order(&bytes[..], bit_order);
+            for index in 0..2000u128 {
+                let width = (index % 100 + 1) as usize;
+                assert_eq!(
+                    reader.read_bits(width).await.unwrap(),
+                    sync_reader.read_bits(width).unwrap()
+                );
+            }
+            assert_eq!(reader.read_bytes(3).await.unwrap(), [1, 2, 3]);
+            reader.align_to_byte(true).await.unwrap();
+            assert_eq!(reader.bits_read(), bytes.len() as u64 * 8);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn skips_and_runs_out() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let mut reader = AsyncBitReader::new(&bytes[..]);
+        reader.skip_bits(3).await.unwrap();
+        reader.skip_bits(8 * 200 + 5).await.unwrap();
+        assert_eq!(reader.read_byte().await.unwrap(), 201);
+        assert_eq!(reader.read_bytes(50).await.unwrap(), bytes[202..252]);
+        let error = reader.read_bits(40).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnexpectedEof {
+                bits_missing: 8,
+                ..
+            }
+        ));
+        assert!(reader.skip_bits(100).await.is_err());
+    }
+}
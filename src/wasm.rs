@@ -0,0 +1,177 @@
+// Reader and Writer for JavaScript through wasm-bindgen, so a browser tool can run the same
+// parsing code as everything else. Bytes come in and go out as Uint8Array. Fields of up to 32 bits
+// are plain numbers and up to 64 bits are BigInts, and positions are numbers, which hold them
+// exactly up to 2^53 bits. Errors are thrown as JS Errors with the crate Error's message.
+use crate::{BitOrder, Error, Reader, Writer};
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+fn bit_order(lsb_first: bool) -> BitOrder {
+    if lsb_first {
+        BitOrder::LsbFirst
+    } else {
+        BitOrder::MsbFirst
+    }
+}
+
+fn check_width(bit_position: u64, number_of_bits: usize, max_bits: usize) -> Result<(), Error> {
+    if number_of_bits > max_bits {
+        return Err(Error::too_wide(bit_position, number_of_bits, max_bits));
+    }
+    Ok(())
+}
+
+#[wasm_bindgen(js_name = BitReader)]
+pub struct WasmBitReader {
+    reader: Reader<Cursor<Vec<u8>>>,
+}
+
+#[wasm_bindgen(js_class = BitReader)]
+impl WasmBitReader {
+    // Reads from a copy of bytes
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: &[u8], lsb_first: bool) -> WasmBitReader {
+        WasmBitReader {
+            reader: Reader::with_bit_order(Cursor::new(bytes.to_vec()), bit_order(lsb_first)),
+        }
+    }
+
+    #[wasm_bindgen(js_name = bitsRead)]
+    pub fn bits_read(&self) -> f64 {
+        self.reader.bits_read() as f64
+    }
+
+    #[wasm_bindgen(js_name = isAligned)]
+    pub fn is_aligned(&self) -> bool {
+        self.reader.is_aligned()
+    }
+
+    #[wasm_bindgen(js_name = readBit)]
+    pub fn read_bit(&mut self) -> Result<bool, JsError> {
+        Ok(self.reader.read_bit()?)
+    }
+
+    #[wasm_bindgen(js_name = readBits)]
+    pub fn read_bits(&mut self, number_of_bits: usize) -> Result<u32, JsError> {
+        check_width(self.reader.bits_read(), number_of_bits, 32)?;
+        Ok(self.reader.read_bits(number_of_bits)? as u32)
+    }
+
+    #[wasm_bindgen(js_name = readBigBits)]
+    pub fn read_big_bits(&mut self, number_of_bits: usize) -> Result<u64, JsError> {
+        check_width(self.reader.bits_read(), number_of_bits, 64)?;
+        Ok(self.reader.read_bits(number_of_bits)? as u64)
+    }
+
+    #[wasm_bindgen(js_name = readBytes)]
+    pub fn read_bytes(&mut self, number_of_bytes: usize) -> Result<Vec<u8>, JsError> {
+        Ok(self.reader.read_bytes(number_of_bytes)?)
+    }
+
+    #[wasm_bindgen(js_name = skipBits)]
+    pub fn skip_bits(&mut self, number_of_bits: f64) -> Result<(), JsError> {
+        Ok(self.reader.skip_bits(number_of_bits as u64)?)
+    }
+
+    #[wasm_bindgen(js_name = alignToByte)]
+    pub fn align_to_byte(&mut self, require_zeros: bool) -> Result<(), JsError> {
+        Ok(self.reader.align_to_byte(require_zeros)?)
+    }
+}
+
+#[wasm_bindgen(js_name = BitWriter)]
+pub struct WasmBitWriter {
+    writer: Writer<Vec<u8>>,
+}
+
+#[wasm_bindgen(js_class = BitWriter)]
+impl WasmBitWriter {
+    #[wasm_bindgen(constructor)]
+    pub fn new(lsb_first: bool) -> WasmBitWriter {
+        WasmBitWriter {
+            writer: Writer::with_bit_order(Vec::new(), bit_order(lsb_first)),
+        }
+    }
+
+    #[wasm_bindgen(js_name = bitsWritten)]
+    pub fn bits_written(&self) -> f64 {
+        self.writer.bits_written() as f64
+    }
+
+    #[wasm_bindgen(js_name = writeBit)]
+    pub fn write_bit(&mut self, write_one: bool) -> Result<(), JsError> {
+        Ok(self.writer.write_bit(write_one)?)
+    }
+
+    // Set bits above number_of_bits are an error rather than dropped, since JS callers can't see
+    // the width a number was meant to have
+    #[wasm_bindgen(js_name = writeBits)]
+    pub fn write_bits(&mut self, bits: u32, number_of_bits: usize) -> Result<(), JsError> {
+        check_width(self.writer.bits_written(), number_of_bits, 32)?;
+        Ok(self
+            .writer
+            .write_bits_checked(bits as u128, number_of_bits)?)
+    }
+
+    #[wasm_bindgen(js_name = writeBigBits)]
+    pub fn write_big_bits(&mut self, bits: u64, number_of_bits: usize) -> Result<(), JsError> {
+        check_width(self.writer.bits_written(), number_of_bits, 64)?;
+        Ok(self
+            .writer
+            .write_bits_checked(bits as u128, number_of_bits)?)
+    }
+
+    #[wasm_bindgen(js_name = writeBytes)]
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        Ok(self.writer.write_bytes(bytes.to_vec())?)
+    }
+
+    // Gives back how many padding bits it added
+    #[wasm_bindgen(js_name = padToByte)]
+    pub fn pad_to_byte(&mut self) -> Result<usize, JsError> {
+        Ok(self.writer.pad_to_byte()?)
+    }
+
+    // Pads to a byte and hands back everything written. The writer can't be used after this.
+    pub fn finish(self) -> Result<Vec<u8>, JsError> {
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+// Anything that throws can't be tested off wasm, since making a JsError calls into JS
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn round_trips() {
+        for lsb_first in [false, true] {
+            let mut writer = WasmBitWriter::new(lsb_first);
+            writer.write_bit(true).unwrap();
+            writer.write_bits(0x1_2345, 17).unwrap();
+            writer.write_big_bits(0xDEAD_BEEF_CAFE, 48).unwrap();
+            assert_eq!(writer.pad_to_byte().unwrap(), 6);
+            writer.write_bytes(&[1, 2, 3]).unwrap();
+            assert_eq!(writer.bits_written(), 96.0);
+            let bytes = writer.finish().unwrap();
+
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order(lsb_first));
+            expected.write_bit(true).unwrap();
+            expected.write_bits(0x1_2345, 17).unwrap();
+            expected.write_bits(0xDEAD_BEEF_CAFE, 48).unwrap();
+            expected.pad_to_byte().unwrap();
+            expected.write_bytes(vec![1, 2, 3]).unwrap();
+            assert_eq!(bytes, expected.into_inner().unwrap());
+
+            let mut reader = WasmBitReader::new(&bytes, lsb_first);
+            assert!(reader.read_bit().unwrap());
+            assert_eq!(reader.read_bits(17).unwrap(), 0x1_2345);
+            assert_eq!(reader.read_big_bits(48).unwrap(), 0xDEAD_BEEF_CAFE);
+            assert!(!reader.is_aligned());
+            reader.align_to_byte(true).unwrap();
+            reader.skip_bits(8.0).unwrap();
+            assert_eq!(reader.read_bytes(2).unwrap(), [2, 3]);
+            assert_eq!(reader.bits_read(), 96.0);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::{Error, ErrorKind};
+
+// Counts bits a parser takes between bits of progress (a frame accepted, a record decoded) and
+// errors once too many go by, so garbage input can't keep a resync loop spinning forever
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchdog {
+    limit: u64,
+    consumed: u64,
+}
+
+impl Watchdog {
+    pub fn new(limit: u64) -> Watchdog {
+        Watchdog { limit, consumed: 0 }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    // Bits taken since the last progress
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    pub fn consume(&mut self, number_of_bits: u64) -> Result<(), Error> {
+        self.consumed = self.consumed.saturating_add(number_of_bits);
+        if self.consumed > self.limit {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("Parser took {} bits without making progress", self.consumed),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn progress(&mut self) {
+        self.consumed = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn trips_without_progress() {
+        let mut watchdog = Watchdog::new(16);
+        watchdog.consume(10).unwrap();
+        watchdog.progress();
+        watchdog.consume(16).unwrap();
+        let error = watchdog.consume(1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert_eq!(watchdog.consumed(), 17);
+    }
+}
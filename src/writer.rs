@@ -1,99 +1,514 @@
 #![allow(dead_code)]
-use std::io::{BufWriter, Error, ErrorKind, Write};
+use crate::bit_write::check_fits;
+use crate::byte_io::ByteSink;
+use crate::{BitOrder, Error, LeftoverBits};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom, Write};
+
+// How a stream's last byte gets filled out by Writer::finish
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FinishPolicy {
+    // A 1 then zeros, always at least the 1, like the RBSP trailing bits in H.264 and HEVC
+    StopBit,
+    Zeros,
+    Ones,
+    // Fails unless the stream already ends on a byte
+    RequireAligned,
+}
+
+// The bits of fill for count places from place (0 to 7) on in a byte, laid out the way bit_order
+// lays out a byte. Places past the end of the byte are zeros.
+pub(crate) fn fill_bits(fill: u8, place: usize, count: usize, bit_order: BitOrder) -> u128 {
+    let bits = match bit_order {
+        BitOrder::MsbFirst => (fill as u128) << count >> (8 - place),
+        BitOrder::LsbFirst => fill as u128 >> place,
+    };
+    bits & 1u128
+        .checked_shl(count as u32)
+        .map_or(u128::MAX, |bit| bit - 1)
+}
 
-pub struct Writer<W: Write> {
-    byte: [u8; 1],
-    byte_offset: usize,
-    writer: BufWriter<W>,
+pub struct Writer<W: ByteSink> {
+    // Bits not written out yet, in the low `cached` bits. The oldest is the top one of those in
+    // MSB first order and the bottom one in LSB first order. A full cache goes out 8 bytes at once.
+    cache: u64,
+    cached: usize,
+    bit_order: BitOrder,
+    bits_written: u64,
+    // Byte the padding bits are taken from, in whichever positions they fall
+    pad_fill: u8,
+    // The first failure while extending from an iterator, which has nowhere else to go, handed
+    // back by the next flush
+    extend_error: Option<Error>,
+    // Whole bytes on their way to the writer, which get there once they fill the buffer or on
+    // flush, like with a BufWriter
+    buffer: Vec<u8>,
+    writer: W,
 }
 
-impl<W: Write> Writer<W> {
+impl<W: ByteSink> Writer<W> {
     pub fn new(inner_writer: W) -> Writer<W> {
+        Writer::with_bit_order(inner_writer, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(inner_writer: W, bit_order: BitOrder) -> Writer<W> {
         Writer {
-            byte: [0],
-            byte_offset: 0,
-            writer: BufWriter::new(inner_writer),
+            cache: 0,
+            cached: 0,
+            bit_order,
+            bits_written: 0,
+            pad_fill: 0,
+            extend_error: None,
+            buffer: Vec::with_capacity(W::BUFFER_SIZE),
+            writer: inner_writer,
         }
     }
 
-    pub fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
-        self.byte[0] <<= 1; // Left shift one so we can add next bit
-        if write_one {
-            self.byte[0] |= 0b0000_0001;
-        }
-        self.byte_offset += 1;
-        if self.byte_offset == 8 {
-            // We're at a full byte, so write it
-            let num_bytes_written = self.writer.write(&self.byte)?;
-            if num_bytes_written == 0 {
-                return Err(Error::new(ErrorKind::WriteZero, "Wrote nothing"));
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    // Whether the next bit starts a byte
+    pub fn is_aligned(&self) -> bool {
+        self.cached.is_multiple_of(8)
+    }
+
+    // Bits in the current byte that haven't been written out yet
+    pub fn pending_bits(&self) -> usize {
+        self.cached % 8
+    }
+
+    // Bits produced so far, padding included. Patching doesn't change it.
+    pub fn bits_written(&self) -> u64 {
+        self.bits_written
+    }
+
+    // Buffers bytes, writing the buffer out first if they don't fit. As many bytes as the buffer
+    // holds or more go straight to the writer.
+    fn write_out(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if self.buffer.len() + bytes.len() > W::BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        if bytes.len() >= W::BUFFER_SIZE {
+            return self.writer.push(bytes, self.bits_written);
+        }
+        self.buffer.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_buffer(&mut self) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            self.writer.push(&self.buffer, self.bits_written)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    // Writes out the buffer and flushes the writer, leaving the cache alone
+    fn flush_buffer(&mut self) -> Result<(), Error> {
+        self.write_buffer()?;
+        self.writer.flush_out(self.bits_written)
+    }
+
+    // Adds number_of_bits (1 to 64, no more than the cache has room for) from the bottom of bits
+    fn push_bits(&mut self, bits: u64, number_of_bits: usize) -> Result<(), Error> {
+        match self.bit_order {
+            BitOrder::MsbFirst => {
+                self.cache = self.cache.checked_shl(number_of_bits as u32).unwrap_or(0) | bits
             }
-            self.byte = [0];
-            self.byte_offset = 0;
+            BitOrder::LsbFirst => self.cache |= bits << self.cached,
+        }
+        self.cached += number_of_bits;
+        self.bits_written += number_of_bits as u64;
+        if self.cached == 64 {
+            let bytes = match self.bit_order {
+                BitOrder::MsbFirst => self.cache.to_be_bytes(),
+                BitOrder::LsbFirst => self.cache.to_le_bytes(),
+            };
+            self.write_out(&bytes)?;
+            self.cache = 0;
+            self.cached = 0;
         }
         Ok(())
     }
 
+    // Writes out every whole byte in the cache, leaving only the partial one
+    fn spill_whole_bytes(&mut self) -> Result<(), Error> {
+        let whole_bytes = self.cached / 8;
+        let mut bytes = [0; 8];
+        for (index, byte) in bytes[..whole_bytes].iter_mut().enumerate() {
+            *byte = match self.bit_order {
+                BitOrder::MsbFirst => self.cache >> (self.cached - 8 * (index + 1)),
+                BitOrder::LsbFirst => self.cache >> (8 * index),
+            } as u8;
+        }
+        self.write_out(&bytes[..whole_bytes])?;
+        self.cached %= 8;
+        self.cache = match self.bit_order {
+            BitOrder::MsbFirst => self.cache & ((1 << self.cached) - 1),
+            BitOrder::LsbFirst => self.cache.checked_shr(8 * whole_bytes as u32).unwrap_or(0),
+        };
+        Ok(())
+    }
+
+    pub fn write_bit(&mut self, write_one: bool) -> Result<(), Error> {
+        self.push_bits(write_one as u64, 1)
+    }
+
     pub fn write_bits(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
         if number_of_bits > 128 {
-            // Make sure we're not writing more than 128 bits
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Tried to write more than 128 bits",
-            ));
+            return Err(Error::too_wide(self.bits_written, number_of_bits, 128));
+        }
+        // Goes into the cache as big a piece as fits at a time
+        let mut remaining = number_of_bits;
+        while remaining > 0 {
+            let count = remaining.min(64 - self.cached);
+            let piece = match self.bit_order {
+                // Most significant bits go first
+                BitOrder::MsbFirst => bits >> (remaining - count),
+                // Least significant bits go first
+                BitOrder::LsbFirst => bits >> (number_of_bits - remaining),
+            } as u64;
+            self.push_bits(piece & (u64::MAX >> (64 - count)), count)?;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
+    // write_bits that fails instead of dropping set bits above number_of_bits
+    pub fn write_bits_checked(&mut self, bits: u128, number_of_bits: usize) -> Result<(), Error> {
+        check_fits(self.bits_written, bits, number_of_bits)?;
+        self.write_bits(bits, number_of_bits)
+    }
+
+    // Writes each value in width bits, the counterpart to Reader::read_packed. Higher bits of the
+    // values are ignored.
+    pub fn write_packed(&mut self, width: usize, values: &[u64]) -> Result<(), Error> {
+        if width > 64 {
+            return Err(Error::too_wide(self.bits_written, width, 64));
+        }
+        if width == 0 {
+            return Ok(());
+        }
+        #[cfg(feature = "simd")]
+        let values = if self.is_aligned() && width <= crate::packing::MAX_WIDTH && values.len() >= 8
+        {
+            // Whole groups of 8 values end on a byte, so they can be packed and written as bytes
+            let (block, rest) = values.split_at(values.len() / 8 * 8);
+            let length = block.len() * width / 8;
+            let mut bytes = vec![0; length + crate::packing::SLACK];
+            crate::packing::pack(width, self.bit_order, block, &mut bytes);
+            bytes.truncate(length);
+            self.write_bytes(bytes)?;
+            rest
+        } else {
+            values
+        };
+        let mask = u64::MAX >> (64 - width);
+        for &value in values {
+            if width <= 64 - self.cached {
+                self.push_bits(value & mask, width)?;
+            } else {
+                // Splits across a full cache
+                self.write_bits((value & mask) as u128, width)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes bits in the order the iterator gives them, up to 64 at a time. Gives back how many.
+    pub fn write_bits_from_iter<I: IntoIterator<Item = bool>>(
+        &mut self,
+        bits: I,
+    ) -> Result<u64, Error> {
+        let mut written = 0;
+        let mut piece = 0u64;
+        let mut count = 0;
+        for bit in bits {
+            match self.bit_order {
+                BitOrder::MsbFirst => piece = piece << 1 | bit as u64,
+                BitOrder::LsbFirst => piece |= (bit as u64) << count,
+            }
+            count += 1;
+            if count == 64 {
+                self.write_bits(piece as u128, 64)?;
+                written += 64;
+                piece = 0;
+                count = 0;
+            }
         }
+        self.write_bits(piece as u128, count)?;
+        Ok(written + count as u64)
+    }
 
-        // Write the bits in order from LSB to MSB by masking everything except the bit we care about
-        for mask_location in 1..number_of_bits + 1 {
-            // assume 8 bit and we want 3 bits 00000111
-            // First: mask_location = 1 => mask = 1 << (3 - 1 = 2) = 100 => grab 00000100
-            // Second: mask_location = 2 => mask = 1 << (3 - 2 = 1) = 10 => grab 00000010
-            // Third: mask_location = 3 => mask = 1 << (3 - 3 = 0) = 1   => grab 00000001
-            let mask: u128 = 1 << (number_of_bits - mask_location);
-            self.write_bit(bits & mask != 0)?;
+    // Flag arrays, packed 64 to a write
+    pub fn write_bools(&mut self, bools: &[bool]) -> Result<(), Error> {
+        for chunk in bools.chunks(64) {
+            let pack = |piece: u64, &bit| piece << 1 | bit as u64;
+            let piece = match self.bit_order {
+                BitOrder::MsbFirst => chunk.iter().fold(0, pack),
+                // The first flag has to end up at the bottom
+                BitOrder::LsbFirst => chunk.iter().rev().fold(0, pack),
+            };
+            self.write_bits(piece as u128, chunk.len())?;
         }
         Ok(())
     }
 
+    // A presence bit, then the value through write if there is one. E can be anything the crate's
+    // Error converts into, so io::Error helpers work as well.
+    pub fn write_optional<T, E: From<Error>>(
+        &mut self,
+        value: Option<T>,
+        write: impl FnOnce(&mut Self, T) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.write_bit(value.is_some())?;
+        match value {
+            Some(value) => write(self, value),
+            None => Ok(()),
+        }
+    }
+
     pub fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
-        Ok(self.write_bits(byte as u128, 8)?)
+        self.write_bits(byte as u128, 8)
     }
 
     pub fn write_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        if self.is_aligned() {
+            // Whole bytes go out the same in either bit order
+            self.spill_whole_bytes()?;
+            self.write_out(&bytes)?;
+            self.bits_written += 8 * bytes.len() as u64;
+            return Ok(());
+        }
         for byte in bytes {
             self.write_byte(byte)?
         }
         Ok(())
     }
 
-    pub fn pad_to_byte(&mut self) -> Result<(), Error> {
-        if self.byte_offset != 0 {
-            self.write_bits(0, 8 - self.byte_offset)?;
+    // Sets what pad_to_byte, front_pad_to_byte and flush pad with. Each padding bit is the bit of
+    // fill in the same place in the byte, so 0xFF pads with ones and 0x55 with alternating bits.
+    // Zeros by default.
+    pub fn set_pad_fill(&mut self, fill: u8) {
+        self.pad_fill = fill;
+    }
+
+    pub fn pad_fill(&self) -> u8 {
+        self.pad_fill
+    }
+
+    // The padding functions give back how many padding bits they added, counted in bits_written
+    pub fn pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.pad_to_byte_with(self.pad_fill)
+    }
+
+    // pad_to_byte with a fill for just this call
+    pub fn pad_to_byte_with(&mut self, fill: u8) -> Result<usize, Error> {
+        if self.is_aligned() {
+            return Ok(0);
         }
-        Ok(())
+        let padding = 8 - self.pending_bits();
+        self.write_fill(fill, padding)?;
+        Ok(padding)
+    }
+
+    // Writes the bits of fill for the next count places in the current byte
+    fn write_fill(&mut self, fill: u8, count: usize) -> Result<(), Error> {
+        let bits = fill_bits(fill, self.pending_bits(), count, self.bit_order);
+        self.write_bits(bits, count)
     }
 
-    pub fn front_pad_to_byte(&mut self) -> Result<(), Error> {
-        let num_bytes_written = self.writer.write(&self.byte)?;
-        if num_bytes_written == 0 {
-            return Err(Error::new(ErrorKind::WriteZero, "Wrote nothing"));
+    // Pads until bits_written is a multiple of alignment bits, e.g. 32 for a word or 16384 for a
+    // 2048 byte sector. Gives back how many padding bits that took.
+    pub fn pad_to_alignment(&mut self, alignment: u64) -> Result<u64, Error> {
+        if alignment == 0 {
+            return Err(Error::invalid_input(
+                self.bits_written,
+                "Alignment has to be at least 1 bit",
+            ));
         }
-        self.byte = [0];
-        self.byte_offset = 0;
-        Ok(())
+        let padding = (alignment - self.bits_written % alignment) % alignment;
+        let mut remaining = padding;
+        while remaining > 0 {
+            let count = remaining.min(8 - self.pending_bits() as u64);
+            self.write_fill(self.pad_fill, count as usize)?;
+            remaining -= count;
+        }
+        Ok(padding)
     }
 
-    pub fn get_ref(&mut self) -> &BufWriter<W> {
+    pub fn front_pad_to_byte(&mut self) -> Result<usize, Error> {
+        self.spill_whole_bytes()?;
+        let mut byte = self.cache as u8;
+        let front = match self.bit_order {
+            BitOrder::MsbFirst => 0xFFu8.checked_shl(self.cached as u32).unwrap_or(0),
+            BitOrder::LsbFirst => {
+                // Bits sit at the back of the byte, so push them to the front
+                byte = byte.checked_shl(8 - self.cached as u32).unwrap_or(0);
+                0xFF >> self.cached
+            }
+        };
+        byte |= self.pad_fill & front;
+        self.write_out(&[byte])?;
+        let padding = 8 - self.cached;
+        self.bits_written += padding as u64;
+        self.cache = 0;
+        self.cached = 0;
+        Ok(padding)
+    }
+
+    // The inner writer, missing any bits still waiting in the cache or buffer until flush
+    pub fn get_ref(&self) -> &W {
         &self.writer
     }
 
-    pub fn flush(&mut self) -> Result<(), Error> {
-        self.pad_to_byte()?;
-        self.writer.flush()
+    pub fn flush(&mut self) -> Result<usize, Error> {
+        if let Some(error) = self.extend_error.take() {
+            return Err(error);
+        }
+        let padding = self.pad_to_byte()?;
+        self.spill_whole_bytes()?;
+        self.flush_buffer()?;
+        Ok(padding)
+    }
+
+    // Flushes every whole byte without padding. The bits of the byte still in progress stay put
+    // and are handed back too, so a new Writer can carry on with write_bits(bits, count).
+    pub fn flush_aligned(&mut self) -> Result<LeftoverBits, Error> {
+        self.spill_whole_bytes()?;
+        self.flush_buffer()?;
+        Ok(LeftoverBits {
+            bits: self.cache as u8,
+            count: self.cached,
+        })
+    }
+
+    // Ends the stream the way policy says and flushes. Gives back how many bits that added.
+    pub fn finish(&mut self, policy: FinishPolicy) -> Result<usize, Error> {
+        let added = match policy {
+            FinishPolicy::StopBit => {
+                self.write_bit(true)?;
+                1 + self.pad_to_byte_with(0)?
+            }
+            FinishPolicy::Zeros => self.pad_to_byte_with(0)?,
+            FinishPolicy::Ones => self.pad_to_byte_with(0xFF)?,
+            FinishPolicy::RequireAligned => {
+                if !self.is_aligned() {
+                    return Err(Error::invalid_input(
+                        self.bits_written,
+                        "Stream doesn't end on a byte boundary",
+                    ));
+                }
+                0
+            }
+        };
+        self.flush()?;
+        Ok(added)
+    }
+
+    // Pads and flushes, then hands back the inner writer
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Extend can't fail, so the first error stops it and waits for the next flush (or finish or
+// into_inner, which flush too)
+impl<W: ByteSink> Extend<bool> for Writer<W> {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, bits: I) {
+        if self.extend_error.is_none() {
+            if let Err(error) = self.write_bits_from_iter(bits) {
+                self.extend_error = Some(error);
+            }
+        }
+    }
+}
+
+// Backpatching needs to read back bytes that were already written, so the neighbouring bits of a
+// field that doesn't start or end on a byte boundary survive the patch
+#[cfg(feature = "std")]
+impl<W: Read + Write + Seek> Writer<W> {
+    // Bits from the start of the inner stream, including the ones waiting in the buffer and cache
+    pub fn bit_position(&mut self) -> Result<u64, Error> {
+        let position = self
+            .writer
+            .stream_position()
+            .map_err(Error::io_at(self.bits_written))?;
+        Ok((position + self.buffer.len() as u64) * 8 + self.cached as u64)
+    }
+
+    // Overwrites number_of_bits already written bits starting at position (from bit_position) and
+    // then carries on writing where it left off
+    pub fn patch_bits(
+        &mut self,
+        position: u64,
+        bits: u128,
+        number_of_bits: usize,
+    ) -> Result<(), Error> {
+        if number_of_bits > 128 {
+            return Err(Error::too_wide(self.bits_written, number_of_bits, 128));
+        }
+        self.spill_whole_bytes()?;
+        self.flush_buffer()?;
+        let at = self.bits_written;
+        let inner = &mut self.writer;
+        let end = inner.stream_position().map_err(Error::io_at(at))?;
+        if position + number_of_bits as u64 > end * 8 + self.cached as u64 {
+            return Err(Error::invalid_input(
+                at,
+                "Patch goes past the bits written so far",
+            ));
+        }
+
+        let mut index = 0;
+        while index < number_of_bits {
+            let byte_index = (position + index as u64) / 8;
+            let first_slot = ((position + index as u64) % 8) as usize;
+            let count = (8 - first_slot).min(number_of_bits - index);
+            // The last byte may still be the partial one that hasn't been written out
+            let pending = byte_index == end;
+            let mut byte = [self.cache as u8];
+            if !pending {
+                inner
+                    .seek(SeekFrom::Start(byte_index))
+                    .and_then(|_| inner.read_exact(&mut byte))
+                    .map_err(Error::io_at(at))?;
+            }
+
+            for slot in first_slot..first_slot + count {
+                let field_bit = index + slot - first_slot;
+                let bit = match self.bit_order {
+                    BitOrder::MsbFirst => bits >> (number_of_bits - 1 - field_bit) & 1,
+                    BitOrder::LsbFirst => bits >> field_bit & 1,
+                };
+                // Where the slot'th bit written to this byte ends up
+                let shift = match self.bit_order {
+                    BitOrder::MsbFirst if pending => self.cached - 1 - slot,
+                    BitOrder::MsbFirst => 7 - slot,
+                    BitOrder::LsbFirst => slot,
+                };
+                byte[0] = byte[0] & !(1 << shift) | (bit as u8) << shift;
+            }
+
+            if pending {
+                self.cache = byte[0] as u64;
+            } else {
+                inner
+                    .seek(SeekFrom::Start(byte_index))
+                    .and_then(|_| inner.write_all(&byte))
+                    .map_err(Error::io_at(at))?;
+            }
+            index += count;
+        }
+        inner.seek(SeekFrom::Start(end)).map_err(Error::io_at(at))?;
+        Ok(())
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
     use std::io::Cursor;
@@ -130,7 +545,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [251, 85, 160]);
+        assert_eq!(*writer.get_ref().get_ref(), [251, 85, 160]);
     }
 
     #[test]
@@ -143,7 +558,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [251, 85]);
+        assert_eq!(*writer.get_ref().get_ref(), [251, 85]);
     }
 
     pub fn write_partial_bits() {
@@ -155,7 +570,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [251, 85]);
+        assert_eq!(*writer.get_ref().get_ref(), [251, 85]);
     }
 
     #[test]
@@ -168,7 +583,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [251, 85]);
+        assert_eq!(*writer.get_ref().get_ref(), [251, 85]);
     }
 
     #[test]
@@ -180,7 +595,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [251, 85]);
+        assert_eq!(*writer.get_ref().get_ref(), [251, 85]);
     }
 
     #[test]
@@ -194,7 +609,7 @@ mod test {
         writer.flush().unwrap();
 
         assert_eq!(
-            *writer.get_ref().get_ref().get_ref(),
+            *writer.get_ref().get_ref(),
             [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 254, 31, 13]
         );
     }
@@ -220,7 +635,7 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [128, 254]);
+        assert_eq!(*writer.get_ref().get_ref(), [128, 254]);
     }
 
     #[test]
@@ -247,6 +662,338 @@ mod test {
 
         writer.flush().unwrap();
 
-        assert_eq!(*writer.get_ref().get_ref().get_ref(), [1, 5, 10]);
+        assert_eq!(*writer.get_ref().get_ref(), [1, 5, 10]);
+    }
+
+    #[test]
+    pub fn write_lsb_first() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::with_bit_order(cursor, BitOrder::LsbFirst);
+
+        // 1011 -> 0000_1011, 101 -> 0101_1011, 1 -> 1101_1011
+        writer.write_bits(0b1011, 4).unwrap();
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bit(true).unwrap();
+        writer.write_byte(85).unwrap();
+
+        // 1 -> 1000_0000
+        writer.write_bit(true).unwrap();
+        writer.front_pad_to_byte().unwrap();
+
+        writer.flush().unwrap();
+
+        assert_eq!(*writer.get_ref().get_ref(), [219, 85, 128]);
+    }
+
+    #[test]
+    pub fn into_inner() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(cursor);
+
+        writer.write_byte(251).unwrap();
+        writer.write_bits(0b101, 3).unwrap();
+
+        assert_eq!(writer.into_inner().unwrap().into_inner(), [251, 160]);
+    }
+
+    #[test]
+    pub fn bits_written() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(cursor);
+
+        writer.write_bits(0b101, 3).unwrap();
+        assert_eq!(writer.bits_written(), 3);
+        assert!(!writer.is_aligned());
+        assert_eq!(writer.pending_bits(), 3);
+        assert_eq!(writer.front_pad_to_byte().unwrap(), 5);
+        assert!(writer.is_aligned());
+        writer.write_byte(7).unwrap();
+        assert_eq!(writer.pad_to_byte().unwrap(), 0);
+        writer.write_bit(true).unwrap();
+        assert_eq!(writer.flush().unwrap(), 7);
+        assert_eq!(writer.flush().unwrap(), 0);
+        assert_eq!(writer.bits_written(), 24);
+    }
+
+    #[test]
+    pub fn patch_bits() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(cursor);
+
+        writer.write_bits(0b101, 3).unwrap();
+        let length_position = writer.bit_position().unwrap();
+        writer.write_bits(0, 10).unwrap();
+        writer.write_bits(0b1111_1111_1111, 12).unwrap();
+        // A 10 bit length straddling three bytes, then a 4 bit field still in the partial byte
+        writer
+            .patch_bits(length_position, 0b10_0000_0001, 10)
+            .unwrap();
+        writer.patch_bits(21, 0b0000, 4).unwrap();
+        assert_eq!(writer.bit_position().unwrap(), 25);
+        writer.write_bits(0b111, 3).unwrap();
+        writer.flush().unwrap();
+
+        // 101 1000000001 11111111 0000 111
+        assert_eq!(
+            *writer.get_ref().get_ref(),
+            [0b1011_0000, 0b0000_1111, 0b1111_1000, 0b0111_0000]
+        );
+        assert!(writer.patch_bits(30, 0, 3).is_err());
+    }
+
+    #[test]
+    pub fn patch_lsb_first() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::with_bit_order(cursor, BitOrder::LsbFirst);
+
+        writer.write_bits(0, 12).unwrap();
+        writer.write_bit(true).unwrap();
+        writer.patch_bits(6, 0b1_0111, 5).unwrap();
+        writer.patch_bits(11, 0b1, 1).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(*writer.get_ref().get_ref(), [0b1100_0000, 0b0001_1101]);
+    }
+
+    #[test]
+    pub fn aligned_bulk_writes() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::with_bit_order(cursor, BitOrder::LsbFirst);
+
+        writer.write_bytes(vec![0xAB, 0xCD]).unwrap();
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bytes(vec![0xFF]).unwrap();
+        assert_eq!(writer.bits_written(), 27);
+        writer.flush().unwrap();
+
+        assert_eq!(
+            *writer.get_ref().get_ref(),
+            [0xAB, 0xCD, 0b1111_1101, 0b0000_0111]
+        );
+    }
+
+    #[test]
+    pub fn write_packed() {
+        let cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(cursor);
+
+        writer.write_bit(true).unwrap();
+        writer.write_packed(3, &[0b101, 0b1111, 0b010]).unwrap();
+        writer.write_packed(0, &[7]).unwrap();
+        writer.write_packed(64, &[u64::MAX]).unwrap();
+        assert_eq!(writer.bits_written(), 74);
+        assert!(writer.write_packed(65, &[0]).is_err());
+        writer.flush().unwrap();
+
+        let mut expected = vec![0b1101_1110, 0b1011_1111];
+        expected.extend_from_slice(&[0xFF; 7]);
+        expected.push(0b1100_0000);
+        assert_eq!(*writer.get_ref().get_ref(), expected);
+    }
+
+    // Takes at most 3 bytes per write call, like a socket with a full send buffer, and is
+    // interrupted before every other one
+    struct Trickle(Vec<u8>, bool);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "Interrupted",
+                ));
+            }
+            let count = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn short_writes() {
+        // Big enough to skip the BufWriter and go straight to the inner writer
+        let bytes: Vec<u8> = (0..20_000).map(|index| index as u8).collect();
+        let mut writer = Writer::new(Trickle(Vec::new(), false));
+
+        writer.write_bytes(bytes.clone()).unwrap();
+        writer.write_bits(u64::MAX as u128, 64).unwrap();
+        writer.write_bits(0b101, 3).unwrap();
+        let output = writer.into_inner().unwrap().0;
+
+        assert_eq!(output.len(), 20_009);
+        assert_eq!(output[..20_000], bytes[..]);
+        assert_eq!(output[20_000..20_008], [0xFF; 8]);
+        assert_eq!(output[20_008], 0b1010_0000);
+    }
+
+    #[test]
+    pub fn flush_aligned() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), bit_order);
+            writer.write_bits(0xABC, 12).unwrap();
+            let leftover = writer.flush_aligned().unwrap();
+            assert_eq!(writer.get_ref().get_ref().len(), 1);
+            assert_eq!(leftover.count, 4);
+            assert_eq!(writer.bits_written(), 12);
+
+            // Carrying on in a fresh writer gives the same bytes as never stopping
+            let mut resumed = Writer::with_bit_order(Cursor::new(Vec::new()), bit_order);
+            resumed
+                .write_bits(leftover.bits as u128, leftover.count)
+                .unwrap();
+            resumed.write_bits(0x5, 4).unwrap();
+            writer.write_bits(0x5, 4).unwrap();
+            writer.flush().unwrap();
+            resumed.flush().unwrap();
+
+            let first = writer.get_ref().get_ref().clone();
+            assert_eq!(first[1..], resumed.get_ref().get_ref()[..]);
+        }
+    }
+
+    #[test]
+    pub fn pad_fill() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.set_pad_fill(0xFF);
+        writer.write_bits(0, 3).unwrap();
+        assert_eq!(writer.pad_to_byte().unwrap(), 5);
+        writer.write_bits(0, 2).unwrap();
+        writer.pad_to_byte_with(0x55).unwrap();
+        writer.write_bits(0, 2).unwrap();
+        writer.front_pad_to_byte().unwrap();
+        writer.write_bit(false).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(
+            *writer.get_ref().get_ref(),
+            [0b0001_1111, 0b0001_0101, 0b1111_1100, 0b0111_1111]
+        );
+
+        let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), BitOrder::LsbFirst);
+        writer.set_pad_fill(0x55);
+        writer.write_bits(0, 3).unwrap();
+        writer.pad_to_byte().unwrap();
+        writer.write_bits(0, 2).unwrap();
+        writer.front_pad_to_byte().unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*writer.get_ref().get_ref(), [0b0101_0000, 0b0001_0101]);
+    }
+
+    #[test]
+    pub fn pad_to_alignment() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.set_pad_fill(0x0F);
+        writer.write_bits(0b11, 2).unwrap();
+        assert_eq!(writer.pad_to_alignment(4).unwrap(), 2);
+        assert_eq!(writer.pad_to_alignment(4).unwrap(), 0);
+        assert_eq!(writer.pad_to_alignment(32).unwrap(), 28);
+        assert_eq!(writer.pad_to_alignment(16384).unwrap(), 16352);
+        assert!(writer.pad_to_alignment(0).is_err());
+        writer.flush().unwrap();
+
+        let output = writer.get_ref().get_ref();
+        assert_eq!(output.len(), 2048);
+        assert_eq!(output[..2], [0b1100_1111, 0x0F]);
+        assert!(output[2..].iter().all(|&byte| byte == 0x0F));
+    }
+
+    #[test]
+    pub fn finish() {
+        let finished = |bits: usize, policy| {
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            writer.set_pad_fill(0x55);
+            writer.write_bits(0, bits).unwrap();
+            let added = writer.finish(policy)?;
+            Ok::<_, Error>((added, writer.into_inner()?.into_inner()))
+        };
+
+        assert_eq!(
+            finished(3, FinishPolicy::StopBit).unwrap(),
+            (5, vec![0b0001_0000])
+        );
+        assert_eq!(
+            finished(8, FinishPolicy::StopBit).unwrap(),
+            (8, vec![0, 0b1000_0000])
+        );
+        assert_eq!(finished(3, FinishPolicy::Zeros).unwrap(), (5, vec![0]));
+        assert_eq!(
+            finished(3, FinishPolicy::Ones).unwrap(),
+            (5, vec![0b0001_1111])
+        );
+        assert_eq!(
+            finished(16, FinishPolicy::RequireAligned).unwrap(),
+            (0, vec![0, 0])
+        );
+        assert!(finished(3, FinishPolicy::RequireAligned).is_err());
+    }
+
+    #[test]
+    pub fn write_bits_checked() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.write_bits_checked(15, 4).unwrap();
+        writer.write_bits_checked(0, 0).unwrap();
+        let error = writer.write_bits_checked(500, 4).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ValueTooWide {
+                bit_position: 4,
+                number_of_bits: 9,
+                max_bits: 4
+            }
+        ));
+        assert!(writer.write_bits_checked(1, 0).is_err());
+        assert_eq!(writer.bits_written(), 4);
+    }
+
+    #[test]
+    pub fn write_from_iterator() {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let bits: Vec<bool> = (0..150).map(|index| index % 3 == 0).collect();
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+            for &bit in &bits {
+                expected.write_bit(bit).unwrap();
+            }
+
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            assert_eq!(
+                writer.write_bits_from_iter(bits[..100].to_vec()).unwrap(),
+                100
+            );
+            writer.extend(bits[100..].iter().copied());
+            assert_eq!(writer.bits_written(), 150);
+            assert_eq!(writer.into_inner().unwrap(), expected.into_inner().unwrap());
+        }
+
+        // A failure inside extend (more than the BufWriter holds, into a full buffer) comes out of
+        // the next flush
+        let mut full = [0u8; 16];
+        let mut writer = Writer::new(&mut full[..]);
+        writer.extend(std::iter::repeat_n(true, 100_000));
+        assert!(writer.bits_written() < 100_000);
+        assert_eq!(
+            writer.flush().unwrap_err().kind(),
+            std::io::ErrorKind::WriteZero
+        );
+    }
+
+    #[test]
+    pub fn write_bools() {
+        let bools: Vec<bool> = (0..150).map(|index| index % 5 < 2).collect();
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut expected = Writer::with_bit_order(Vec::new(), bit_order);
+            expected
+                .write_bits_from_iter(bools.iter().copied())
+                .unwrap();
+
+            let mut writer = Writer::with_bit_order(Vec::new(), bit_order);
+            writer.write_bools(&bools).unwrap();
+            writer.write_bools(&[]).unwrap();
+            assert_eq!(writer.bits_written(), 150);
+            assert_eq!(writer.into_inner().unwrap(), expected.into_inner().unwrap());
+        }
     }
 }
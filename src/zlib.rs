@@ -0,0 +1,175 @@
+pub use crate::crc::adler32;
+use crate::deflate::{BlockType, Deflate};
+use crate::{Reader, Writer};
+use std::io::{Error, ErrorKind, Read, Write};
+
+fn write_u32_be<W: Write>(writer: &mut Writer<W>, value: u32) -> Result<(), Error> {
+    for byte in value.to_be_bytes().iter() {
+        writer.write_byte(*byte)?;
+    }
+    Ok(())
+}
+
+fn read_u32_be<R: Read>(reader: &mut Reader<R>) -> Result<u32, Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_bytes_into(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZlibHeader {
+    // Base two log of the window size, 8 to 15
+    pub window_bits: u8,
+    // 0 (fastest) to 3 (best), informational only
+    pub level: u8,
+    pub dictionary_id: Option<u32>,
+}
+
+impl Default for ZlibHeader {
+    fn default() -> ZlibHeader {
+        ZlibHeader {
+            window_bits: 15,
+            level: 2,
+            dictionary_id: None,
+        }
+    }
+}
+
+impl ZlibHeader {
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        if self.window_bits < 8 || self.window_bits > 15 || self.level > 3 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Invalid zlib window size or level",
+            ));
+        }
+        let cmf = (self.window_bits - 8) << 4 | 8;
+        let mut flg = self.level << 6;
+        if self.dictionary_id.is_some() {
+            flg |= 0b0010_0000;
+        }
+        // FCHECK makes the two bytes a multiple of 31
+        flg |= ((31 - (cmf as u16 * 256 + flg as u16) % 31) % 31) as u8;
+        writer.write_byte(cmf)?;
+        writer.write_byte(flg)?;
+        if let Some(id) = self.dictionary_id {
+            write_u32_be(writer, id)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut Reader<R>) -> Result<ZlibHeader, Error> {
+        let cmf = reader.read_byte()?;
+        let flg = reader.read_byte()?;
+        if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad zlib header check"));
+        }
+        if cmf & 0x0F != 8 || cmf >> 4 > 7 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "zlib stream is not DEFLATE with a window of 32K or less",
+            ));
+        }
+        let dictionary_id = if flg & 0b0010_0000 != 0 {
+            Some(read_u32_be(reader)?)
+        } else {
+            None
+        };
+        Ok(ZlibHeader {
+            window_bits: (cmf >> 4) + 8,
+            level: flg >> 6,
+            dictionary_id,
+        })
+    }
+}
+
+// The ADLER32 of the uncompressed data, aligned to the next byte
+pub fn write_trailer<W: Write>(writer: &mut Writer<W>, data: &[u8]) -> Result<(), Error> {
+    writer.pad_to_byte()?;
+    write_u32_be(writer, adler32(data))
+}
+
+pub fn read_trailer<R: Read>(reader: &mut Reader<R>) -> Result<u32, Error> {
+    read_u32_be(reader)
+}
+
+// Checks a trailer against the uncompressed data
+pub fn verify_trailer<R: Read>(reader: &mut Reader<R>, data: &[u8]) -> Result<(), Error> {
+    if read_trailer(reader)? != adler32(data) {
+        return Err(Error::new(ErrorKind::InvalidData, "zlib ADLER32 mismatch"));
+    }
+    Ok(())
+}
+
+// Header, DEFLATE stream and trailer in one go. The writer has to be LSB first.
+pub fn write_stream<W: Write>(
+    writer: &mut Writer<W>,
+    data: &[u8],
+    block_type: BlockType,
+) -> Result<(), Error> {
+    ZlibHeader::default().write(writer)?;
+    Deflate::new(block_type).encode(data, writer)?;
+    write_trailer(writer, data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BitOrder;
+    use flate2::read::ZlibDecoder;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+        assert_eq!(adler32(&[255; 100_000]), 0x149A_302C);
+    }
+
+    #[test]
+    pub fn header_round_trip() {
+        let header = ZlibHeader {
+            window_bits: 12,
+            level: 3,
+            dictionary_id: Some(0xDEAD_BEEF),
+        };
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        header.write(&mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let cursor = Cursor::new(writer.get_ref().get_ref().clone());
+        let mut reader = Reader::new(cursor);
+        assert_eq!(ZlibHeader::read(&mut reader).unwrap(), header);
+    }
+
+    #[test]
+    pub fn default_header() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        ZlibHeader::default().write(&mut writer).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*writer.get_ref().get_ref(), [0x78, 0x9C]);
+
+        let mut reader = Reader::new(Cursor::new(vec![0x78, 0x9D]));
+        assert!(ZlibHeader::read(&mut reader).is_err());
+    }
+
+    #[test]
+    pub fn stream_decodes_with_flate2() {
+        let data = b"zlib wraps deflate, zlib wraps deflate, zlib wraps deflate".to_vec();
+        for block_type in [BlockType::Stored, BlockType::FixedHuffman].iter() {
+            let mut writer = Writer::with_bit_order(Cursor::new(Vec::new()), BitOrder::LsbFirst);
+            write_stream(&mut writer, &data, *block_type).unwrap();
+            writer.flush().unwrap();
+
+            let encoded = writer.get_ref().get_ref().clone();
+            let mut decoded = Vec::new();
+            ZlibDecoder::new(&encoded[..])
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+
+            let mut reader = Reader::new(Cursor::new(encoded[encoded.len() - 4..].to_vec()));
+            verify_trailer(&mut reader, &data).unwrap();
+        }
+    }
+}